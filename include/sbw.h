@@ -0,0 +1,28 @@
+/* C ABI of solana-balance-watcher, built with the `ffi` feature. See
+ * src/ffi.rs for the details of each function. */
+#ifndef SBW_H
+#define SBW_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Starts the watchers described by the JSON form of the --config file.
+ * Returns NULL on success, or an error message to free. */
+char *sbw_start(const char *config_json);
+
+/* JSON array of the balances observed since the previous call, to free, or
+ * NULL if not started. */
+char *sbw_poll_events(void);
+
+/* Asks every watcher to stop. */
+void sbw_stop(void);
+
+/* Frees a string returned by the library. */
+void sbw_free_string(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,153 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::{SecondsFormat, Utc};
+use once_cell::sync::OnceCell;
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::metrics::{
+    update_metric_program_accounts_changes, update_metric_program_accounts_matched,
+};
+
+static STATE_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// Starts persisting the accounts matched by every program-accounts scan to
+/// `dir`. Can only be called once.
+pub fn set_state_dir(dir: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(dir).map_err(|err| {
+        anyhow::anyhow!("Cannot create state directory '{}': {err}", dir.display())
+    })?;
+    STATE_DIR
+        .set(dir.to_path_buf())
+        .map_err(|_| anyhow::anyhow!("State directory is already set"))
+}
+
+/// Additions and removals between two scans.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountSetDiff {
+    pub added: Vec<Pubkey>,
+    pub removed: Vec<Pubkey>,
+}
+
+impl AccountSetDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// `name` with every byte but ASCII letters, digits, `-` and `_`
+/// percent-encoded, so that distinct names never share a file.
+fn file_name(name: &str) -> String {
+    name.bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => char::from(byte).to_string(),
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Appends `line` to the changes at `changes_path`, then replaces the set at
+/// `set_path` with `pubkeys`.
+fn persist(
+    changes_path: &Path,
+    set_path: &Path,
+    line: &str,
+    pubkeys: &BTreeSet<Pubkey>,
+) -> anyhow::Result<()> {
+    let mut changes = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(changes_path)?;
+    writeln!(changes, "{line}")?;
+    changes.sync_data()?;
+
+    // Replaced as a whole, so that a crash never leaves a partial set.
+    let temp_path = set_path.with_extension("pubkeys.tmp");
+    let content: String = pubkeys.iter().map(|pubkey| format!("{pubkey}\n")).collect();
+    fs::write(&temp_path, content)?;
+    fs::rename(&temp_path, set_path)?;
+    Ok(())
+}
+
+/// The accounts a program-accounts scan matched last, kept in
+/// `<name>.pubkeys`, one pubkey per line, with the name percent-encoded.
+/// Every change to the set is appended to `<name>.changes.jsonl`, so that a
+/// mirror of the set can be maintained by replaying the changes.
+pub struct AccountSetTracker {
+    name: String,
+    set_path: PathBuf,
+    changes_path: PathBuf,
+    pubkeys: Option<BTreeSet<Pubkey>>,
+}
+
+impl AccountSetTracker {
+    /// Loads the set persisted by a previous run. `None` unless a state
+    /// directory is set.
+    pub async fn open(name: &str) -> anyhow::Result<Option<Self>> {
+        let Some(dir) = STATE_DIR.get() else {
+            return Ok(None);
+        };
+        let file_name = file_name(name);
+        let set_path = dir.join(format!("{file_name}.pubkeys"));
+        let pubkeys = match tokio::fs::read_to_string(&set_path).await {
+            Ok(content) => Some(
+                content
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| line.trim().parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|err| {
+                        anyhow::anyhow!("Malformed account set '{}': {err}", set_path.display())
+                    })?,
+            ),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => anyhow::bail!("Cannot read '{}': {err}", set_path.display()),
+        };
+        Ok(Some(AccountSetTracker {
+            name: name.to_string(),
+            changes_path: dir.join(format!("{file_name}.changes.jsonl")),
+            set_path,
+            pubkeys,
+        }))
+    }
+
+    /// Records the accounts matched by the latest scan, returning what changed
+    /// since the previous one. Without a previous scan, every account counts
+    /// as added.
+    pub async fn update(&mut self, pubkeys: BTreeSet<Pubkey>) -> anyhow::Result<AccountSetDiff> {
+        let empty = BTreeSet::new();
+        let previous = self.pubkeys.as_ref().unwrap_or(&empty);
+        let diff = AccountSetDiff {
+            added: pubkeys.difference(previous).copied().collect(),
+            removed: previous.difference(&pubkeys).copied().collect(),
+        };
+        update_metric_program_accounts_matched(&self.name, pubkeys.len());
+        if diff.is_empty() && self.pubkeys.is_some() {
+            return Ok(diff);
+        }
+
+        let line = json!({
+            "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            "name": self.name,
+            "added": diff.added.iter().map(|pubkey| pubkey.to_string()).collect::<Vec<_>>(),
+            "removed": diff.removed.iter().map(|pubkey| pubkey.to_string()).collect::<Vec<_>>(),
+            "count": pubkeys.len(),
+        })
+        .to_string();
+        let changes_path = self.changes_path.clone();
+        let set_path = self.set_path.clone();
+        let pubkeys = tokio::task::spawn_blocking(move || {
+            persist(&changes_path, &set_path, &line, &pubkeys).map(|()| pubkeys)
+        })
+        .await??;
+
+        update_metric_program_accounts_changes(&self.name, diff.added.len(), diff.removed.len());
+        self.pubkeys = Some(pubkeys);
+        Ok(diff)
+    }
+}
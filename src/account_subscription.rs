@@ -0,0 +1,289 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use futures::{stream::select_all, StreamExt};
+use log::{error, info, warn};
+use once_cell::sync::OnceCell;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::{pubsub_client::PubsubClient, rpc_client::RpcClient},
+    rpc_config::RpcAccountInfoConfig,
+};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    balance::{check_balances, export_balance, AccountExpectations, WATCHER_NAME},
+    data_slice::AccountType,
+    health::record_successful_check,
+    intervals::DEFAULT_BACKOFF_DURATION,
+    metrics::update_metric_balance_subscription_active,
+    program_accounts_balance::{
+        export_total_balance, get_program_accounts, ProgramAccountsBalanceConfig,
+    },
+    rate_limit::RateLimiter,
+    rpc::RpcClientFactory,
+    shutdown::{is_shutdown_requested, shutdown_requested, sleep_unless_shutdown},
+};
+
+static WEBSOCKET_URL: OnceCell<String> = OnceCell::new();
+
+/// Pushes balance changes of named addresses from `accountSubscribe`, and of
+/// program-accounts scans with `subscribe:true` from `programSubscribe`, on
+/// the WebSocket at `url`, rather than polling them. Can only be called once.
+pub fn set_websocket_url(url: String) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        url.starts_with("ws://") || url.starts_with("wss://"),
+        "WebSocket URL must start with ws:// or wss://, got '{url}'"
+    );
+    WEBSOCKET_URL
+        .set(url)
+        .map_err(|_| anyhow::anyhow!("WebSocket URL is already set"))
+}
+
+pub(crate) fn websocket_url() -> Option<&'static str> {
+    WEBSOCKET_URL.get().map(String::as_str)
+}
+
+/// Subscribes to every named address, reads their balances once so that
+/// changes made before the subscriptions took effect are not missed, then
+/// exports every change pushed until the socket drops or a shutdown is
+/// requested. `subscribed` is set while changes are pushed.
+async fn subscribe_balances(
+    url: &str,
+    rpc_clients: &RpcClientFactory,
+    rpc_client: &RpcClient,
+    rate_limiter: &RateLimiter,
+    named_pubkeys: &HashMap<Pubkey, String>,
+    expectations: &HashMap<Pubkey, AccountExpectations>,
+    subscribed: &AtomicBool,
+) -> anyhow::Result<()> {
+    let pubsub_client = PubsubClient::new(url).await?;
+    let config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        data_slice: Some(AccountType::Lamports.data_slice()),
+        commitment: Some(rpc_client.commitment()),
+        ..Default::default()
+    };
+    let mut subscriptions = vec![];
+    for (pubkey, name) in named_pubkeys {
+        let (notifications, _unsubscribe) = pubsub_client
+            .account_subscribe(pubkey, Some(config.clone()))
+            .await?;
+        subscriptions.push(notifications.map(move |response| (pubkey, name, response)));
+    }
+
+    let pubkeys: Vec<_> = named_pubkeys.keys().copied().collect();
+    rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+    check_balances(
+        rpc_clients,
+        rpc_client,
+        &pubkeys,
+        named_pubkeys,
+        expectations,
+    )
+    .await?;
+    record_successful_check(WATCHER_NAME);
+    subscribed.store(true, Ordering::Relaxed);
+    update_metric_balance_subscription_active(WATCHER_NAME, true);
+    info!(
+        "Subscribed to {} named addresses, pushing balance changes",
+        pubkeys.len()
+    );
+
+    let mut notifications = select_all(subscriptions);
+    loop {
+        tokio::select! {
+            notification = notifications.next() => match notification {
+                Some((pubkey, name, response)) => {
+                    export_balance(
+                        name,
+                        pubkey,
+                        Some(response.value),
+                        response.context.slot,
+                        Duration::ZERO,
+                        expectations.get(pubkey),
+                    );
+                    record_successful_check(WATCHER_NAME);
+                }
+                None => anyhow::bail!("WebSocket closed"),
+            },
+            _ = shutdown_requested() => return Ok(()),
+        }
+    }
+}
+
+/// Runs `subscribe` again after a backoff whenever the subscription of
+/// `watcher` drops or fails, until a shutdown is requested. `subscribed` is
+/// cleared meanwhile, for the watcher to poll instead.
+async fn keep_subscribed<F, Fut>(watcher: &str, subscribed: &AtomicBool, mut subscribe: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    loop {
+        let result = subscribe().await;
+        let was_subscribed = subscribed.swap(false, Ordering::Relaxed);
+        update_metric_balance_subscription_active(watcher, false);
+        if is_shutdown_requested() {
+            break;
+        }
+        match (result, was_subscribed) {
+            (Err(err), true) => {
+                warn!("Subscription of '{watcher}' dropped, polling meanwhile: {err}")
+            }
+            (Err(err), false) => {
+                error!("Failed to subscribe for '{watcher}', polling meanwhile: {err}")
+            }
+            (Ok(()), _) => break,
+        }
+        if !sleep_unless_shutdown(DEFAULT_BACKOFF_DURATION).await {
+            break;
+        }
+    }
+}
+
+/// Keeps balance changes of `named_pubkeys` pushed over the WebSocket at
+/// `url`, resubscribing whenever the subscription drops.
+pub(crate) async fn push_balances(
+    url: &str,
+    rpc_clients: &RpcClientFactory,
+    rpc_client: &RpcClient,
+    rate_limiter: &RateLimiter,
+    named_pubkeys: &HashMap<Pubkey, String>,
+    expectations: &HashMap<Pubkey, AccountExpectations>,
+    subscribed: &AtomicBool,
+) {
+    if named_pubkeys.is_empty() {
+        return;
+    }
+    keep_subscribed(WATCHER_NAME, subscribed, || {
+        subscribe_balances(
+            url,
+            rpc_clients,
+            rpc_client,
+            rate_limiter,
+            named_pubkeys,
+            expectations,
+            subscribed,
+        )
+    })
+    .await
+}
+
+/// Scans the accounts matching `config`, returning the slot the scan
+/// started at along with the balance of every account.
+async fn scan_program_accounts(
+    rpc_client: &RpcClient,
+    rate_limiter: &RateLimiter,
+    config: &ProgramAccountsBalanceConfig,
+) -> anyhow::Result<(u64, HashMap<Pubkey, u64>)> {
+    let (weight, cost) = config.rate_limit();
+    rate_limiter.acquire(config.name(), weight, cost).await;
+    let slot = rpc_client.get_slot().await?;
+    let balances = get_program_accounts(rpc_client, config, None)
+        .await?
+        .into_iter()
+        .map(|(pubkey, account)| (pubkey, account.lamports))
+        .collect();
+    Ok((slot, balances))
+}
+
+/// Subscribes to the accounts matching `config`, scans them to know every
+/// balance, then keeps their total up to date with each change pushed until
+/// the socket drops or a shutdown is requested. Changes older than the scan
+/// are skipped, later ones carry the new balance of their account, so that
+/// changes already seen by the scan are harmless. As no change is pushed
+/// when an account is closed or stops matching the filters, the accounts are
+/// scanned again every `check_interval` to reconcile the total.
+async fn subscribe_program_accounts(
+    url: &str,
+    rpc_client: &RpcClient,
+    rate_limiter: &RateLimiter,
+    config: &ProgramAccountsBalanceConfig,
+    check_interval: Duration,
+    subscribed: &AtomicBool,
+) -> anyhow::Result<()> {
+    let watcher = config.name();
+    let pubsub_client = PubsubClient::new(url).await?;
+    let (mut notifications, _unsubscribe) = pubsub_client
+        .program_subscribe(
+            config.program(),
+            Some(config.rpc_config(RpcAccountInfoConfig {
+                data_slice: Some(AccountType::Lamports.data_slice()),
+                commitment: Some(rpc_client.commitment()),
+                ..Default::default()
+            })),
+        )
+        .await?;
+
+    let start = Instant::now();
+    let (mut scan_slot, mut balances) =
+        scan_program_accounts(rpc_client, rate_limiter, config).await?;
+    let mut total: u64 = balances.values().sum();
+    export_total_balance(config, total, Some(scan_slot), start.elapsed());
+    record_successful_check(watcher);
+    subscribed.store(true, Ordering::Relaxed);
+    update_metric_balance_subscription_active(watcher, true);
+    info!(
+        "Subscribed to {} accounts of '{watcher}', pushing changes of their total balance",
+        balances.len()
+    );
+
+    let mut reconcile =
+        tokio::time::interval_at(tokio::time::Instant::now() + check_interval, check_interval);
+    loop {
+        tokio::select! {
+            _ = reconcile.tick() => {
+                let start = Instant::now();
+                (scan_slot, balances) = scan_program_accounts(rpc_client, rate_limiter, config).await?;
+                total = balances.values().sum();
+                export_total_balance(config, total, Some(scan_slot), start.elapsed());
+                record_successful_check(watcher);
+            }
+            notification = notifications.next() => match notification {
+                Some(response) if response.context.slot < scan_slot => {}
+                Some(response) => {
+                    let pubkey: Pubkey = response.value.pubkey.parse()?;
+                    let lamports = response.value.account.lamports;
+                    let previous = match lamports {
+                        0 => balances.remove(&pubkey),
+                        _ => balances.insert(pubkey, lamports),
+                    };
+                    total = total - previous.unwrap_or(0) + lamports;
+                    export_total_balance(config, total, Some(response.context.slot), Duration::ZERO);
+                    record_successful_check(watcher);
+                }
+                None => anyhow::bail!("WebSocket closed"),
+            },
+            _ = shutdown_requested() => return Ok(()),
+        }
+    }
+}
+
+/// Keeps the total balance of the accounts matching `config` pushed over the
+/// WebSocket at `url`, reconciled every `check_interval`, resubscribing
+/// whenever the subscription drops.
+pub(crate) async fn push_program_accounts(
+    url: &str,
+    rpc_client: &RpcClient,
+    rate_limiter: &RateLimiter,
+    config: &ProgramAccountsBalanceConfig,
+    check_interval: Duration,
+    subscribed: &AtomicBool,
+) {
+    keep_subscribed(config.name(), subscribed, || {
+        subscribe_program_accounts(
+            url,
+            rpc_client,
+            rate_limiter,
+            config,
+            check_interval,
+            subscribed,
+        )
+    })
+    .await
+}
@@ -0,0 +1,177 @@
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::{error, info, warn};
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcAccountInfoConfig};
+use solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey};
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, BufReader, Lines},
+    task::JoinHandle,
+};
+
+use crate::{
+    data_slice::AccountType,
+    health::{record_failed_check, record_successful_check},
+    intervals::backoff_duration,
+    metrics::{remove_metric_balance_sol, update_metric_balance_sol, update_metric_watcher_info},
+    observations::{publish_observation, Observation},
+    rate_limit::RateLimiter,
+    shutdown::{is_shutdown_requested, sleep_unless_shutdown},
+};
+
+pub const WATCHER_NAME: &str = "address_file";
+/// Maximum number of accounts accepted by a single `getMultipleAccounts` call.
+const PAGE_SIZE: usize = 100;
+
+/// Reads the next page of `name=pubkey` entries, skipping blank lines, `#`
+/// comments and malformed entries. Returns an empty page at the end of the file.
+async fn read_page(lines: &mut Lines<BufReader<File>>) -> anyhow::Result<Vec<(String, Pubkey)>> {
+    let mut page = Vec::with_capacity(PAGE_SIZE);
+    while page.len() < PAGE_SIZE {
+        let Some(line) = lines.next_line().await? else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line
+            .split_once('=')
+            .map(|(name, pubkey)| (name, Pubkey::from_str(pubkey)))
+        {
+            Some((name, Ok(pubkey))) => page.push((name.to_string(), pubkey)),
+            _ => warn!("Skipping malformed address list entry '{line}'"),
+        }
+    }
+    Ok(page)
+}
+
+/// Removes the balances of the addresses currently listed in `path`, once it
+/// is no longer watched.
+pub async fn remove_address_file_metrics(path: &Path) -> anyhow::Result<()> {
+    let mut lines = BufReader::new(File::open(path).await?).lines();
+    loop {
+        let page = read_page(&mut lines).await?;
+        if page.is_empty() {
+            return Ok(());
+        }
+        for (name, pubkey) in page {
+            remove_metric_balance_sol(&name, &pubkey.to_string());
+        }
+    }
+}
+
+/// Watches balances of the addresses listed in `path`. The file is streamed
+/// page by page on every check, so memory use stays bounded by the page size
+/// regardless of how many addresses it lists, and edits are picked up on the
+/// next cycle.
+pub fn spawn_address_file_balance_watcher(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    path: PathBuf,
+    check_interval: Duration,
+) -> JoinHandle<()> {
+    update_metric_watcher_info(
+        &path.display().to_string(),
+        "address_file",
+        "",
+        check_interval,
+    );
+    tokio::spawn(async move {
+        let watcher = path.display().to_string();
+        info!("Watching addresses listed in {watcher}");
+        'watch: loop {
+            let mut lines = match File::open(&path).await {
+                Ok(file) => BufReader::new(file).lines(),
+                Err(err) => {
+                    error!("Failed to open {watcher}: {err}");
+                    record_failed_check(&watcher, &err.to_string());
+                    if !sleep_unless_shutdown(backoff_duration(&watcher)).await {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            let mut count = 0;
+            let mut complete = true;
+            loop {
+                if is_shutdown_requested() {
+                    break 'watch;
+                }
+                let page = match read_page(&mut lines).await {
+                    Ok(page) if page.is_empty() => break,
+                    Ok(page) => page,
+                    Err(err) => {
+                        error!("Failed to read {watcher}: {err}");
+                        record_failed_check(&watcher, &err.to_string());
+                        complete = false;
+                        break;
+                    }
+                };
+                let pubkeys: Vec<_> = page.iter().map(|(_, pubkey)| *pubkey).collect();
+
+                let (response, duration) = loop {
+                    rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+                    let start = Instant::now();
+                    let response = rpc_client
+                        .get_multiple_accounts_with_config(
+                            &pubkeys,
+                            RpcAccountInfoConfig {
+                                data_slice: Some(AccountType::Lamports.data_slice()),
+                                ..Default::default()
+                            },
+                        )
+                        .await;
+
+                    match response {
+                        Ok(response) => break (response, start.elapsed()),
+                        Err(err) => {
+                            error!("Failed to get RPC response: {err}");
+                            record_failed_check(&watcher, &err.to_string());
+                            for (name, pubkey) in page.iter() {
+                                remove_metric_balance_sol(name, &pubkey.to_string());
+                            }
+                            if !sleep_unless_shutdown(backoff_duration(&watcher)).await {
+                                break 'watch;
+                            }
+                        }
+                    }
+                };
+
+                for ((name, pubkey), account) in page.iter().zip(response.value) {
+                    if account.is_none() {
+                        error!("Account {pubkey} does not exist");
+                    }
+
+                    let lamports = account.map(|a| a.lamports).unwrap_or(0);
+                    update_metric_balance_sol(name, &pubkey.to_string(), lamports_to_sol(lamports));
+                    publish_observation(Observation {
+                        watcher: watcher.clone(),
+                        name: name.clone(),
+                        pubkey: Some(*pubkey),
+                        lamports,
+                        slot: Some(response.context.slot),
+                        duration,
+                        observed_at: SystemTime::now(),
+                    });
+                }
+                count += page.len();
+            }
+
+            info!("Updated balances of {count} accounts from {watcher}");
+            if complete {
+                record_successful_check(&watcher);
+            }
+            if !sleep_unless_shutdown(check_interval).await {
+                break;
+            }
+        }
+        info!("Stopped watching addresses listed in {watcher}");
+    })
+}
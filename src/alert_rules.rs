@@ -0,0 +1,420 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use log::{error, info, warn};
+use once_cell::sync::OnceCell;
+use solana_sdk::native_token::{lamports_to_sol, sol_to_lamports};
+use tokio::{sync::broadcast::error::RecvError, task::JoinHandle};
+
+use crate::{
+    metrics::update_metric_alert_firing,
+    observations::{latest_observations, subscribe_observations, Observation},
+    shutdown::shutdown_requested,
+    tenant::tenant_of,
+};
+
+/// Rules being evaluated, kept for dry runs.
+static ALERT_RULES: OnceCell<Vec<AlertRule>> = OnceCell::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Below,
+    AtMost,
+    Above,
+    AtLeast,
+}
+
+impl Comparison {
+    fn holds(&self, lamports: u64, threshold: u64) -> bool {
+        match self {
+            Comparison::Below => lamports < threshold,
+            Comparison::AtMost => lamports <= threshold,
+            Comparison::Above => lamports > threshold,
+            Comparison::AtLeast => lamports >= threshold,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Comparison::Below => "<",
+            Comparison::AtMost => "<=",
+            Comparison::Above => ">",
+            Comparison::AtLeast => ">=",
+        }
+    }
+}
+
+/// An observed balance, `[watcher/]name`. Without a watcher, the balance of
+/// `name` observed by any watcher is meant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceRef {
+    watcher: Option<String>,
+    name: String,
+}
+
+impl BalanceRef {
+    pub fn matches(&self, watcher: &str, name: &str) -> bool {
+        name == self.name
+            && self
+                .watcher
+                .as_ref()
+                .filter(|expected| *expected != watcher)
+                .is_none()
+    }
+}
+
+impl FromStr for BalanceRef {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (watcher, name) = match s.trim().split_once('/') {
+            Some((watcher, name)) => (Some(watcher.to_string()), name),
+            None => (None, s.trim()),
+        };
+        anyhow::ensure!(!name.is_empty(), "Missing balance name in '{s}'");
+        Ok(BalanceRef {
+            watcher,
+            name: name.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for BalanceRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(watcher) = &self.watcher {
+            write!(f, "{watcher}/")?;
+        }
+        write!(f, "{}", self.name)
+    }
+}
+
+/// A balance compared against a threshold, `[watcher/]name<SOL`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Condition {
+    balance: BalanceRef,
+    comparison: Comparison,
+    lamports: u64,
+}
+
+impl Condition {
+    fn matches(&self, watcher: &str, name: &str) -> bool {
+        self.balance.matches(watcher, name)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Two character operators first, as `<` is a prefix of `<=`.
+        let comparisons = [
+            Comparison::AtMost,
+            Comparison::AtLeast,
+            Comparison::Below,
+            Comparison::Above,
+        ];
+        let Some((balance, comparison, sol)) = comparisons.iter().find_map(|comparison| {
+            s.split_once(comparison.as_str())
+                .map(|(balance, sol)| (balance, *comparison, sol))
+        }) else {
+            anyhow::bail!("Cannot parse condition '{s}', expected syntax: [watcher/]name<SOL");
+        };
+        let sol: f64 = sol.trim().parse()?;
+        anyhow::ensure!(
+            (0.0..=lamports_to_sol(u64::MAX)).contains(&sol),
+            "Threshold of '{s}' must be between 0 and {} SOL",
+            lamports_to_sol(u64::MAX)
+        );
+        Ok(Condition {
+            balance: balance.parse()?,
+            comparison,
+            lamports: sol_to_lamports(sol),
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sol = lamports_to_sol(self.lamports);
+        write!(f, "{}{}{sol}", self.balance, self.comparison.as_str())
+    }
+}
+
+/// Alert firing while all conditions of any of its alternatives hold, parsed
+/// from `name=condition AND condition OR condition`, where `AND` binds tighter
+/// than `OR`. Conditions on balances not observed yet do not hold, so that
+/// rules only fire on what is actually known.
+#[derive(Debug, Clone)]
+pub struct AlertRule {
+    pub name: String,
+    alternatives: Vec<Vec<Condition>>,
+}
+
+impl FromStr for AlertRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, expression)) = s.split_once('=') else {
+            anyhow::bail!("Cannot parse alert rule, expected syntax: name=condition AND condition");
+        };
+        anyhow::ensure!(!name.is_empty(), "Alert rule name cannot be empty");
+        let alternatives = expression
+            .split(" OR ")
+            .map(|alternative| alternative.split(" AND ").map(str::parse).collect())
+            .collect::<anyhow::Result<_>>()?;
+        Ok(AlertRule {
+            name: name.to_string(),
+            alternatives,
+        })
+    }
+}
+
+impl AlertRule {
+    fn conditions(&self) -> impl Iterator<Item = &Condition> {
+        self.alternatives.iter().flatten()
+    }
+
+    /// Tenant owning every balance the rule reads, `None` if they belong to
+    /// different tenants or to none.
+    pub fn tenant(&self) -> Option<&'static str> {
+        let mut tenants = self.conditions().map(|condition| {
+            let balance = &condition.balance;
+            tenant_of(
+                balance.watcher.as_deref().unwrap_or_default(),
+                &balance.name,
+            )
+        });
+        let tenant = tenants.next()??;
+        tenants.all(|other| other == Some(tenant)).then_some(tenant)
+    }
+
+    pub(crate) fn is_firing(&self, balances: &HashMap<(String, String), u64>) -> bool {
+        let holds = |condition: &Condition| {
+            balances
+                .iter()
+                .filter(|((watcher, name), _)| condition.matches(watcher, name))
+                .any(|(_, lamports)| condition.comparison.holds(*lamports, condition.lamports))
+        };
+        self.alternatives
+            .iter()
+            .any(|conditions| conditions.iter().all(holds))
+    }
+}
+
+impl fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, conditions) in self.alternatives.iter().enumerate() {
+            if i > 0 {
+                write!(f, " OR ")?;
+            }
+            for (j, condition) in conditions.iter().enumerate() {
+                if j > 0 {
+                    write!(f, " AND ")?;
+                }
+                write!(f, "{condition}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn evaluate(
+    rules: &[AlertRule],
+    firing: &mut [bool],
+    balances: &mut HashMap<(String, String), u64>,
+    observation: Observation,
+) {
+    if !rules
+        .iter()
+        .flat_map(AlertRule::conditions)
+        .any(|condition| condition.matches(&observation.watcher, &observation.name))
+    {
+        return;
+    }
+    balances.insert(
+        (observation.watcher, observation.name),
+        observation.lamports,
+    );
+    for (rule, firing) in rules.iter().zip(firing) {
+        let is_firing = rule.is_firing(balances);
+        if is_firing && !*firing {
+            error!("Alert '{}' is firing: {rule}", rule.name);
+        } else if !is_firing && *firing {
+            info!("Alert '{}' resolved", rule.name);
+        }
+        *firing = is_firing;
+        update_metric_alert_firing(&rule.name, is_firing);
+    }
+}
+
+/// Thresholds the alert rules set on the balance `name` observed by
+/// `watcher`, each with whether `lamports` is past it.
+pub fn thresholds_of(watcher: &str, name: &str, lamports: u64) -> Vec<(String, bool)> {
+    let Some(rules) = ALERT_RULES.get() else {
+        return vec![];
+    };
+    let mut thresholds: Vec<_> = rules
+        .iter()
+        .flat_map(AlertRule::conditions)
+        .filter(|condition| condition.matches(watcher, name))
+        .map(|condition| {
+            let breached = condition.comparison.holds(lamports, condition.lamports);
+            (condition.to_string(), breached)
+        })
+        .collect();
+    thresholds.sort();
+    thresholds.dedup();
+    thresholds
+}
+
+/// How a rule evaluates on a hypothetical observation.
+#[derive(Debug, Clone)]
+pub struct DryRunResult {
+    pub rule: String,
+    pub expression: String,
+    /// Tenant owning every balance the rule reads, if any.
+    pub tenant: Option<String>,
+    /// Whether the rule would fire after the observation.
+    pub firing: bool,
+    /// Whether the rule fires on the balances observed so far.
+    pub firing_now: bool,
+}
+
+/// Evaluates the rules as if `observation` had just been made, on top of the
+/// latest observed balances, without affecting the alerts.
+pub fn dry_run_alert_rules(observation: &Observation) -> Vec<DryRunResult> {
+    let Some(rules) = ALERT_RULES.get() else {
+        return vec![];
+    };
+    let balances: HashMap<_, _> = latest_observations()
+        .into_iter()
+        .map(|balance| {
+            let latest = balance.latest;
+            ((latest.watcher, latest.name), latest.lamports)
+        })
+        .collect();
+    let mut hypothetical = balances.clone();
+    hypothetical.insert(
+        (observation.watcher.clone(), observation.name.clone()),
+        observation.lamports,
+    );
+    rules
+        .iter()
+        .map(|rule| DryRunResult {
+            rule: rule.name.clone(),
+            expression: rule.to_string(),
+            tenant: rule.tenant().map(str::to_string),
+            firing: rule.is_firing(&hypothetical),
+            firing_now: rule.is_firing(&balances),
+        })
+        .collect()
+}
+
+/// Evaluates `rules` against the latest observed balances whenever one of the
+/// balances they refer to changes, exporting `alert_firing` and logging when
+/// an alert starts or stops firing.
+pub fn spawn_alert_evaluator(rules: Vec<AlertRule>) -> JoinHandle<()> {
+    for rule in &rules {
+        info!("Alert rule '{}': {rule}", rule.name);
+        update_metric_alert_firing(&rule.name, false);
+    }
+    let _ = ALERT_RULES.set(rules.clone());
+    let mut observations = subscribe_observations();
+    tokio::spawn(async move {
+        let mut firing = vec![false; rules.len()];
+        let mut balances = HashMap::new();
+        loop {
+            tokio::select! {
+                observation = observations.recv() => match observation {
+                    Ok(observation) => evaluate(&rules, &mut firing, &mut balances, observation),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Alert evaluator fell behind, skipped {skipped} observations")
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = shutdown_requested() => break,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn balances(entries: &[(&str, &str, u64)]) -> HashMap<(String, String), u64> {
+        entries
+            .iter()
+            .map(|(watcher, name, lamports)| ((watcher.to_string(), name.to_string()), *lamports))
+            .collect()
+    }
+
+    #[test]
+    fn parses_operators_longest_first() {
+        let rule: AlertRule = "low=hot<=1.5".parse().unwrap();
+        assert_eq!(rule.name, "low");
+        assert_eq!(rule.to_string(), "hot<=1.5");
+        let rule: AlertRule = "high=cold>=2".parse().unwrap();
+        assert_eq!(rule.to_string(), "cold>=2");
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let rule: AlertRule = "drained=a<1 AND b<1 OR c<1".parse().unwrap();
+        let sol = sol_to_lamports(1.0);
+        let only_a = balances(&[("balance", "a", 0), ("balance", "b", sol)]);
+        assert!(!rule.is_firing(&only_a));
+        let a_and_b = balances(&[("balance", "a", 0), ("balance", "b", 0)]);
+        assert!(rule.is_firing(&a_and_b));
+        let only_c = balances(&[("balance", "c", 0)]);
+        assert!(rule.is_firing(&only_c));
+    }
+
+    #[test]
+    fn watcher_prefix_restricts_balance() {
+        let rule: AlertRule = "low=token/hot<1".parse().unwrap();
+        assert!(!rule.is_firing(&balances(&[("balance", "hot", 0)])));
+        assert!(rule.is_firing(&balances(&[("token", "hot", 0)])));
+    }
+
+    #[test]
+    fn unobserved_balances_do_not_fire() {
+        let rule: AlertRule = "low=hot<1".parse().unwrap();
+        assert!(!rule.is_firing(&HashMap::new()));
+    }
+
+    #[test]
+    fn zero_threshold() {
+        let below: AlertRule = "empty=hot<0".parse().unwrap();
+        let at_most: AlertRule = "empty=hot<=0".parse().unwrap();
+        let zero = balances(&[("balance", "hot", 0)]);
+        assert!(!below.is_firing(&zero));
+        assert!(at_most.is_firing(&zero));
+    }
+
+    #[test]
+    fn rejects_empty_parts() {
+        assert!("".parse::<AlertRule>().is_err());
+        assert!("=hot<1".parse::<AlertRule>().is_err());
+        assert!("low=".parse::<AlertRule>().is_err());
+        assert!("low=<1".parse::<AlertRule>().is_err());
+        assert!("low=token/<1".parse::<AlertRule>().is_err());
+        assert!("low=hot<".parse::<AlertRule>().is_err());
+        assert!("low=hot<1 AND ".parse::<AlertRule>().is_err());
+    }
+
+    #[test]
+    fn rejects_thresholds_out_of_range() {
+        assert!("low=hot<-1".parse::<AlertRule>().is_err());
+        assert!("low=hot<NaN".parse::<AlertRule>().is_err());
+        assert!("low=hot<inf".parse::<AlertRule>().is_err());
+        assert!("low=hot<1e20".parse::<AlertRule>().is_err());
+    }
+
+    #[test]
+    fn largest_threshold_does_not_overflow() {
+        let rule: AlertRule = format!("high=hot>={}", lamports_to_sol(u64::MAX))
+            .parse()
+            .unwrap();
+        assert!(rule.is_firing(&balances(&[("balance", "hot", u64::MAX)])));
+    }
+}
@@ -0,0 +1,149 @@
+use std::{str::FromStr, sync::Mutex};
+
+use once_cell::sync::{Lazy, OnceCell};
+use solana_sdk::native_token::lamports_to_sol;
+
+use crate::metrics::update_metric_price_usd;
+
+/// Decimal places of USD amounts unless configured otherwise.
+const DEFAULT_USD_DECIMALS: usize = 2;
+
+/// Unit amounts are shown in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AmountUnit {
+    #[default]
+    Sol,
+    Lamports,
+    /// SOL valued at the latest known price, in SOL while no price is known.
+    Usd,
+}
+
+impl AmountUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AmountUnit::Sol => "SOL",
+            AmountUnit::Lamports => "lamports",
+            AmountUnit::Usd => "USD",
+        }
+    }
+}
+
+impl FromStr for AmountUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "sol" => AmountUnit::Sol,
+            "lamports" => AmountUnit::Lamports,
+            "usd" => AmountUnit::Usd,
+            _ => anyhow::bail!("Unsupported amount unit '{s}', expected sol, lamports or usd"),
+        })
+    }
+}
+
+/// How amounts are written in notifications and reports meant for people.
+/// Metrics and JSON APIs keep exact values.
+#[derive(Debug, Clone)]
+pub struct AmountFormat {
+    pub unit: AmountUnit,
+    /// Fixed number of decimal places. SOL amounts are exact without it.
+    pub decimals: Option<usize>,
+    pub thousands_separator: Option<char>,
+    pub decimal_separator: char,
+}
+
+impl Default for AmountFormat {
+    fn default() -> Self {
+        AmountFormat {
+            unit: AmountUnit::Sol,
+            decimals: None,
+            thousands_separator: None,
+            decimal_separator: '.',
+        }
+    }
+}
+
+static AMOUNT_FORMAT: OnceCell<AmountFormat> = OnceCell::new();
+static SOL_PRICE_USD: Lazy<Mutex<Option<f64>>> = Lazy::new(Default::default);
+
+/// Sets how amounts are formatted. Can only be called once; until then,
+/// amounts are written in SOL with all their decimals.
+pub fn set_amount_format(format: AmountFormat) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        format.thousands_separator != Some(format.decimal_separator),
+        "Thousands and decimal separators must differ"
+    );
+    AMOUNT_FORMAT
+        .set(format)
+        .map_err(|_| anyhow::anyhow!("Amount format is already set"))
+}
+
+/// Sets the price of one SOL that USD amounts and valuations are computed
+/// with.
+pub fn set_sol_price_usd(price: f64) {
+    *SOL_PRICE_USD.lock().unwrap() = Some(price);
+    update_metric_price_usd("SOL", price);
+}
+
+/// Latest known price of one SOL in USD.
+pub fn sol_price_usd() -> Option<f64> {
+    *SOL_PRICE_USD.lock().unwrap()
+}
+
+fn amount_format() -> &'static AmountFormat {
+    static DEFAULT: Lazy<AmountFormat> = Lazy::new(AmountFormat::default);
+    AMOUNT_FORMAT.get().unwrap_or(&DEFAULT)
+}
+
+/// Unit that [`format_lamports`] currently writes amounts in.
+pub fn amount_unit() -> AmountUnit {
+    match amount_format().unit {
+        AmountUnit::Usd if sol_price_usd().is_none() => AmountUnit::Sol,
+        unit => unit,
+    }
+}
+
+/// Writes an amount of lamports in the configured format, including the unit,
+/// e.g. `1,234.50 USD`.
+pub fn format_lamports(lamports: u64) -> String {
+    format!("{} {}", format_number(lamports), amount_unit().as_str())
+}
+
+/// Like [`format_lamports`], but with a sign, for balance changes.
+pub fn format_lamports_delta(delta: i128) -> String {
+    let sign = if delta < 0 { '-' } else { '+' };
+    format!("{sign}{}", format_lamports(delta.unsigned_abs() as u64))
+}
+
+/// Writes an amount of lamports in the configured format, without the unit.
+pub fn format_number(lamports: u64) -> String {
+    let format = amount_format();
+    let number = match (amount_unit(), format.decimals) {
+        (AmountUnit::Lamports, _) => lamports.to_string(),
+        (AmountUnit::Sol, None) => lamports_to_sol(lamports).to_string(),
+        (AmountUnit::Sol, Some(decimals)) => format!("{:.decimals$}", lamports_to_sol(lamports)),
+        (AmountUnit::Usd, decimals) => {
+            let price = sol_price_usd().unwrap_or_default();
+            let decimals = decimals.unwrap_or(DEFAULT_USD_DECIMALS);
+            format!("{:.decimals$}", lamports_to_sol(lamports) * price)
+        }
+    };
+    let (integer, fraction) = match number.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (number.as_str(), None),
+    };
+    let mut formatted = String::with_capacity(number.len() + integer.len() / 3);
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            if let Some(separator) = format.thousands_separator {
+                formatted.push(separator);
+            }
+        }
+        formatted.push(digit);
+    }
+    if let Some(fraction) = fraction {
+        formatted.push(format.decimal_separator);
+        formatted.push_str(fraction);
+    }
+    formatted
+}
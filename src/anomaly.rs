@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use log::warn;
+use solana_sdk::native_token::{lamports_to_sol, sol_to_lamports};
+use tokio::{sync::broadcast::error::RecvError, task::JoinHandle};
+
+use crate::{
+    amount_format::format_lamports,
+    metrics::update_metric_anomaly_score,
+    observations::{subscribe_observations, Observation},
+    shutdown::shutdown_requested,
+};
+
+/// Smallest standard deviation used for scoring, so that the first movement
+/// of a constant balance gets a large but finite score.
+const MIN_STD_DEV_SOL: f64 = 1e-9;
+
+#[derive(Debug, Clone)]
+pub struct AnomalyConfig {
+    /// Weight of the latest observation in the moving average, in (0, 1].
+    pub alpha: f64,
+    /// Score at and above which a movement is reported as anomalous.
+    pub threshold: f64,
+    /// Observations of a balance before it is scored.
+    pub warmup: u32,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            alpha: 0.1,
+            threshold: 4.0,
+            warmup: 10,
+        }
+    }
+}
+
+/// Exponentially weighted moving average and variance of a balance.
+#[derive(Debug, Default)]
+struct Ewma {
+    mean: f64,
+    variance: f64,
+    count: u32,
+}
+
+impl Ewma {
+    /// Scores `value` against the current bands, then folds it in.
+    fn update(&mut self, value: f64, alpha: f64) -> f64 {
+        if self.count == 0 {
+            self.mean = value;
+            self.count = 1;
+            return 0.0;
+        }
+        let diff = value - self.mean;
+        let score = diff.abs() / self.variance.sqrt().max(MIN_STD_DEV_SOL);
+        self.mean += alpha * diff;
+        self.variance = (1.0 - alpha) * (self.variance + alpha * diff * diff);
+        self.count = self.count.saturating_add(1);
+        score
+    }
+}
+
+fn score(
+    config: &AnomalyConfig,
+    balances: &mut HashMap<(String, String), Ewma>,
+    observation: Observation,
+) {
+    let balance = lamports_to_sol(observation.lamports);
+    let ewma = balances
+        .entry((observation.watcher.clone(), observation.name.clone()))
+        .or_default();
+    let score = ewma.update(balance, config.alpha);
+    if ewma.count <= config.warmup {
+        return;
+    }
+    update_metric_anomaly_score(&observation.watcher, &observation.name, score);
+    if score >= config.threshold {
+        warn!(
+            "Anomalous balance of '{}' in {}: {} deviates {score:.1} standard deviations from its moving average {}",
+            observation.name,
+            observation.watcher,
+            format_lamports(observation.lamports),
+            format_lamports(sol_to_lamports(ewma.mean))
+        );
+    }
+}
+
+/// Scores every observed balance against exponentially weighted moving
+/// average bands and exports the score as `anomaly_score`, catching unusual
+/// movements that fixed thresholds miss.
+pub fn spawn_anomaly_detector(config: AnomalyConfig) -> anyhow::Result<JoinHandle<()>> {
+    anyhow::ensure!(
+        config.alpha > 0.0 && config.alpha <= 1.0,
+        "Anomaly smoothing factor must be in (0, 1], got {}",
+        config.alpha
+    );
+    let mut observations = subscribe_observations();
+    Ok(tokio::spawn(async move {
+        let mut balances = HashMap::new();
+        loop {
+            tokio::select! {
+                observation = observations.recv() => match observation {
+                    Ok(observation) => score(&config, &mut balances, observation),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Anomaly detector fell behind, skipped {skipped} observations")
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = shutdown_requested() => break,
+            }
+        }
+    }))
+}
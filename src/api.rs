@@ -0,0 +1,525 @@
+use std::time::{Duration, SystemTime};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::info;
+use serde_json::{json, Value};
+use solana_sdk::{
+    native_token::{lamports_to_sol, sol_to_lamports},
+    pubkey::Pubkey,
+};
+
+use crate::{
+    alert_rules::dry_run_alert_rules,
+    audit::{record_audit_event, AuditAction},
+    auth::{require_role, ApiKeys, Caller, Role},
+    balance::{self, WatchedAddress},
+    config::{NamedAddressConfig, ProgramAccountsConfig},
+    explorer::account_url,
+    health::watcher_health,
+    observations::{latest_observations, Observation},
+    program_accounts_balance::ProgramAccountsBalanceConfig,
+    reload::{CheckResult, SharedWatchers},
+    rpc::RpcClientFactory,
+    selftest::run_selftest,
+    sink::sinks_publishing,
+    tenant::{is_visible_to, tenant_of},
+};
+
+fn timestamp(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Read-only view of watcher health and latest balances. Requires a
+/// read-only or admin API key when any API keys are configured, keys
+/// restricted to a tenant only see that tenant's watchers and balances.
+async fn status(caller: Option<Extension<Caller>>) -> Json<Value> {
+    let tenant = caller.and_then(|Extension(caller)| caller.tenant);
+    let tenant = tenant.as_deref();
+    let watchers: Vec<_> = watcher_health()
+        .into_iter()
+        .filter(|(watcher, _)| is_visible_to(tenant, watcher, watcher))
+        .map(|(watcher, health)| {
+            json!({
+                "watcher": watcher,
+                "last_success": health.last_success.map(timestamp),
+                "last_failure": health.last_failure.map(|(time, error)| json!({
+                    "timestamp": timestamp(time),
+                    "error": error,
+                })),
+            })
+        })
+        .collect();
+
+    let balances: Vec<_> = latest_observations()
+        .into_iter()
+        .filter(|balance| is_visible_to(tenant, &balance.latest.watcher, &balance.latest.name))
+        .map(|balance| {
+            let observation = &balance.latest;
+            json!({
+                "watcher": observation.watcher,
+                "name": observation.name,
+                "pubkey": observation.pubkey.map(|pubkey| pubkey.to_string()),
+                "explorer_url": observation.pubkey.as_ref().and_then(account_url),
+                "lamports": observation.lamports,
+                "sol": lamports_to_sol(observation.lamports),
+                "delta_lamports": balance.delta_lamports().map(|delta| delta as i64),
+                "slot": observation.slot,
+                "observed_at": timestamp(observation.observed_at),
+            })
+        })
+        .collect();
+
+    Json(json!({ "watchers": watchers, "balances": balances }))
+}
+
+/// Runs the self-test, failing with 500 if any component fails so that
+/// scripts can rely on the status code. Keys restricted to a tenant only get
+/// the outcome of each component, not the details about the deployment
+/// shared with other tenants.
+async fn selftest(caller: Option<Extension<Caller>>) -> (StatusCode, Json<Value>) {
+    let restricted = caller.is_some_and(|Extension(caller)| caller.tenant.is_some());
+    let results = run_selftest().await;
+    let ok = results.iter().all(|result| result.ok);
+    let components: Vec<_> = results
+        .iter()
+        .map(|result| {
+            json!({
+                "component": result.component,
+                "ok": result.ok,
+                "detail": (!restricted).then_some(&result.detail),
+            })
+        })
+        .collect();
+    let status = match ok {
+        true => StatusCode::OK,
+        false => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, Json(json!({ "ok": ok, "components": components })))
+}
+
+/// Evaluates the alert rules on a hypothetical balance, `{"name": ...,
+/// "balance": SOL}` optionally with a `watcher`, and lists the rules that
+/// would fire and the notifiers that would be called, without sending
+/// anything. Without a watcher, the one that last observed `name` is assumed.
+/// The rules and notifiers are listed under the tenant owning the balance,
+/// and keys restricted to a tenant can only test that tenant's balances
+/// against that tenant's rules.
+async fn test_alerts(
+    caller: Option<Extension<Caller>>,
+    Json(body): Json<Value>,
+) -> Result<Json<Value>, ApiError> {
+    let tenant = caller.and_then(|Extension(caller)| caller.tenant);
+    let tenant = tenant.as_deref();
+    let Some(name) = body["name"].as_str() else {
+        return Err((StatusCode::BAD_REQUEST, "Missing 'name'".to_string()));
+    };
+    let Some(sol) = body["balance"].as_f64().filter(|sol| *sol >= 0.0) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Missing or negative 'balance'".to_string(),
+        ));
+    };
+    let latest = latest_observations()
+        .into_iter()
+        .map(|balance| balance.latest)
+        .find(|latest| latest.name == name && is_visible_to(tenant, &latest.watcher, name));
+    let watcher = match (body["watcher"].as_str(), &latest) {
+        (Some(watcher), _) => watcher.to_string(),
+        (None, Some(latest)) => latest.watcher.clone(),
+        (None, None) => balance::WATCHER_NAME.to_string(),
+    };
+    if !is_visible_to(tenant, &watcher, name) {
+        return Err(outside_tenant(tenant, name));
+    }
+    let observation = Observation {
+        watcher,
+        name: name.to_string(),
+        pubkey: latest.and_then(|latest| latest.pubkey),
+        lamports: sol_to_lamports(sol),
+        slot: None,
+        duration: Duration::ZERO,
+        observed_at: SystemTime::now(),
+    };
+
+    let rules: Vec<_> = dry_run_alert_rules(&observation)
+        .into_iter()
+        .filter(|result| tenant.is_none() || result.tenant.as_deref() == tenant)
+        .map(|result| {
+            json!({
+                "rule": result.rule,
+                "expression": result.expression,
+                "tenant": result.tenant,
+                "firing": result.firing,
+                "firing_now": result.firing_now,
+            })
+        })
+        .collect();
+    Ok(Json(json!({
+        "observation": observation_json(&observation),
+        "tenant": tenant_of(&observation.watcher, &observation.name),
+        "rules": rules,
+        "notifiers": sinks_publishing(&observation),
+    })))
+}
+
+pub fn status_router(keys: &ApiKeys) -> Router {
+    let router = Router::new()
+        .route("/status", get(status))
+        .route("/selftest", get(selftest))
+        .route("/alerts/test", post(test_alerts));
+    match keys.is_empty() {
+        true => router,
+        false => require_role(router, keys, Role::ReadOnly),
+    }
+}
+
+type ApiError = (StatusCode, String);
+
+fn bad_request(err: anyhow::Error) -> ApiError {
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+fn outside_tenant(tenant: Option<&str>, name: &str) -> ApiError {
+    (
+        StatusCode::FORBIDDEN,
+        format!(
+            "'{name}' is not assigned to tenant '{}'",
+            tenant.unwrap_or_default()
+        ),
+    )
+}
+
+/// Fails with 403 unless `caller` may see and change the balance `name` of
+/// `watcher`.
+fn ensure_visible(caller: &Caller, watcher: &str, name: &str) -> Result<(), ApiError> {
+    let tenant = caller.tenant.as_deref();
+    match is_visible_to(tenant, watcher, name) {
+        true => Ok(()),
+        false => Err(outside_tenant(tenant, name)),
+    }
+}
+
+/// Fails without disclosing the name the address is watched as.
+fn watched_for_another_tenant(pubkey: &Pubkey) -> ApiError {
+    (
+        StatusCode::FORBIDDEN,
+        format!("{pubkey} is watched for another tenant"),
+    )
+}
+
+/// Fails with 403 if `caller` is restricted to a tenant, for changes
+/// affecting every tenant.
+fn ensure_unrestricted(caller: &Caller) -> Result<(), ApiError> {
+    match &caller.tenant {
+        None => Ok(()),
+        Some(tenant) => Err((
+            StatusCode::FORBIDDEN,
+            format!("Keys restricted to tenant '{tenant}' cannot change RPC endpoints"),
+        )),
+    }
+}
+
+fn url_of(body: &Value) -> Result<String, ApiError> {
+    match body["url"].as_str() {
+        Some(url) => Ok(url.to_string()),
+        None => Err((StatusCode::BAD_REQUEST, "Missing 'url'".to_string())),
+    }
+}
+
+async fn list_endpoints(State(rpc_clients): State<RpcClientFactory>) -> Json<Value> {
+    Json(json!({ "endpoints": rpc_clients.endpoint_labels() }))
+}
+
+async fn add_endpoint(
+    State(rpc_clients): State<RpcClientFactory>,
+    Extension(caller): Extension<Caller>,
+    Json(body): Json<Value>,
+) -> Result<Json<Value>, ApiError> {
+    ensure_unrestricted(&caller)?;
+    let label = rpc_clients
+        .add_endpoint(url_of(&body)?)
+        .await
+        .map_err(bad_request)?;
+    info!("RPC endpoint {label} added by {}", caller.name);
+    record_audit_event(&caller.name, AuditAction::EndpointAdded, &label, json!({}));
+    Ok(Json(json!({ "endpoint": label })))
+}
+
+async fn replace_endpoint(
+    State(rpc_clients): State<RpcClientFactory>,
+    Extension(caller): Extension<Caller>,
+    Path(label): Path<String>,
+    Json(body): Json<Value>,
+) -> Result<Json<Value>, ApiError> {
+    ensure_unrestricted(&caller)?;
+    let new_label = rpc_clients
+        .replace_endpoint(&label, url_of(&body)?)
+        .await
+        .map_err(bad_request)?;
+    info!(
+        "RPC endpoint {label} replaced by {new_label} by {}",
+        caller.name
+    );
+    record_audit_event(
+        &caller.name,
+        AuditAction::EndpointReplaced,
+        &label,
+        json!({ "replaced_by": new_label }),
+    );
+    Ok(Json(json!({ "endpoint": new_label })))
+}
+
+async fn remove_endpoint(
+    State(rpc_clients): State<RpcClientFactory>,
+    Extension(caller): Extension<Caller>,
+    Path(label): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    ensure_unrestricted(&caller)?;
+    rpc_clients.remove_endpoint(&label).map_err(bad_request)?;
+    info!("RPC endpoint {label} removed by {}", caller.name);
+    record_audit_event(
+        &caller.name,
+        AuditAction::EndpointRemoved,
+        &label,
+        json!({}),
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Lists, adds, replaces and removes RPC endpoints at runtime. Requests
+/// already sent to a removed endpoint are left to complete. Requires an admin
+/// API key, so nothing is served unless API keys are configured, and changes
+/// require one not restricted to a tenant.
+pub fn endpoints_router(keys: &ApiKeys, rpc_clients: RpcClientFactory) -> Router {
+    if keys.is_empty() {
+        return Router::new();
+    }
+    let router = Router::new()
+        .route("/endpoints", get(list_endpoints).post(add_endpoint))
+        .route(
+            "/endpoints/:label",
+            delete(remove_endpoint).put(replace_endpoint),
+        )
+        .with_state(rpc_clients);
+    require_role(router, keys, Role::Admin)
+}
+
+async fn list_addresses(
+    State(watchers): State<SharedWatchers>,
+    Extension(caller): Extension<Caller>,
+) -> Json<Value> {
+    let tenant = caller.tenant.as_deref();
+    let watch_list = watchers.lock().await.watch_list();
+    let mut addresses: Vec<_> = watch_list
+        .named_pubkeys
+        .iter()
+        .filter(|(_, name)| is_visible_to(tenant, balance::WATCHER_NAME, name))
+        .map(|(pubkey, name)| {
+            let expectations = watch_list.expectations.get(pubkey);
+            json!({
+                "name": name,
+                "pubkey": pubkey.to_string(),
+                "owner": expectations.and_then(|e| e.owner).map(|owner| owner.to_string()),
+                "size": expectations.and_then(|e| e.size),
+                "interval": watch_list.check_intervals.get(pubkey).map(Duration::as_secs),
+            })
+        })
+        .collect();
+    addresses.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+    let program_accounts: Vec<_> = watch_list
+        .program_accounts_configs
+        .iter()
+        .filter(|(_, config)| is_visible_to(tenant, config.name(), config.name()))
+        .map(|(arg, config)| json!({ "name": config.name(), "config": arg }))
+        .collect();
+    Json(json!({ "addresses": addresses, "program_accounts": program_accounts }))
+}
+
+async fn add_address(
+    State(watchers): State<SharedWatchers>,
+    Extension(caller): Extension<Caller>,
+    Json(body): Json<Value>,
+) -> Result<StatusCode, ApiError> {
+    let address: NamedAddressConfig =
+        serde_json::from_value(body).map_err(|err| bad_request(err.into()))?;
+    let address = WatchedAddress::try_from(&address).map_err(bad_request)?;
+    ensure_visible(&caller, balance::WATCHER_NAME, &address.name)?;
+    let pubkey = address.pubkey;
+    let mut watchers = watchers.lock().await;
+    let mut watch_list = watchers.watch_list();
+    if let Some(existing) = watch_list.named_pubkeys.get(&pubkey) {
+        if !is_visible_to(caller.tenant.as_deref(), balance::WATCHER_NAME, existing) {
+            return Err(watched_for_another_tenant(&pubkey));
+        }
+        return Err((
+            StatusCode::CONFLICT,
+            format!("{pubkey} is already watched as '{existing}'"),
+        ));
+    }
+    watch_list.named_pubkeys.insert(pubkey, address.name);
+    if !address.expectations.is_empty() {
+        watch_list.expectations.insert(pubkey, address.expectations);
+    }
+    if let Some(check_interval) = address.check_interval {
+        watch_list.check_intervals.insert(pubkey, check_interval);
+    }
+    watchers.apply(&caller.name, watch_list).await;
+    Ok(StatusCode::CREATED)
+}
+
+async fn remove_address(
+    State(watchers): State<SharedWatchers>,
+    Extension(caller): Extension<Caller>,
+    Path(pubkey): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let pubkey: Pubkey = pubkey
+        .parse()
+        .map_err(|err: solana_sdk::pubkey::ParsePubkeyError| bad_request(err.into()))?;
+    let mut watchers = watchers.lock().await;
+    let mut watch_list = watchers.watch_list();
+    let Some(name) = watch_list.named_pubkeys.remove(&pubkey) else {
+        return Err((StatusCode::NOT_FOUND, format!("{pubkey} is not watched")));
+    };
+    if !is_visible_to(caller.tenant.as_deref(), balance::WATCHER_NAME, &name) {
+        return Err(watched_for_another_tenant(&pubkey));
+    }
+    watch_list.expectations.remove(&pubkey);
+    watch_list.check_intervals.remove(&pubkey);
+    watchers.apply(&caller.name, watch_list).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn add_program_accounts(
+    State(watchers): State<SharedWatchers>,
+    Extension(caller): Extension<Caller>,
+    Json(body): Json<Value>,
+) -> Result<StatusCode, ApiError> {
+    let scan: ProgramAccountsConfig =
+        serde_json::from_value(body).map_err(|err| bad_request(err.into()))?;
+    let config = ProgramAccountsBalanceConfig::try_from(&scan).map_err(bad_request)?;
+    ensure_visible(&caller, config.name(), config.name())?;
+    let arg = scan.to_arg();
+    let mut watchers = watchers.lock().await;
+    let mut watch_list = watchers.watch_list();
+    if watch_list
+        .program_accounts_configs
+        .iter()
+        .any(|(_, existing)| existing.name() == config.name())
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Program-accounts scan '{}' already exists", config.name()),
+        ));
+    }
+    watch_list.program_accounts_configs.push((arg, config));
+    watchers.apply(&caller.name, watch_list).await;
+    Ok(StatusCode::CREATED)
+}
+
+async fn remove_program_accounts(
+    State(watchers): State<SharedWatchers>,
+    Extension(caller): Extension<Caller>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    ensure_visible(&caller, &name, &name)?;
+    let mut watchers = watchers.lock().await;
+    let mut watch_list = watchers.watch_list();
+    let count = watch_list.program_accounts_configs.len();
+    watch_list
+        .program_accounts_configs
+        .retain(|(_, config)| config.name() != name);
+    if watch_list.program_accounts_configs.len() == count {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("No program-accounts scan '{name}'"),
+        ));
+    }
+    watchers.apply(&caller.name, watch_list).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn observation_json(observation: &Observation) -> Value {
+    json!({
+        "watcher": observation.watcher,
+        "name": observation.name,
+        "pubkey": observation.pubkey.map(|pubkey| pubkey.to_string()),
+        "lamports": observation.lamports,
+        "sol": lamports_to_sol(observation.lamports),
+        "slot": observation.slot,
+        "observed_at": timestamp(observation.observed_at),
+        "duration_ms": observation.duration.as_millis() as u64,
+    })
+}
+
+async fn check_now(
+    State(watchers): State<SharedWatchers>,
+    Extension(caller): Extension<Caller>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    // The lock is released before checking, scans can take long.
+    let check = watchers.lock().await.check_now(&name);
+    let Some(check) = check else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("Nothing watched as '{name}'"),
+        ));
+    };
+    ensure_visible(&caller, check.watcher(), &name)?;
+    record_audit_event(&caller.name, AuditAction::CheckRequested, &name, json!({}));
+    let result = check
+        .run()
+        .await
+        .map_err(|err| (StatusCode::BAD_GATEWAY, err.to_string()))?;
+    Ok(Json(match result {
+        CheckResult::Balances(checks) => {
+            let accounts: Vec<_> = checks
+                .iter()
+                .map(|check| {
+                    let mut account = observation_json(&check.observation);
+                    account["exists"] = json!(check.exists);
+                    account["failed_assertions"] = json!(check.failed_assertions);
+                    account
+                })
+                .collect();
+            json!({ "name": name, "accounts": accounts })
+        }
+        CheckResult::ProgramAccounts {
+            observation,
+            accounts,
+        } => {
+            let mut result = observation_json(&observation);
+            result["accounts"] = json!(accounts);
+            result
+        }
+    }))
+}
+
+/// Adds and removes named addresses and program-accounts scans at runtime,
+/// with the same JSON fields as in the config file. Changes last until the
+/// config file is reloaded or the watcher restarts. `POST /check/{name}`
+/// checks the named addresses or program-accounts scan called `name` right
+/// away and returns the fresh balances. Requires an admin API key, so
+/// nothing is served unless API keys are configured. Keys restricted to a
+/// tenant only see and change that tenant's addresses and scans.
+pub fn admin_router(keys: &ApiKeys, watchers: SharedWatchers) -> Router {
+    if keys.is_empty() {
+        return Router::new();
+    }
+    let router = Router::new()
+        .route("/admin/addresses", get(list_addresses).post(add_address))
+        .route("/admin/addresses/:pubkey", delete(remove_address))
+        .route("/admin/program-accounts", post(add_program_accounts))
+        .route(
+            "/admin/program-accounts/:name",
+            delete(remove_program_accounts),
+        )
+        .route("/check/:name", post(check_now))
+        .with_state(watchers);
+    require_role(router, keys, Role::Admin)
+}
@@ -0,0 +1,117 @@
+use std::{collections::HashMap, str::FromStr, time::Duration};
+
+use solana_sdk::{native_token::sol_to_lamports, pubkey::Pubkey};
+
+use crate::{
+    amount_format::format_lamports,
+    check::{report, with_timeout, MAX_ACCOUNTS_PER_REQUEST},
+    historical::{get_balances_as_of, get_program_accounts_as_of, AsOf},
+    reload::WatchListArgs,
+    rpc::RpcClientFactory,
+};
+
+const WATCHER_NAME: &str = "assert";
+
+/// Smallest acceptable balance of a watched address or program-accounts
+/// total, parsed from `name=SOL`.
+#[derive(Debug, Clone)]
+pub struct MinBalance {
+    pub name: String,
+    pub min_lamports: u64,
+}
+
+impl FromStr for MinBalance {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, sol)) = s.split_once('=') else {
+            anyhow::bail!("Cannot parse minimum balance '{s}', expected syntax: name=SOL");
+        };
+        let sol: f64 = sol.parse()?;
+        anyhow::ensure!(sol >= 0.0, "Minimum balance of '{name}' cannot be negative");
+        Ok(MinBalance {
+            name: name.to_string(),
+            min_lamports: sol_to_lamports(sol),
+        })
+    }
+}
+
+/// Checks every balance in `min_balances` once against the live RPC
+/// endpoints. Balances are referred to by the name or pubkey of a
+/// `--named-address`, or by the name of a `--program-accounts` config for its
+/// total, and read as of `as_of` when given. Prints one report line per assertion and returns the number of
+/// violated ones, counting balances that could not be fetched as violated.
+pub async fn run_assertions(
+    rpc_clients: &RpcClientFactory,
+    watch_list: &WatchListArgs,
+    min_balances: &[MinBalance],
+    as_of: Option<AsOf>,
+    timeout: Duration,
+) -> anyhow::Result<usize> {
+    let mut addresses = HashMap::new();
+    for (_, address) in watch_list.watched_addresses() {
+        let address = address?;
+        addresses.insert(address.pubkey.to_string(), address.pubkey);
+        addresses.insert(address.name, address.pubkey);
+    }
+    let mut configs = HashMap::new();
+    for (_, config) in watch_list.program_accounts() {
+        let config = config?;
+        configs.insert(config.name().to_string(), config);
+    }
+
+    let rpc_client = rpc_clients.for_watcher(WATCHER_NAME);
+
+    let pubkeys: Vec<Pubkey> = min_balances
+        .iter()
+        .filter_map(|min_balance| addresses.get(&min_balance.name).copied())
+        .collect();
+    let mut lamports: HashMap<Pubkey, Result<u64, String>> = HashMap::new();
+    for chunk in pubkeys.chunks(MAX_ACCOUNTS_PER_REQUEST) {
+        let response = with_timeout(timeout, get_balances_as_of(&rpc_client, chunk, as_of)).await;
+        for (index, pubkey) in chunk.iter().enumerate() {
+            let balance = match &response {
+                Ok(balances) => match balances[index] {
+                    Some(lamports) => Ok(lamports),
+                    None => Err("account does not exist".to_string()),
+                },
+                Err(err) => Err(err.clone()),
+            };
+            lamports.insert(*pubkey, balance);
+        }
+    }
+
+    let mut violations = 0;
+    for min_balance in min_balances {
+        let name = min_balance.name.as_str();
+        let balance = if let Some(pubkey) = addresses.get(name) {
+            lamports[pubkey].clone()
+        } else if let Some(config) = configs.get(name) {
+            with_timeout(
+                timeout,
+                get_program_accounts_as_of(&rpc_client, config, as_of),
+            )
+            .await
+            .map(|accounts| accounts.iter().map(|(_, account)| account.lamports).sum())
+        } else {
+            Err("no watched address or program-accounts config with this name".to_string())
+        };
+
+        let item = format!("balance {name}");
+        let minimum = format_lamports(min_balance.min_lamports);
+        violations += match balance {
+            Ok(lamports) if lamports >= min_balance.min_lamports => report(
+                true,
+                &item,
+                format!("{} >= {minimum}", format_lamports(lamports)),
+            ),
+            Ok(lamports) => report(
+                false,
+                &item,
+                format!("{} < {minimum}", format_lamports(lamports)),
+            ),
+            Err(err) => report(false, &item, err),
+        };
+    }
+    Ok(violations)
+}
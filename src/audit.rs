@@ -0,0 +1,74 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::Mutex,
+};
+
+use chrono::{SecondsFormat, Utc};
+use log::error;
+use once_cell::sync::OnceCell;
+use serde_json::json;
+
+static AUDIT_LOG: OnceCell<Mutex<File>> = OnceCell::new();
+
+/// Kinds of changes to what is being watched and to the RPC endpoints, and
+/// checks requested by admins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    WatcherAdded,
+    WatcherRemoved,
+    WatcherChanged,
+    CheckRequested,
+    EndpointAdded,
+    EndpointRemoved,
+    EndpointReplaced,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::WatcherAdded => "watcher_added",
+            AuditAction::WatcherRemoved => "watcher_removed",
+            AuditAction::WatcherChanged => "watcher_changed",
+            AuditAction::CheckRequested => "check_requested",
+            AuditAction::EndpointAdded => "endpoint_added",
+            AuditAction::EndpointRemoved => "endpoint_removed",
+            AuditAction::EndpointReplaced => "endpoint_replaced",
+        }
+    }
+}
+
+/// Starts recording audit events to `path`. The file is only ever appended to.
+pub fn open_audit_log(path: &Path) -> anyhow::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    if AUDIT_LOG.set(Mutex::new(file)).is_err() {
+        anyhow::bail!("Audit log is already open");
+    }
+    Ok(())
+}
+
+/// Appends a JSON line describing a configuration change made by `source`
+/// (e.g. `startup`, `reload` or the identity of an admin API caller). Does
+/// nothing unless an audit log was opened.
+pub fn record_audit_event(
+    source: &str,
+    action: AuditAction,
+    watcher: &str,
+    details: serde_json::Value,
+) {
+    let Some(audit_log) = AUDIT_LOG.get() else {
+        return;
+    };
+    let line = json!({
+        "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        "source": source,
+        "action": action.as_str(),
+        "watcher": watcher,
+        "details": details,
+    });
+    let mut file = audit_log.lock().unwrap();
+    if let Err(err) = writeln!(file, "{line}").and_then(|_| file.sync_data()) {
+        error!("Failed to write audit event: {err}");
+    }
+}
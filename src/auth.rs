@@ -0,0 +1,167 @@
+use std::{fmt, str::FromStr, sync::Arc};
+
+use axum::{
+    extract::State,
+    http::{header::AUTHORIZATION, HeaderMap, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+
+use crate::secrets::resolve_secret;
+
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Permissions granted to an API key. Admins can do everything readers can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    ReadOnly,
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "read-only" | "readonly" => Role::ReadOnly,
+            "admin" => Role::Admin,
+            _ => anyhow::bail!("Unsupported role '{s}', expected read-only or admin"),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct ApiKey {
+    pub name: String,
+    /// Tenant the key is restricted to, `None` for keys that see everything.
+    pub tenant: Option<String>,
+    pub role: Role,
+    secret: String,
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiKey")
+            .field("name", &self.name)
+            .field("tenant", &self.tenant)
+            .field("role", &self.role)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FromStr for ApiKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, rest)) = s.split_once('=') else {
+            anyhow::bail!("Cannot parse API key, expected syntax: name[@tenant]=role:secret");
+        };
+        let Some((role, secret)) = rest.split_once(':') else {
+            anyhow::bail!(
+                "Cannot parse API key '{name}', expected syntax: name[@tenant]=role:secret"
+            );
+        };
+        anyhow::ensure!(!secret.is_empty(), "API key '{name}' has an empty secret");
+        let (name, tenant) = match name.split_once('@') {
+            Some((name, tenant)) => (name, Some(tenant.to_string())),
+            None => (name, None),
+        };
+        Ok(ApiKey {
+            name: name.to_string(),
+            tenant,
+            role: role.parse()?,
+            secret: secret.to_string(),
+        })
+    }
+}
+
+impl ApiKey {
+    /// Resolves the secret with [`resolve_secret`], so that it can be read
+    /// from a file or be age-encrypted.
+    pub fn with_resolved_secret(mut self) -> anyhow::Result<Self> {
+        self.secret = resolve_secret(&self.secret)?;
+        Ok(self)
+    }
+}
+
+/// Identity of the API key that authorized a request, available to handlers
+/// as a request extension.
+#[derive(Debug, Clone)]
+pub struct Caller {
+    pub name: String,
+    /// Tenant whose watchers and balances are the only ones visible to the
+    /// caller, if the key is restricted to one.
+    pub tenant: Option<String>,
+}
+
+/// API keys accepted by the HTTP API, passed either as `Authorization: Bearer
+/// <secret>` or `X-API-Key: <secret>`.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys(Arc<Vec<ApiKey>>);
+
+impl ApiKeys {
+    pub fn new(keys: Vec<ApiKey>) -> Self {
+        Self(Arc::new(keys))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn authenticate(&self, headers: &HeaderMap) -> Option<&ApiKey> {
+        let secret = headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| {
+                headers
+                    .get(API_KEY_HEADER)
+                    .and_then(|value| value.to_str().ok())
+            })?;
+        self.0
+            .iter()
+            .find(|key| constant_time_eq(key.secret.as_bytes(), secret.as_bytes()))
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[derive(Clone)]
+struct Requirement {
+    keys: ApiKeys,
+    role: Role,
+}
+
+async fn authorize<B>(
+    State(requirement): State<Requirement>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let caller = match requirement.keys.authenticate(request.headers()) {
+        None => return (StatusCode::UNAUTHORIZED, "Missing or unknown API key").into_response(),
+        Some(key) if key.role < requirement.role => {
+            return (StatusCode::FORBIDDEN, "API key lacks the required role").into_response()
+        }
+        Some(key) => Caller {
+            name: key.name.clone(),
+            tenant: key.tenant.clone(),
+        },
+    };
+    request.extensions_mut().insert(caller);
+    next.run(request).await
+}
+
+/// Only lets requests through to `router` when they carry an API key with at
+/// least `role`.
+pub fn require_role(router: Router, keys: &ApiKeys, role: Role) -> Router {
+    router.route_layer(middleware::from_fn_with_state(
+        Requirement {
+            keys: keys.clone(),
+            role,
+        },
+        authorize,
+    ))
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Value};
+use solana_client::client_error::reqwest;
+use solana_sdk::native_token::lamports_to_sol;
+use tokio::sync::Mutex;
+
+use crate::{health::watcher_health, observations::Observation, sink::MetricSink};
+
+const SCOPE: &str = "https://monitoring.azure.com/.default";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Tokens are refreshed this long before they expire.
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(300);
+
+pub const DEFAULT_NAMESPACE: &str = "SolanaBalanceWatcher";
+
+/// Azure AD application used to obtain tokens for the custom metrics API.
+#[derive(Debug, Clone)]
+pub struct ServicePrincipal {
+    pub tenant_id: String,
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+/// Publishes balances and watcher health as Azure Monitor custom metrics of
+/// the resource `resource_id`. Mirrors the metrics of the CloudWatch sink:
+/// `BalanceSol` with `Watcher` and `Name` dimensions and `CheckSucceeded`
+/// with a `Watcher` dimension.
+pub struct AzureMonitorSink {
+    region: String,
+    resource_id: String,
+    namespace: String,
+    principal: ServicePrincipal,
+    http_client: reqwest::Client,
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+impl AzureMonitorSink {
+    pub fn new(
+        region: String,
+        resource_id: String,
+        namespace: String,
+        principal: ServicePrincipal,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            resource_id.starts_with('/'),
+            "Azure resource ID must start with '/', got '{resource_id}'"
+        );
+        Ok(Self {
+            region,
+            resource_id,
+            namespace,
+            principal,
+            http_client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()?,
+            token: Default::default(),
+        })
+    }
+
+    async fn access_token(&self) -> anyhow::Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some((token, expires_at)) = token.as_ref() {
+            if Instant::now() < *expires_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let response: Value = self
+            .http_client
+            .post(format!(
+                "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+                self.principal.tenant_id
+            ))
+            .form(&[
+                ("grant_type", "client_credentials"),
+                ("client_id", &self.principal.client_id),
+                ("client_secret", &self.principal.client_secret),
+                ("scope", SCOPE),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let Some(access_token) = response["access_token"].as_str() else {
+            anyhow::bail!("Token response does not contain an access token");
+        };
+        let expires_in = Duration::from_secs(response["expires_in"].as_u64().unwrap_or(0));
+        let expires_at = Instant::now() + expires_in.saturating_sub(TOKEN_EXPIRY_MARGIN);
+        *token = Some((access_token.to_string(), expires_at));
+        Ok(access_token.to_string())
+    }
+
+    async fn post_metric(
+        &self,
+        token: &str,
+        metric: &str,
+        dimensions: &[&str],
+        series: Vec<Value>,
+    ) -> anyhow::Result<()> {
+        let body = json!({
+            "time": Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            "data": {
+                "baseData": {
+                    "metric": metric,
+                    "namespace": self.namespace,
+                    "dimNames": dimensions,
+                    "series": series,
+                },
+            },
+        });
+        let response = self
+            .http_client
+            .post(format!(
+                "https://{}.monitoring.azure.com{}/metrics",
+                self.region, self.resource_id
+            ))
+            .bearer_auth(token)
+            .json(&body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("Posting {metric} failed with {status}: {text}");
+        }
+        Ok(())
+    }
+}
+
+fn series(dimensions: &[&str], value: f64) -> Value {
+    json!({
+        "dimValues": dimensions,
+        "min": value,
+        "max": value,
+        "sum": value,
+        "count": 1,
+    })
+}
+
+#[async_trait]
+impl MetricSink for AzureMonitorSink {
+    fn name(&self) -> &str {
+        "azure_monitor"
+    }
+
+    async fn publish(&self, observations: &[Observation]) -> anyhow::Result<()> {
+        let token = self.access_token().await?;
+
+        // Custom metrics take a single time per request, so only the latest
+        // observation of every balance in the batch is sent.
+        let balances: BTreeMap<_, _> = observations
+            .iter()
+            .map(|observation| ((&observation.watcher, &observation.name), observation))
+            .collect();
+        if !balances.is_empty() {
+            let balances = balances
+                .into_iter()
+                .map(|((watcher, name), observation)| {
+                    series(&[watcher, name], lamports_to_sol(observation.lamports))
+                })
+                .collect();
+            self.post_metric(&token, "BalanceSol", &["Watcher", "Name"], balances)
+                .await?;
+        }
+
+        let health: Vec<_> = watcher_health()
+            .into_iter()
+            .map(|(watcher, health)| {
+                series(&[&watcher], if health.is_healthy() { 1.0 } else { 0.0 })
+            })
+            .collect();
+        if !health.is_empty() {
+            self.post_metric(&token, "CheckSucceeded", &["Watcher"], health)
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -1,61 +1,531 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
-use log::{error, info};
-use solana_account_decoder::UiDataSliceConfig;
-use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcAccountInfoConfig};
+use futures::future::join_all;
+use log::{error, info, warn};
+use once_cell::sync::OnceCell;
+use serde_json::json;
+use solana_account_decoder::UiAccount;
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind, Result as ClientResult},
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::RpcAccountInfoConfig,
+    rpc_request::RpcRequest,
+    rpc_response::{Response, RpcResult},
+};
 use solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey};
-use tokio::{task::JoinHandle, time::sleep};
+use tokio::task::JoinHandle;
 
-use crate::metrics::{reset_metric_balance_sol, update_metric_balance_sol};
+use crate::{
+    account_subscription::{push_balances, websocket_url},
+    config::NamedAddressConfig,
+    data_slice::AccountType,
+    health::{record_failed_check, record_successful_check},
+    intervals::{backoff_duration, positive_interval},
+    metrics::{
+        remove_metric_balance_sol, reset_metric_balance_sol,
+        update_metric_account_assertion_failed, update_metric_balance_discrepancy,
+        update_metric_balance_sol, update_metric_watcher_info,
+    },
+    observations::{record_observation, Observation},
+    rate_limit::RateLimiter,
+    rpc::RpcClientFactory,
+    shutdown::sleep_unless_shutdown,
+};
 
-const CHECK_INTERVAL: Duration = Duration::from_secs(300);
-const BACKOFF_DURATION: Duration = Duration::from_secs(10);
+pub const WATCHER_NAME: &str = "balance";
 
+/// RPC endpoints that must report the same balance for it to be exported.
+static BALANCE_QUORUM: OnceCell<usize> = OnceCell::new();
+
+/// Addresses checked by the first check of the first balance watcher, taken
+/// when it starts so that watchers spawned on reloads check every address.
+static STARTUP_PROBE_LIMIT: Mutex<Option<usize>> = Mutex::new(None);
+
+/// Limits the first check after startup to `limit` addresses spread across
+/// the configuration, so that readiness is reported quickly however many
+/// addresses are watched. The others are first checked one interval later.
+pub fn set_startup_probe_limit(limit: usize) -> anyhow::Result<()> {
+    anyhow::ensure!(limit > 0, "Startup probe limit must be positive");
+    *STARTUP_PROBE_LIMIT.lock().unwrap() = Some(limit);
+    Ok(())
+}
+
+/// Reads every balance from every RPC endpoint, exporting the spread of the
+/// balances they report, and only exports balances at least `quorum` of them
+/// agree on, to catch lying or stale nodes. Can only be called once.
+pub fn set_balance_quorum(quorum: usize) -> anyhow::Result<()> {
+    anyhow::ensure!(quorum >= 2, "Balance quorum must be at least 2");
+    BALANCE_QUORUM
+        .set(quorum)
+        .map_err(|_| anyhow::anyhow!("Balance quorum is already set"))
+}
+
+/// At most `limit` of `pubkeys`, evenly spread across them.
+fn probe_sample(pubkeys: &[Pubkey], limit: usize) -> Vec<Pubkey> {
+    let step = pubkeys.len().div_ceil(limit).max(1);
+    pubkeys.iter().step_by(step).copied().collect()
+}
+
+/// What a watched account is expected to look like, to catch addresses that
+/// point at the wrong or a re-created account.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountExpectations {
+    pub owner: Option<Pubkey>,
+    pub size: Option<u64>,
+}
+
+impl AccountExpectations {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// An address as passed to `--named-address`.
+#[derive(Debug, Clone)]
+pub struct WatchedAddress {
+    pub name: String,
+    pub pubkey: Pubkey,
+    pub expectations: AccountExpectations,
+    /// Interval between checks, when it differs from the one of the other
+    /// watchers.
+    pub check_interval: Option<Duration>,
+}
+
+/// Parses a `name=pubkey` pair as passed to `--named-address`, optionally
+/// followed by space separated `owner:PROGRAM` and `size:BYTES` expectations
+/// and an `interval:SECS` between checks. A bare `pubkey` is named after
+/// itself.
+pub fn parse_watched_address(named_address: &str) -> anyhow::Result<WatchedAddress> {
+    let mut params = named_address.split(' ');
+    let address = params.next().unwrap_or_default();
+    let (name, pubkey) = match address.split_once('=') {
+        Some((name, pubkey)) => (name, pubkey),
+        None => (address, address),
+    };
+    let pubkey = match Pubkey::from_str(pubkey) {
+        Ok(pubkey) => pubkey,
+        Err(err) => anyhow::bail!(
+            "Cannot parse pubkey from '{pubkey}', expected syntax: name=pubkey or pubkey: {err}"
+        ),
+    };
+
+    let mut expectations = AccountExpectations::default();
+    let mut check_interval = None;
+    for param in params.filter(|param| !param.is_empty()) {
+        match param.split_once(':') {
+            Some(("owner", owner)) => expectations.owner = Some(Pubkey::from_str(owner)?),
+            Some(("size", size)) => expectations.size = Some(size.parse()?),
+            Some(("interval", secs)) => {
+                check_interval = Some(positive_interval(name, secs.parse()?)?)
+            }
+            _ => anyhow::bail!("Unsupported parameter '{param}' of address '{name}'"),
+        }
+    }
+    Ok(WatchedAddress {
+        name: name.to_string(),
+        pubkey,
+        expectations,
+        check_interval,
+    })
+}
+
+impl TryFrom<&NamedAddressConfig> for WatchedAddress {
+    type Error = anyhow::Error;
+
+    fn try_from(config: &NamedAddressConfig) -> Result<Self, Self::Error> {
+        let pubkey = Pubkey::from_str(&config.pubkey)
+            .map_err(|err| anyhow::anyhow!("Cannot parse pubkey of '{}': {err}", config.name))?;
+        let owner = match &config.owner {
+            Some(owner) => Some(Pubkey::from_str(owner)?),
+            None => None,
+        };
+        Ok(WatchedAddress {
+            name: config.name.clone(),
+            pubkey,
+            expectations: AccountExpectations {
+                owner,
+                size: config.size,
+            },
+            check_interval: config
+                .interval
+                .map(|secs| positive_interval(&config.name, secs))
+                .transpose()?,
+        })
+    }
+}
+
+/// Like [`parse_watched_address`], ignoring any expectations and interval.
+pub fn parse_named_address(named_address: &str) -> anyhow::Result<(String, Pubkey)> {
+    parse_watched_address(named_address).map(|address| (address.name, address.pubkey))
+}
+
+/// `getMultipleAccounts` keeping the accounts as returned by the RPC, which
+/// unlike decoded accounts carry their full data size when sliced and can be
+/// requested as parsed JSON.
+pub(crate) async fn get_multiple_ui_accounts(
+    rpc_client: &RpcClient,
+    pubkeys: &[Pubkey],
+    config: RpcAccountInfoConfig,
+) -> RpcResult<Vec<Option<UiAccount>>> {
+    let config = RpcAccountInfoConfig {
+        commitment: config.commitment.or_else(|| Some(rpc_client.commitment())),
+        ..config
+    };
+    let pubkeys: Vec<_> = pubkeys.iter().map(|pubkey| pubkey.to_string()).collect();
+    let response = rpc_client
+        .send(RpcRequest::GetMultipleAccounts, json!([pubkeys, config]))
+        .await?;
+    Ok(serde_json::from_value::<Response<Vec<Option<UiAccount>>>>(
+        response,
+    )?)
+}
+
+/// Compares `account` against `expectations`, exporting and logging every
+/// assertion that does not hold. Sizes are only checked when the RPC reports
+/// them. Returns the assertions that failed.
+fn check_expectations(
+    name: &str,
+    pubkey: &Pubkey,
+    account: Option<&UiAccount>,
+    expectations: &AccountExpectations,
+) -> Vec<&'static str> {
+    let pubkey = pubkey.to_string();
+    let mut failed_assertions = vec![];
+    if let Some(owner) = expectations.owner {
+        let actual = account.map(|account| account.owner.as_str());
+        let failed = actual != Some(owner.to_string().as_str());
+        if failed {
+            let actual = actual.unwrap_or("no program, as it does not exist");
+            error!("Account {name} ({pubkey}) is owned by {actual}, expected {owner}");
+            failed_assertions.push("owner");
+        }
+        update_metric_account_assertion_failed(name, &pubkey, "owner", failed);
+    }
+    if let Some(size) = expectations.size {
+        let actual = account.and_then(|account| account.space);
+        if account.is_none() || actual.is_some() {
+            let failed = actual != Some(size);
+            if failed {
+                let actual = actual.map_or("no data, as it does not exist".to_string(), |size| {
+                    format!("{size} bytes")
+                });
+                error!("Account {name} ({pubkey}) has {actual}, expected {size} bytes");
+                failed_assertions.push("size");
+            }
+            update_metric_account_assertion_failed(name, &pubkey, "size", failed);
+        }
+    }
+    failed_assertions
+}
+
+/// Outcome of checking one watched account.
+#[derive(Debug, Clone)]
+pub struct BalanceCheck {
+    pub observation: Observation,
+    pub exists: bool,
+    pub failed_assertions: Vec<&'static str>,
+}
+
+/// Reads `pubkeys` from every RPC endpoint, exporting how far apart the
+/// balances they report are. Returns the lowest slot any endpoint answered
+/// at, and each account as reported by at least `quorum` endpoints, or
+/// `None` where too few agree on its balance.
+async fn get_quorum_accounts(
+    rpc_clients: &RpcClientFactory,
+    pubkeys: &[Pubkey],
+    named_pubkeys: &HashMap<Pubkey, String>,
+    quorum: usize,
+) -> ClientResult<(u64, Vec<Option<Option<UiAccount>>>)> {
+    let clients = rpc_clients.endpoint_clients_for_watcher(WATCHER_NAME);
+    let responses = join_all(clients.iter().map(|(_, client)| {
+        get_multiple_ui_accounts(
+            client,
+            pubkeys,
+            RpcAccountInfoConfig {
+                data_slice: Some(AccountType::Lamports.data_slice()),
+                ..Default::default()
+            },
+        )
+    }))
+    .await;
+    let mut answers = vec![];
+    for ((label, _), response) in clients.iter().zip(responses) {
+        match response {
+            Ok(response) => answers.push((label, response)),
+            Err(err) => warn!("RPC endpoint {label} failed to report balances: {err}"),
+        }
+    }
+    if answers.len() < quorum {
+        return Err(ClientError::from(ClientErrorKind::Custom(format!(
+            "Only {} of {} RPC endpoints reported balances, {quorum} required",
+            answers.len(),
+            clients.len()
+        ))));
+    }
+
+    let slot = answers
+        .iter()
+        .map(|(_, response)| response.context.slot)
+        .min()
+        .unwrap_or_default();
+    let mut accounts = Vec::with_capacity(pubkeys.len());
+    for (index, pubkey) in pubkeys.iter().enumerate() {
+        let reported: Vec<_> = answers
+            .iter()
+            .map(|(label, response)| (label, response.value.get(index).cloned().flatten()))
+            .collect();
+        let lamports = |account: &Option<UiAccount>| account.as_ref().map(|a| a.lamports);
+        let balances = reported
+            .iter()
+            .map(|(_, account)| lamports(account).unwrap_or(0));
+        let discrepancy = balances.clone().max().unwrap_or(0) - balances.min().unwrap_or(0);
+        let name = named_pubkeys.get(pubkey).unwrap();
+        update_metric_balance_discrepancy(name, &pubkey.to_string(), discrepancy);
+
+        let agreed = reported.iter().find(|(_, account)| {
+            let agreeing = reported
+                .iter()
+                .filter(|(_, other)| lamports(other) == lamports(account))
+                .count();
+            agreeing >= quorum
+        });
+        match agreed {
+            Some((_, account)) => accounts.push(Some(account.clone())),
+            None => {
+                let reported: Vec<_> = reported
+                    .iter()
+                    .map(|(label, account)| match lamports(account) {
+                        Some(lamports) => format!("{label}: {lamports} lamports"),
+                        None => format!("{label}: does not exist"),
+                    })
+                    .collect();
+                warn!(
+                    "Fewer than {quorum} RPC endpoints agree on the balance of {name} ({pubkey}), not exporting it: {}",
+                    reported.join(", ")
+                );
+                accounts.push(None);
+            }
+        }
+    }
+    Ok((slot, accounts))
+}
+
+/// Fetches the balances of `pubkeys` and checks their expectations,
+/// exporting and recording the results. With a balance quorum, balances are
+/// read from every endpoint of `rpc_clients` rather than through
+/// `rpc_client`, and those without a quorum are left out.
+pub async fn check_balances(
+    rpc_clients: &RpcClientFactory,
+    rpc_client: &RpcClient,
+    pubkeys: &[Pubkey],
+    named_pubkeys: &HashMap<Pubkey, String>,
+    expectations: &HashMap<Pubkey, AccountExpectations>,
+) -> ClientResult<Vec<BalanceCheck>> {
+    let start = Instant::now();
+    let (slot, accounts) = match BALANCE_QUORUM.get() {
+        Some(&quorum) => get_quorum_accounts(rpc_clients, pubkeys, named_pubkeys, quorum).await?,
+        None => {
+            let response = get_multiple_ui_accounts(
+                rpc_client,
+                pubkeys,
+                RpcAccountInfoConfig {
+                    data_slice: Some(AccountType::Lamports.data_slice()),
+                    ..Default::default()
+                },
+            )
+            .await?;
+            let accounts = response.value.into_iter().map(Some).collect();
+            (response.context.slot, accounts)
+        }
+    };
+
+    let duration = start.elapsed();
+    let mut checks = vec![];
+    for (pubkey, account) in pubkeys.iter().zip(accounts) {
+        let Some(account) = account else {
+            remove_metric_balance_sol(named_pubkeys.get(pubkey).unwrap(), &pubkey.to_string());
+            continue;
+        };
+        let name = named_pubkeys.get(pubkey).unwrap();
+        checks.push(export_balance(
+            name,
+            pubkey,
+            account,
+            slot,
+            duration,
+            expectations.get(pubkey),
+        ));
+    }
+    Ok(checks)
+}
+
+/// Checks the expectations of `account` as read at `slot`, and exports and
+/// records its balance.
+pub(crate) fn export_balance(
+    name: &str,
+    pubkey: &Pubkey,
+    account: Option<UiAccount>,
+    slot: u64,
+    duration: Duration,
+    expectations: Option<&AccountExpectations>,
+) -> BalanceCheck {
+    if account.is_none() {
+        error!("Account {pubkey} does not exist");
+    }
+
+    let lamports = account.as_ref().map(|a| a.lamports).unwrap_or(0);
+    let balance = lamports_to_sol(lamports);
+    let failed_assertions = match expectations {
+        Some(expectations) => check_expectations(name, pubkey, account.as_ref(), expectations),
+        None => vec![],
+    };
+    info!("Balance {pubkey}: {balance}");
+    update_metric_balance_sol(name, &pubkey.to_string(), balance);
+    let observation = Observation {
+        watcher: WATCHER_NAME.to_string(),
+        name: name.to_string(),
+        pubkey: Some(*pubkey),
+        lamports,
+        slot: Some(slot),
+        duration,
+        observed_at: SystemTime::now(),
+    };
+    record_observation(observation.clone());
+    BalanceCheck {
+        observation,
+        exists: account.is_some(),
+        failed_assertions,
+    }
+}
+
+/// Checks every pubkey at the interval given for it in `check_intervals`, or
+/// else every `default_check_interval`. Pubkeys that are due together are
+/// checked in one request. With a WebSocket URL set, balance changes are
+/// pushed instead, and polling only goes on while the subscription is down.
 pub fn spawn_balance_watcher(
-    rpc_client: Arc<RpcClient>,
+    rpc_clients: RpcClientFactory,
+    rate_limiter: Arc<RateLimiter>,
     named_pubkeys: HashMap<Pubkey, String>,
+    expectations: HashMap<Pubkey, AccountExpectations>,
+    check_intervals: HashMap<Pubkey, Duration>,
+    default_check_interval: Duration,
 ) -> JoinHandle<()> {
+    let mut pubkeys_by_interval: BTreeMap<Duration, Vec<Pubkey>> = BTreeMap::new();
+    for pubkey in named_pubkeys.keys() {
+        let interval = check_intervals
+            .get(pubkey)
+            .copied()
+            .unwrap_or(default_check_interval);
+        pubkeys_by_interval
+            .entry(interval)
+            .or_default()
+            .push(*pubkey);
+    }
+    let shortest_interval = pubkeys_by_interval
+        .keys()
+        .next()
+        .copied()
+        .unwrap_or(default_check_interval);
+    update_metric_watcher_info(WATCHER_NAME, WATCHER_NAME, "", shortest_interval);
+    let mut probe_limit = STARTUP_PROBE_LIMIT.lock().unwrap().take();
+    let rpc_client = rpc_clients.for_watcher(WATCHER_NAME);
     tokio::spawn(async move {
-        let pubkeys: Vec<_> = named_pubkeys.keys().cloned().collect();
-        loop {
-            let response = rpc_client
-                .get_multiple_accounts_with_config(
-                    pubkeys.as_slice(),
-                    RpcAccountInfoConfig {
-                        data_slice: Some(UiDataSliceConfig {
-                            offset: 0,
-                            length: 0,
-                        }),
-                        ..Default::default()
-                    },
-                )
-                .await;
-
-            let response = match response {
-                Ok(response) => response,
-                Err(err) => {
-                    error!("Failed to get RPC response: {err}");
-                    reset_metric_balance_sol();
-                    sleep(BACKOFF_DURATION).await;
-                    continue;
+        let subscribed = AtomicBool::new(false);
+        let polling = async {
+            let start = Instant::now();
+            let mut next_checks: BTreeMap<Duration, Instant> = pubkeys_by_interval
+                .keys()
+                .map(|interval| (*interval, start))
+                .collect();
+            loop {
+                let now = Instant::now();
+                let due: Vec<_> = next_checks
+                    .iter()
+                    .filter(|(_, next_check)| **next_check <= now)
+                    .map(|(interval, _)| *interval)
+                    .collect();
+                let pubkeys: Vec<_> = due
+                    .iter()
+                    .flat_map(|interval| pubkeys_by_interval[interval].iter().copied())
+                    .collect();
+                let pubkeys = match probe_limit {
+                    Some(limit) if pubkeys.len() > limit => {
+                        let sample = probe_sample(&pubkeys, limit);
+                        info!(
+                        "Probing {} of {} addresses at startup, checking the others on schedule",
+                        sample.len(),
+                        pubkeys.len()
+                    );
+                        sample
+                    }
+                    _ => pubkeys,
+                };
+                // Balances are pushed while subscribed, polling only resumes
+                // when the subscription drops.
+                if !subscribed.load(Ordering::Relaxed) {
+                    rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+                    let checks = check_balances(
+                        &rpc_clients,
+                        &rpc_client,
+                        &pubkeys,
+                        &named_pubkeys,
+                        &expectations,
+                    )
+                    .await;
+                    if let Err(err) = checks {
+                        error!("Failed to get RPC response: {err}");
+                        record_failed_check(WATCHER_NAME, &err.to_string());
+                        reset_metric_balance_sol();
+                        if !sleep_unless_shutdown(backoff_duration(WATCHER_NAME)).await {
+                            break;
+                        }
+                        continue;
+                    }
+                    record_successful_check(WATCHER_NAME);
+                    probe_limit = None;
+                } else {
+                    // Pushed balances are up to date, for the watchdog to
+                    // tell this loop from a wedged one.
+                    record_successful_check(WATCHER_NAME);
                 }
-            };
 
-            for (pubkey, account) in pubkeys.iter().zip(response.value.into_iter()) {
-                if let None = account {
-                    error!("Account {pubkey} does not exist");
+                let checked_at = Instant::now();
+                for interval in due {
+                    next_checks.insert(interval, checked_at + interval);
                 }
-
-                let balance = lamports_to_sol(account.map(|a| a.lamports).unwrap_or(0));
-                info!("Balance {pubkey}: {balance}");
-                update_metric_balance_sol(
-                    named_pubkeys.get(pubkey).unwrap(),
-                    &pubkey.to_string(),
-                    balance,
+                let next_check = next_checks
+                    .values()
+                    .min()
+                    .copied()
+                    .unwrap_or(checked_at + default_check_interval);
+                if !sleep_unless_shutdown(next_check.saturating_duration_since(checked_at)).await {
+                    break;
+                }
+            }
+        };
+        match websocket_url() {
+            Some(url) => {
+                let pushing = push_balances(
+                    url,
+                    &rpc_clients,
+                    &rpc_client,
+                    &rate_limiter,
+                    &named_pubkeys,
+                    &expectations,
+                    &subscribed,
                 );
+                tokio::join!(polling, pushing);
             }
-
-            sleep(CHECK_INTERVAL).await;
+            None => polling.await,
         }
+        info!("Balance watcher stopped");
     })
 }
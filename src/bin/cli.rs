@@ -1,82 +1,1613 @@
-use clap::Parser;
+use axum::Router;
+use clap::{Parser, Subcommand};
 use futures::future::join_all;
-use log::info;
+use log::{error, info, warn};
+use serde_json::json;
 use solana_balance_watcher::{
-    balance::spawn_balance_watcher,
-    metrics::spawn_metrics_server,
-    program_accounts_balance::{
-        spawn_program_accounts_balance_watcher, ProgramAccountsBalanceConfig,
+    account_set::set_state_dir,
+    account_subscription::set_websocket_url,
+    alert_rules::{spawn_alert_evaluator, AlertRule},
+    amount_format::{set_amount_format, set_sol_price_usd, AmountFormat, AmountUnit},
+    anomaly::{spawn_anomaly_detector, AnomalyConfig},
+    api::{admin_router, endpoints_router, status_router},
+    assertions::{run_assertions, MinBalance},
+    audit::{open_audit_log, record_audit_event, AuditAction},
+    auth::{ApiKey, ApiKeys},
+    balance::{parse_named_address, set_balance_quorum, set_startup_probe_limit},
+    change_webhook::ChangeWebhook,
+    check::run_check,
+    cluster::{self, spawn_cluster_watcher},
+    config::{ConfigFile, ConfigWatcher},
+    daemon::daemonize,
+    decoder::{self, spawn_decoded_account_watcher, DecodedAccount, Decoder},
+    derived::{spawn_derived_balance_watcher, spawn_program_accounts_discovery},
+    epoch::{self, spawn_epoch_info_watcher, spawn_epoch_snapshotter},
+    explorer::{set_explorer, Cluster, Explorer},
+    governance::{spawn_governance_discovery, GovernanceRealm},
+    grafana::{generate_dashboard, push_dashboard, DashboardOptions},
+    health::{fatal_failure, set_failure_policy, FailurePolicy},
+    heartbeat::{spawn_heartbeat, HeartbeatMethod},
+    historical::AsOf,
+    history::{flows_router, set_history_retention, DEFAULT_HISTORY_RETENTION},
+    intervals::{
+        check_interval, set_backoff_policy, set_check_interval, BackoffPolicy,
+        DEFAULT_BACKOFF_JITTER, DEFAULT_BACKOFF_MAX, DEFAULT_CHECK_INTERVAL,
     },
+    log_file::{spawn_log_file_reopener, LogFile},
+    metrics::{spawn_metrics_server, update_metric_shutting_down},
+    mint_authority::{self, spawn_mint_authority_watcher, WatchedMint},
+    observation_log::spawn_observation_logger,
+    outbound::{set_outbound_policy, AllowedDestination, OutboundPolicy},
+    preflight::check_named_addresses_exist,
+    prices::{spawn_price_feed, PriceFeedConfig},
+    program_accounts_balance::ProgramAccountsBalanceConfig,
+    pyth::{self, spawn_pyth_price_watcher, PythPriceAccount},
+    rate_limit::RateLimiter,
+    registry::{spawn_registry_discovery, RegistryAccount},
+    reload::{ReloadableWatchers, SharedWatchers, WatchListArgs},
+    replay::spawn_replay,
+    rpc::{
+        parse_commitment, HttpClientConfig, HttpVersion, RpcClientFactory, RpcHeader,
+        WatcherRouting, WatcherTimeout, DEFAULT_POOL_IDLE_TIMEOUT,
+    },
+    rpc_cost::{set_method_costs, MethodCost},
+    rpc_health::spawn_rpc_health_watcher,
+    secrets::resolve_secret,
+    shutdown::{request_shutdown, shutdown_requested},
+    sink::{spawn_sink, BatchConfig},
+    smoothing::{spawn_balance_smoother, SmoothedBalance},
+    snapshot::{set_snapshot_signing_key, snapshot_router, Snapshotter},
+    sns::{self, resolve_sns_names},
+    stake::{self, spawn_stake_watcher, spawn_withdraw_authority_watcher},
+    status_page::spawn_status_page,
+    systemd::spawn_systemd_notifier,
+    tenant::{set_tenants, Tenant},
+    token_balance::{
+        self, parse_token_account, spawn_derived_token_balance_watcher, spawn_token_balance_watcher,
+    },
+    validator::{self, resolve_validators},
+    vesting::{self, spawn_vesting_watcher, VestingContract},
+    vote::{self, spawn_vote_account_watcher},
+    wallet_pairs::{spawn_wallet_pair_evaluator, WalletPair},
+    zabbix::{self, ZabbixSender},
+};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::Mutex,
+    task::JoinHandle,
+    time::timeout,
 };
-use solana_client::nonblocking::rpc_client::RpcClient;
-use solana_sdk::pubkey::Pubkey;
-use std::{collections::HashMap, str::FromStr, sync::Arc};
 use tracing_log::LogTracer;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+/// Audit source of the configuration given on the command line.
+const AUDIT_SOURCE: &str = "startup";
+/// Audit source of changes to the config file applied while running.
+const RELOAD_AUDIT_SOURCE: &str = "reload";
 
 #[derive(Debug, Parser)]
+#[command(subcommand_negates_reqs = true)]
 struct Flags {
-    #[clap(long, required = true, env)]
-    rpc_url: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// TOML or YAML file configuring watchers, extended and overridden by
+    /// command line flags. Named addresses, address files and program-accounts
+    /// scans are reloaded from it on SIGHUP.
+    #[arg(long, env)]
+    config: Option<PathBuf>,
+
+    /// Reloads the config file whenever its content changes, as on SIGHUP,
+    /// for configs that are updated without sending signals, e.g. mounted
+    /// from a Kubernetes ConfigMap
+    #[arg(long, env, requires = "config")]
+    watch_config: bool,
+
+    /// Replays observations recorded with --log-observations-json through the
+    /// metrics, alert rules and sinks instead of watching anything, e.g. to
+    /// test alerting against a past incident
+    #[arg(long, env)]
+    replay: Option<PathBuf>,
+
+    /// How many times faster than recorded observations are replayed
+    #[arg(long, env, default_value_t = 60.0)]
+    replay_speed: f64,
 
-    #[clap(long, required = true)]
-    metrics_port: u16,
+    /// RPC endpoint URLs. Requests go to the fastest one and fail over to the
+    /// next when it errors or times out. Like other credentials, they can be
+    /// read from a file with `file:PATH` and be age-encrypted
+    #[clap(long = "rpc-url", env = "RPC_URL", value_delimiter = ',')]
+    rpc_urls: Vec<String>,
 
+    #[clap(long, required_unless_present = "config")]
+    metrics_port: Option<u16>,
+
+    /// Address to serve a read-only HTML status page on, such as
+    /// 0.0.0.0:8080, apart from the metrics and API
+    #[arg(long, env)]
+    status_page_addr: Option<SocketAddr>,
+
+    #[arg(long, env, default_value = "Solana balances")]
+    status_page_title: String,
+
+    /// `name=pubkey`, optionally followed by space separated `owner:PROGRAM`
+    /// and `size:BYTES` the account is expected to have, and `interval:SECS`
+    /// between its checks.
     #[arg(long = "named-address")]
     named_addresses: Vec<String>,
 
+    /// Names addresses given without a name after their primary .sol domain
+    #[arg(long, env)]
+    resolve_sns_names: bool,
+
+    /// Fails on startup if any named address does not exist, or if that
+    /// cannot be checked, instead of warning about it
+    #[arg(long, env)]
+    strict: bool,
+
+    /// `name=pubkey` of an SPL Token or Token-2022 account, or
+    /// `name=OWNER:MINT` of the associated token account of OWNER for MINT,
+    /// optionally followed by `:spl-token` or `:spl-token-2022` to derive it
+    /// without looking up the program that owns the mint
+    #[arg(long = "token-account")]
+    token_accounts: Vec<String>,
+
+    /// `name=MINT [mint:PUBKEY|none] [freeze:PUBKEY|none]` of a token mint
+    /// whose mint and freeze authorities to resolve and watch the balances
+    /// of, optionally with the authorities it is expected to have
+    #[arg(long = "watched-mint")]
+    watched_mints: Vec<WatchedMint>,
+
+    /// `name=pubkey` of a stake account to report active, activating,
+    /// deactivating and inactive stake of
+    #[arg(long = "stake-account")]
+    stake_accounts: Vec<String>,
+
+    /// `name=IDENTITY` of a validator whose vote account to resolve, watching
+    /// the SOL balances of both as `NAME_identity` and `NAME_vote` and the
+    /// vote state as `NAME`
+    #[arg(long = "validator")]
+    validators: Vec<String>,
+
+    /// `name=pubkey` of a vote account to report the credits, commission,
+    /// last vote and root slot of
+    #[arg(long = "vote-account")]
+    vote_accounts: Vec<String>,
+
+    /// `name=pubkey` of a withdraw authority to report the total stake of,
+    /// across all stake accounts it controls
+    #[arg(long = "stake-withdraw-authority")]
+    stake_withdraw_authorities: Vec<String>,
+
+    #[arg(long = "named-addresses-file")]
+    named_addresses_files: Vec<PathBuf>,
+
+    /// `name=PROGRAM`, followed by space separated `b58:OFFSET:BYTES` and
+    /// `size:N` filters, and optionally `weight:N`, `cost:N`,
+    /// `interval:SECS` between scans and `subscribe:true` to push changes
+    /// over `--websocket-url` rather than scan
     #[arg(long = "program-accounts")]
     program_accounts_configs: Vec<String>,
+
+    /// Same syntax as --program-accounts, watching the balance of each
+    /// matching account individually. Matching accounts are rediscovered
+    /// every check interval.
+    #[arg(long = "derived-balances")]
+    derived_balances_configs: Vec<String>,
+
+    /// `name=REALM`, optionally followed by `program:PROGRAM` for another
+    /// deployment of SPL Governance, to watch the SOL and token treasuries of
+    /// every governance of the realm under its name. Treasuries are
+    /// rediscovered every check interval.
+    #[arg(long = "governance-realm")]
+    governance_realms: Vec<GovernanceRealm>,
+
+    /// `name=PUBKEY offsets:OFFSET,...` and/or `vecs:OFFSET,...` of an
+    /// account, such as a protocol's config, whose data references the
+    /// accounts to watch under its name, as single pubkeys or as Borsh
+    /// `Vec<Pubkey>` at those offsets. It is reread every check interval.
+    #[arg(long = "registry-account")]
+    registry_accounts: Vec<RegistryAccount>,
+
+    /// `name=pubkey decoder:NAME` of a vesting contract to report vested and
+    /// unvested amounts of, with decoder streamflow or bonfida
+    #[arg(long = "vesting-contract")]
+    vesting_contracts: Vec<VestingContract>,
+
+    /// `asset=pubkey` of a Pyth price account to read the USD price of SOL or
+    /// of a token mint from, over RPC instead of an external price API
+    #[arg(long = "pyth-price-account")]
+    pyth_price_accounts: Vec<PythPriceAccount>,
+
+    /// `name=field:offset:type[:scale],...` decoding little-endian bool, u8
+    /// to u128, i8 to i128, f32 or f64 fields at byte offsets
+    #[arg(long = "decoder")]
+    decoders: Vec<Decoder>,
+
+    /// `name=pubkey decoder:NAME` of an account to export decoded fields of
+    #[arg(long = "decoded-account")]
+    decoded_accounts: Vec<String>,
+
+    /// Seconds between checks of every watcher, overriding their defaults of
+    /// 300, or 60 for Pyth prices and vote accounts. Named addresses and
+    /// program-accounts scans with an interval of their own keep it
+    #[arg(long = "check-interval", env = "CHECK_INTERVAL")]
+    check_interval_secs: Option<u64>,
+
+    /// Seconds a watcher waits after a failed check before retrying, 10 by
+    /// default, doubled with every further check failing in a row
+    #[arg(long = "backoff-duration", env = "BACKOFF_DURATION")]
+    backoff_duration_secs: Option<u64>,
+
+    /// Longest wait in seconds after checks failing in a row
+    #[arg(long = "backoff-max", env = "BACKOFF_MAX", default_value_t = DEFAULT_BACKOFF_MAX.as_secs())]
+    backoff_max_secs: u64,
+
+    /// Share of each wait after a failed check, from 0 to 1, taken off at
+    /// random so that watchers failing together retry apart
+    #[arg(long, env, default_value_t = DEFAULT_BACKOFF_JITTER)]
+    backoff_jitter: f64,
+
+    /// RPC endpoints, out of all of them, that must report the same balance
+    /// of a named address for it to be exported. Every balance is then read
+    /// from every endpoint, and `balance_discrepancy_lamports` exports how
+    /// far apart the reported balances are
+    #[arg(long, env)]
+    balance_quorum: Option<usize>,
+
+    /// WebSocket endpoint to subscribe to named addresses with
+    /// `accountSubscribe`, and to program accounts with `subscribe:true` with
+    /// `programSubscribe`, exporting balances as soon as they change. They
+    /// are polled while the socket is down, until it resubscribes
+    #[arg(long, env, conflicts_with = "balance_quorum")]
+    websocket_url: Option<String>,
+
+    /// Named addresses checked at startup, by the check that the addresses
+    /// exist and by the first balance check, so that readiness is reported
+    /// quickly for very large configurations. The others are first checked
+    /// one check interval later
+    #[arg(long, env)]
+    startup_probe_limit: Option<usize>,
+
+    /// Checks of a watcher failing in a row before the failure policy
+    /// applies
+    #[arg(long, env)]
+    max_consecutive_failures: Option<u32>,
+
+    /// What happens once a watcher failed --max-consecutive-failures checks
+    /// in a row: `retry`, `stale` to export `watcher_stale` until a check
+    /// succeeds, or `exit` to shut down with a non-zero exit code
+    #[arg(
+        long,
+        env,
+        default_value = "retry",
+        requires = "max_consecutive_failures"
+    )]
+    failure_policy: FailurePolicy,
+
+    /// Requests per second sent to the RPC provider by all watchers together,
+    /// queuing the others, such as to stay below the limit of a free tier.
+    /// The time requests waited is exported as `rpc_rate_limit_wait_seconds`
+    #[arg(long, env)]
+    rpc_rate_limit: Option<f64>,
+
+    /// Requests that can be sent at once under `--rpc-rate-limit`, defaults to
+    /// one second worth of requests
+    #[arg(long, env)]
+    rpc_rate_limit_burst: Option<f64>,
+
+    /// `method=credits` an RPC provider charges per call of a JSON-RPC method,
+    /// for estimating credit usage per watcher. Other methods cost 1 credit.
+    #[arg(long = "rpc-method-cost")]
+    rpc_method_costs: Vec<MethodCost>,
+
+    /// `watcher=STRATEGY` routing the requests of a watcher, such as a heavy
+    /// program-accounts scan, with `round-robin` or `lru` to spread them
+    /// across the RPC endpoints instead of sending them to the fastest one
+    /// (`latency`). Requests fail over to the other endpoints either way
+    #[arg(long = "rpc-routing")]
+    rpc_routing: Vec<WatcherRouting>,
+
+    /// Commitment of every RPC query, `processed` for the fastest alerts,
+    /// `confirmed`, or `finalized` (the default) for balances that cannot be
+    /// rolled back
+    #[arg(long, env, value_parser = parse_commitment)]
+    commitment: Option<CommitmentConfig>,
+
+    #[arg(long, env)]
+    rpc_pool_max_idle_per_host: Option<usize>,
+
+    #[arg(long, env, default_value_t = DEFAULT_POOL_IDLE_TIMEOUT.as_secs())]
+    rpc_pool_idle_timeout_secs: u64,
+
+    #[arg(long, env)]
+    rpc_tcp_keepalive_secs: Option<u64>,
+
+    /// Seconds to wait for a connection to an RPC endpoint to be established
+    #[arg(long, env)]
+    rpc_connect_timeout_secs: Option<u64>,
+
+    /// Seconds to wait for the response to an RPC request before giving up
+    #[arg(long, env, default_value_t = 30)]
+    rpc_request_timeout_secs: u64,
+
+    /// `watcher=SECS` giving up on the requests of a watcher sooner than
+    /// `--rpc-request-timeout-secs`, such as a heavy program-accounts scan
+    /// that should not stall its loop when an endpoint hangs. Requests fail
+    /// over to the other endpoints after each timeout
+    #[arg(long = "rpc-timeout")]
+    rpc_timeouts: Vec<WatcherTimeout>,
+
+    #[arg(long, env, default_value = "auto")]
+    rpc_http_version: HttpVersion,
+
+    /// `Name: value` of an HTTP header to send with every RPC request, such
+    /// as `Authorization: Bearer TOKEN`. The value can be read from a file
+    /// with `file:PATH`, and is never logged
+    #[arg(long = "rpc-header")]
+    rpc_headers: Vec<RpcHeader>,
+
+    /// Serve CPU profiles and memory statistics under `/debug` on the metrics
+    /// port, requiring an admin API key when any API keys are configured
+    #[cfg(feature = "profiling")]
+    #[arg(long, env)]
+    enable_profiling: bool,
+
+    #[arg(long, env)]
+    log_observations_json: bool,
+
+    /// How long balance changes are kept in memory for `/flows/{name}` and
+    /// GraphQL history queries
+    #[arg(long, env, default_value_t = DEFAULT_HISTORY_RETENTION.as_secs())]
+    history_retention_secs: u64,
+
+    #[arg(long, env)]
+    audit_log: Option<PathBuf>,
+
+    /// Directory where the accounts matched by each program-accounts scan are
+    /// kept, along with a JSON line log of accounts added and removed
+    #[arg(long, env)]
+    program_accounts_state_dir: Option<PathBuf>,
+
+    #[arg(long, env)]
+    detect_anomalies: bool,
+
+    #[arg(long, env, default_value_t = AnomalyConfig::default().alpha)]
+    anomaly_alpha: f64,
+
+    #[arg(long, env, default_value_t = AnomalyConfig::default().threshold)]
+    anomaly_threshold: f64,
+
+    #[arg(long, env, default_value_t = AnomalyConfig::default().warmup)]
+    anomaly_warmup: u32,
+
+    /// `[watcher/]name=HALF_LIFE_SECS` of a balance to also export
+    /// exponentially smoothed, for accounts with constant small churn
+    #[arg(long = "smoothed-balance")]
+    smoothed_balances: Vec<SmoothedBalance>,
+
+    /// `name=condition AND condition OR condition` alerting while the
+    /// conditions hold, each condition being `[watcher/]name<SOL` with <, <=,
+    /// > or >=
+    #[arg(long = "alert-rule")]
+    alert_rules: Vec<AlertRule>,
+
+    /// `name=hot:[watcher/]name cold:[watcher/]name max:SHARE [target:SHARE]`
+    /// tracking the share of the combined funds held by a hot wallet and
+    /// alerting while it exceeds `max`
+    #[arg(long = "wallet-pair")]
+    wallet_pairs: Vec<WalletPair>,
+
+    /// Snapshots all balances whenever a new epoch starts
+    #[arg(long, env)]
+    epoch_snapshots: bool,
+
+    /// ed25519 keypair, as written by `solana-keygen`, signing epoch and
+    /// on-demand snapshots so that auditors can verify them independently.
+    /// Resolved like other secrets, e.g. `file:PATH`
+    #[arg(long, env)]
+    snapshot_signing_key: Option<String>,
+
+    /// Exports the current epoch and its progress, which epoch snapshots do
+    /// as well
+    #[arg(long, env)]
+    epoch_info: bool,
+
+    /// Exports the slot and block height of the RPC endpoint, to confirm it
+    /// is progressing
+    #[arg(long, env)]
+    cluster_info: bool,
+
+    /// Polls getHealth on every RPC endpoint, exporting whether it is healthy
+    #[arg(long, env)]
+    rpc_health_check: bool,
+
+    #[arg(long, env)]
+    heartbeat_url: Option<String>,
+
+    #[arg(long, env, default_value = "get")]
+    heartbeat_method: HeartbeatMethod,
+
+    #[arg(long, env)]
+    change_webhook_url: Option<String>,
+
+    #[arg(long, env, default_value_t = 0)]
+    change_webhook_epsilon_lamports: u64,
+
+    #[arg(long, env, default_value_t = 100)]
+    change_webhook_batch_size: usize,
+
+    #[arg(long, env, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    change_webhook_flush_interval_secs: u64,
+
+    /// Destinations the heartbeat and change webhook may send to, given as a
+    /// host name, *.DOMAIN, an IP address or a CIDR network. A host not
+    /// allowed by name must only resolve to allowed addresses. Anywhere when
+    /// not given
+    #[arg(long = "outbound-allow", env = "OUTBOUND_ALLOW", value_delimiter = ',')]
+    outbound_allowlist: Vec<AllowedDestination>,
+
+    /// Resolves the heartbeat and change webhook hosts once at startup and
+    /// keeps connecting to those addresses
+    #[arg(long, env)]
+    pin_outbound_dns: bool,
+
+    /// Unit of amounts in notifications and reports: sol, lamports or usd
+    #[arg(long, env, default_value = "sol")]
+    amount_unit: AmountUnit,
+
+    /// Decimal places of amounts in notifications and reports, all of them
+    /// for SOL and 2 for USD by default
+    #[arg(long, env)]
+    amount_decimals: Option<usize>,
+
+    #[arg(long, env)]
+    amount_thousands_separator: Option<char>,
+
+    #[arg(long, env, default_value_t = '.')]
+    amount_decimal_separator: char,
+
+    /// Price of one SOL that USD amounts are computed with
+    #[arg(long, env)]
+    sol_price_usd: Option<f64>,
+
+    /// CoinGecko-compatible API to fetch SOL and token prices from, e.g.
+    /// https://api.coingecko.com/api/v3, for exporting balances in USD
+    #[arg(long, env)]
+    price_feed_url: Option<String>,
+
+    #[arg(long, env, hide_env_values = true)]
+    price_feed_api_key: Option<String>,
+
+    #[arg(long, env, default_value = "x-cg-demo-api-key")]
+    price_feed_api_key_header: String,
+
+    #[arg(long, env, default_value_t = 300)]
+    price_feed_interval_secs: u64,
+
+    #[arg(long, env, requires = "zabbix_host")]
+    zabbix_server: Option<String>,
+
+    #[arg(long, env)]
+    zabbix_host: Option<String>,
+
+    /// Item key template, `{watcher}`, `{name}` and `{pubkey}` are substituted
+    #[arg(long, env, default_value = zabbix::DEFAULT_ITEM_KEY)]
+    zabbix_item_key: String,
+
+    #[arg(long = "api-key", env = "API_KEYS", value_delimiter = ',')]
+    api_keys: Vec<ApiKey>,
+
+    #[arg(long = "tenant")]
+    tenants: Vec<Tenant>,
+
+    /// Explorer linked to from /status and notifications: solscan, solanafm
+    /// or xray
+    #[arg(long, env, conflicts_with = "explorer_account_url_template")]
+    explorer: Option<String>,
+
+    #[arg(long, env, default_value = "mainnet-beta")]
+    explorer_cluster: Cluster,
+
+    /// Cluster every RPC endpoint must serve, mainnet-beta, devnet or
+    /// testnet, verified by genesis hash on startup and whenever an endpoint
+    /// is added or replaced
+    #[arg(long, env)]
+    expected_cluster: Option<Cluster>,
+
+    /// Custom explorer account URL, `{pubkey}` is substituted
+    #[arg(long, env, requires = "explorer_tx_url_template")]
+    explorer_account_url_template: Option<String>,
+
+    /// Custom explorer transaction URL, `{signature}` is substituted
+    #[arg(long, env, requires = "explorer_account_url_template")]
+    explorer_tx_url_template: Option<String>,
+
+    #[cfg(feature = "azure-monitor")]
+    #[arg(long, env, requires_all = ["azure_monitor_resource_id", "azure_tenant_id", "azure_client_id", "azure_client_secret"])]
+    azure_monitor_region: Option<String>,
+
+    #[cfg(feature = "azure-monitor")]
+    #[arg(long, env)]
+    azure_monitor_resource_id: Option<String>,
+
+    #[cfg(feature = "azure-monitor")]
+    #[arg(long, env, default_value = solana_balance_watcher::azure_monitor::DEFAULT_NAMESPACE)]
+    azure_monitor_namespace: String,
+
+    #[cfg(feature = "azure-monitor")]
+    #[arg(long, env = "AZURE_TENANT_ID")]
+    azure_tenant_id: Option<String>,
+
+    #[cfg(feature = "azure-monitor")]
+    #[arg(long, env = "AZURE_CLIENT_ID")]
+    azure_client_id: Option<String>,
+
+    #[cfg(feature = "azure-monitor")]
+    #[arg(long, env = "AZURE_CLIENT_SECRET", hide_env_values = true)]
+    azure_client_secret: Option<String>,
+
+    #[cfg(feature = "cloudwatch")]
+    #[arg(long, env = "AWS_REGION")]
+    cloudwatch_region: Option<String>,
+
+    #[cfg(feature = "cloudwatch")]
+    #[arg(long, env, default_value = solana_balance_watcher::cloudwatch::DEFAULT_NAMESPACE)]
+    cloudwatch_namespace: String,
+
+    #[cfg(feature = "cloudwatch")]
+    #[arg(long = "cloudwatch-dimension")]
+    cloudwatch_dimensions: Vec<String>,
+
+    #[cfg(feature = "grpc")]
+    #[arg(long, env)]
+    grpc_port: Option<u16>,
+
+    #[cfg(feature = "sentry")]
+    #[arg(long, env = "SENTRY_DSN", hide_env_values = true)]
+    sentry_dsn: Option<String>,
+
+    /// File with the age identities that age-encrypted secrets are
+    /// decrypted with
+    #[cfg(feature = "age")]
+    #[arg(long, env)]
+    age_identity_file: Option<PathBuf>,
+
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    tui: bool,
+
+    #[arg(long, env)]
+    daemon: bool,
+
+    #[arg(long, env, requires = "daemon")]
+    pid_file: Option<PathBuf>,
+
+    #[arg(long, env)]
+    log_file: Option<PathBuf>,
+
+    #[arg(long, env, requires = "log_file")]
+    log_max_size_bytes: Option<u64>,
+
+    #[arg(long, env, default_value_t = 5)]
+    log_max_files: usize,
+
+    #[arg(long, env, conflicts_with = "current_thread_runtime")]
+    worker_threads: Option<usize>,
+
+    #[arg(long, env)]
+    current_thread_runtime: bool,
+
+    #[arg(long, env, default_value_t = 30)]
+    shutdown_timeout_secs: u64,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let flags: Flags = Flags::parse();
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Checks connectivity and every configured item once, then exits
+    Check {
+        /// Reads balances at the end of a past finalized slot, from the last
+        /// transaction touching each address, or at a commitment level
+        #[arg(long)]
+        as_of: Option<AsOf>,
+
+        #[arg(long, default_value_t = 30)]
+        timeout_secs: u64,
+    },
+    /// Checks balances once against expected minimums given as name=SOL,
+    /// exiting with an error listing the violations
+    Assert {
+        #[arg(long = "min-balance", required = true)]
+        min_balances: Vec<MinBalance>,
+
+        /// Reads balances at the end of a past finalized slot, from the last
+        /// transaction touching each address, or at a commitment level
+        #[arg(long)]
+        as_of: Option<AsOf>,
+
+        #[arg(long, default_value_t = 30)]
+        timeout_secs: u64,
+    },
+    /// Prints a Grafana dashboard for the configured watchers and alert
+    /// rules, or pushes it to Grafana when --grafana-url is given
+    Dashboard {
+        #[arg(long, default_value = "Solana balances")]
+        title: String,
+
+        /// Dashboard UID, pushing again replaces the dashboard with this UID
+        #[arg(long, default_value = "solana-balance-watcher")]
+        uid: String,
+
+        /// Prometheus data source to query, chosen in the dashboard otherwise
+        #[arg(long)]
+        datasource_uid: Option<String>,
+
+        #[arg(long, env, requires = "grafana_token")]
+        grafana_url: Option<String>,
+
+        /// Service account token with permission to write dashboards
+        #[arg(long, env, hide_env_values = true)]
+        grafana_token: Option<String>,
+
+        #[arg(long, requires = "grafana_url")]
+        grafana_folder_uid: Option<String>,
+    },
+}
+
+/// Fills in every setting not given on the command line from `config`,
+/// leaving the watch lists to [`WatchListArgs::merged`].
+fn merge_config(flags: &mut Flags, config: ConfigFile) {
+    if flags.rpc_urls.is_empty() {
+        flags.rpc_urls = config.rpc.urls;
+    }
+    flags.rpc_rate_limit = flags.rpc_rate_limit.or(config.rpc.rate_limit);
+    flags.rpc_rate_limit_burst = flags.rpc_rate_limit_burst.or(config.rpc.rate_limit_burst);
+    flags.metrics_port = flags.metrics_port.or(config.metrics.port);
+    flags.check_interval_secs = flags.check_interval_secs.or(config.check_interval_secs);
+}
+
+/// Reads the secrets given as `file:PATH` and decrypts age-encrypted ones,
+/// wherever they came from.
+fn resolve_secrets(flags: &mut Flags) -> anyhow::Result<()> {
+    #[cfg(feature = "age")]
+    if let Some(path) = &flags.age_identity_file {
+        solana_balance_watcher::secrets::set_age_identities(path)?;
+    }
+    for url in &mut flags.rpc_urls {
+        *url = resolve_secret(url)?;
+    }
+    resolve_optional_secret(&mut flags.heartbeat_url)?;
+    resolve_optional_secret(&mut flags.change_webhook_url)?;
+    resolve_optional_secret(&mut flags.price_feed_api_key)?;
+    resolve_optional_secret(&mut flags.snapshot_signing_key)?;
+    #[cfg(feature = "azure-monitor")]
+    resolve_optional_secret(&mut flags.azure_client_secret)?;
+    #[cfg(feature = "sentry")]
+    resolve_optional_secret(&mut flags.sentry_dsn)?;
+    flags.rpc_headers = std::mem::take(&mut flags.rpc_headers)
+        .into_iter()
+        .map(RpcHeader::with_resolved_secret)
+        .collect::<anyhow::Result<_>>()?;
+    flags.api_keys = std::mem::take(&mut flags.api_keys)
+        .into_iter()
+        .map(ApiKey::with_resolved_secret)
+        .collect::<anyhow::Result<_>>()?;
+    Ok(())
+}
+
+fn resolve_optional_secret(secret: &mut Option<String>) -> anyhow::Result<()> {
+    if let Some(secret) = secret {
+        *secret = resolve_secret(secret)?;
+    }
+    Ok(())
+}
+
+fn watch_list_args(flags: &Flags) -> WatchListArgs {
+    WatchListArgs {
+        named_addresses: flags.named_addresses.clone(),
+        named_addresses_files: flags.named_addresses_files.clone(),
+        program_accounts_configs: flags.program_accounts_configs.clone(),
+        ..Default::default()
+    }
+}
+
+/// Names the addresses given without a name after their primary .sol domain.
+async fn resolve_unnamed_addresses(
+    rpc_clients: &RpcClientFactory,
+    named_pubkeys: &mut HashMap<Pubkey, String>,
+) {
+    let unnamed: Vec<_> = named_pubkeys
+        .iter()
+        .filter(|(pubkey, name)| pubkey.to_string() == **name)
+        .map(|(pubkey, _)| *pubkey)
+        .collect();
+    let rpc_client = rpc_clients.for_watcher(sns::WATCHER_NAME);
+    match resolve_sns_names(&rpc_client, &unnamed).await {
+        Ok(names) => named_pubkeys.extend(names),
+        Err(err) => warn!("Failed to resolve .sol domains, keeping pubkeys as names: {err}"),
+    }
+}
+
+/// Re-reads the config file and applies its watch lists, merged with the ones
+/// given on the command line, and its RPC URLs when `reload_rpc_urls`, i.e.
+/// none were given on the command line. Other settings only apply on restart.
+async fn reload_config(
+    path: &Path,
+    command_line: &WatchListArgs,
+    reload_rpc_urls: bool,
+    resolve_sns: bool,
+    rpc_clients: &RpcClientFactory,
+    watchers: &SharedWatchers,
+) -> anyhow::Result<()> {
+    // Loading may run sops to decrypt the file.
+    let config = tokio::task::spawn_blocking({
+        let path = path.to_path_buf();
+        move || ConfigFile::load(&path)
+    })
+    .await??;
+    let mut watch_list = command_line.merged(&config).parse()?;
+    if reload_rpc_urls && !config.rpc.urls.is_empty() {
+        let urls = config
+            .rpc
+            .urls
+            .iter()
+            .map(|url| resolve_secret(url))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let (added, removed) = rpc_clients.sync_endpoints(urls).await?;
+        for label in added {
+            info!("RPC endpoint {label} added on reload");
+            record_audit_event(
+                RELOAD_AUDIT_SOURCE,
+                AuditAction::EndpointAdded,
+                &label,
+                json!({}),
+            );
+        }
+        for label in removed {
+            info!("RPC endpoint {label} removed on reload");
+            record_audit_event(
+                RELOAD_AUDIT_SOURCE,
+                AuditAction::EndpointRemoved,
+                &label,
+                json!({}),
+            );
+        }
+    }
+    if resolve_sns {
+        resolve_unnamed_addresses(rpc_clients, &mut watch_list.named_pubkeys).await;
+    }
+    watchers
+        .lock()
+        .await
+        .apply(RELOAD_AUDIT_SOURCE, watch_list)
+        .await;
+    Ok(())
+}
+
+/// Replays recorded observations until done or interrupted, then stops the
+/// observation consumers in `handles`.
+async fn run_replay(
+    path: PathBuf,
+    speed: f64,
+    mut handles: Vec<JoinHandle<()>>,
+    shutdown_timeout: Duration,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(speed > 0.0, "--replay-speed must be positive");
+    let mut replay = spawn_replay(path, speed);
+    let mut terminate = signal(SignalKind::terminate())?;
+    let interrupted = tokio::select! {
+        _ = terminate.recv() => {
+            info!("Received SIGTERM");
+            true
+        }
+        _ = tokio::signal::ctrl_c() => {
+            info!("Received SIGINT");
+            true
+        }
+        _ = &mut replay => false,
+    };
+
+    request_shutdown();
+    if interrupted {
+        handles.push(replay);
+    }
+    if timeout(shutdown_timeout, join_all(handles)).await.is_err() {
+        warn!("Replay did not stop within {shutdown_timeout:?}, exiting anyway");
+    }
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut flags: Flags = Flags::parse();
+    // Kept apart to be merged again with the config file on SIGHUP.
+    let command_line = watch_list_args(&flags);
+    let reload_rpc_urls = flags.rpc_urls.is_empty();
+    let mut watch_list = command_line.clone();
+    if let Some(path) = flags.config.clone() {
+        let config = ConfigFile::load(&path)?;
+        watch_list = command_line.merged(&config);
+        merge_config(&mut flags, config);
+    }
+    resolve_secrets(&mut flags)?;
+    anyhow::ensure!(
+        flags.replay.is_some()
+            || matches!(flags.command, Some(Command::Dashboard { .. }))
+            || !flags.rpc_urls.is_empty(),
+        "At least one RPC URL is required"
+    );
+    anyhow::ensure!(
+        flags.command.is_some() || flags.metrics_port.is_some(),
+        "--metrics-port is required, either as a flag or in the config file"
+    );
+
+    if flags.daemon {
+        daemonize(flags.pid_file.as_deref())?;
+    }
+
+    let mut runtime = if flags.current_thread_runtime {
+        tokio::runtime::Builder::new_current_thread()
+    } else {
+        let mut runtime = tokio::runtime::Builder::new_multi_thread();
+        if let Some(worker_threads) = flags.worker_threads {
+            runtime.worker_threads(worker_threads);
+        }
+        runtime
+    };
+
+    runtime
+        .enable_all()
+        .build()?
+        .block_on(run(flags, watch_list, command_line, reload_rpc_urls))
+}
+
+async fn run(
+    mut flags: Flags,
+    mut watch_list_args: WatchListArgs,
+    mut command_line: WatchListArgs,
+    reload_rpc_urls: bool,
+) -> anyhow::Result<()> {
     LogTracer::init().expect("Logger setup failed");
+    let log_file = match &flags.log_file {
+        Some(path) => Some(Arc::new(LogFile::open(
+            path.clone(),
+            flags.log_max_size_bytes,
+            flags.log_max_files,
+        )?)),
+        None => None,
+    };
+    let log_writer = match &log_file {
+        Some(log_file) => BoxMakeWriter::new(log_file.clone()),
+        None => BoxMakeWriter::new(std::io::stderr),
+    };
+    // Log lines would garble the dashboard.
+    #[cfg(feature = "tui")]
+    let log_writer = match flags.tui && log_file.is_none() {
+        true => BoxMakeWriter::new(std::io::sink),
+        false => log_writer,
+    };
     let subscriber = tracing_subscriber::fmt::Subscriber::builder()
         .with_target(false)
-        .with_writer(std::io::stderr)
+        .with_writer(log_writer)
+        .with_ansi(flags.log_file.is_none())
         .with_max_level(tracing::Level::INFO)
         .compact()
         .finish();
 
     tracing::subscriber::set_global_default(subscriber).unwrap();
 
+    // Initialized before the panic hook so that panics are reported too.
+    #[cfg(feature = "sentry")]
+    let _sentry = flags
+        .sentry_dsn
+        .as_deref()
+        .map(solana_balance_watcher::error_reporting::init_error_reporting)
+        .transpose()?;
+
+    if let Some(log_file) = log_file {
+        spawn_log_file_reopener(log_file)?;
+    }
+
     let default_panic = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
         default_panic(info);
         log::error!("Worker thread panicked, exiting.");
+        #[cfg(feature = "sentry")]
+        solana_balance_watcher::error_reporting::flush_error_reports();
         std::process::exit(1);
     }));
 
-    let mut named_pubkeys: HashMap<Pubkey, String> = Default::default();
+    let http_config = HttpClientConfig {
+        pool_max_idle_per_host: flags.rpc_pool_max_idle_per_host,
+        pool_idle_timeout: Duration::from_secs(flags.rpc_pool_idle_timeout_secs),
+        tcp_keepalive: flags.rpc_tcp_keepalive_secs.map(Duration::from_secs),
+        http_version: flags.rpc_http_version,
+        headers: flags.rpc_headers,
+        connect_timeout: flags.rpc_connect_timeout_secs.map(Duration::from_secs),
+        request_timeout: Duration::from_secs(flags.rpc_request_timeout_secs),
+    };
+    if let Some(timeout) = flags
+        .rpc_timeouts
+        .iter()
+        .find(|timeout| timeout.timeout > http_config.request_timeout)
+    {
+        anyhow::bail!(
+            "Timeout of '{}' exceeds --rpc-request-timeout-secs of {}s",
+            timeout.watcher,
+            flags.rpc_request_timeout_secs
+        );
+    }
+    set_method_costs(flags.rpc_method_costs)?;
+    set_outbound_policy(OutboundPolicy {
+        allowed: flags.outbound_allowlist,
+        pin_dns: flags.pin_outbound_dns,
+    })?;
+    set_amount_format(AmountFormat {
+        unit: flags.amount_unit,
+        decimals: flags.amount_decimals,
+        thousands_separator: flags.amount_thousands_separator,
+        decimal_separator: flags.amount_decimal_separator,
+    })?;
+    if let Some(price) = flags.sol_price_usd {
+        set_sol_price_usd(price);
+    }
+    let mut rpc_clients = RpcClientFactory::new(flags.rpc_urls, &http_config)?;
+    rpc_clients.set_routing(flags.rpc_routing);
+    rpc_clients.set_timeouts(flags.rpc_timeouts);
+    if let Some(commitment) = flags.commitment {
+        rpc_clients.set_commitment(commitment);
+    }
+    if let Some(cluster) = flags.expected_cluster {
+        rpc_clients
+            .require_genesis_hash(cluster.genesis_hash())
+            .await?;
+    }
+    if !flags.validators.is_empty() {
+        let identities = flags
+            .validators
+            .iter()
+            .map(|validator| parse_named_address(validator))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let rpc_client = rpc_clients.for_watcher(validator::WATCHER_NAME);
+        for validator in resolve_validators(&rpc_client, &identities).await? {
+            watch_list_args
+                .named_addresses
+                .extend(validator.named_addresses());
+            command_line
+                .named_addresses
+                .extend(validator.named_addresses());
+            flags.vote_accounts.push(validator.vote_account_arg());
+        }
+    }
 
-    for named_address in flags.named_addresses {
-        if let Some((name, pubkey_str)) = named_address.split_once('=') {
-            let pubkey = Pubkey::from_str(pubkey_str)
-                .expect(&format!("Cannot parse pubkey from '{pubkey_str}'"));
-            if let Some(previous_name) = named_pubkeys.get(&pubkey) {
-                panic!("Trying to store pubkey '{pubkey}' with name '{name}' but it is stored with a different name '{previous_name}' already");
+    match flags.command {
+        Some(Command::Check {
+            as_of,
+            timeout_secs,
+        }) => {
+            let failures = run_check(
+                &rpc_clients,
+                &watch_list_args,
+                as_of,
+                Duration::from_secs(timeout_secs),
+            )
+            .await;
+            anyhow::ensure!(failures == 0, "{failures} checks failed");
+            return Ok(());
+        }
+        Some(Command::Assert {
+            min_balances,
+            as_of,
+            timeout_secs,
+        }) => {
+            let violations = run_assertions(
+                &rpc_clients,
+                &watch_list_args,
+                &min_balances,
+                as_of,
+                Duration::from_secs(timeout_secs),
+            )
+            .await?;
+            anyhow::ensure!(violations == 0, "{violations} balance assertions failed");
+            return Ok(());
+        }
+        Some(Command::Dashboard {
+            title,
+            uid,
+            datasource_uid,
+            grafana_url,
+            grafana_token,
+            grafana_folder_uid,
+        }) => {
+            let watch_list = watch_list_args.parse()?;
+            let token_accounts = flags
+                .token_accounts
+                .iter()
+                .map(|token_account| Ok(parse_token_account(token_account)?.0))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let alert_rules: Vec<_> = flags
+                .alert_rules
+                .iter()
+                .map(|rule| rule.name.clone())
+                .collect();
+            let options = DashboardOptions {
+                title,
+                uid,
+                datasource_uid,
+            };
+            let dashboard =
+                generate_dashboard(&options, &watch_list, &token_accounts, &alert_rules);
+            match (grafana_url, grafana_token) {
+                (Some(url), Some(token)) => {
+                    let token = resolve_secret(&token)?;
+                    let url = push_dashboard(&url, &token, dashboard, grafana_folder_uid).await?;
+                    println!("Pushed dashboard to {url}");
+                }
+                _ => println!("{}", serde_json::to_string_pretty(&dashboard)?),
             }
-            named_pubkeys.insert(pubkey, name.into());
-            info!("Watching {name} ({pubkey})");
-        } else {
-            panic!("Failed to parse '{named_address}'");
+            return Ok(());
         }
+        None => {}
+    }
+
+    set_tenants(flags.tenants)?;
+    if let Some(name) = &flags.explorer {
+        set_explorer(Explorer::preset(name, flags.explorer_cluster)?)?;
+    }
+    if let (Some(account_template), Some(transaction_template)) = (
+        flags.explorer_account_url_template,
+        flags.explorer_tx_url_template,
+    ) {
+        set_explorer(Explorer {
+            account_template,
+            transaction_template,
+        })?;
+    }
+
+    if let Some(path) = &flags.audit_log {
+        open_audit_log(path)?;
+    }
+    if let Some(dir) = &flags.program_accounts_state_dir {
+        set_state_dir(dir)?;
+    }
+    if let Some(secs) = flags.check_interval_secs {
+        set_check_interval(Duration::from_secs(secs))?;
+    }
+    set_backoff_policy(BackoffPolicy {
+        base: flags.backoff_duration_secs.map(Duration::from_secs),
+        max: Duration::from_secs(flags.backoff_max_secs),
+        jitter: flags.backoff_jitter,
+    })?;
+    if let Some(max_consecutive_failures) = flags.max_consecutive_failures {
+        set_failure_policy(max_consecutive_failures, flags.failure_policy)?;
+    }
+
+    let mut watch_list = watch_list_args.parse()?;
+    if flags.resolve_sns_names {
+        resolve_unnamed_addresses(&rpc_clients, &mut watch_list.named_pubkeys).await;
+    }
+    if let Some(limit) = flags.startup_probe_limit {
+        set_startup_probe_limit(limit)?;
+    }
+    if let Some(quorum) = flags.balance_quorum {
+        anyhow::ensure!(
+            quorum <= rpc_clients.endpoint_labels().len(),
+            "Balance quorum of {quorum} exceeds the number of RPC endpoints"
+        );
+        set_balance_quorum(quorum)?;
+    }
+    if let Some(url) = flags.websocket_url.clone() {
+        set_websocket_url(url)?;
+    }
+    if !watch_list.named_pubkeys.is_empty() {
+        let probed: HashMap<_, _> = match flags.startup_probe_limit {
+            Some(limit) => watch_list
+                .named_pubkeys
+                .iter()
+                .take(limit)
+                .map(|(pubkey, name)| (*pubkey, name.clone()))
+                .collect(),
+            None => watch_list.named_pubkeys.clone(),
+        };
+        match check_named_addresses_exist(&rpc_clients, &probed).await {
+            Ok(missing) => anyhow::ensure!(
+                !flags.strict || missing.is_empty(),
+                "Named addresses do not exist: {}",
+                missing.join(", ")
+            ),
+            Err(err) if flags.strict => {
+                anyhow::bail!("Cannot check that named addresses exist: {err}")
+            }
+            Err(err) => warn!("Cannot check that named addresses exist: {err}"),
+        }
+    }
+
+    let rate_limiter = Arc::new(match flags.rpc_rate_limit {
+        Some(rate) => RateLimiter::new(rate, flags.rpc_rate_limit_burst.unwrap_or(rate)),
+        None => RateLimiter::unlimited(),
+    });
+    let check_interval = check_interval(DEFAULT_CHECK_INTERVAL);
+    let reloadable: SharedWatchers = Arc::new(Mutex::new(ReloadableWatchers::new(
+        rpc_clients.clone(),
+        rate_limiter.clone(),
+        check_interval,
+    )));
+
+    if let Some(keypair_json) = &flags.snapshot_signing_key {
+        let signer = set_snapshot_signing_key(keypair_json)?;
+        info!("Signing snapshots as {signer}");
+    }
+    let snapshotter = Arc::new(Snapshotter::new(
+        &rpc_clients,
+        rate_limiter.clone(),
+        &watch_list.named_pubkeys,
+        watch_list
+            .program_accounts_configs
+            .iter()
+            .map(|(_, config)| config.clone())
+            .collect(),
+    ));
+    set_history_retention(Duration::from_secs(flags.history_retention_secs))?;
+    let api_keys = ApiKeys::new(flags.api_keys);
+    #[allow(unused_mut)]
+    let mut routes = Router::new()
+        .merge(status_router(&api_keys))
+        .merge(endpoints_router(&api_keys, rpc_clients.clone()))
+        .merge(admin_router(&api_keys, reloadable.clone()))
+        .merge(snapshot_router(&api_keys, snapshotter.clone()))
+        .merge(flows_router(&api_keys));
+    #[cfg(feature = "graphql")]
+    {
+        routes = routes.merge(solana_balance_watcher::graphql::graphql_router(&api_keys));
+    }
+    #[cfg(feature = "profiling")]
+    if flags.enable_profiling {
+        routes = routes.merge(solana_balance_watcher::profiling::profiling_router(
+            &api_keys,
+        ));
     }
 
-    let rpc_client = Arc::new(RpcClient::new(flags.rpc_url));
+    let _metrics_server = spawn_metrics_server(flags.metrics_port.unwrap(), routes);
+    let _status_page = flags
+        .status_page_addr
+        .map(|addr| spawn_status_page(addr, flags.status_page_title.clone()));
+    #[cfg(feature = "grpc")]
+    let _grpc_server = flags
+        .grpc_port
+        .map(solana_balance_watcher::grpc::spawn_grpc_server);
+
+    // Subscribe before any watcher runs so that no observation is missed.
+    let observation_logger = flags.log_observations_json.then(spawn_observation_logger);
+    let mut consumers = vec![];
+    if flags.detect_anomalies {
+        consumers.push(spawn_anomaly_detector(AnomalyConfig {
+            alpha: flags.anomaly_alpha,
+            threshold: flags.anomaly_threshold,
+            warmup: flags.anomaly_warmup,
+        })?);
+    }
+    if !flags.smoothed_balances.is_empty() {
+        consumers.push(spawn_balance_smoother(flags.smoothed_balances));
+    }
+    if !flags.alert_rules.is_empty() {
+        consumers.push(spawn_alert_evaluator(flags.alert_rules));
+    }
+    if !flags.wallet_pairs.is_empty() {
+        consumers.push(spawn_wallet_pair_evaluator(flags.wallet_pairs));
+    }
+    if let Some(url) = flags.change_webhook_url {
+        let config = BatchConfig {
+            max_batch_size: flags.change_webhook_batch_size,
+            flush_interval: Duration::from_secs(flags.change_webhook_flush_interval_secs),
+        };
+        let webhook = ChangeWebhook::new(url, flags.change_webhook_epsilon_lamports).await?;
+        consumers.push(spawn_sink(webhook, config));
+    }
+    if let (Some(server), Some(host)) = (flags.zabbix_server, flags.zabbix_host) {
+        let sender = ZabbixSender::new(server, host, flags.zabbix_item_key);
+        consumers.push(spawn_sink(sender, BatchConfig::default()));
+    }
+    #[cfg(feature = "azure-monitor")]
+    if let Some(region) = flags.azure_monitor_region {
+        use solana_balance_watcher::azure_monitor::{AzureMonitorSink, ServicePrincipal};
+        let principal = ServicePrincipal {
+            tenant_id: flags.azure_tenant_id.unwrap(),
+            client_id: flags.azure_client_id.unwrap(),
+            client_secret: flags.azure_client_secret.unwrap(),
+        };
+        let sink = AzureMonitorSink::new(
+            region,
+            flags.azure_monitor_resource_id.unwrap(),
+            flags.azure_monitor_namespace,
+            principal,
+        )?;
+        let config = BatchConfig {
+            flush_interval: Duration::from_secs(60),
+            ..Default::default()
+        };
+        consumers.push(spawn_sink(sink, config));
+    }
+    #[cfg(feature = "cloudwatch")]
+    if let Some(region) = flags.cloudwatch_region {
+        use solana_balance_watcher::cloudwatch::{self, parse_dimension, CloudWatchSink};
+        let dimensions = flags
+            .cloudwatch_dimensions
+            .iter()
+            .map(|dimension| parse_dimension(dimension))
+            .collect::<anyhow::Result<_>>()?;
+        let sink = CloudWatchSink::new(region, flags.cloudwatch_namespace, dimensions)?;
+        let config = BatchConfig {
+            // Leave room for the health metrics sent along with every batch.
+            max_batch_size: cloudwatch::MAX_BATCH_SIZE / 2,
+            flush_interval: Duration::from_secs(60),
+        };
+        consumers.push(spawn_sink(sink, config));
+    }
+
+    if let Some(path) = flags.replay {
+        let handles = observation_logger.into_iter().chain(consumers).collect();
+        let shutdown_timeout = Duration::from_secs(flags.shutdown_timeout_secs);
+        return run_replay(path, flags.replay_speed, handles, shutdown_timeout).await;
+    }
+
+    reloadable
+        .lock()
+        .await
+        .apply(AUDIT_SOURCE, watch_list)
+        .await;
 
     let mut handles = vec![];
-    handles.push(spawn_metrics_server(flags.metrics_port));
-    handles.push(spawn_balance_watcher(rpc_client.clone(), named_pubkeys));
-    for program_account_config in flags.program_accounts_configs {
-        handles.push(spawn_program_accounts_balance_watcher(
-            rpc_client.clone(),
-            ProgramAccountsBalanceConfig::from_str(&program_account_config)?,
+    for derived_balances_config in flags.derived_balances_configs {
+        let config = ProgramAccountsBalanceConfig::from_str(&derived_balances_config)?;
+        record_audit_event(
+            AUDIT_SOURCE,
+            AuditAction::WatcherAdded,
+            config.name(),
+            json!({ "config": derived_balances_config, "derived": true }),
+        );
+        let name = config.name().to_string();
+        let check_interval = config.check_interval().unwrap_or(check_interval);
+        let (discovery, pubkey_set) = spawn_program_accounts_discovery(
+            rpc_clients.for_watcher(&format!("{name}/discovery")),
+            rate_limiter.clone(),
+            config,
+            check_interval,
+        );
+        handles.push(discovery);
+        handles.push(spawn_derived_balance_watcher(
+            rpc_clients.for_watcher(&name),
+            rate_limiter.clone(),
+            name,
+            pubkey_set,
+            check_interval,
+        ));
+    }
+    for realm in flags.governance_realms {
+        record_audit_event(
+            AUDIT_SOURCE,
+            AuditAction::WatcherAdded,
+            &realm.name,
+            json!({
+                "realm": realm.realm.to_string(),
+                "program": realm.program.to_string(),
+                "governance": true,
+            }),
+        );
+        let name = realm.name.clone();
+        let (discovery, native_treasuries, token_accounts) = spawn_governance_discovery(
+            rpc_clients.for_watcher(&format!("{name}/discovery")),
+            rate_limiter.clone(),
+            realm,
+            check_interval,
+        );
+        handles.push(discovery);
+        handles.push(spawn_derived_balance_watcher(
+            rpc_clients.for_watcher(&name),
+            rate_limiter.clone(),
+            name.clone(),
+            native_treasuries,
+            check_interval,
+        ));
+        handles.push(spawn_derived_token_balance_watcher(
+            rpc_clients.for_watcher(&format!("{name}/tokens")),
+            rate_limiter.clone(),
+            name,
+            token_accounts,
+            check_interval,
+        ));
+    }
+    for registry in flags.registry_accounts {
+        record_audit_event(
+            AUDIT_SOURCE,
+            AuditAction::WatcherAdded,
+            &registry.name,
+            json!({
+                "registry": registry.pubkey.to_string(),
+                "offsets": registry.offsets,
+                "vecs": registry.vecs,
+            }),
+        );
+        let name = registry.name.clone();
+        let (discovery, pubkey_set) = spawn_registry_discovery(
+            rpc_clients.for_watcher(&format!("{name}/discovery")),
+            rate_limiter.clone(),
+            registry,
+            check_interval,
+        );
+        handles.push(discovery);
+        handles.push(spawn_derived_balance_watcher(
+            rpc_clients.for_watcher(&name),
+            rate_limiter.clone(),
+            name,
+            pubkey_set,
+            check_interval,
+        ));
+    }
+    if !flags.token_accounts.is_empty() {
+        let mut token_accounts = vec![];
+        for token_account in &flags.token_accounts {
+            let (name, address) = parse_token_account(token_account)?;
+            record_audit_event(
+                AUDIT_SOURCE,
+                AuditAction::WatcherAdded,
+                &name,
+                json!({ "token_account": address.to_string() }),
+            );
+            token_accounts.push((name, address));
+        }
+        handles.push(spawn_token_balance_watcher(
+            rpc_clients.for_watcher(token_balance::WATCHER_NAME),
+            rate_limiter.clone(),
+            token_accounts,
+        ));
+    }
+    if !flags.watched_mints.is_empty() {
+        for mint in &flags.watched_mints {
+            record_audit_event(
+                AUDIT_SOURCE,
+                AuditAction::WatcherAdded,
+                &mint.name,
+                json!({ "mint": mint.mint.to_string() }),
+            );
+        }
+        handles.push(spawn_mint_authority_watcher(
+            rpc_clients.for_watcher(mint_authority::WATCHER_NAME),
+            rate_limiter.clone(),
+            flags.watched_mints,
+        ));
+    }
+    if !flags.stake_accounts.is_empty() {
+        let mut stake_accounts = vec![];
+        for stake_account in &flags.stake_accounts {
+            let (name, pubkey) = parse_named_address(stake_account)?;
+            record_audit_event(
+                AUDIT_SOURCE,
+                AuditAction::WatcherAdded,
+                &name,
+                json!({ "stake_account": pubkey.to_string() }),
+            );
+            stake_accounts.push((name, pubkey));
+        }
+        handles.push(spawn_stake_watcher(
+            rpc_clients.for_watcher(stake::WATCHER_NAME),
+            rate_limiter.clone(),
+            stake_accounts,
+        ));
+    }
+    if !flags.vote_accounts.is_empty() {
+        let mut vote_accounts = vec![];
+        for vote_account in &flags.vote_accounts {
+            let (name, pubkey) = parse_named_address(vote_account)?;
+            record_audit_event(
+                AUDIT_SOURCE,
+                AuditAction::WatcherAdded,
+                &name,
+                json!({ "vote_account": pubkey.to_string() }),
+            );
+            vote_accounts.push((name, pubkey));
+        }
+        handles.push(spawn_vote_account_watcher(
+            rpc_clients.for_watcher(vote::WATCHER_NAME),
+            rate_limiter.clone(),
+            vote_accounts,
+        ));
+    }
+    if !flags.stake_withdraw_authorities.is_empty() {
+        let mut authorities = vec![];
+        for authority in &flags.stake_withdraw_authorities {
+            let (name, pubkey) = parse_named_address(authority)?;
+            record_audit_event(
+                AUDIT_SOURCE,
+                AuditAction::WatcherAdded,
+                &name,
+                json!({ "stake_withdraw_authority": pubkey.to_string() }),
+            );
+            authorities.push((name, pubkey));
+        }
+        handles.push(spawn_withdraw_authority_watcher(
+            rpc_clients.for_watcher(stake::AUTHORITY_WATCHER_NAME),
+            rate_limiter.clone(),
+            authorities,
+        ));
+    }
+    if !flags.decoded_accounts.is_empty() {
+        let decoders: HashMap<_, _> = flags
+            .decoders
+            .into_iter()
+            .map(|decoder| (decoder.name.clone(), Arc::new(decoder)))
+            .collect();
+        let mut accounts = vec![];
+        for decoded_account in &flags.decoded_accounts {
+            let account = DecodedAccount::parse(decoded_account, &decoders)?;
+            record_audit_event(
+                AUDIT_SOURCE,
+                AuditAction::WatcherAdded,
+                &account.name,
+                json!({ "config": decoded_account }),
+            );
+            accounts.push(account);
+        }
+        handles.push(spawn_decoded_account_watcher(
+            rpc_clients.for_watcher(decoder::WATCHER_NAME),
+            rate_limiter.clone(),
+            accounts,
+        ));
+    }
+    if !flags.vesting_contracts.is_empty() {
+        for contract in &flags.vesting_contracts {
+            record_audit_event(
+                AUDIT_SOURCE,
+                AuditAction::WatcherAdded,
+                &contract.name,
+                json!({ "pubkey": contract.pubkey.to_string(), "decoder": contract.decoder.name() }),
+            );
+        }
+        handles.push(spawn_vesting_watcher(
+            rpc_clients.for_watcher(vesting::WATCHER_NAME),
+            rate_limiter.clone(),
+            flags.vesting_contracts,
+        ));
+    }
+    if !flags.pyth_price_accounts.is_empty() {
+        for account in &flags.pyth_price_accounts {
+            record_audit_event(
+                AUDIT_SOURCE,
+                AuditAction::WatcherAdded,
+                pyth::WATCHER_NAME,
+                json!({ "asset": account.asset.to_string(), "pubkey": account.pubkey.to_string() }),
+            );
+        }
+        handles.push(spawn_pyth_price_watcher(
+            rpc_clients.for_watcher(pyth::WATCHER_NAME),
+            rate_limiter.clone(),
+            flags.pyth_price_accounts,
         ));
     }
 
-    join_all(handles).await;
+    handles.extend(observation_logger);
+    handles.extend(consumers);
+    if flags.epoch_snapshots {
+        handles.push(spawn_epoch_snapshotter(
+            rpc_clients.for_watcher(epoch::SNAPSHOTTER_WATCHER_NAME),
+            rate_limiter.clone(),
+            snapshotter,
+        ));
+    } else if flags.epoch_info {
+        handles.push(spawn_epoch_info_watcher(
+            rpc_clients.for_watcher(epoch::WATCHER_NAME),
+            rate_limiter.clone(),
+        ));
+    }
+    if flags.cluster_info {
+        handles.push(spawn_cluster_watcher(
+            rpc_clients.for_watcher(cluster::WATCHER_NAME),
+            rate_limiter.clone(),
+        ));
+    }
+    if flags.rpc_health_check {
+        handles.push(spawn_rpc_health_watcher(
+            rpc_clients.clone(),
+            rate_limiter.clone(),
+        ));
+    }
+    for watcher in rpc_clients.unused_timeouts() {
+        warn!("--rpc-timeout of '{watcher}' matches no running watcher");
+    }
+    if let Some(url) = flags.heartbeat_url {
+        handles.push(spawn_heartbeat(url, flags.heartbeat_method).await?);
+    }
+    if let Some(url) = flags.price_feed_url {
+        handles.push(spawn_price_feed(PriceFeedConfig {
+            url,
+            api_key: flags.price_feed_api_key,
+            api_key_header: flags.price_feed_api_key_header,
+            interval: Duration::from_secs(flags.price_feed_interval_secs),
+        })?);
+    }
+    if let Some(notifier) = spawn_systemd_notifier() {
+        handles.push(notifier);
+    }
 
-    Ok(())
+    #[cfg(feature = "tui")]
+    let dashboard = flags.tui.then(|| {
+        let endpoints = rpc_clients.endpoint_labels();
+        tokio::task::spawn_blocking(move || solana_balance_watcher::tui::run_dashboard(&endpoints))
+    });
+    #[cfg(not(feature = "tui"))]
+    let dashboard: Option<JoinHandle<anyhow::Result<()>>> = None;
+
+    let mut dashboard = dashboard;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+    let mut config_watcher = match (&flags.config, flags.watch_config) {
+        (Some(path), true) => Some(ConfigWatcher::new(path)?),
+        _ => None,
+    };
+    loop {
+        let trigger = tokio::select! {
+            _ = terminate.recv() => {
+                info!("Received SIGTERM");
+                break;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                info!("Received SIGINT");
+                break;
+            }
+            _ = shutdown_requested() => break,
+            Some(result) = async { Some(dashboard.as_mut()?.await) } => {
+                match result {
+                    Ok(Ok(())) => info!("Dashboard closed"),
+                    Ok(Err(err)) => error!("Dashboard failed: {err}"),
+                    Err(err) => error!("Dashboard failed: {err}"),
+                }
+                break;
+            }
+            _ = hangup.recv() => "SIGHUP",
+            Some(()) = async {
+                config_watcher.as_mut()?.changed().await;
+                Some(())
+            } => "file change",
+        };
+        let Some(path) = &flags.config else {
+            warn!("Received {trigger} without --config, nothing to reload");
+            continue;
+        };
+        info!("Reloading {} on {trigger}", path.display());
+        let reloaded = reload_config(
+            path,
+            &command_line,
+            reload_rpc_urls,
+            flags.resolve_sns_names,
+            &rpc_clients,
+            &reloadable,
+        )
+        .await;
+        match reloaded {
+            Ok(()) => info!("Reloaded {}", path.display()),
+            Err(err) => error!("Failed to reload, keeping the current watchers: {err}"),
+        }
+    }
+
+    request_shutdown();
+    update_metric_shutting_down();
+    let shutdown_timeout = Duration::from_secs(flags.shutdown_timeout_secs);
+    info!("Waiting up to {shutdown_timeout:?} for in-flight checks to finish");
+    handles.extend(reloadable.lock().await.take_handles());
+    if timeout(shutdown_timeout, join_all(handles)).await.is_err() {
+        warn!("Watchers did not stop within {shutdown_timeout:?}, exiting anyway");
+    }
+    info!("Shutdown complete");
+
+    match fatal_failure() {
+        Some(failure) => Err(anyhow::anyhow!("{failure}")),
+        None => Ok(()),
+    }
 }
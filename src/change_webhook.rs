@@ -0,0 +1,104 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::json;
+use solana_client::client_error::reqwest;
+
+use crate::{
+    amount_format::{format_lamports, format_lamports_delta},
+    explorer::account_url,
+    observations::Observation,
+    outbound::notifier_http_client,
+    sink::MetricSink,
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// POSTs every balance change larger than `epsilon_lamports` to a URL. The
+/// first observation of a balance only establishes its baseline.
+pub struct ChangeWebhook {
+    url: String,
+    epsilon_lamports: u64,
+    http_client: reqwest::Client,
+    /// Last notified balance per watcher and name.
+    notified: Mutex<HashMap<(String, String), u64>>,
+}
+
+impl ChangeWebhook {
+    pub async fn new(url: String, epsilon_lamports: u64) -> anyhow::Result<Self> {
+        Ok(Self {
+            http_client: notifier_http_client(&url, REQUEST_TIMEOUT).await?,
+            url,
+            epsilon_lamports,
+            notified: Default::default(),
+        })
+    }
+}
+
+#[async_trait]
+impl MetricSink for ChangeWebhook {
+    fn name(&self) -> &str {
+        "change_webhook"
+    }
+
+    fn would_publish(&self, observation: &Observation) -> bool {
+        let key = (observation.watcher.clone(), observation.name.clone());
+        self.notified
+            .lock()
+            .unwrap()
+            .get(&key)
+            .is_some_and(|previous| {
+                observation.lamports.abs_diff(*previous) > self.epsilon_lamports
+            })
+    }
+
+    async fn publish(&self, observations: &[Observation]) -> anyhow::Result<()> {
+        let mut updated: HashMap<(String, String), u64> = HashMap::new();
+        let mut changes = vec![];
+        {
+            let notified = self.notified.lock().unwrap();
+            for observation in observations {
+                let key = (observation.watcher.clone(), observation.name.clone());
+                let previous = updated.get(&key).or_else(|| notified.get(&key)).copied();
+                match previous {
+                    Some(previous)
+                        if observation.lamports.abs_diff(previous) > self.epsilon_lamports =>
+                    {
+                        changes.push(json!({
+                            "watcher": observation.watcher,
+                            "name": observation.name,
+                            "pubkey": observation.pubkey.map(|pubkey| pubkey.to_string()),
+                            "explorer_url": observation.pubkey.as_ref().and_then(account_url),
+                            "previous_lamports": previous,
+                            "lamports": observation.lamports,
+                            "delta_lamports": observation.lamports as i64 - previous as i64,
+                            "amount": format_lamports(observation.lamports),
+                            "previous_amount": format_lamports(previous),
+                            "delta": format_lamports_delta(
+                                i128::from(observation.lamports) - i128::from(previous)
+                            ),
+                            "slot": observation.slot,
+                            "observed_at": DateTime::<Utc>::from(observation.observed_at)
+                                .to_rfc3339_opts(SecondsFormat::Millis, true),
+                        }));
+                    }
+                    Some(_) => continue,
+                    None => {}
+                }
+                updated.insert(key, observation.lamports);
+            }
+        }
+
+        if !changes.is_empty() {
+            self.http_client
+                .post(&self.url)
+                .json(&json!({ "changes": changes }))
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        self.notified.lock().unwrap().extend(updated);
+        Ok(())
+    }
+}
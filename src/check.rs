@@ -0,0 +1,103 @@
+use std::{fmt::Display, future::Future, time::Duration};
+
+use crate::{
+    amount_format::format_lamports,
+    historical::{get_balances_as_of, get_program_accounts_as_of, AsOf},
+    reload::WatchListArgs,
+    rpc::RpcClientFactory,
+};
+
+const WATCHER_NAME: &str = "check";
+/// Maximum number of accounts accepted by a single `getMultipleAccounts` call.
+pub(crate) const MAX_ACCOUNTS_PER_REQUEST: usize = 100;
+
+/// Prints a report line for `item` and returns 1 if it failed.
+pub(crate) fn report(ok: bool, item: &str, detail: impl Display) -> usize {
+    println!("{} {item}: {detail}", if ok { "OK  " } else { "FAIL" });
+    usize::from(!ok)
+}
+
+pub(crate) async fn with_timeout<T, E: Display>(
+    timeout: Duration,
+    future: impl Future<Output = Result<T, E>>,
+) -> Result<T, String> {
+    match tokio::time::timeout(timeout, future).await {
+        Ok(result) => result.map_err(|err| err.to_string()),
+        Err(_) => Err(format!("timed out after {timeout:?}")),
+    }
+}
+
+/// Verifies the configuration once against the live RPC endpoints: every
+/// endpoint answers, every named address parses and exists, and every
+/// program-accounts scan completes within `timeout`. Balances are read as of
+/// `as_of` when given. Prints one report line per item and returns the
+/// number of failed items.
+pub async fn run_check(
+    rpc_clients: &RpcClientFactory,
+    watch_list: &WatchListArgs,
+    as_of: Option<AsOf>,
+    timeout: Duration,
+) -> usize {
+    let mut failures = 0;
+
+    for (endpoint, rpc_client) in rpc_clients.endpoint_clients() {
+        let item = format!("rpc {endpoint}");
+        failures += match with_timeout(timeout, rpc_client.get_version()).await {
+            Ok(version) => report(true, &item, format!("solana-core {}", version.solana_core)),
+            Err(err) => report(false, &item, err),
+        };
+    }
+
+    let rpc_client = rpc_clients.for_watcher(WATCHER_NAME);
+
+    let mut named_pubkeys = vec![];
+    for (named_address, address) in watch_list.watched_addresses() {
+        match address {
+            Ok(address) => named_pubkeys.push((address.name, address.pubkey)),
+            Err(err) => failures += report(false, &format!("address {named_address}"), err),
+        }
+    }
+    for chunk in named_pubkeys.chunks(MAX_ACCOUNTS_PER_REQUEST) {
+        let pubkeys: Vec<_> = chunk.iter().map(|(_, pubkey)| *pubkey).collect();
+        let response =
+            with_timeout(timeout, get_balances_as_of(&rpc_client, &pubkeys, as_of)).await;
+
+        for (index, (name, pubkey)) in chunk.iter().enumerate() {
+            let item = format!("address {name} ({pubkey})");
+            failures += match &response {
+                Ok(balances) => match balances[index] {
+                    Some(lamports) => report(true, &item, format_lamports(lamports)),
+                    None => report(false, &item, "account does not exist"),
+                },
+                Err(err) => report(false, &item, err),
+            };
+        }
+    }
+
+    for (program_accounts_config, config) in watch_list.program_accounts() {
+        let config = match config {
+            Ok(config) => config,
+            Err(err) => {
+                let item = format!("program-accounts {program_accounts_config}");
+                failures += report(false, &item, err);
+                continue;
+            }
+        };
+        let item = format!("program-accounts {}", config.name());
+        failures += match with_timeout(
+            timeout,
+            get_program_accounts_as_of(&rpc_client, &config, as_of),
+        )
+        .await
+        {
+            Ok(accounts) => {
+                let lamports = accounts.iter().map(|(_, account)| account.lamports).sum();
+                let detail = format!("{} accounts, {}", accounts.len(), format_lamports(lamports));
+                report(true, &item, detail)
+            }
+            Err(err) => report(false, &item, err),
+        };
+    }
+
+    failures
+}
@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use solana_client::client_error::reqwest;
+use solana_sdk::native_token::lamports_to_sol;
+
+use crate::{health::watcher_health, observations::Observation, sink::MetricSink};
+
+const SERVICE: &str = "monitoring";
+const API_VERSION: &str = "2010-08-01";
+const CONTENT_TYPE: &str = "application/x-www-form-urlencoded; charset=utf-8";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Maximum number of metrics accepted by a single `PutMetricData` call.
+pub const MAX_BATCH_SIZE: usize = 1000;
+
+pub const DEFAULT_NAMESPACE: &str = "SolanaBalanceWatcher";
+
+/// Static credentials taken from the standard AWS environment variables.
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl Credentials {
+    fn from_env() -> anyhow::Result<Self> {
+        let var = |name| std::env::var(name).map_err(|_| anyhow::anyhow!("{name} is not set"));
+        Ok(Self {
+            access_key_id: var("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+/// Publishes balances and watcher health to CloudWatch with `PutMetricData`.
+/// Balances are sent as `BalanceSol` with `Watcher` and `Name` dimensions,
+/// health as `CheckSucceeded` (1 when the latest check of a watcher
+/// succeeded) with a `Watcher` dimension. Extra static dimensions are added
+/// to every metric.
+pub struct CloudWatchSink {
+    region: String,
+    namespace: String,
+    dimensions: Vec<(String, String)>,
+    credentials: Credentials,
+    http_client: reqwest::Client,
+}
+
+/// Parses a `name=value` dimension as passed to `--cloudwatch-dimension`.
+pub fn parse_dimension(dimension: &str) -> anyhow::Result<(String, String)> {
+    match dimension.split_once('=') {
+        Some((name, value)) => Ok((name.to_string(), value.to_string())),
+        None => anyhow::bail!("Failed to parse '{dimension}', expected syntax: name=value"),
+    }
+}
+
+impl CloudWatchSink {
+    pub fn new(
+        region: String,
+        namespace: String,
+        dimensions: Vec<(String, String)>,
+    ) -> anyhow::Result<Self> {
+        Ok(Self {
+            region,
+            namespace,
+            dimensions,
+            credentials: Credentials::from_env()?,
+            http_client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()?,
+        })
+    }
+
+    fn host(&self) -> String {
+        format!("{SERVICE}.{}.amazonaws.com", self.region)
+    }
+
+    fn push_metric(
+        &self,
+        params: &mut Vec<(String, String)>,
+        index: usize,
+        name: &str,
+        value: f64,
+        timestamp: DateTime<Utc>,
+        dimensions: &[(&str, &str)],
+    ) {
+        let prefix = format!("MetricData.member.{index}");
+        params.push((format!("{prefix}.MetricName"), name.to_string()));
+        params.push((format!("{prefix}.Value"), value.to_string()));
+        params.push((
+            format!("{prefix}.Timestamp"),
+            timestamp.to_rfc3339_opts(SecondsFormat::Secs, true),
+        ));
+        let dimensions = dimensions.iter().copied().chain(
+            self.dimensions
+                .iter()
+                .map(|(n, v)| (n.as_str(), v.as_str())),
+        );
+        for (i, (dimension, value)) in dimensions.enumerate() {
+            let prefix = format!("{prefix}.Dimensions.member.{}", i + 1);
+            params.push((format!("{prefix}.Name"), dimension.to_string()));
+            params.push((format!("{prefix}.Value"), value.to_string()));
+        }
+    }
+
+    /// Signs the request with AWS Signature Version 4.
+    fn authorization(&self, now: DateTime<Utc>, body: &str) -> String {
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let mut headers = vec![
+            ("content-type", CONTENT_TYPE.to_string()),
+            ("host", self.host()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "POST\n/\n\n{canonical_headers}\n{signed_headers}\n{}",
+            hex::encode(Sha256::digest(body))
+        );
+        let scope = format!("{date}/{}/{SERVICE}/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical_request))
+        );
+
+        let key = format!("AWS4{}", self.credentials.secret_access_key);
+        let key = hmac_sha256(key.as_bytes(), date.as_bytes());
+        let key = hmac_sha256(&key, self.region.as_bytes());
+        let key = hmac_sha256(&key, SERVICE.as_bytes());
+        let key = hmac_sha256(&key, b"aws4_request");
+        let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.credentials.access_key_id
+        )
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes everything but the unreserved characters, as required
+/// for the canonical form of SigV4 signed requests.
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[async_trait]
+impl MetricSink for CloudWatchSink {
+    fn name(&self) -> &str {
+        "cloudwatch"
+    }
+
+    async fn publish(&self, observations: &[Observation]) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let mut params = vec![
+            ("Action".to_string(), "PutMetricData".to_string()),
+            ("Version".to_string(), API_VERSION.to_string()),
+            ("Namespace".to_string(), self.namespace.clone()),
+        ];
+        let mut index = 0;
+        for observation in observations {
+            index += 1;
+            self.push_metric(
+                &mut params,
+                index,
+                "BalanceSol",
+                lamports_to_sol(observation.lamports),
+                observation.observed_at.into(),
+                &[
+                    ("Watcher", &observation.watcher),
+                    ("Name", &observation.name),
+                ],
+            );
+        }
+        for (watcher, health) in watcher_health() {
+            index += 1;
+            self.push_metric(
+                &mut params,
+                index,
+                "CheckSucceeded",
+                if health.is_healthy() { 1.0 } else { 0.0 },
+                now,
+                &[("Watcher", &watcher)],
+            );
+        }
+
+        let body = params
+            .iter()
+            .map(|(name, value)| format!("{}={}", uri_encode(name), uri_encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+        let mut request = self
+            .http_client
+            .post(format!("https://{}/", self.host()))
+            .header("content-type", CONTENT_TYPE)
+            .header("x-amz-date", now.format("%Y%m%dT%H%M%SZ").to_string())
+            .header("authorization", self.authorization(now, &body));
+        if let Some(token) = &self.credentials.session_token {
+            request = request.header("x-amz-security-token", token);
+        }
+        let response = request.body(body).send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!("PutMetricData failed with {status}: {text}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{error, info};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use tokio::task::JoinHandle;
+
+use crate::{
+    health::{record_failed_check, record_successful_check},
+    intervals::backoff_duration,
+    metrics::{update_metric_cluster_progress, update_metric_watcher_info},
+    rate_limit::RateLimiter,
+    shutdown::sleep_unless_shutdown,
+};
+
+pub const WATCHER_NAME: &str = "cluster";
+
+/// A slot lasts about 400ms, so a stalled endpoint shows as a flat line
+/// after a few polls.
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+async fn check_cluster_progress(
+    rpc_client: &RpcClient,
+    rate_limiter: &RateLimiter,
+) -> anyhow::Result<()> {
+    rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+    let slot = rpc_client.get_slot().await?;
+    rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+    let block_height = rpc_client.get_block_height().await?;
+    update_metric_cluster_progress(slot, block_height);
+    Ok(())
+}
+
+/// Exports the slot and block height the RPC endpoint is at, as
+/// `current_slot` and `block_height`, to confirm it keeps progressing while
+/// the balance watchers report data from it.
+pub fn spawn_cluster_watcher(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+) -> JoinHandle<()> {
+    update_metric_watcher_info(WATCHER_NAME, WATCHER_NAME, "", POLL_INTERVAL);
+    tokio::spawn(async move {
+        loop {
+            if let Err(err) = check_cluster_progress(&rpc_client, &rate_limiter).await {
+                error!("Failed to get slot and block height: {err}");
+                record_failed_check(WATCHER_NAME, &err.to_string());
+                if !sleep_unless_shutdown(backoff_duration(WATCHER_NAME)).await {
+                    break;
+                }
+                continue;
+            }
+            record_successful_check(WATCHER_NAME);
+
+            if !sleep_unless_shutdown(POLL_INTERVAL).await {
+                break;
+            }
+        }
+        info!("Cluster watcher stopped");
+    })
+}
@@ -0,0 +1,220 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::warn;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::secrets::{decrypt_sops, is_sops_encrypted};
+
+/// Time given to a change to complete, as editors and ConfigMap updates
+/// touch files in several steps.
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// A named address, with the same settings as `--named-address`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NamedAddressConfig {
+    pub name: String,
+    pub pubkey: String,
+    pub owner: Option<String>,
+    pub size: Option<u64>,
+    /// Seconds between checks, when it differs from `check-interval-secs`.
+    pub interval: Option<u64>,
+}
+
+/// A program-accounts scan, with the same settings as `--program-accounts`.
+/// Filters keep their command line syntax, `b58:OFFSET:BYTES` or `size:N`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProgramAccountsConfig {
+    pub name: String,
+    pub program: String,
+    #[serde(default)]
+    pub filters: Vec<String>,
+    pub weight: Option<u32>,
+    pub cost: Option<u32>,
+    /// Seconds between scans, when it differs from `check-interval-secs`.
+    pub interval: Option<u64>,
+    /// Whether to push changes with `programSubscribe` rather than scan.
+    pub subscribe: Option<bool>,
+}
+
+impl ProgramAccountsConfig {
+    /// The scan in the `--program-accounts` syntax, to describe it and tell
+    /// whether it changed. Names containing spaces or `=` do not parse back.
+    pub fn to_arg(&self) -> String {
+        let mut arg = format!("{}={}", self.name, self.program);
+        for filter in &self.filters {
+            arg.push_str(&format!(" {filter}"));
+        }
+        if let Some(weight) = self.weight {
+            arg.push_str(&format!(" weight:{weight}"));
+        }
+        if let Some(cost) = self.cost {
+            arg.push_str(&format!(" cost:{cost}"));
+        }
+        if let Some(interval) = self.interval {
+            arg.push_str(&format!(" interval:{interval}"));
+        }
+        if let Some(subscribe) = self.subscribe {
+            arg.push_str(&format!(" subscribe:{subscribe}"));
+        }
+        arg
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RpcConfig {
+    /// Secrets such as `file:/run/secrets/rpc-url` are resolved like on the
+    /// command line.
+    #[serde(default)]
+    pub urls: Vec<String>,
+    pub rate_limit: Option<f64>,
+    pub rate_limit_burst: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub port: Option<u16>,
+}
+
+/// Watcher configuration read with `--config`, as an alternative to long
+/// lists of command line flags. Flags given on the command line take
+/// precedence, and list flags are appended to the lists from the file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ConfigFile {
+    #[serde(default)]
+    pub rpc: RpcConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    /// Interval between checks of every watcher, like `--check-interval`.
+    pub check_interval_secs: Option<u64>,
+    #[serde(default)]
+    pub named_addresses: Vec<NamedAddressConfig>,
+    #[serde(default)]
+    pub named_addresses_files: Vec<String>,
+    #[serde(default)]
+    pub program_accounts: Vec<ProgramAccountsConfig>,
+}
+
+impl ConfigFile {
+    /// Reads a TOML file, or a YAML file when its extension is `.yaml` or
+    /// `.yml`. SOPS-encrypted YAML files are decrypted with `sops`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("Cannot read config '{}': {err}", path.display()))?;
+        let config = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml" | "yml") => parse_yaml(&content, path),
+            _ => toml::from_str(&content).map_err(anyhow::Error::from),
+        };
+        config.map_err(|err| anyhow::anyhow!("Cannot parse config '{}': {err}", path.display()))
+    }
+}
+
+fn parse_yaml(content: &str, path: &Path) -> anyhow::Result<ConfigFile> {
+    let mut document = yaml_document(content)?;
+    if document.as_ref().is_some_and(is_sops_encrypted) {
+        document = yaml_document(&decrypt_sops(path)?)?;
+    }
+    match document {
+        Some(document) => Ok(serde_json::from_value(yaml_to_json(document)?)?),
+        None => Ok(ConfigFile::default()),
+    }
+}
+
+fn yaml_document(content: &str) -> anyhow::Result<Option<yaml_rust::Yaml>> {
+    let mut documents = yaml_rust::YamlLoader::load_from_str(content)?;
+    anyhow::ensure!(documents.len() <= 1, "Expected a single YAML document");
+    Ok(documents.pop())
+}
+
+/// Converts YAML to JSON so that the same serde structs read both.
+fn yaml_to_json(yaml: yaml_rust::Yaml) -> anyhow::Result<serde_json::Value> {
+    use serde_json::Value;
+    use yaml_rust::Yaml;
+
+    Ok(match yaml {
+        Yaml::Null => Value::Null,
+        Yaml::Boolean(value) => Value::Bool(value),
+        Yaml::Integer(value) => Value::from(value),
+        Yaml::Real(value) => Value::from(value.parse::<f64>()?),
+        Yaml::String(value) => Value::String(value),
+        Yaml::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(yaml_to_json)
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Yaml::Hash(entries) => Value::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| {
+                    let Yaml::String(key) = key else {
+                        anyhow::bail!("Only string keys are supported, got {key:?}");
+                    };
+                    Ok((key, yaml_to_json(value)?))
+                })
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Yaml::Alias(_) | Yaml::BadValue => anyhow::bail!("Unsupported YAML value"),
+    })
+}
+
+/// Notices changes to the content of a config file. The directory is watched
+/// rather than the file itself, so that files replaced by a rename or a
+/// symlink swap, as in Kubernetes ConfigMap volumes, keep being noticed.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    content: Option<Vec<u8>>,
+    events: mpsc::UnboundedReceiver<()>,
+    _watcher: RecommendedWatcher,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &Path) -> anyhow::Result<Self> {
+        let (sender, events) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(_) => {
+                    let _ = sender.send(());
+                }
+                Err(err) => warn!("Failed to watch config: {err}"),
+            })?;
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        watcher.watch(directory, RecursiveMode::NonRecursive)?;
+        Ok(ConfigWatcher {
+            path: path.to_path_buf(),
+            content: std::fs::read(path).ok(),
+            events,
+            _watcher: watcher,
+        })
+    }
+
+    /// Waits until the content of the file differs from when it was last
+    /// seen. A missing file is not a change, so that it can be replaced.
+    pub async fn changed(&mut self) {
+        loop {
+            if self.events.recv().await.is_none() {
+                return std::future::pending().await;
+            }
+            tokio::time::sleep(WATCH_DEBOUNCE).await;
+            while self.events.try_recv().is_ok() {}
+
+            let content = tokio::fs::read(&self.path).await.ok();
+            if content.is_some() && content != self.content {
+                self.content = content;
+                return;
+            }
+        }
+    }
+}
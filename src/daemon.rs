@@ -0,0 +1,17 @@
+use std::path::Path;
+
+use daemonize::Daemonize;
+
+/// Detaches from the terminal and continues in the background, writing the
+/// PID of the daemon to `pid_file`. Standard streams are redirected to
+/// `/dev/null` and the working directory is kept, so relative paths given on
+/// the command line still resolve. Must be called before any threads are
+/// started, i.e. before the async runtime is built.
+pub fn daemonize(pid_file: Option<&Path>) -> anyhow::Result<()> {
+    let mut daemon = Daemonize::new().working_directory(std::env::current_dir()?);
+    if let Some(pid_file) = pid_file {
+        daemon = daemon.pid_file(pid_file);
+    }
+    daemon.start()?;
+    Ok(())
+}
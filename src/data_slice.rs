@@ -0,0 +1,88 @@
+use solana_account_decoder::UiDataSliceConfig;
+use solana_sdk::pubkey::Pubkey;
+
+/// Byte range of a single field within an account's data.
+#[derive(Debug, Clone, Copy)]
+pub struct Field {
+    pub offset: usize,
+    pub length: usize,
+}
+
+impl Field {
+    pub const fn new(offset: usize, length: usize) -> Self {
+        Self { offset, length }
+    }
+
+    /// Reads the field from full, unsliced account data.
+    pub fn read_u64(&self, data: &[u8]) -> Option<u64> {
+        let bytes = data.get(self.offset..self.offset + self.length)?;
+        Some(u64::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    /// Reads the field from full, unsliced account data.
+    pub fn read_pubkey(&self, data: &[u8]) -> Option<Pubkey> {
+        let bytes = data.get(self.offset..self.offset + self.length)?;
+        Some(Pubkey::new_from_array(bytes.try_into().ok()?))
+    }
+}
+
+/// SPL token account: `mint (32) | owner (32) | amount (u64) | ...`.
+pub const TOKEN_AMOUNT: Field = Field::new(64, 8);
+
+/// Stake account (`StakeStateV2`): `tag (u32) | meta (120) | delegation | ...`.
+pub const STAKE_STATE_TAG: Field = Field::new(0, 4);
+pub const STAKE_DELEGATION_VOTER: Field = Field::new(124, 32);
+pub const STAKE_DELEGATION_AMOUNT: Field = Field::new(156, 8);
+pub const STAKE_ACTIVATION_EPOCH: Field = Field::new(164, 8);
+pub const STAKE_DEACTIVATION_EPOCH: Field = Field::new(172, 8);
+
+/// Smallest contiguous slice of account data covering all `fields`, empty
+/// without any.
+pub fn covering_slice<'a>(fields: impl IntoIterator<Item = &'a Field>) -> UiDataSliceConfig {
+    let (start, end) = fields
+        .into_iter()
+        .map(|f| (f.offset, f.offset + f.length))
+        .reduce(|(start, end), (offset, field_end)| (start.min(offset), end.max(field_end)))
+        .unwrap_or((0, 0));
+    UiDataSliceConfig {
+        offset: start,
+        length: end - start,
+    }
+}
+
+/// Kinds of accounts watchers fetch, each decoding a known set of fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountType {
+    /// Only lamports are needed, no account data at all.
+    Lamports,
+    Token,
+    Stake,
+}
+
+impl AccountType {
+    pub fn fields(&self) -> &'static [Field] {
+        match self {
+            AccountType::Lamports => &[],
+            AccountType::Token => &[TOKEN_AMOUNT],
+            AccountType::Stake => &[
+                STAKE_STATE_TAG,
+                STAKE_DELEGATION_VOTER,
+                STAKE_DELEGATION_AMOUNT,
+                STAKE_ACTIVATION_EPOCH,
+                STAKE_DEACTIVATION_EPOCH,
+            ],
+        }
+    }
+
+    /// Smallest contiguous slice of account data covering every decoded field,
+    /// so that large scans only transfer the bytes that are actually read.
+    pub fn data_slice(&self) -> UiDataSliceConfig {
+        covering_slice(self.fields())
+    }
+
+    /// Reads `field` from account data fetched with [`AccountType::data_slice`].
+    pub fn read<'a>(&self, data: &'a [u8], field: Field) -> Option<&'a [u8]> {
+        let start = field.offset.checked_sub(self.data_slice().offset)?;
+        data.get(start..start + field.length)
+    }
+}
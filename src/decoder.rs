@@ -0,0 +1,297 @@
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+use log::{error, info};
+use solana_account_decoder::UiDataSliceConfig;
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcAccountInfoConfig};
+use solana_sdk::pubkey::Pubkey;
+use tokio::task::JoinHandle;
+
+use crate::{
+    data_slice::Field,
+    health::{record_failed_check, record_successful_check},
+    intervals::{backoff_duration, check_interval, DEFAULT_CHECK_INTERVAL},
+    metrics::{
+        reset_metric_decoded_account_field, update_metric_decoded_account_field,
+        update_metric_watcher_info,
+    },
+    rate_limit::RateLimiter,
+    shutdown::sleep_unless_shutdown,
+};
+
+pub const WATCHER_NAME: &str = "decoder";
+
+/// Little-endian primitive stored at a fixed offset in account data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F32,
+    F64,
+}
+
+impl FromStr for FieldType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "bool" => FieldType::Bool,
+            "u8" => FieldType::U8,
+            "u16" => FieldType::U16,
+            "u32" => FieldType::U32,
+            "u64" => FieldType::U64,
+            "u128" => FieldType::U128,
+            "i8" => FieldType::I8,
+            "i16" => FieldType::I16,
+            "i32" => FieldType::I32,
+            "i64" => FieldType::I64,
+            "i128" => FieldType::I128,
+            "f32" => FieldType::F32,
+            "f64" => FieldType::F64,
+            _ => anyhow::bail!("Unsupported field type '{s}'"),
+        })
+    }
+}
+
+impl FieldType {
+    pub fn size(&self) -> usize {
+        match self {
+            FieldType::Bool | FieldType::U8 | FieldType::I8 => 1,
+            FieldType::U16 | FieldType::I16 => 2,
+            FieldType::U32 | FieldType::I32 | FieldType::F32 => 4,
+            FieldType::U64 | FieldType::I64 | FieldType::F64 => 8,
+            FieldType::U128 | FieldType::I128 => 16,
+        }
+    }
+
+    /// Decodes `bytes`, exactly [`FieldType::size`] long, as a float. Integers
+    /// wider than 53 bits lose precision.
+    fn decode(&self, bytes: &[u8]) -> f64 {
+        macro_rules! le {
+            ($t:ty) => {
+                <$t>::from_le_bytes(bytes.try_into().unwrap()) as f64
+            };
+        }
+        match self {
+            FieldType::Bool => f64::from(u8::from(bytes[0] != 0)),
+            FieldType::U8 => le!(u8),
+            FieldType::U16 => le!(u16),
+            FieldType::U32 => le!(u32),
+            FieldType::U64 => le!(u64),
+            FieldType::U128 => le!(u128),
+            FieldType::I8 => le!(i8),
+            FieldType::I16 => le!(i16),
+            FieldType::I32 => le!(i32),
+            FieldType::I64 => le!(i64),
+            FieldType::I128 => le!(i128),
+            FieldType::F32 => le!(f32),
+            FieldType::F64 => le!(f64),
+        }
+    }
+}
+
+/// A named field of a [`Decoder`], `field:offset:type[:scale]`. The decoded
+/// value is multiplied by `scale`, e.g. `0.000001` for amounts with 6
+/// decimals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedField {
+    pub name: String,
+    pub offset: usize,
+    pub field_type: FieldType,
+    pub scale: f64,
+}
+
+impl FromStr for DecodedField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<_> = s.split(':').collect();
+        let [name, offset, field_type, rest @ ..] = parts.as_slice() else {
+            anyhow::bail!("Cannot parse field '{s}', expected syntax: field:offset:type[:scale]");
+        };
+        let scale = match rest {
+            [] => 1.0,
+            [scale] => scale.parse()?,
+            _ => anyhow::bail!(
+                "Cannot parse field '{s}', expected syntax: field:offset:type[:scale]"
+            ),
+        };
+        Ok(DecodedField {
+            name: name.to_string(),
+            offset: offset.parse()?,
+            field_type: field_type.parse()?,
+            scale,
+        })
+    }
+}
+
+impl DecodedField {
+    fn field(&self) -> Field {
+        Field::new(self.offset, self.field_type.size())
+    }
+}
+
+/// Declarative decoder of bespoke program state, `name=field:offset:type[:scale],...`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoder {
+    pub name: String,
+    pub fields: Vec<DecodedField>,
+}
+
+impl FromStr for Decoder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, fields)) = s.split_once('=') else {
+            anyhow::bail!(
+                "Cannot parse decoder, expected syntax: name=field:offset:type[:scale],..."
+            );
+        };
+        let fields = fields
+            .split(',')
+            .map(str::parse)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        anyhow::ensure!(!fields.is_empty(), "Decoder '{name}' has no fields");
+        Ok(Decoder {
+            name: name.to_string(),
+            fields,
+        })
+    }
+}
+
+/// A watched account whose data is decoded by a [`Decoder`].
+#[derive(Debug, Clone)]
+pub struct DecodedAccount {
+    pub name: String,
+    pub pubkey: Pubkey,
+    pub decoder: Arc<Decoder>,
+}
+
+impl DecodedAccount {
+    /// Parses `name=pubkey decoder:NAME`, looking the decoder up in `decoders`.
+    pub fn parse(s: &str, decoders: &HashMap<String, Arc<Decoder>>) -> anyhow::Result<Self> {
+        let Some((name, params)) = s.split_once('=') else {
+            anyhow::bail!(
+                "Cannot parse decoded account, expected syntax: name=pubkey decoder:NAME"
+            );
+        };
+        let mut params = params.split(' ').filter(|param| !param.is_empty());
+        let pubkey = params.next().unwrap_or_default();
+        let Ok(pubkey) = Pubkey::from_str(pubkey) else {
+            anyhow::bail!("Failed to parse decoded account pubkey from '{pubkey}'");
+        };
+        let mut decoder = None;
+        for param in params {
+            match param.split_once(':') {
+                Some(("decoder", value)) => match decoders.get(value) {
+                    Some(found) => decoder = Some(found.clone()),
+                    None => anyhow::bail!("Unknown decoder '{value}' of account '{name}'"),
+                },
+                _ => anyhow::bail!("Unsupported parameter '{param}' of decoded account '{name}'"),
+            }
+        }
+        let Some(decoder) = decoder else {
+            anyhow::bail!("Decoded account '{name}' requires a decoder:NAME parameter");
+        };
+        Ok(DecodedAccount {
+            name: name.to_string(),
+            pubkey,
+            decoder,
+        })
+    }
+}
+
+/// Smallest slice of account data covering the fields of every decoder.
+fn data_slice(accounts: &[DecodedAccount]) -> UiDataSliceConfig {
+    let fields = accounts
+        .iter()
+        .flat_map(|account| &account.decoder.fields)
+        .map(DecodedField::field);
+    let start = fields.clone().map(|f| f.offset).min().unwrap_or(0);
+    let end = fields.map(|f| f.offset + f.length).max().unwrap_or(0);
+    UiDataSliceConfig {
+        offset: start,
+        length: end - start,
+    }
+}
+
+/// Exports the fields decoded from each of `accounts` as
+/// `decoded_account_field` gauges.
+pub fn spawn_decoded_account_watcher(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    accounts: Vec<DecodedAccount>,
+) -> JoinHandle<()> {
+    update_metric_watcher_info(
+        WATCHER_NAME,
+        WATCHER_NAME,
+        "",
+        check_interval(DEFAULT_CHECK_INTERVAL),
+    );
+    tokio::spawn(async move {
+        info!("Watching decoded accounts: {accounts:?}");
+        let pubkeys: Vec<_> = accounts.iter().map(|account| account.pubkey).collect();
+        let slice = data_slice(&accounts);
+        loop {
+            rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+            let response = rpc_client
+                .get_multiple_accounts_with_config(
+                    &pubkeys,
+                    RpcAccountInfoConfig {
+                        data_slice: Some(slice),
+                        ..Default::default()
+                    },
+                )
+                .await;
+            let response = match response {
+                Ok(response) => response,
+                Err(err) => {
+                    error!("Failed to get RPC response: {err}");
+                    record_failed_check(WATCHER_NAME, &err.to_string());
+                    reset_metric_decoded_account_field();
+                    if !sleep_unless_shutdown(backoff_duration(WATCHER_NAME)).await {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            for (account, data) in accounts.iter().zip(response.value) {
+                let Some(data) = data.map(|data| data.data) else {
+                    error!(
+                        "Account {} ({}) does not exist",
+                        account.name, account.pubkey
+                    );
+                    continue;
+                };
+                let pubkey = account.pubkey.to_string();
+                for field in &account.decoder.fields {
+                    let start = field.offset - slice.offset;
+                    let Some(bytes) = data.get(start..start + field.field_type.size()) else {
+                        error!(
+                            "Account {} ({pubkey}) is too short for field '{}'",
+                            account.name, field.name
+                        );
+                        continue;
+                    };
+                    let value = field.field_type.decode(bytes) * field.scale;
+                    update_metric_decoded_account_field(&account.name, &pubkey, &field.name, value);
+                }
+            }
+            record_successful_check(WATCHER_NAME);
+
+            if !sleep_unless_shutdown(check_interval(DEFAULT_CHECK_INTERVAL)).await {
+                break;
+            }
+        }
+        info!("Decoded account watcher stopped");
+    })
+}
@@ -0,0 +1,26 @@
+use solana_sdk::{pubkey, pubkey::Pubkey};
+
+pub const SPL_TOKEN_PROGRAM_ID: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
+    pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+/// Parses a token program given as `spl-token`, `spl-token-2022` or its
+/// program id.
+pub fn parse_token_program(s: &str) -> anyhow::Result<Pubkey> {
+    Ok(match s {
+        "spl-token" => SPL_TOKEN_PROGRAM_ID,
+        "spl-token-2022" => spl_token_2022::id(),
+        _ => s.parse().map_err(|err| {
+            anyhow::anyhow!(
+                "Unsupported token program '{s}', expected spl-token, spl-token-2022 or a pubkey: {err}"
+            )
+        })?,
+    })
+}
+
+/// Address of the associated token account of `owner` for `mint`, which
+/// depends on the token program that owns the mint.
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    let seeds = [owner.as_ref(), token_program.as_ref(), mint.as_ref()];
+    Pubkey::find_program_address(&seeds, &ASSOCIATED_TOKEN_PROGRAM_ID).0
+}
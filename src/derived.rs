@@ -0,0 +1,175 @@
+use std::{
+    collections::BTreeSet,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::{error, info};
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcAccountInfoConfig};
+use solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey};
+use tokio::{sync::watch, task::JoinHandle};
+
+use crate::{
+    check::MAX_ACCOUNTS_PER_REQUEST,
+    data_slice::AccountType,
+    health::{record_failed_check, record_successful_check},
+    intervals::backoff_duration,
+    metrics::{remove_metric_balance_sol, update_metric_balance_sol, update_metric_watcher_info},
+    observations::{publish_observation, Observation},
+    program_accounts_balance::{get_program_accounts, ProgramAccountsBalanceConfig},
+    rate_limit::RateLimiter,
+    shutdown::sleep_unless_shutdown,
+};
+
+/// Latest set of pubkeys produced by an upstream watcher.
+pub type PubkeySet = watch::Receiver<Arc<BTreeSet<Pubkey>>>;
+
+/// Discovers the accounts matching `config` every `interval`, publishing the
+/// set of their pubkeys whenever it changes. Downstream watchers only wake up
+/// for actual changes.
+pub fn spawn_program_accounts_discovery(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    config: ProgramAccountsBalanceConfig,
+    interval: Duration,
+) -> (JoinHandle<()>, PubkeySet) {
+    update_metric_watcher_info(
+        &format!("{}/discovery", config.name()),
+        "program_accounts_discovery",
+        &config.program().to_string(),
+        interval,
+    );
+    let (sender, receiver) = watch::channel(Arc::new(BTreeSet::new()));
+    let handle = tokio::spawn(async move {
+        let watcher = format!("{}/discovery", config.name());
+        // The first set is published even if empty, for downstream watchers
+        // to start checking.
+        let mut published = false;
+        loop {
+            let (weight, cost) = config.rate_limit();
+            rate_limiter.acquire(&watcher, weight, cost).await;
+            match get_program_accounts(&rpc_client, &config, None).await {
+                Ok(accounts) => {
+                    let pubkeys: BTreeSet<_> =
+                        accounts.into_iter().map(|(pubkey, _)| pubkey).collect();
+                    sender.send_if_modified(|current| {
+                        if published && **current == pubkeys {
+                            return false;
+                        }
+                        info!(
+                            "Discovered {} accounts for '{}'",
+                            pubkeys.len(),
+                            config.name()
+                        );
+                        *current = Arc::new(pubkeys);
+                        true
+                    });
+                    published = true;
+                    record_successful_check(&watcher);
+                }
+                Err(err) => {
+                    error!("Failed to discover accounts for '{}': {err}", config.name());
+                    record_failed_check(&watcher, &err.to_string());
+                    if !sleep_unless_shutdown(backoff_duration(&watcher)).await {
+                        break;
+                    }
+                    continue;
+                }
+            }
+
+            if !sleep_unless_shutdown(interval).await {
+                break;
+            }
+        }
+        info!("Stopped discovering accounts for '{}'", config.name());
+    });
+    (handle, receiver)
+}
+
+/// Watches the balance of every account in the set produced upstream,
+/// exported under `name`. When the set changes, balances of accounts that
+/// left it are removed and the new set is checked right away.
+pub fn spawn_derived_balance_watcher(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    name: String,
+    mut pubkey_set: PubkeySet,
+    check_interval: Duration,
+) -> JoinHandle<()> {
+    update_metric_watcher_info(&name, "derived_balance", "", check_interval);
+    tokio::spawn(async move {
+        info!("Watching balances of accounts derived for '{name}'");
+        // Nothing to check until the upstream set was produced once.
+        if pubkey_set.changed().await.is_err() {
+            return;
+        }
+        let mut watched = Arc::new(BTreeSet::new());
+        loop {
+            let current = pubkey_set.borrow_and_update().clone();
+            for pubkey in watched.difference(&current) {
+                remove_metric_balance_sol(&name, &pubkey.to_string());
+            }
+            watched = current;
+
+            let pubkeys: Vec<_> = watched.iter().copied().collect();
+            let mut complete = true;
+            for chunk in pubkeys.chunks(MAX_ACCOUNTS_PER_REQUEST) {
+                rate_limiter.acquire(&name, 1, 1).await;
+                let start = Instant::now();
+                let response = rpc_client
+                    .get_multiple_accounts_with_config(
+                        chunk,
+                        RpcAccountInfoConfig {
+                            data_slice: Some(AccountType::Lamports.data_slice()),
+                            ..Default::default()
+                        },
+                    )
+                    .await;
+                let response = match response {
+                    Ok(response) => response,
+                    Err(err) => {
+                        error!("Failed to get RPC response: {err}");
+                        record_failed_check(&name, &err.to_string());
+                        complete = false;
+                        break;
+                    }
+                };
+                let duration = start.elapsed();
+                for (pubkey, account) in chunk.iter().zip(response.value) {
+                    let lamports = account.map(|a| a.lamports).unwrap_or(0);
+                    update_metric_balance_sol(
+                        &name,
+                        &pubkey.to_string(),
+                        lamports_to_sol(lamports),
+                    );
+                    publish_observation(Observation {
+                        watcher: name.clone(),
+                        name: name.clone(),
+                        pubkey: Some(*pubkey),
+                        lamports,
+                        slot: Some(response.context.slot),
+                        duration,
+                        observed_at: SystemTime::now(),
+                    });
+                }
+            }
+            if complete {
+                record_successful_check(&name);
+            }
+
+            let interval = match complete {
+                true => check_interval,
+                false => backoff_duration(&name),
+            };
+            tokio::select! {
+                keep_running = sleep_unless_shutdown(interval) => if !keep_running {
+                    break;
+                },
+                changed = pubkey_set.changed() => if changed.is_err() {
+                    break;
+                },
+            }
+        }
+        info!("Stopped watching balances of accounts derived for '{name}'");
+    })
+}
@@ -0,0 +1,145 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{error, info};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::native_token::lamports_to_sol;
+use tokio::task::JoinHandle;
+
+use crate::{
+    health::{record_failed_check, record_successful_check},
+    intervals::backoff_duration,
+    metrics::{
+        update_metric_epoch_info, update_metric_epoch_start_balance_sol,
+        update_metric_epoch_start_total_balance_sol, update_metric_snapshot_epoch,
+        update_metric_watcher_info,
+    },
+    rate_limit::RateLimiter,
+    shutdown::sleep_unless_shutdown,
+    snapshot::Snapshotter,
+};
+
+pub const WATCHER_NAME: &str = "epoch";
+pub const SNAPSHOTTER_WATCHER_NAME: &str = "epoch_snapshots";
+
+/// Epochs last about two days, so a rollover is noticed within a small
+/// fraction of its first slots.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Exports the current epoch, the slot within it, its length and progress,
+/// as `epoch`, `slot_index`, `slots_in_epoch` and `epoch_progress_ratio`.
+/// Not needed along with the epoch snapshotter, which exports them too.
+pub fn spawn_epoch_info_watcher(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+) -> JoinHandle<()> {
+    update_metric_watcher_info(WATCHER_NAME, "epoch_info", "", POLL_INTERVAL);
+    tokio::spawn(async move {
+        loop {
+            rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+            match rpc_client.get_epoch_info().await {
+                Ok(epoch_info) => {
+                    update_metric_epoch_info(&epoch_info);
+                    record_successful_check(WATCHER_NAME);
+                }
+                Err(err) => {
+                    error!("Failed to get epoch info: {err}");
+                    record_failed_check(WATCHER_NAME, &err.to_string());
+                    if !sleep_unless_shutdown(backoff_duration(WATCHER_NAME)).await {
+                        break;
+                    }
+                    continue;
+                }
+            }
+
+            if !sleep_unless_shutdown(POLL_INTERVAL).await {
+                break;
+            }
+        }
+        info!("Epoch info watcher stopped");
+    })
+}
+
+/// Snapshots the named addresses and program-accounts totals as soon as a new
+/// epoch starts, exporting them as `epoch_start_balance_sol` and
+/// `epoch_start_total_balance_sol` along with the epoch they belong to. The
+/// epoch the watcher starts in is not snapshotted, as its start is long past.
+/// Exports the same epoch info as the epoch info watcher.
+pub fn spawn_epoch_snapshotter(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    snapshotter: Arc<Snapshotter>,
+) -> JoinHandle<()> {
+    update_metric_watcher_info(
+        SNAPSHOTTER_WATCHER_NAME,
+        "epoch_snapshots",
+        "",
+        POLL_INTERVAL,
+    );
+    tokio::spawn(async move {
+        let mut current_epoch = None;
+        // Epoch whose snapshot is still to be taken, kept across failures.
+        let mut pending_epoch = None;
+        loop {
+            rate_limiter.acquire(SNAPSHOTTER_WATCHER_NAME, 1, 1).await;
+            match rpc_client.get_epoch_info().await {
+                Ok(epoch_info) => {
+                    update_metric_epoch_info(&epoch_info);
+                    if current_epoch.is_some_and(|epoch| epoch < epoch_info.epoch) {
+                        info!("Epoch {} started", epoch_info.epoch);
+                        pending_epoch = Some(epoch_info.epoch);
+                    }
+                    current_epoch = Some(epoch_info.epoch);
+                }
+                Err(err) => {
+                    error!("Failed to get epoch info: {err}");
+                    record_failed_check(SNAPSHOTTER_WATCHER_NAME, &err.to_string());
+                    if !sleep_unless_shutdown(backoff_duration(SNAPSHOTTER_WATCHER_NAME)).await {
+                        break;
+                    }
+                    continue;
+                }
+            }
+
+            if let Some(epoch) = pending_epoch {
+                match snapshotter.snapshot().await {
+                    Ok(snapshot) => {
+                        for balance in &snapshot.balances {
+                            update_metric_epoch_start_balance_sol(
+                                &balance.name,
+                                &balance.pubkey.to_string(),
+                                lamports_to_sol(balance.lamports),
+                            );
+                        }
+                        for total in &snapshot.totals {
+                            update_metric_epoch_start_total_balance_sol(
+                                &total.name,
+                                lamports_to_sol(total.lamports),
+                            );
+                        }
+                        update_metric_snapshot_epoch(epoch);
+                        info!(
+                            "Epoch {epoch} start snapshot: {}",
+                            snapshot.to_attestation()
+                        );
+                        pending_epoch = None;
+                    }
+                    Err(err) => {
+                        error!("Failed to snapshot balances at the start of epoch {epoch}: {err}");
+                        record_failed_check(SNAPSHOTTER_WATCHER_NAME, &err.to_string());
+                        if !sleep_unless_shutdown(backoff_duration(SNAPSHOTTER_WATCHER_NAME)).await
+                        {
+                            break;
+                        }
+                        continue;
+                    }
+                }
+            }
+            record_successful_check(SNAPSHOTTER_WATCHER_NAME);
+
+            if !sleep_unless_shutdown(POLL_INTERVAL).await {
+                break;
+            }
+        }
+        info!("Epoch snapshotter stopped");
+    })
+}
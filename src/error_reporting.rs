@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use sentry::{ClientInitGuard, Level};
+
+/// Consecutive failed checks of a watcher after which it is reported.
+const REPEATED_FAILURES: u32 = 3;
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Starts reporting panics and errors to Sentry. Reports are sent until the
+/// returned guard is dropped.
+pub fn init_error_reporting(dsn: &str) -> anyhow::Result<ClientInitGuard> {
+    let guard = sentry::init(sentry::ClientOptions {
+        dsn: Some(dsn.parse()?),
+        release: sentry::release_name!(),
+        ..Default::default()
+    });
+    anyhow::ensure!(guard.is_enabled(), "Sentry client is disabled");
+    Ok(guard)
+}
+
+/// Waits for queued reports to be sent, for use before exiting the process
+/// without unwinding.
+pub fn flush_error_reports() {
+    if let Some(client) = sentry::Hub::current().client() {
+        client.flush(Some(FLUSH_TIMEOUT));
+    }
+}
+
+fn capture(level: Level, watcher: &str, message: &str) {
+    sentry::with_scope(
+        |scope| scope.set_tag("watcher", watcher),
+        || sentry::capture_message(message, level),
+    );
+}
+
+/// Reports a watcher once per streak of failed checks, when the streak
+/// reaches `REPEATED_FAILURES`.
+pub fn report_failed_check(watcher: &str, error: &str, consecutive_failures: u32) {
+    if consecutive_failures == REPEATED_FAILURES {
+        capture(
+            Level::Error,
+            watcher,
+            &format!("{consecutive_failures} consecutive checks failed: {error}"),
+        );
+    }
+}
+
+pub fn report_sink_error(sink: &str, error: &str) {
+    sentry::with_scope(
+        |scope| scope.set_tag("sink", sink),
+        || sentry::capture_message(&format!("Sink {sink} failed: {error}"), Level::Error),
+    );
+}
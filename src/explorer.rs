@@ -0,0 +1,134 @@
+use std::str::FromStr;
+
+use once_cell::sync::OnceCell;
+use solana_sdk::{hash::Hash, pubkey::Pubkey, signature::Signature};
+
+/// Cluster links point to, as explorers serve all clusters from one host.
+/// Also identifies the cluster RPC endpoints are expected to serve.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Cluster {
+    #[default]
+    MainnetBeta,
+    Devnet,
+    Testnet,
+}
+
+impl FromStr for Cluster {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "mainnet-beta" | "mainnet" => Cluster::MainnetBeta,
+            "devnet" => Cluster::Devnet,
+            "testnet" => Cluster::Testnet,
+            _ => {
+                anyhow::bail!("Unsupported cluster '{s}', expected mainnet-beta, devnet or testnet")
+            }
+        })
+    }
+}
+
+impl Cluster {
+    pub const ALL: [Cluster; 3] = [Cluster::MainnetBeta, Cluster::Devnet, Cluster::Testnet];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Cluster::MainnetBeta => "mainnet-beta",
+            Cluster::Devnet => "devnet",
+            Cluster::Testnet => "testnet",
+        }
+    }
+
+    /// Cluster with `genesis_hash`, unless it is a private one.
+    pub fn from_genesis_hash(genesis_hash: &Hash) -> Option<Cluster> {
+        Cluster::ALL
+            .into_iter()
+            .find(|cluster| cluster.genesis_hash() == *genesis_hash)
+    }
+
+    /// Rate-limited public endpoint of the Solana Foundation, only suited for
+    /// occasional lookups.
+    pub fn public_rpc_url(&self) -> &'static str {
+        match self {
+            Cluster::MainnetBeta => "https://api.mainnet-beta.solana.com",
+            Cluster::Devnet => "https://api.devnet.solana.com",
+            Cluster::Testnet => "https://api.testnet.solana.com",
+        }
+    }
+
+    pub fn genesis_hash(&self) -> Hash {
+        let hash = match self {
+            Cluster::MainnetBeta => "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d",
+            Cluster::Devnet => "EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG",
+            Cluster::Testnet => "4uhcVJyU9pJkvQyS88uRDiswHXSCkY3zQawwpjk2NsNY",
+        };
+        hash.parse().unwrap()
+    }
+}
+
+/// URL templates of an explorer, with `{pubkey}` or `{signature}` replaced
+/// when generating links.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explorer {
+    pub account_template: String,
+    pub transaction_template: String,
+}
+
+impl Explorer {
+    /// Templates of a well-known explorer: solscan, solanafm or xray.
+    pub fn preset(name: &str, cluster: Cluster) -> anyhow::Result<Self> {
+        let (base, query) = match (name, cluster) {
+            ("solscan", Cluster::MainnetBeta) => ("https://solscan.io", ""),
+            ("solscan", Cluster::Devnet) => ("https://solscan.io", "?cluster=devnet"),
+            ("solscan", Cluster::Testnet) => ("https://solscan.io", "?cluster=testnet"),
+            ("solanafm", Cluster::MainnetBeta) => ("https://solana.fm", "?cluster=mainnet-alpha"),
+            ("solanafm", Cluster::Devnet) => ("https://solana.fm", "?cluster=devnet-alpha"),
+            ("solanafm", Cluster::Testnet) => ("https://solana.fm", "?cluster=testnet-solana"),
+            ("xray", Cluster::MainnetBeta) => ("https://xray.helius.xyz", "?network=mainnet"),
+            ("xray", Cluster::Devnet) => ("https://xray.helius.xyz", "?network=devnet"),
+            ("xray", Cluster::Testnet) => anyhow::bail!("XRAY does not support testnet"),
+            _ => anyhow::bail!("Unsupported explorer '{name}', expected solscan, solanafm or xray"),
+        };
+        let account = if name == "solanafm" {
+            "address"
+        } else {
+            "account"
+        };
+        Ok(Explorer {
+            account_template: format!("{base}/{account}/{{pubkey}}{query}"),
+            transaction_template: format!("{base}/tx/{{signature}}{query}"),
+        })
+    }
+
+    pub fn account_url(&self, pubkey: &Pubkey) -> String {
+        self.account_template
+            .replace("{pubkey}", &pubkey.to_string())
+    }
+
+    pub fn transaction_url(&self, signature: &Signature) -> String {
+        self.transaction_template
+            .replace("{signature}", &signature.to_string())
+    }
+}
+
+static EXPLORER: OnceCell<Explorer> = OnceCell::new();
+
+/// Sets the explorer links are generated for. Can only be called once; no
+/// links are generated until then.
+pub fn set_explorer(explorer: Explorer) -> anyhow::Result<()> {
+    EXPLORER
+        .set(explorer)
+        .map_err(|_| anyhow::anyhow!("Explorer is already set"))
+}
+
+/// Link to `pubkey` on the configured explorer, if any.
+pub fn account_url(pubkey: &Pubkey) -> Option<String> {
+    EXPLORER.get().map(|explorer| explorer.account_url(pubkey))
+}
+
+/// Link to the transaction `signature` on the configured explorer, if any.
+pub fn transaction_url(signature: &Signature) -> Option<String> {
+    EXPLORER
+        .get()
+        .map(|explorer| explorer.transaction_url(signature))
+}
@@ -0,0 +1,181 @@
+//! C ABI for embedding the watcher in programs written in other languages,
+//! such as Go or Python supervisors. Build the shared library with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+//!
+//! The engine is started once per process with [`sbw_start`], given a
+//! configuration in the JSON form of the `--config` file, and balances are
+//! then read with [`sbw_poll_events`]. Strings returned by the library are
+//! owned by the caller and released with [`sbw_free_string`]. The matching
+//! declarations are in `include/sbw.h`.
+
+use std::{
+    ffi::{c_char, CStr, CString},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::Router;
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::warn;
+use once_cell::sync::OnceCell;
+use serde_json::{json, Value};
+use tokio::{
+    runtime::Runtime,
+    sync::broadcast::{self, error::TryRecvError},
+};
+
+use crate::{
+    config::ConfigFile,
+    intervals::{check_interval, set_check_interval, DEFAULT_CHECK_INTERVAL},
+    metrics::spawn_metrics_server,
+    observations::{subscribe_observations, Observation},
+    rate_limit::RateLimiter,
+    reload::{ReloadableWatchers, WatchListArgs},
+    rpc::{HttpClientConfig, RpcClientFactory},
+    secrets::resolve_secret,
+    shutdown::request_shutdown,
+};
+
+/// Recorded as the source of the watchers started through the C ABI.
+const AUDIT_SOURCE: &str = "ffi";
+
+struct Engine {
+    /// Runs the watchers in the background, between calls from the host.
+    _runtime: Runtime,
+    _watchers: ReloadableWatchers,
+    observations: Mutex<broadcast::Receiver<Observation>>,
+}
+
+static ENGINE: OnceCell<Engine> = OnceCell::new();
+
+fn start(config_json: &str) -> anyhow::Result<Engine> {
+    let config: ConfigFile = serde_json::from_str(config_json)
+        .map_err(|err| anyhow::anyhow!("Cannot parse config: {err}"))?;
+    anyhow::ensure!(
+        !config.rpc.urls.is_empty(),
+        "At least one RPC URL is required"
+    );
+    let urls = config
+        .rpc
+        .urls
+        .iter()
+        .map(|url| resolve_secret(url))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if let Some(secs) = config.check_interval_secs {
+        set_check_interval(Duration::from_secs(secs))?;
+    }
+    let watch_list = WatchListArgs::default().merged(&config).parse()?;
+
+    let runtime = Runtime::new()?;
+    // Subscribed before any watcher runs so that no observation is missed.
+    let observations = subscribe_observations();
+    let watchers = runtime.block_on(async {
+        let rpc_clients = RpcClientFactory::new(urls, &HttpClientConfig::default())?;
+        let rate_limiter = Arc::new(match config.rpc.rate_limit {
+            Some(rate) => RateLimiter::new(rate, config.rpc.rate_limit_burst.unwrap_or(rate)),
+            None => RateLimiter::unlimited(),
+        });
+        if let Some(port) = config.metrics.port {
+            spawn_metrics_server(port, Router::new());
+        }
+        let mut watchers = ReloadableWatchers::new(
+            rpc_clients,
+            rate_limiter,
+            check_interval(DEFAULT_CHECK_INTERVAL),
+        );
+        watchers.apply(AUDIT_SOURCE, watch_list).await;
+        anyhow::Ok(watchers)
+    })?;
+    Ok(Engine {
+        _runtime: runtime,
+        _watchers: watchers,
+        observations: Mutex::new(observations),
+    })
+}
+
+fn observation_json(observation: &Observation) -> Value {
+    json!({
+        "watcher": observation.watcher,
+        "name": observation.name,
+        "pubkey": observation.pubkey.map(|pubkey| pubkey.to_string()),
+        "lamports": observation.lamports,
+        "slot": observation.slot,
+        "observed_at": DateTime::<Utc>::from(observation.observed_at)
+            .to_rfc3339_opts(SecondsFormat::Millis, true),
+    })
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    // Messages and JSON never contain NUL bytes, except from malformed input.
+    CString::new(s.replace('\0', ""))
+        .expect("NUL bytes are removed")
+        .into_raw()
+}
+
+/// Starts watching the balances described by `config_json`, the JSON form of
+/// the `--config` file, on background threads. Returns NULL on success, or an
+/// error message to release with [`sbw_free_string`]. Only succeeds once
+/// per process.
+///
+/// # Safety
+///
+/// `config_json` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sbw_start(config_json: *const c_char) -> *mut c_char {
+    if config_json.is_null() {
+        return into_c_string("Config is NULL".to_string());
+    }
+    let config_json = match CStr::from_ptr(config_json).to_str() {
+        Ok(config_json) => config_json,
+        Err(err) => return into_c_string(format!("Config is not UTF-8: {err}")),
+    };
+    if ENGINE.get().is_some() {
+        return into_c_string("Already started".to_string());
+    }
+    match ENGINE.get_or_try_init(|| start(config_json)) {
+        Ok(_) => std::ptr::null_mut(),
+        Err(err) => into_c_string(err.to_string()),
+    }
+}
+
+/// Returns the balances observed since the previous call as a JSON array of
+/// `{"watcher", "name", "pubkey", "lamports", "slot", "observed_at"}`
+/// objects, to release with [`sbw_free_string`], or NULL if the engine was
+/// not started.
+#[no_mangle]
+pub extern "C" fn sbw_poll_events() -> *mut c_char {
+    let Some(engine) = ENGINE.get() else {
+        return std::ptr::null_mut();
+    };
+    let mut observations = engine.observations.lock().unwrap();
+    let mut events = vec![];
+    loop {
+        match observations.try_recv() {
+            Ok(observation) => events.push(observation_json(&observation)),
+            Err(TryRecvError::Lagged(skipped)) => {
+                warn!("Events were not polled in time, skipped {skipped} observations")
+            }
+            Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+        }
+    }
+    into_c_string(Value::Array(events).to_string())
+}
+
+/// Asks every watcher to stop. The engine cannot be started again.
+#[no_mangle]
+pub extern "C" fn sbw_stop() {
+    request_shutdown();
+}
+
+/// Releases a string returned by the library.
+///
+/// # Safety
+///
+/// `s` must be NULL or a string returned by the library that was not
+/// released yet.
+#[no_mangle]
+pub unsafe extern "C" fn sbw_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
@@ -0,0 +1,223 @@
+use std::{collections::BTreeSet, str::FromStr, sync::Arc, time::Duration};
+
+use log::{error, info};
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+    rpc_request::TokenAccountsFilter,
+};
+use solana_sdk::{pubkey, pubkey::Pubkey};
+use tokio::{sync::watch, task::JoinHandle};
+
+use crate::{
+    derive::SPL_TOKEN_PROGRAM_ID,
+    derived::PubkeySet,
+    health::{record_failed_check, record_successful_check},
+    intervals::backoff_duration,
+    metrics::update_metric_watcher_info,
+    rate_limit::RateLimiter,
+    shutdown::sleep_unless_shutdown,
+};
+
+pub const SPL_GOVERNANCE_PROGRAM_ID: Pubkey =
+    pubkey!("GovER5Lthms3bLBqWub97yVrMmEogzX7xNjdXpPPCVZw");
+
+/// Offset of the realm in governance accounts, after the account type.
+const REALM_OFFSET: usize = 1;
+/// `GovernanceAccountType` of account, program, mint and token governances,
+/// V1 and V2. Token owner records and realm configs of the realm carry it at
+/// the same offset.
+const GOVERNANCE_ACCOUNT_TYPES: [u8; 8] = [3, 4, 9, 10, 18, 19, 20, 21];
+
+/// A realm whose treasuries are watched, given as `name=REALM`, optionally
+/// followed by `program:PROGRAM` for realms of another deployment of the
+/// SPL Governance program.
+#[derive(Debug, Clone)]
+pub struct GovernanceRealm {
+    pub name: String,
+    pub realm: Pubkey,
+    pub program: Pubkey,
+}
+
+impl FromStr for GovernanceRealm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, params)) = s.split_once('=') else {
+            anyhow::bail!("Cannot parse governance realm '{s}', expected syntax: name=REALM");
+        };
+        let mut params = params.split(' ').filter(|param| !param.is_empty());
+        let realm = params.next().unwrap_or_default();
+        let realm = Pubkey::from_str(realm)
+            .map_err(|err| anyhow::anyhow!("Cannot parse realm '{realm}' of '{name}': {err}"))?;
+        let mut program = SPL_GOVERNANCE_PROGRAM_ID;
+        for param in params {
+            match param.split_once(':') {
+                Some(("program", value)) => program = Pubkey::from_str(value)?,
+                _ => anyhow::bail!("Unsupported parameter '{param}' of realm '{name}'"),
+            }
+        }
+        Ok(GovernanceRealm {
+            name: name.to_string(),
+            realm,
+            program,
+        })
+    }
+}
+
+/// Treasuries of the governances of a realm.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Treasuries {
+    /// Native treasuries holding SOL, of governances that created one.
+    native: BTreeSet<Pubkey>,
+    /// SPL Token and Token-2022 accounts owned by a governance or its native
+    /// treasury.
+    token_accounts: BTreeSet<Pubkey>,
+}
+
+async fn get_governances(
+    rpc_client: &RpcClient,
+    realm: &GovernanceRealm,
+) -> anyhow::Result<Vec<Pubkey>> {
+    let accounts = rpc_client
+        .get_program_accounts_with_config(
+            &realm.program,
+            RpcProgramAccountsConfig {
+                filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                    REALM_OFFSET,
+                    realm.realm.to_bytes().to_vec(),
+                ))]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    data_slice: Some(UiDataSliceConfig {
+                        offset: 0,
+                        length: 1,
+                    }),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await?;
+    Ok(accounts
+        .into_iter()
+        .filter(|(_, account)| {
+            account
+                .data
+                .first()
+                .is_some_and(|account_type| GOVERNANCE_ACCOUNT_TYPES.contains(account_type))
+        })
+        .map(|(pubkey, _)| pubkey)
+        .collect())
+}
+
+async fn discover_treasuries(
+    rpc_client: &RpcClient,
+    rate_limiter: &RateLimiter,
+    watcher: &str,
+    realm: &GovernanceRealm,
+) -> anyhow::Result<Treasuries> {
+    rate_limiter.acquire(watcher, 1, 1).await;
+    let governances = get_governances(rpc_client, realm).await?;
+    let native_treasuries: Vec<_> = governances
+        .iter()
+        .map(|governance| {
+            let seeds: [&[u8]; 2] = [b"native-treasury", governance.as_ref()];
+            Pubkey::find_program_address(&seeds, &realm.program).0
+        })
+        .collect();
+    rate_limiter.acquire(watcher, 1, 1).await;
+    let accounts = rpc_client.get_multiple_accounts(&native_treasuries).await?;
+    let native: BTreeSet<_> = native_treasuries
+        .iter()
+        .zip(accounts)
+        .filter(|(_, account)| account.is_some())
+        .map(|(pubkey, _)| *pubkey)
+        .collect();
+
+    let mut token_accounts = BTreeSet::new();
+    for owner in governances.iter().chain(&native) {
+        for token_program in [SPL_TOKEN_PROGRAM_ID, spl_token_2022::id()] {
+            rate_limiter.acquire(watcher, 1, 1).await;
+            let accounts = rpc_client
+                .get_token_accounts_by_owner(owner, TokenAccountsFilter::ProgramId(token_program))
+                .await?;
+            for account in accounts {
+                token_accounts.insert(account.pubkey.parse()?);
+            }
+        }
+    }
+    Ok(Treasuries {
+        native,
+        token_accounts,
+    })
+}
+
+/// Discovers the treasuries of every governance of `realm` every `interval`,
+/// publishing the native treasuries and the token accounts whenever they
+/// change, for the derived balance watchers to watch under the realm's name.
+pub fn spawn_governance_discovery(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    realm: GovernanceRealm,
+    interval: Duration,
+) -> (JoinHandle<()>, PubkeySet, PubkeySet) {
+    let watcher = format!("{}/discovery", realm.name);
+    update_metric_watcher_info(
+        &watcher,
+        "governance_discovery",
+        &realm.program.to_string(),
+        interval,
+    );
+    let (native_sender, native_receiver) = watch::channel(Arc::new(BTreeSet::new()));
+    let (token_sender, token_receiver) = watch::channel(Arc::new(BTreeSet::new()));
+    let handle = tokio::spawn(async move {
+        // The first sets are published even if empty, for the derived balance
+        // watchers to start checking.
+        let mut published = false;
+        loop {
+            match discover_treasuries(&rpc_client, &rate_limiter, &watcher, &realm).await {
+                Ok(treasuries) => {
+                    for (sender, pubkeys, kind) in [
+                        (&native_sender, treasuries.native, "native treasuries"),
+                        (&token_sender, treasuries.token_accounts, "token accounts"),
+                    ] {
+                        sender.send_if_modified(|current| {
+                            if published && **current == pubkeys {
+                                return false;
+                            }
+                            info!(
+                                "Discovered {} {kind} of realm '{}'",
+                                pubkeys.len(),
+                                realm.name
+                            );
+                            *current = Arc::new(pubkeys);
+                            true
+                        });
+                    }
+                    published = true;
+                    record_successful_check(&watcher);
+                }
+                Err(err) => {
+                    error!(
+                        "Failed to discover treasuries of realm '{}': {err}",
+                        realm.name
+                    );
+                    record_failed_check(&watcher, &err.to_string());
+                    if !sleep_unless_shutdown(backoff_duration(&watcher)).await {
+                        break;
+                    }
+                    continue;
+                }
+            }
+
+            if !sleep_unless_shutdown(interval).await {
+                break;
+            }
+        }
+        info!("Stopped discovering treasuries of realm '{}'", realm.name);
+    });
+    (handle, native_receiver, token_receiver)
+}
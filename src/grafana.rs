@@ -0,0 +1,271 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use solana_client::client_error::reqwest;
+
+use crate::reload::WatchList;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const GRID_WIDTH: u32 = 24;
+const STAT_WIDTH: u32 = 6;
+const STAT_HEIGHT: u32 = 4;
+const GRAPH_HEIGHT: u32 = 8;
+
+/// What the generated dashboard is called and which Prometheus data source
+/// it queries. Without a data source UID, the dashboard lets the viewer pick
+/// one.
+#[derive(Debug, Clone)]
+pub struct DashboardOptions {
+    pub title: String,
+    pub uid: String,
+    pub datasource_uid: Option<String>,
+}
+
+/// Quotes a PromQL label value.
+fn label(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Places panels left to right in rows of the Grafana grid.
+struct Layout {
+    datasource: Value,
+    panels: Vec<Value>,
+    x: u32,
+    y: u32,
+    row_height: u32,
+}
+
+impl Layout {
+    fn row(&mut self, title: &str) {
+        self.new_line();
+        self.panels.push(json!({
+            "type": "row",
+            "title": title,
+            "collapsed": false,
+            "panels": [],
+            "gridPos": { "x": 0, "y": self.y, "w": GRID_WIDTH, "h": 1 },
+        }));
+        self.y += 1;
+    }
+
+    fn new_line(&mut self) {
+        if self.x > 0 {
+            self.y += self.row_height;
+            self.x = 0;
+            self.row_height = 0;
+        }
+    }
+
+    fn panel(&mut self, mut panel: Value, expr: &str, legend: &str, width: u32, height: u32) {
+        if self.x + width > GRID_WIDTH {
+            self.new_line();
+        }
+        panel["id"] = json!(self.panels.len() + 1);
+        panel["datasource"] = self.datasource.clone();
+        panel["targets"] = json!([{
+            "refId": "A",
+            "datasource": self.datasource,
+            "expr": expr,
+            "legendFormat": legend,
+        }]);
+        if panel["type"] == "table" {
+            panel["targets"][0]["instant"] = json!(true);
+            panel["targets"][0]["format"] = json!("table");
+        }
+        panel["gridPos"] = json!({ "x": self.x, "y": self.y, "w": width, "h": height });
+        self.panels.push(panel);
+        self.x += width;
+        self.row_height = self.row_height.max(height);
+    }
+
+    fn balance_stat(&mut self, title: &str, expr: &str) {
+        let panel = json!({
+            "type": "stat",
+            "title": title,
+            "fieldConfig": { "defaults": { "unit": "suffix: SOL", "decimals": 3 }, "overrides": [] },
+            "options": { "reduceOptions": { "calcs": ["lastNotNull"] }, "graphMode": "none" },
+        });
+        self.panel(panel, expr, "", STAT_WIDTH, STAT_HEIGHT);
+    }
+
+    /// A time series filling the rest of the line.
+    fn graph(&mut self, title: &str, expr: &str, legend: &str, unit: &str) {
+        let panel = json!({
+            "type": "timeseries",
+            "title": title,
+            "fieldConfig": { "defaults": { "unit": unit }, "overrides": [] },
+        });
+        self.panel(panel, expr, legend, GRID_WIDTH - self.x, GRAPH_HEIGHT);
+    }
+
+    /// A stat that turns red while `expr` is 1.
+    fn state_stat(&mut self, title: &str, expr: &str, failed: &str) {
+        let panel = json!({
+            "type": "stat",
+            "title": title,
+            "fieldConfig": {
+                "defaults": {
+                    "mappings": [{
+                        "type": "value",
+                        "options": {
+                            "0": { "text": "OK", "color": "green" },
+                            "1": { "text": failed, "color": "red" },
+                        },
+                    }],
+                },
+                "overrides": [],
+            },
+            "options": {
+                "reduceOptions": { "calcs": ["lastNotNull"] },
+                "colorMode": "background",
+                "graphMode": "none",
+            },
+        });
+        self.panel(panel, expr, "", STAT_WIDTH, STAT_HEIGHT);
+    }
+}
+
+/// Generates a Grafana dashboard for what is watched: an overview of all
+/// balances and RPC usage, the state of every alert rule, a row per named
+/// address and program-accounts scan, and the token balances.
+pub fn generate_dashboard(
+    options: &DashboardOptions,
+    watch_list: &WatchList,
+    token_accounts: &[String],
+    alert_rules: &[String],
+) -> Value {
+    let datasource = match &options.datasource_uid {
+        Some(uid) => json!({ "type": "prometheus", "uid": uid }),
+        None => json!({ "type": "prometheus", "uid": "${datasource}" }),
+    };
+    let mut layout = Layout {
+        datasource,
+        panels: vec![],
+        x: 0,
+        y: 0,
+        row_height: 0,
+    };
+
+    layout.row("Overview");
+    let table = json!({
+        "type": "table",
+        "title": "Balances",
+        "fieldConfig": { "defaults": { "unit": "suffix: SOL" }, "overrides": [] },
+        "transformations": [{
+            "id": "organize",
+            "options": { "excludeByName": { "Time": true, "__name__": true } },
+        }],
+    });
+    layout.panel(
+        table,
+        "sort_desc(balance_sol)",
+        "{{name}}",
+        GRID_WIDTH / 2,
+        GRAPH_HEIGHT,
+    );
+    layout.graph(
+        "RPC requests",
+        "sum by (watcher, method) (rate(rpc_requests_total[5m]))",
+        "{{watcher}} {{method}}",
+        "reqps",
+    );
+
+    if !alert_rules.is_empty() {
+        layout.row("Alerts");
+        for rule in alert_rules {
+            let expr = format!("alert_firing{{rule={}}}", label(rule));
+            layout.state_stat(rule, &expr, "FIRING");
+        }
+    }
+
+    let mut named_pubkeys: Vec<_> = watch_list.named_pubkeys.iter().collect();
+    named_pubkeys.sort_by_key(|(pubkey, name)| (*name, **pubkey));
+    for (pubkey, name) in named_pubkeys {
+        let selector = format!("name={},pubkey={}", label(name), label(&pubkey.to_string()));
+        layout.row(&format!("{name} ({pubkey})"));
+        let expr = format!("balance_sol{{{selector}}}");
+        layout.balance_stat("Balance", &expr);
+        if watch_list
+            .expectations
+            .get(pubkey)
+            .is_some_and(|expectations| !expectations.is_empty())
+        {
+            let expr = format!("max(account_assertion_failed{{{selector}}})");
+            layout.state_stat("Account assertions", &expr, "FAILED");
+        }
+        layout.graph("Balance history", &expr, "{{name}}", "suffix: SOL");
+    }
+
+    for (_, config) in &watch_list.program_accounts_configs {
+        let expr = format!("total_balance_sol{{name={}}}", label(config.name()));
+        layout.row(config.name());
+        layout.balance_stat("Total balance", &expr);
+        layout.graph("Total balance history", &expr, "{{name}}", "suffix: SOL");
+    }
+
+    if !token_accounts.is_empty() {
+        layout.row("Token accounts");
+        for name in token_accounts {
+            let expr = format!("token_balance{{name={}}}", label(name));
+            layout.graph(name, &expr, "{{kind}}", "short");
+        }
+    }
+
+    let templating = match options.datasource_uid {
+        Some(_) => json!({ "list": [] }),
+        None => json!({ "list": [{
+            "name": "datasource",
+            "label": "Data source",
+            "type": "datasource",
+            "query": "prometheus",
+        }] }),
+    };
+    json!({
+        "uid": options.uid,
+        "title": options.title,
+        "tags": ["solana"],
+        "timezone": "browser",
+        "schemaVersion": 39,
+        "refresh": "1m",
+        "time": { "from": "now-24h", "to": "now" },
+        "templating": templating,
+        "panels": layout.panels,
+    })
+}
+
+/// Creates or replaces the dashboard through the Grafana HTTP API,
+/// authenticated with a service account token. Returns the dashboard URL.
+pub async fn push_dashboard(
+    grafana_url: &str,
+    token: &str,
+    dashboard: Value,
+    folder_uid: Option<String>,
+) -> anyhow::Result<String> {
+    let mut base_url = reqwest::Url::parse(grafana_url)?;
+    if !base_url.path().ends_with('/') {
+        base_url.set_path(&format!("{}/", base_url.path()));
+    }
+    let http_client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+    let response = http_client
+        .post(base_url.join("api/dashboards/db")?)
+        .bearer_auth(token)
+        .json(&json!({
+            "dashboard": dashboard,
+            "folderUid": folder_uid,
+            "overwrite": true,
+            "message": "Generated by solana-balance-watcher",
+        }))
+        .send()
+        .await?;
+    let status = response.status();
+    let body: Value = response.json().await.unwrap_or_default();
+    anyhow::ensure!(
+        status.is_success(),
+        "Grafana rejected the dashboard with {status}: {}",
+        body["message"].as_str().unwrap_or_default()
+    );
+    let path = body["url"].as_str().unwrap_or_default();
+    Ok(base_url.join(path)?.to_string())
+}
@@ -0,0 +1,193 @@
+use std::time::SystemTime;
+
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Schema, SimpleObject};
+use axum::{routing::post, Extension, Json, Router};
+use chrono::{DateTime, Utc};
+use solana_sdk::native_token::lamports_to_sol;
+
+use crate::{
+    auth::{require_role, ApiKeys, Caller, Role},
+    explorer::account_url,
+    health::watcher_health,
+    history::{history_since, window_start, WindowedHistory},
+    observations::{latest_observations, ObservedBalance},
+    tenant::is_visible_to,
+};
+
+type BalanceSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+#[derive(SimpleObject)]
+struct Failure {
+    timestamp: DateTime<Utc>,
+    error: String,
+}
+
+#[derive(SimpleObject)]
+struct Watcher {
+    name: String,
+    last_success: Option<DateTime<Utc>>,
+    last_failure: Option<Failure>,
+}
+
+#[derive(SimpleObject)]
+struct Balance {
+    watcher: String,
+    name: String,
+    /// Watched account, null for totals aggregated over many accounts.
+    pubkey: Option<String>,
+    /// Link to the watched account on the configured explorer.
+    explorer_url: Option<String>,
+    lamports: u64,
+    sol: f64,
+    delta_lamports: Option<i64>,
+    slot: Option<u64>,
+    observed_at: DateTime<Utc>,
+}
+
+impl From<ObservedBalance> for Balance {
+    fn from(balance: ObservedBalance) -> Self {
+        let delta_lamports = balance.delta_lamports().map(|delta| delta as i64);
+        let observation = balance.latest;
+        Balance {
+            watcher: observation.watcher,
+            name: observation.name,
+            pubkey: observation.pubkey.map(|pubkey| pubkey.to_string()),
+            explorer_url: observation.pubkey.as_ref().and_then(account_url),
+            lamports: observation.lamports,
+            sol: lamports_to_sol(observation.lamports),
+            delta_lamports,
+            slot: observation.slot,
+            observed_at: DateTime::<Utc>::from(observation.observed_at),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct BalanceChange {
+    timestamp: DateTime<Utc>,
+    lamports: u64,
+    sol: f64,
+}
+
+#[derive(SimpleObject)]
+struct History {
+    watcher: String,
+    name: String,
+    /// Whether the history reaches back to the start of the window,
+    /// otherwise it starts with the first observation.
+    complete: bool,
+    /// Balance in effect at the start of the window followed by every
+    /// change since.
+    changes: Vec<BalanceChange>,
+}
+
+impl From<WindowedHistory> for History {
+    fn from(history: WindowedHistory) -> Self {
+        History {
+            watcher: history.watcher,
+            name: history.name,
+            complete: history.complete,
+            changes: history
+                .changes
+                .into_iter()
+                .map(|(time, lamports)| BalanceChange {
+                    timestamp: datetime(time),
+                    lamports,
+                    sol: lamports_to_sol(lamports),
+                })
+                .collect(),
+        }
+    }
+}
+
+fn datetime(time: SystemTime) -> DateTime<Utc> {
+    DateTime::<Utc>::from(time)
+}
+
+/// Tenant the caller is restricted to, if any.
+struct TenantScope(Option<String>);
+
+fn tenant<'a>(ctx: &Context<'a>) -> Option<&'a str> {
+    ctx.data_opt::<TenantScope>()
+        .and_then(|scope| scope.0.as_deref())
+}
+
+struct Query;
+
+#[Object]
+impl Query {
+    /// Watchers that completed or failed at least one check.
+    async fn watchers(&self, ctx: &Context<'_>) -> Vec<Watcher> {
+        let tenant = tenant(ctx);
+        watcher_health()
+            .into_iter()
+            .filter(|(name, _)| is_visible_to(tenant, name, name))
+            .map(|(name, health)| Watcher {
+                name,
+                last_success: health.last_success.map(datetime),
+                last_failure: health.last_failure.map(|(time, error)| Failure {
+                    timestamp: datetime(time),
+                    error,
+                }),
+            })
+            .collect()
+    }
+
+    /// Latest balances, optionally restricted to a single watcher.
+    async fn balances(&self, ctx: &Context<'_>, watcher: Option<String>) -> Vec<Balance> {
+        let tenant = tenant(ctx);
+        latest_observations()
+            .into_iter()
+            .filter(|balance| {
+                watcher.is_none() || watcher.as_ref() == Some(&balance.latest.watcher)
+            })
+            .filter(|balance| is_visible_to(tenant, &balance.latest.watcher, &balance.latest.name))
+            .map(Balance::from)
+            .collect()
+    }
+
+    /// Balance changes over a window such as `24h`, no longer than the
+    /// history retention, optionally restricted to a single watcher or name.
+    async fn history(
+        &self,
+        ctx: &Context<'_>,
+        window: String,
+        watcher: Option<String>,
+        name: Option<String>,
+    ) -> async_graphql::Result<Vec<History>> {
+        let tenant = tenant(ctx);
+        let from = window_start(&window)?;
+        Ok(history_since(from, |balance_watcher, balance_name| {
+            (watcher.is_none() || watcher.as_deref() == Some(balance_watcher))
+                && (name.is_none() || name.as_deref() == Some(balance_name))
+                && is_visible_to(tenant, balance_watcher, balance_name)
+        })
+        .into_iter()
+        .map(History::from)
+        .collect())
+    }
+}
+
+async fn graphql(
+    Extension(schema): Extension<BalanceSchema>,
+    caller: Option<Extension<Caller>>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    let tenant = caller.and_then(|Extension(caller)| caller.tenant);
+    Json(schema.execute(request.data(TenantScope(tenant))).await)
+}
+
+/// GraphQL view of watcher health, latest balances and their history served
+/// on `/graphql`.
+/// Requires a read-only or admin API key when any API keys are configured,
+/// keys restricted to a tenant only see that tenant's watchers and balances.
+pub fn graphql_router(keys: &ApiKeys) -> Router {
+    let schema = Schema::new(Query, EmptyMutation, EmptySubscription);
+    let router = Router::new()
+        .route("/graphql", post(graphql))
+        .layer(Extension(schema));
+    match keys.is_empty() {
+        true => router,
+        false => require_role(router, keys, Role::ReadOnly),
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    pin::Pin,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use futures::{stream, Stream};
+use log::{error, info};
+use tokio::{sync::broadcast::error::RecvError, task::JoinHandle};
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::{
+    observations::{latest_observations, subscribe_observations, Observation},
+    shutdown::shutdown_requested,
+};
+
+mod proto {
+    tonic::include_proto!("balance_watcher.v1");
+}
+
+use proto::{
+    balance_watcher_server::{BalanceWatcher, BalanceWatcherServer},
+    Balance, BalanceChange, GetBalancesRequest, GetBalancesResponse,
+    SubscribeBalanceChangesRequest,
+};
+
+fn unix_ms(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as i64)
+}
+
+impl From<&Observation> for Balance {
+    fn from(observation: &Observation) -> Self {
+        Balance {
+            watcher: observation.watcher.clone(),
+            name: observation.name.clone(),
+            pubkey: observation
+                .pubkey
+                .map(|pubkey| pubkey.to_string())
+                .unwrap_or_default(),
+            lamports: observation.lamports,
+            slot: observation.slot,
+            observed_at_unix_ms: unix_ms(observation.observed_at),
+        }
+    }
+}
+
+fn matches_watcher(filter: &str, observation: &Observation) -> bool {
+    filter.is_empty() || filter == observation.watcher
+}
+
+struct BalanceWatcherService;
+
+type BalanceChangeStream = Pin<Box<dyn Stream<Item = Result<BalanceChange, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl BalanceWatcher for BalanceWatcherService {
+    async fn get_balances(
+        &self,
+        request: Request<GetBalancesRequest>,
+    ) -> Result<Response<GetBalancesResponse>, Status> {
+        let filter = request.into_inner().watcher;
+        let balances = latest_observations()
+            .iter()
+            .filter(|balance| matches_watcher(&filter, &balance.latest))
+            .map(|balance| Balance::from(&balance.latest))
+            .collect();
+        Ok(Response::new(GetBalancesResponse { balances }))
+    }
+
+    type SubscribeBalanceChangesStream = BalanceChangeStream;
+
+    async fn subscribe_balance_changes(
+        &self,
+        request: Request<SubscribeBalanceChangesRequest>,
+    ) -> Result<Response<Self::SubscribeBalanceChangesStream>, Status> {
+        let filter = request.into_inner().watcher;
+        let observations = subscribe_observations();
+        // Balances already known are only streamed once they change.
+        let known: HashMap<_, _> = latest_observations()
+            .into_iter()
+            .map(|balance| {
+                let key = (balance.latest.watcher, balance.latest.name);
+                (key, balance.latest.lamports)
+            })
+            .collect();
+
+        let changes = stream::unfold(
+            (observations, known),
+            move |(mut observations, mut known)| {
+                let filter = filter.clone();
+                async move {
+                    loop {
+                        let observation = match observations.recv().await {
+                            Ok(observation) => observation,
+                            Err(RecvError::Lagged(skipped)) => {
+                                let status = Status::data_loss(format!(
+                                    "Subscriber fell behind, skipped {skipped} observations"
+                                ));
+                                return Some((Err(status), (observations, known)));
+                            }
+                            Err(RecvError::Closed) => return None,
+                        };
+                        if !matches_watcher(&filter, &observation) {
+                            continue;
+                        }
+                        let key = (observation.watcher.clone(), observation.name.clone());
+                        let previous = known.insert(key, observation.lamports);
+                        if previous == Some(observation.lamports) {
+                            continue;
+                        }
+                        let change = BalanceChange {
+                            balance: Some(Balance::from(&observation)),
+                            delta_lamports: previous
+                                .map(|previous| observation.lamports as i64 - previous as i64),
+                        };
+                        return Some((Ok(change), (observations, known)));
+                    }
+                }
+            },
+        );
+
+        Ok(Response::new(Box::pin(changes)))
+    }
+}
+
+/// Serves the `balance_watcher.v1.BalanceWatcher` gRPC service until shutdown.
+pub fn spawn_grpc_server(port: u16) -> JoinHandle<()> {
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    info!("Serving gRPC on {}", addr);
+
+    tokio::spawn(async move {
+        let result = Server::builder()
+            .add_service(BalanceWatcherServer::new(BalanceWatcherService))
+            .serve_with_shutdown(addr, shutdown_requested())
+            .await;
+        if let Err(err) = result {
+            error!("gRPC server failed: {err}");
+        }
+    })
+}
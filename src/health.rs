@@ -0,0 +1,192 @@
+use std::{collections::BTreeMap, str::FromStr, sync::Mutex, time::SystemTime};
+
+use log::{error, warn};
+use once_cell::sync::{Lazy, OnceCell};
+
+use crate::{
+    history::forget_history,
+    metrics::{
+        remove_metric_balance_subscription_active, remove_metric_last_successful_check,
+        remove_metric_rpc_rate_limit_wait, remove_metric_watcher_rpc_endpoint,
+        remove_metric_watcher_stale, update_metric_last_successful_check,
+        update_metric_watcher_stale, watcher_intervals,
+    },
+    rpc_cost::forget_rpc_usage,
+    shutdown::request_shutdown,
+};
+
+/// What happens once a watcher failed the configured number of checks in a
+/// row.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Keeps retrying, only logging the failures.
+    #[default]
+    Retry,
+    /// Keeps retrying, exporting `watcher_stale` until a check succeeds
+    /// again.
+    Stale,
+    /// Shuts down and exits with a non-zero code, for the orchestrator to
+    /// restart the process.
+    Exit,
+}
+
+impl FromStr for FailurePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "retry" => FailurePolicy::Retry,
+            "stale" => FailurePolicy::Stale,
+            "exit" => FailurePolicy::Exit,
+            _ => anyhow::bail!("Unsupported failure policy '{s}', expected retry, stale or exit"),
+        })
+    }
+}
+
+/// Outcome of the most recent checks of a watcher.
+#[derive(Debug, Clone, Default)]
+pub struct WatcherHealth {
+    pub last_success: Option<SystemTime>,
+    pub last_failure: Option<(SystemTime, String)>,
+    /// Failed checks since the last successful one.
+    pub consecutive_failures: u32,
+}
+
+impl WatcherHealth {
+    /// Time of the most recent check, whether it succeeded or not.
+    pub fn last_check(&self) -> Option<SystemTime> {
+        let last_failure = self.last_failure.as_ref().map(|(time, _)| *time);
+        self.last_success.max(last_failure)
+    }
+
+    /// Whether the most recent check succeeded.
+    pub fn is_healthy(&self) -> bool {
+        match (self.last_success, &self.last_failure) {
+            (Some(success), Some((failure, _))) => success > *failure,
+            (success, _) => success.is_some(),
+        }
+    }
+}
+
+static WATCHERS: Lazy<Mutex<BTreeMap<String, WatcherHealth>>> = Lazy::new(Default::default);
+static FAILURE_POLICY: OnceCell<(u32, FailurePolicy)> = OnceCell::new();
+static FATAL_FAILURE: OnceCell<String> = OnceCell::new();
+
+/// Applies `policy` to watchers once `max_consecutive_failures` of their
+/// checks failed in a row. Can only be called once.
+pub fn set_failure_policy(
+    max_consecutive_failures: u32,
+    policy: FailurePolicy,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        max_consecutive_failures > 0,
+        "Maximum consecutive failures must be positive"
+    );
+    FAILURE_POLICY
+        .set((max_consecutive_failures, policy))
+        .map_err(|_| anyhow::anyhow!("Failure policy is already set"))
+}
+
+/// The failure that shut the process down under [`FailurePolicy::Exit`].
+pub fn fatal_failure() -> Option<&'static str> {
+    FATAL_FAILURE.get().map(String::as_str)
+}
+
+pub fn record_successful_check(watcher: &str) {
+    let now = SystemTime::now();
+    {
+        let mut watchers = WATCHERS.lock().unwrap();
+        let health = watchers.entry(watcher.to_string()).or_default();
+        health.last_success = Some(now);
+        health.consecutive_failures = 0;
+    }
+    update_metric_last_successful_check(watcher, now);
+    if let Some((_, FailurePolicy::Stale)) = FAILURE_POLICY.get() {
+        update_metric_watcher_stale(watcher, false);
+    }
+}
+
+pub fn record_failed_check(watcher: &str, error: &str) {
+    let consecutive_failures = {
+        let mut watchers = WATCHERS.lock().unwrap();
+        let health = watchers.entry(watcher.to_string()).or_default();
+        health.last_failure = Some((SystemTime::now(), error.to_string()));
+        health.consecutive_failures += 1;
+        health.consecutive_failures
+    };
+    #[cfg(feature = "sentry")]
+    crate::error_reporting::report_failed_check(watcher, error, consecutive_failures);
+    match FAILURE_POLICY.get() {
+        Some(&(max, policy)) if consecutive_failures == max => {
+            apply_failure_policy(watcher, error, max, policy)
+        }
+        _ => {}
+    }
+}
+
+fn apply_failure_policy(watcher: &str, error: &str, failures: u32, policy: FailurePolicy) {
+    match policy {
+        FailurePolicy::Retry => {
+            warn!("Watcher '{watcher}' failed {failures} checks in a row, retrying: {error}")
+        }
+        FailurePolicy::Stale => {
+            warn!(
+                "Watcher '{watcher}' failed {failures} checks in a row, marking it stale: {error}"
+            );
+            update_metric_watcher_stale(watcher, true);
+        }
+        FailurePolicy::Exit => {
+            let failure = format!("Watcher '{watcher}' failed {failures} checks in a row: {error}");
+            error!("{failure}, shutting down");
+            let _ = FATAL_FAILURE.set(failure);
+            request_shutdown();
+        }
+    }
+}
+
+/// Checks of `watcher` that failed since its last successful one.
+pub fn consecutive_failures(watcher: &str) -> u32 {
+    WATCHERS
+        .lock()
+        .unwrap()
+        .get(watcher)
+        .map_or(0, |health| health.consecutive_failures)
+}
+
+/// Drops the health of a watcher that is no longer running, e.g. after a
+/// reload removed it.
+pub fn forget_watcher(watcher: &str) {
+    WATCHERS.lock().unwrap().remove(watcher);
+    remove_metric_last_successful_check(watcher);
+    remove_metric_watcher_stale(watcher);
+    remove_metric_watcher_rpc_endpoint(watcher);
+    remove_metric_balance_subscription_active(watcher);
+    remove_metric_rpc_rate_limit_wait(watcher);
+    forget_history(watcher);
+    forget_rpc_usage(watcher);
+}
+
+/// Health of every running watcher, that is every watcher described by
+/// `watcher_info`, including the ones yet to complete a check.
+pub fn running_watcher_health() -> Vec<(String, WatcherHealth)> {
+    let watchers = WATCHERS.lock().unwrap();
+    let mut health: Vec<_> = watcher_intervals()
+        .into_keys()
+        .map(|watcher| {
+            let health = watchers.get(&watcher).cloned().unwrap_or_default();
+            (watcher, health)
+        })
+        .collect();
+    health.sort_by(|(a, _), (b, _)| a.cmp(b));
+    health
+}
+
+/// Health of every watcher that completed or failed a check, ordered by name.
+pub fn watcher_health() -> Vec<(String, WatcherHealth)> {
+    WATCHERS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(watcher, health)| (watcher.clone(), health.clone()))
+        .collect()
+}
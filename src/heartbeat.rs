@@ -0,0 +1,85 @@
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use log::{info, warn};
+use tokio::{task::JoinHandle, time::interval};
+
+use crate::{
+    health::running_watcher_health,
+    metrics::{update_metric_notifications_failed, update_metric_notifications_sent},
+    outbound::notifier_http_client,
+    shutdown::shutdown_requested,
+};
+
+const NOTIFIER_NAME: &str = "heartbeat";
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy)]
+pub enum HeartbeatMethod {
+    Get,
+    Post,
+}
+
+impl FromStr for HeartbeatMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "get" => HeartbeatMethod::Get,
+            "post" => HeartbeatMethod::Post,
+            _ => anyhow::bail!("Unsupported heartbeat method '{s}', expected get or post"),
+        })
+    }
+}
+
+/// Whether every running watcher succeeded since `last_ping`, with no failure
+/// after its latest success.
+fn cycle_completed(last_ping: Option<SystemTime>) -> bool {
+    running_watcher_health()
+        .iter()
+        .all(|(_, health)| health.is_healthy() && health.last_success > last_ping)
+}
+
+/// Pings `url` after each check cycle in which all running watchers
+/// succeeded, so that a dead man's switch such as healthchecks.io raises an
+/// alert once the pings stop.
+pub async fn spawn_heartbeat(
+    url: String,
+    method: HeartbeatMethod,
+) -> anyhow::Result<JoinHandle<()>> {
+    let http_client = notifier_http_client(&url, REQUEST_TIMEOUT).await?;
+    info!("Sending heartbeats to {url}");
+
+    Ok(tokio::spawn(async move {
+        let mut last_ping = None;
+        let mut ticks = interval(POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => {}
+                _ = shutdown_requested() => break,
+            }
+            if !cycle_completed(last_ping) {
+                continue;
+            }
+
+            let now = SystemTime::now();
+            let request = match method {
+                HeartbeatMethod::Get => http_client.get(&url),
+                HeartbeatMethod::Post => http_client.post(&url),
+            };
+            match request.send().await.and_then(|r| r.error_for_status()) {
+                Ok(_) => {
+                    update_metric_notifications_sent(NOTIFIER_NAME);
+                    last_ping = Some(now);
+                }
+                Err(err) => {
+                    update_metric_notifications_failed(NOTIFIER_NAME);
+                    warn!("Failed to send heartbeat: {err}");
+                }
+            }
+        }
+    }))
+}
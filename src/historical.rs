@@ -0,0 +1,189 @@
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_client::GetConfirmedSignaturesForAddress2Config,
+    rpc_config::RpcAccountInfoConfig, rpc_request::RpcRequest,
+};
+use solana_sdk::{
+    account::Account, clock::Slot, commitment_config::CommitmentConfig, pubkey::Pubkey,
+    signature::Signature,
+};
+
+use crate::{
+    data_slice::AccountType,
+    program_accounts_balance::{
+        get_program_accounts_with_account_config, ProgramAccountsBalanceConfig,
+    },
+    rpc::parse_commitment,
+};
+
+/// Chain state to read balances at in one-shot mode, parsed from a slot or
+/// from `processed`, `confirmed` or `finalized`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsOf {
+    /// The end of a finalized slot. No RPC serves accounts at past slots, so
+    /// balances are taken from the last transaction touching each account up
+    /// to the slot, which needs the ledger history of the RPC to reach back
+    /// to it. Rewards credited at epoch boundaries without a transaction,
+    /// such as staking and voting rewards, are not reflected.
+    Slot(Slot),
+    /// The latest state at a commitment level.
+    Commitment(CommitmentConfig),
+}
+
+impl FromStr for AsOf {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(slot) = s.parse() {
+            return Ok(AsOf::Slot(slot));
+        }
+        match parse_commitment(s) {
+            Ok(commitment) => Ok(AsOf::Commitment(commitment)),
+            Err(_) => anyhow::bail!(
+                "Cannot parse '{s}', expected a slot or one of processed, confirmed or finalized"
+            ),
+        }
+    }
+}
+
+/// Balance of `pubkey` after the transaction `signature`, which touched it.
+async fn balance_after_transaction(
+    rpc_client: &RpcClient,
+    pubkey: &Pubkey,
+    signature: &str,
+) -> anyhow::Result<u64> {
+    let config = json!({
+        "encoding": "json",
+        "commitment": "finalized",
+        "maxSupportedTransactionVersion": 0,
+    });
+    let transaction = rpc_client
+        .send::<Value>(RpcRequest::GetTransaction, json!([signature, config]))
+        .await?;
+    anyhow::ensure!(
+        !transaction.is_null(),
+        "Transaction {signature} is not available, the ledger history of the RPC may not reach back to it"
+    );
+    let meta = &transaction["meta"];
+    let loaded_addresses = &meta["loadedAddresses"];
+    let account_keys = transaction["transaction"]["message"]["accountKeys"]
+        .as_array()
+        .into_iter()
+        .chain(loaded_addresses["writable"].as_array())
+        .chain(loaded_addresses["readonly"].as_array())
+        .flatten();
+    let pubkey = pubkey.to_string();
+    let Some(index) = account_keys
+        .into_iter()
+        .position(|key| key.as_str() == Some(&pubkey))
+    else {
+        anyhow::bail!("Transaction {signature} does not list account {pubkey}");
+    };
+    meta["postBalances"][index].as_u64().ok_or_else(|| {
+        anyhow::anyhow!("Transaction {signature} has no balance of account {pubkey}")
+    })
+}
+
+/// Balance of `pubkey` at the end of `slot`, from the last transaction that
+/// touched it up to then, or `None` if none did.
+async fn get_balance_at_slot(
+    rpc_client: &RpcClient,
+    pubkey: &Pubkey,
+    slot: Slot,
+) -> anyhow::Result<Option<u64>> {
+    let mut before = None;
+    loop {
+        // Signatures come newest first, also within a slot.
+        let signatures = rpc_client
+            .get_signatures_for_address_with_config(
+                pubkey,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until: None,
+                    limit: None,
+                    commitment: Some(CommitmentConfig::finalized()),
+                },
+            )
+            .await?;
+        if let Some(signature) = signatures.iter().find(|signature| signature.slot <= slot) {
+            return balance_after_transaction(rpc_client, pubkey, &signature.signature)
+                .await
+                .map(Some);
+        }
+        let Some(oldest) = signatures.last() else {
+            return Ok(None);
+        };
+        before = Some(Signature::from_str(&oldest.signature)?);
+    }
+}
+
+/// Lamports of each of `pubkeys` as of `as_of`, or as of the commitment of
+/// `rpc_client` when not given, with `None` for accounts that did not exist.
+pub async fn get_balances_as_of(
+    rpc_client: &RpcClient,
+    pubkeys: &[Pubkey],
+    as_of: Option<AsOf>,
+) -> anyhow::Result<Vec<Option<u64>>> {
+    let commitment = match as_of {
+        Some(AsOf::Slot(slot)) => {
+            let finalized = rpc_client
+                .get_slot_with_commitment(CommitmentConfig::finalized())
+                .await?;
+            anyhow::ensure!(
+                slot <= finalized,
+                "Slot {slot} is not finalized yet, the latest finalized slot is {finalized}"
+            );
+            let mut balances = vec![];
+            for pubkey in pubkeys {
+                balances.push(get_balance_at_slot(rpc_client, pubkey, slot).await?);
+            }
+            return Ok(balances);
+        }
+        Some(AsOf::Commitment(commitment)) => Some(commitment),
+        None => None,
+    };
+    let response = rpc_client
+        .get_multiple_accounts_with_config(
+            pubkeys,
+            RpcAccountInfoConfig {
+                data_slice: Some(AccountType::Lamports.data_slice()),
+                commitment,
+                ..Default::default()
+            },
+        )
+        .await?;
+    Ok(response
+        .value
+        .into_iter()
+        .map(|account| account.map(|account| account.lamports))
+        .collect())
+}
+
+/// Accounts matching `config` as of `as_of`, or as of the commitment of
+/// `rpc_client` when not given. Scans cannot be read at a past slot, as
+/// they find accounts by their current state.
+pub async fn get_program_accounts_as_of(
+    rpc_client: &RpcClient,
+    config: &ProgramAccountsBalanceConfig,
+    as_of: Option<AsOf>,
+) -> anyhow::Result<Vec<(Pubkey, Account)>> {
+    let commitment = match as_of {
+        Some(AsOf::Slot(slot)) => {
+            anyhow::bail!("Program-accounts scans cannot be read as of slot {slot}")
+        }
+        Some(AsOf::Commitment(commitment)) => Some(commitment),
+        None => None,
+    };
+    Ok(get_program_accounts_with_account_config(
+        rpc_client,
+        config,
+        RpcAccountInfoConfig {
+            data_slice: Some(AccountType::Lamports.data_slice()),
+            commitment,
+            ..Default::default()
+        },
+    )
+    .await?)
+}
@@ -0,0 +1,287 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::RwLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    routing::get,
+    Extension, Json, Router,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::native_token::{lamports_to_sol, LAMPORTS_PER_SOL};
+
+use crate::{
+    auth::{require_role, ApiKeys, Caller, Role},
+    observations::Observation,
+    tenant::is_visible_to,
+};
+
+/// How long balances are kept unless configured otherwise.
+pub const DEFAULT_HISTORY_RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+static RETENTION: OnceCell<Duration> = OnceCell::new();
+
+/// Balances of a watcher and name over time. Only changes are kept, so that
+/// balances at rest cost nothing however often they are checked.
+#[derive(Debug)]
+struct BalanceHistory {
+    /// Time of the first observation, before which the balance is unknown.
+    since: SystemTime,
+    /// Balance after each change, oldest first, starting with the balance
+    /// at `since` or, once pruned, the balance in effect at the cutoff.
+    changes: VecDeque<(SystemTime, u64)>,
+}
+
+static HISTORY: Lazy<RwLock<BTreeMap<(String, String), BalanceHistory>>> =
+    Lazy::new(Default::default);
+
+/// Sets how long balance changes are kept for flow queries. Can only be
+/// called once.
+pub fn set_history_retention(retention: Duration) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        SystemTime::now().duration_since(UNIX_EPOCH)? > retention,
+        "History retention of {}s reaches back before the Unix epoch",
+        retention.as_secs()
+    );
+    RETENTION
+        .set(retention)
+        .map_err(|_| anyhow::anyhow!("History retention is already set"))
+}
+
+fn retention() -> Duration {
+    RETENTION
+        .get()
+        .copied()
+        .unwrap_or(DEFAULT_HISTORY_RETENTION)
+}
+
+/// Appends `observation` to the history of its watcher and name if the
+/// balance changed, and drops changes older than the retention, keeping the
+/// one in effect at the cutoff.
+pub(crate) fn record_history(observation: &Observation) {
+    let mut history = HISTORY.write().unwrap();
+    let balance = history
+        .entry((observation.watcher.clone(), observation.name.clone()))
+        .or_insert_with(|| BalanceHistory {
+            since: observation.observed_at,
+            changes: VecDeque::new(),
+        });
+    if balance.changes.back().map(|(_, lamports)| *lamports) != Some(observation.lamports) {
+        balance
+            .changes
+            .push_back((observation.observed_at, observation.lamports));
+    }
+    // Observations replayed from far enough back have nothing to prune.
+    let Some(cutoff) = observation.observed_at.checked_sub(retention()) else {
+        return;
+    };
+    while balance
+        .changes
+        .get(1)
+        .is_some_and(|(time, _)| *time <= cutoff)
+    {
+        balance.changes.pop_front();
+    }
+    balance.since = balance.since.max(cutoff);
+}
+
+/// Drops the history of every balance of `watcher`.
+pub(crate) fn forget_history(watcher: &str) {
+    HISTORY
+        .write()
+        .unwrap()
+        .retain(|(balance_watcher, _), _| balance_watcher != watcher);
+}
+
+/// Drops the history of the balance `name` of `watcher`.
+pub(crate) fn forget_balance_history(watcher: &str, name: &str) {
+    HISTORY
+        .write()
+        .unwrap()
+        .remove(&(watcher.to_string(), name.to_string()));
+}
+
+/// Balance movements over a window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Flow {
+    /// Sum of the increases of the balance.
+    inflow: u64,
+    /// Sum of the decreases of the balance.
+    outflow: u64,
+    start: u64,
+    end: u64,
+}
+
+impl Flow {
+    fn net(&self) -> i128 {
+        i128::from(self.end) - i128::from(self.start)
+    }
+}
+
+impl BalanceHistory {
+    /// Changes from `from` until now, starting with the one in effect at
+    /// `from`, or with the first one known if the history starts later.
+    fn changes_since(&self, from: SystemTime) -> impl Iterator<Item = &(SystemTime, u64)> {
+        let first_in_window = self.changes.partition_point(|(time, _)| *time <= from);
+        self.changes.range(first_in_window.saturating_sub(1)..)
+    }
+
+    /// Movements from `from` until now, starting from the balance in effect
+    /// at `from`, or from the first one known if the history starts later.
+    fn flow(&self, from: SystemTime) -> Option<Flow> {
+        let mut changes = self.changes_since(from).map(|(_, lamports)| *lamports);
+        let start = changes.next()?;
+        let mut flow = Flow {
+            inflow: 0,
+            outflow: 0,
+            start,
+            end: start,
+        };
+        for lamports in changes {
+            match lamports > flow.end {
+                true => flow.inflow += lamports - flow.end,
+                false => flow.outflow += flow.end - lamports,
+            }
+            flow.end = lamports;
+        }
+        Some(flow)
+    }
+}
+
+/// Parses a window such as `90s`, `30m`, `24h` or `7d`.
+fn parse_window(s: &str) -> anyhow::Result<Duration> {
+    let unit = match s.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 3600,
+        Some('d') => 24 * 3600,
+        _ => anyhow::bail!("Cannot parse window '{s}', expected a number followed by s, m, h or d"),
+    };
+    let count: u64 = s[..s.len() - 1]
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Cannot parse window '{s}'"))?;
+    anyhow::ensure!(count > 0, "Window must be positive, got '{s}'");
+    Ok(Duration::from_secs(count.saturating_mul(unit)))
+}
+
+/// Start of `window`, a window such as `24h` no longer than the retention,
+/// ending now.
+pub(crate) fn window_start(window: &str) -> anyhow::Result<SystemTime> {
+    let duration = parse_window(window)?;
+    anyhow::ensure!(
+        duration <= retention(),
+        "Window {window} exceeds the history retention of {}s",
+        retention().as_secs()
+    );
+    Ok(SystemTime::now() - duration)
+}
+
+/// Balance changes of a watcher and name over a window.
+#[cfg(feature = "graphql")]
+pub(crate) struct WindowedHistory {
+    pub watcher: String,
+    pub name: String,
+    /// Whether the history reaches back to the start of the window.
+    pub complete: bool,
+    /// Balance in effect at the start of the window, or the first one known
+    /// if the history starts later, followed by every change since.
+    pub changes: Vec<(SystemTime, u64)>,
+}
+
+/// Histories from `from` until now of the balances `filter` accepts by
+/// watcher and name.
+#[cfg(feature = "graphql")]
+pub(crate) fn history_since(
+    from: SystemTime,
+    filter: impl Fn(&str, &str) -> bool,
+) -> Vec<WindowedHistory> {
+    HISTORY
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|((watcher, name), _)| filter(watcher, name))
+        .map(|((watcher, name), history)| WindowedHistory {
+            watcher: watcher.clone(),
+            name: name.clone(),
+            complete: history.since <= from,
+            changes: history.changes_since(from).copied().collect(),
+        })
+        .collect()
+}
+
+fn timestamp(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[derive(Debug, Deserialize)]
+struct FlowsQuery {
+    window: String,
+}
+
+/// Inflow, outflow and net change of the balance `name` over `window`, per
+/// watcher observing it. A watcher's flow is `complete` when its history
+/// reaches back to the start of the window; otherwise it covers the window
+/// from its first observation on.
+async fn flows(
+    caller: Option<Extension<Caller>>,
+    Path(name): Path<String>,
+    Query(query): Query<FlowsQuery>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let from =
+        window_start(&query.window).map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+    let tenant = caller.and_then(|Extension(caller)| caller.tenant);
+    let now = SystemTime::now();
+    let flows: Vec<_> = HISTORY
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|((watcher, balance_name), _)| {
+            *balance_name == name && is_visible_to(tenant.as_deref(), watcher, balance_name)
+        })
+        .filter_map(|((watcher, _), history)| {
+            let flow = history.flow(from)?;
+            Some(json!({
+                "watcher": watcher,
+                "complete": history.since <= from,
+                "start_lamports": flow.start,
+                "end_lamports": flow.end,
+                "inflow_lamports": flow.inflow,
+                "outflow_lamports": flow.outflow,
+                "net_lamports": flow.net() as i64,
+                "inflow_sol": lamports_to_sol(flow.inflow),
+                "outflow_sol": lamports_to_sol(flow.outflow),
+                "net_sol": flow.net() as f64 / LAMPORTS_PER_SOL as f64,
+            }))
+        })
+        .collect();
+    if flows.is_empty() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("No history of balance '{name}'"),
+        ));
+    }
+    Ok(Json(json!({
+        "name": name,
+        "window": query.window,
+        "from": timestamp(from),
+        "to": timestamp(now),
+        "flows": flows,
+    })))
+}
+
+/// Flows of balances over a window, from the balance changes kept in
+/// memory. Requires a read-only or admin API key when any API keys are
+/// configured, keys restricted to a tenant only see that tenant's balances.
+pub fn flows_router(keys: &ApiKeys) -> Router {
+    let router = Router::new().route("/flows/:name", get(flows));
+    match keys.is_empty() {
+        true => router,
+        false => require_role(router, keys, Role::ReadOnly),
+    }
+}
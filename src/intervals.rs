@@ -0,0 +1,209 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use once_cell::sync::OnceCell;
+
+use crate::health::consecutive_failures;
+
+/// Interval between checks unless configured otherwise.
+pub const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+/// Wait after a first failed check unless configured otherwise.
+pub const DEFAULT_BACKOFF_DURATION: Duration = Duration::from_secs(10);
+/// Longest wait after failed checks unless configured otherwise.
+pub const DEFAULT_BACKOFF_MAX: Duration = Duration::from_secs(300);
+/// Share of each wait taken off at random unless configured otherwise.
+pub const DEFAULT_BACKOFF_JITTER: f64 = 0.5;
+
+static CHECK_INTERVAL: OnceCell<Duration> = OnceCell::new();
+static BACKOFF_POLICY: OnceCell<BackoffPolicy> = OnceCell::new();
+
+/// How long watchers wait after failed checks: the base duration, doubled
+/// with every further check failing in a row, up to `max`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BackoffPolicy {
+    /// Wait after a first failure, overriding the default of every watcher.
+    pub base: Option<Duration>,
+    /// Longest wait, however many checks failed in a row.
+    pub max: Duration,
+    /// Share of each wait, from 0 to 1, taken off at random so that watchers
+    /// failing together, e.g. when rate limited, retry apart.
+    pub jitter: f64,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        BackoffPolicy {
+            base: None,
+            max: DEFAULT_BACKOFF_MAX,
+            jitter: DEFAULT_BACKOFF_JITTER,
+        }
+    }
+}
+
+/// Overrides the interval between checks of every watcher, unless configured
+/// per watcher. Can only be called once.
+pub fn set_check_interval(interval: Duration) -> anyhow::Result<()> {
+    anyhow::ensure!(!interval.is_zero(), "Check interval must be positive");
+    CHECK_INTERVAL
+        .set(interval)
+        .map_err(|_| anyhow::anyhow!("Check interval is already set"))
+}
+
+/// Sets how long every watcher waits after failed checks. Can only be called
+/// once.
+pub fn set_backoff_policy(policy: BackoffPolicy) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        (0.0..=1.0).contains(&policy.jitter),
+        "Backoff jitter must be between 0 and 1, got {}",
+        policy.jitter
+    );
+    anyhow::ensure!(
+        policy.base != Some(Duration::ZERO),
+        "Backoff duration must be positive"
+    );
+    anyhow::ensure!(!policy.max.is_zero(), "Maximum backoff must be positive");
+    BACKOFF_POLICY
+        .set(policy)
+        .map_err(|_| anyhow::anyhow!("Backoff policy is already set"))
+}
+
+/// The check interval set with [`set_check_interval`], or else `default`.
+pub fn check_interval(default: Duration) -> Duration {
+    CHECK_INTERVAL.get().copied().unwrap_or(default)
+}
+
+/// Check interval of `secs` seconds configured for `name`, which must be
+/// positive.
+pub(crate) fn positive_interval(name: &str, secs: u64) -> anyhow::Result<Duration> {
+    anyhow::ensure!(secs > 0, "Check interval of '{name}' must be positive");
+    Ok(Duration::from_secs(secs))
+}
+
+/// Longest time any watcher waits after failed checks.
+pub fn backoff_max() -> Duration {
+    BACKOFF_POLICY.get().copied().unwrap_or_default().max
+}
+
+/// Time `watcher` waits after its latest failed check, growing exponentially
+/// from the base of the backoff policy, or else [`DEFAULT_BACKOFF_DURATION`],
+/// with the checks that failed in a row since its last successful one.
+pub fn backoff_duration(watcher: &str) -> Duration {
+    let policy = BACKOFF_POLICY.get().copied().unwrap_or_default();
+    policy.backoff(consecutive_failures(watcher), random_fraction())
+}
+
+impl BackoffPolicy {
+    /// Wait after `consecutive_failures` checks failed in a row, with
+    /// `fraction` of the jitter taken off.
+    fn backoff(&self, consecutive_failures: u32, fraction: f64) -> Duration {
+        let base = self.base.unwrap_or(DEFAULT_BACKOFF_DURATION);
+        let doublings = consecutive_failures.saturating_sub(1).min(31);
+        let duration = base.saturating_mul(1 << doublings).min(self.max.max(base));
+        // Past what `f64` seconds hold exactly, e.g. near `Duration::MAX`,
+        // the wait is left whole rather than overflowing.
+        Duration::try_from_secs_f64(duration.as_secs_f64() * (1.0 - self.jitter * fraction))
+            .unwrap_or(duration)
+    }
+}
+
+/// Uniformly distributed in `[0, 1]`, from the random keys of a fresh
+/// `RandomState`.
+fn random_fraction() -> f64 {
+    RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(base: u64, max: u64, jitter: f64) -> BackoffPolicy {
+        BackoffPolicy {
+            base: Some(Duration::from_secs(base)),
+            max: Duration::from_secs(max),
+            jitter,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let policy = policy(10, 300, 0.0);
+        let waits: Vec<_> = (1..=7)
+            .map(|failures| policy.backoff(failures, 0.0).as_secs())
+            .collect();
+        assert_eq!(waits, [10, 20, 40, 80, 160, 300, 300]);
+    }
+
+    #[test]
+    fn backoff_without_failures_is_base() {
+        assert_eq!(
+            policy(10, 300, 0.0).backoff(0, 0.0),
+            Duration::from_secs(10)
+        );
+    }
+
+    #[test]
+    fn backoff_defaults_base() {
+        let policy = BackoffPolicy {
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(1, 0.0), DEFAULT_BACKOFF_DURATION);
+    }
+
+    #[test]
+    fn backoff_max_below_base_keeps_base() {
+        assert_eq!(policy(60, 10, 0.0).backoff(5, 0.0), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn backoff_saturates_on_many_failures() {
+        let policy = policy(10, u64::MAX, 0.0);
+        assert_eq!(policy.backoff(u32::MAX, 0.0), Duration::from_secs(10 << 31));
+    }
+
+    #[test]
+    fn backoff_does_not_overflow_near_max_duration() {
+        let policy = policy(u64::MAX, u64::MAX, 0.5);
+        assert_eq!(policy.backoff(u32::MAX, 0.0), Duration::from_secs(u64::MAX));
+        assert!(policy.backoff(u32::MAX, 1.0) <= Duration::from_secs(u64::MAX));
+    }
+
+    #[test]
+    fn backoff_jitter_takes_off_share() {
+        let policy = policy(10, 300, 0.5);
+        assert_eq!(policy.backoff(1, 1.0), Duration::from_secs(5));
+        assert_eq!(policy.backoff(1, 0.0), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn full_jitter_can_wait_zero() {
+        assert_eq!(policy(10, 300, 1.0).backoff(3, 1.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn rejects_zero_durations() {
+        assert!(set_backoff_policy(policy(0, 300, 0.5)).is_err());
+        assert!(set_backoff_policy(policy(10, 0, 0.5)).is_err());
+        assert!(set_backoff_policy(policy(10, 300, 1.5)).is_err());
+        assert!(set_check_interval(Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn rejects_zero_interval_of_watcher() {
+        assert!(positive_interval("treasury", 0).is_err());
+        assert_eq!(
+            positive_interval("treasury", 1).unwrap(),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn random_fraction_is_within_bounds() {
+        for _ in 0..100 {
+            assert!((0.0..=1.0).contains(&random_fraction()));
+        }
+    }
+}
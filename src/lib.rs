@@ -1,3 +1,80 @@
+pub mod account_set;
+pub mod account_subscription;
+pub mod address_file_balance;
+pub mod alert_rules;
+pub mod amount_format;
+pub mod anomaly;
+pub mod api;
+pub mod assertions;
+pub mod audit;
+pub mod auth;
+#[cfg(feature = "azure-monitor")]
+pub mod azure_monitor;
 pub mod balance;
+pub mod change_webhook;
+pub mod check;
+#[cfg(feature = "cloudwatch")]
+pub mod cloudwatch;
+pub mod cluster;
+pub mod config;
+pub mod daemon;
+pub mod data_slice;
+pub mod decoder;
+pub mod derive;
+pub mod derived;
+pub mod epoch;
+#[cfg(feature = "sentry")]
+pub mod error_reporting;
+pub mod explorer;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod governance;
+pub mod grafana;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod health;
+pub mod heartbeat;
+pub mod historical;
+pub mod history;
+pub mod intervals;
+pub mod log_file;
 pub mod metrics;
+pub mod mint_authority;
+pub mod observation_log;
+pub mod observations;
+pub mod outbound;
+pub mod preflight;
+pub mod prices;
+#[cfg(feature = "profiling")]
+pub mod profiling;
 pub mod program_accounts_balance;
+pub mod pyth;
+pub mod rate_limit;
+pub mod registry;
+pub mod reload;
+pub mod replay;
+pub mod rpc;
+pub mod rpc_cost;
+pub mod rpc_health;
+pub mod scan_hook;
+pub mod secrets;
+pub mod selftest;
+pub mod shutdown;
+pub mod sink;
+pub mod smoothing;
+pub mod snapshot;
+pub mod sns;
+pub mod stake;
+pub mod status_page;
+pub mod systemd;
+pub mod tenant;
+pub mod token_balance;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod validator;
+pub mod vesting;
+pub mod vote;
+pub mod wallet_pairs;
+pub mod zabbix;
@@ -0,0 +1,102 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use log::{info, warn};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    task::JoinHandle,
+};
+
+/// Log file that is reopened on request, e.g. after `logrotate` moved it
+/// away, and optionally rotated by the process itself once it grows beyond
+/// `max_size` bytes. Rotated files are kept as `<path>.1` (most recent) up to
+/// `<path>.<max_files>`.
+pub struct LogFile {
+    path: PathBuf,
+    max_size: Option<u64>,
+    max_files: usize,
+    reopen: AtomicBool,
+    state: Mutex<(File, u64)>,
+}
+
+fn open(path: &Path) -> io::Result<(File, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+impl LogFile {
+    pub fn open(path: PathBuf, max_size: Option<u64>, max_files: usize) -> anyhow::Result<Self> {
+        anyhow::ensure!(max_files > 0, "At least one rotated log file must be kept");
+        let state = open(&path)?;
+        Ok(Self {
+            path,
+            max_size,
+            max_files,
+            reopen: AtomicBool::new(false),
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Makes the next write go to a freshly opened file at the same path.
+    pub fn request_reopen(&self) {
+        self.reopen.store(true, Ordering::Relaxed);
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    fn rotate(&self) -> io::Result<()> {
+        for index in (1..self.max_files).rev() {
+            match fs::rename(self.rotated_path(index), self.rotated_path(index + 1)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))
+    }
+}
+
+impl Write for &LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        let full = self
+            .max_size
+            .is_some_and(|max_size| state.1 > 0 && state.1 + buf.len() as u64 > max_size);
+        if full {
+            self.rotate()?;
+        }
+        if full || self.reopen.swap(false, Ordering::Relaxed) {
+            *state = open(&self.path)?;
+        }
+        let written = state.0.write(buf)?;
+        state.1 += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.lock().unwrap().0.flush()
+    }
+}
+
+/// Reopens `log_file` whenever the process receives SIGUSR1.
+pub fn spawn_log_file_reopener(log_file: Arc<LogFile>) -> anyhow::Result<JoinHandle<()>> {
+    let mut signals = signal(SignalKind::user_defined1())?;
+    Ok(tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            log_file.request_reopen();
+            info!("Reopened log file {}", log_file.path.display());
+        }
+        warn!("Stopped listening for SIGUSR1");
+    }))
+}
@@ -1,11 +1,26 @@
-use std::net::SocketAddr;
+use std::{
+    collections::{BTreeSet, HashMap},
+    net::SocketAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use axum::{response::Html, routing::get, Router};
 use log::info;
 use once_cell::sync::Lazy;
-use prometheus::{register_gauge_vec, Encoder, GaugeVec, TextEncoder};
+use prometheus::{
+    core::Collector, register_counter_vec, register_gauge, register_gauge_vec,
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    CounterVec, Encoder, Gauge, GaugeVec, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
+};
+use solana_sdk::{epoch_info::EpochInfo, native_token::lamports_to_sol};
 use tokio::task::JoinHandle;
 
+use crate::{
+    prices::update_usd_valuations, rpc::RequestClass, rpc_cost::update_rpc_credits_projections,
+    stake::StakeActivation, tenant::label_metric_families, vote::VoteAccountState,
+};
+
 pub static METRIC_BALANCE_SOL: Lazy<GaugeVec> = Lazy::new(|| {
     register_gauge_vec!(
         "balance_sol",
@@ -15,6 +30,15 @@ pub static METRIC_BALANCE_SOL: Lazy<GaugeVec> = Lazy::new(|| {
     .unwrap()
 });
 
+pub static METRIC_BALANCE_DISCREPANCY_LAMPORTS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "balance_discrepancy_lamports",
+        "Difference between the highest and the lowest balance RPC endpoints reported for a Solana account",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
 pub static METRIC_TOTAL_BALANCE_SOL: Lazy<GaugeVec> = Lazy::new(|| {
     register_gauge_vec!(
         "total_balance_sol",
@@ -24,36 +48,1348 @@ pub static METRIC_TOTAL_BALANCE_SOL: Lazy<GaugeVec> = Lazy::new(|| {
     .unwrap()
 });
 
+pub static METRIC_RPC_RESPONSE_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "rpc_response_bytes_total",
+        "Size of JSON-RPC results received by a watcher",
+        &["watcher", "method"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_WATCHER_INFO: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "watcher_info",
+        "Configuration of a running watcher, always 1: its type, the program it scans, if any, and its check interval in seconds",
+        &["watcher", "type", "program", "interval"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_LAST_SUCCESSFUL_CHECK_TIMESTAMP: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "last_successful_check_timestamp_seconds",
+        "Unix time of the last check a watcher completed successfully",
+        &["watcher"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_WATCHER_RPC_ENDPOINT: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "watcher_rpc_endpoint_info",
+        "RPC endpoint that answered the latest request of a watcher",
+        &["watcher", "endpoint"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_WATCHER_STALE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "watcher_stale",
+        "Whether a watcher failed too many checks in a row for its metrics to be trusted",
+        &["watcher"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_RPC_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "rpc_requests_total",
+        "JSON-RPC requests sent by a watcher, including failed ones",
+        &["watcher", "method"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_RPC_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "rpc_errors_total",
+        "Failed JSON-RPC requests sent by a watcher, by kind of error",
+        &["watcher", "method", "kind"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_RPC_REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "rpc_request_duration_seconds",
+        "Duration of JSON-RPC requests sent by a watcher, including failed ones",
+        &["watcher", "method"],
+        vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0]
+    )
+    .unwrap()
+});
+
+pub static METRIC_RPC_CREDITS: Lazy<CounterVec> = Lazy::new(|| {
+    register_counter_vec!(
+        "rpc_credits_total",
+        "Estimated RPC provider credits used by a watcher",
+        &["watcher", "method"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_RPC_CREDITS_MONTHLY_PROJECTION: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "rpc_credits_monthly_projection",
+        "Estimated RPC provider credits a watcher uses in 30 days at its rate so far",
+        &["watcher"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_RPC_ENDPOINT_REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "rpc_endpoint_request_duration_seconds",
+        "Duration of requests sent to an RPC endpoint, by request class",
+        &["endpoint", "class"],
+        vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0]
+    )
+    .unwrap()
+});
+
+pub static METRIC_RPC_MAX_ACCOUNTS_PER_REQUEST: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "rpc_max_accounts_per_request",
+        "Accounts per getMultipleAccounts request sent to an RPC endpoint, lowered when it rejects larger ones",
+        &["endpoint"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_RPC_RATE_LIMIT_WAIT: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "rpc_rate_limit_wait_seconds",
+        "Time the latest request of a watcher waited for the client-side RPC rate limit",
+        &["watcher"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_RPC_ACTIVE_ENDPOINT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "rpc_active_endpoint",
+        "Whether an RPC endpoint answered the latest request of any watcher",
+        &["endpoint"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_RPC_FAILOVERS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "rpc_failovers_total",
+        "Requests retried against the next RPC endpoint after an endpoint failed them",
+        &["endpoint"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_RPC_HEALTHY: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "rpc_healthy",
+        "Whether an RPC endpoint reported itself healthy on its latest getHealth",
+        &["endpoint"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_RPC_HEALTH_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "rpc_health_failures_total",
+        "getHealth requests to an RPC endpoint that failed or reported it unhealthy",
+        &["endpoint"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_ANOMALY_SCORE: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "anomaly_score",
+        "Deviation of the latest balance from its moving average, in standard deviations",
+        &["watcher", "name"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_BALANCE_SMOOTHED_SOL: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "balance_smoothed_sol",
+        "Exponentially weighted moving average of a balance of SOL",
+        &["watcher", "name"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_PROGRAM_ACCOUNTS_AGGREGATE: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "program_accounts_aggregate",
+        "Aggregate computed by a scan hook over the accounts matched by a program-accounts scan",
+        &["name", "hook", "aggregate"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_PROGRAM_ACCOUNTS_MATCHED: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "program_accounts_matched",
+        "Number of accounts matched by a program-accounts scan",
+        &["name"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_PROGRAM_ACCOUNTS_CHANGES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "program_accounts_changes_total",
+        "Accounts added to or removed from the set matched by a program-accounts scan",
+        &["name", "change"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_NOTIFICATIONS_SENT: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "notifications_sent_total",
+        "Notifications delivered by a notifier or sink",
+        &["notifier"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_NOTIFICATIONS_FAILED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "notifications_failed_total",
+        "Failed attempts to deliver a notification, including ones retried later",
+        &["notifier"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_ACCOUNT_ASSERTION_FAILED: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "account_assertion_failed",
+        "Set to 1 while a watched account does not have its expected owner or size",
+        &["name", "pubkey", "assertion"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_VESTING_AMOUNT: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "vesting_amount",
+        "Tokens held by a vesting contract in the smallest unit of their mint, by vesting state",
+        &["name", "pubkey", "mint", "state"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_ALERT_FIRING: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "alert_firing",
+        "Set to 1 while all conditions of an alert rule hold",
+        &["rule"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_WALLET_PAIR_HOT_SHARE: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "wallet_pair_hot_share",
+        "Share of the combined funds of a hot and cold wallet pair held by the hot wallet",
+        &["pair"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_WALLET_PAIR_HOT_SHARE_EXCEEDED: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "wallet_pair_hot_share_exceeded",
+        "Set to 1 while the hot wallet of a pair holds more than its allowed share",
+        &["pair"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_WALLET_PAIR_MAX_HOT_SHARE: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "wallet_pair_max_hot_share",
+        "Largest share of the combined funds the hot wallet of a pair may hold",
+        &["pair"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_WALLET_PAIR_TARGET_HOT_SHARE: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "wallet_pair_target_hot_share",
+        "Share of the combined funds the hot wallet of a pair is kept at",
+        &["pair"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_WALLET_PAIR_REBALANCE_SOL: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "wallet_pair_rebalance_sol",
+        "SOL to move from the hot to the cold wallet of a pair to reach the target share, negative to top up the hot wallet",
+        &["pair"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_STAKE_ACTIVE_SOL: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "stake_active_sol",
+        "Effective stake of a stake account that is not deactivating",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_STAKE_ACTIVATING_SOL: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "stake_activating_sol",
+        "Stake of a stake account that is warming up",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_STAKE_DEACTIVATING_SOL: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "stake_deactivating_sol",
+        "Stake of a stake account that is cooling down",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_STAKE_INACTIVE_SOL: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "stake_inactive_sol",
+        "Undelegated SOL in a stake account, including the rent-exempt reserve",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_WITHDRAW_AUTHORITY_STAKE_SOL: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "withdraw_authority_stake_sol",
+        "Total SOL by activation state in stake accounts of a withdraw authority",
+        &["name", "authority", "state"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_WITHDRAW_AUTHORITY_STAKE_ACCOUNTS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "withdraw_authority_stake_accounts",
+        "Number of stake accounts of a withdraw authority",
+        &["name", "authority"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_WITHDRAW_AUTHORITY_STAKE_ACCOUNT_CHANGES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "withdraw_authority_stake_account_changes_total",
+        "Merges and splits of stake accounts of a withdraw authority, and accounts added or removed otherwise",
+        &["name", "authority", "change"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_MINT_AUTHORITY: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "mint_authority",
+        "Current mint or freeze authority of a token mint, none if revoked",
+        &["name", "mint", "authority", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_MINT_AUTHORITY_BALANCE_SOL: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "mint_authority_balance_sol",
+        "Balance of SOL of the mint or freeze authority of a token mint",
+        &["name", "authority", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_MINT_AUTHORITY_CHANGES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "mint_authority_changes_total",
+        "Number of times the mint or freeze authority of a token mint changed",
+        &["name", "authority"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_MINT_AUTHORITY_UNEXPECTED: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "mint_authority_unexpected",
+        "Whether the mint or freeze authority of a token mint differs from the expected one",
+        &["name", "authority"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_VOTE_CREDITS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "vote_credits",
+        "Credits earned by a vote account over its lifetime",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_VOTE_EPOCH_CREDITS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "vote_epoch_credits",
+        "Credits earned by a vote account in the latest epoch it voted in",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_VOTE_COMMISSION_PERCENT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "vote_commission_percent",
+        "Commission of a vote account in percent",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_VOTE_LAST_VOTE_SLOT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "vote_last_vote_slot",
+        "Slot of the latest vote of a vote account",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_VOTE_ROOT_SLOT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "vote_root_slot",
+        "Root slot of a vote account",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_VALIDATOR_DELINQUENT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "validator_delinquent",
+        "Whether the validator of a vote account has not voted in the last 128 slots",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_SLOTS_BEHIND: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "slots_behind",
+        "Slots since the latest vote of a vote account",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_STAKE_DELEGATION: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "stake_delegation",
+        "Set to 1 for the vote account a stake account is delegated to",
+        &["name", "pubkey", "voter"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_STAKE_ACTIVATION_EPOCH: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "stake_activation_epoch",
+        "Epoch the delegation of a stake account was activated in",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_STAKE_DEACTIVATION_EPOCH: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "stake_deactivation_epoch",
+        "Epoch the delegation of a stake account was deactivated in, absent while it is not deactivated",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_EPOCH_START_BALANCE_SOL: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "epoch_start_balance_sol",
+        "Balance of SOL in a Solana account at the start of the epoch in snapshot_epoch",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_EPOCH_START_TOTAL_BALANCE_SOL: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "epoch_start_total_balance_sol",
+        "Total balance of SOL in many Solana accounts at the start of the epoch in snapshot_epoch",
+        &["name"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_PRICE_USD: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "price_usd",
+        "Latest USD price of SOL or of a token mint",
+        &["asset"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_BALANCE_USD: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "balance_usd",
+        "Balance of SOL in a Solana account valued in USD",
+        &["name", "pubkey"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_TOTAL_BALANCE_USD: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "total_balance_usd",
+        "Total balance of SOL in many Solana accounts valued in USD",
+        &["name"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_TOKEN_BALANCE_USD: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "token_balance_usd",
+        "Balance of a token account valued in USD, gross or net of the transfer fee",
+        &["name", "pubkey", "mint", "kind"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_SNAPSHOT_EPOCH: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "snapshot_epoch",
+        "Epoch whose start the epoch_start_* balances were snapshotted at"
+    )
+    .unwrap()
+});
+
+pub static METRIC_TOKEN_BALANCE: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "token_balance",
+        "Balance of a token account, gross or net of the transfer fee for withdrawing all of it",
+        &["name", "pubkey", "mint", "kind"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_TOKEN_AMOUNT_RAW: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "token_amount_raw",
+        "Amount in a token account in the smallest unit of its mint, gross or net of the transfer fee for withdrawing all of it, or withheld transfer fees",
+        &["name", "pubkey", "mint", "program", "kind"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_TOKEN_WITHHELD_FEES: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "token_withheld_fees",
+        "Token-2022 transfer fees withheld in a token account",
+        &["name", "pubkey", "mint"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_DECODED_ACCOUNT_FIELD: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "decoded_account_field",
+        "Scaled value of a field decoded from account data by a declarative decoder",
+        &["name", "pubkey", "field"]
+    )
+    .unwrap()
+});
+
+pub static METRIC_EPOCH: Lazy<IntGauge> =
+    Lazy::new(|| register_int_gauge!("epoch", "Current epoch").unwrap());
+
+pub static METRIC_SLOT_INDEX: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "slot_index",
+        "Slot of the current epoch, counted from its start"
+    )
+    .unwrap()
+});
+
+pub static METRIC_SLOTS_IN_EPOCH: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!("slots_in_epoch", "Number of slots in the current epoch").unwrap()
+});
+
+pub static METRIC_EPOCH_PROGRESS_RATIO: Lazy<Gauge> = Lazy::new(|| {
+    register_gauge!(
+        "epoch_progress_ratio",
+        "Share of the slots of the current epoch that have passed"
+    )
+    .unwrap()
+});
+
+pub static METRIC_CURRENT_SLOT: Lazy<IntGauge> =
+    Lazy::new(|| register_int_gauge!("current_slot", "Slot the RPC endpoint is at").unwrap());
+
+pub static METRIC_BLOCK_HEIGHT: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!("block_height", "Block height the RPC endpoint is at").unwrap()
+});
+
+pub static METRIC_SHUTTING_DOWN: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "shutting_down",
+        "Set to 1 once the watcher received a termination signal"
+    )
+    .unwrap()
+});
+
+pub static METRIC_BALANCE_SUBSCRIPTION_ACTIVE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "balance_subscription_active",
+        "Set to 1 while balance changes of a watcher are pushed over the WebSocket, 0 while they are polled",
+        &["watcher"]
+    )
+    .unwrap()
+});
+
 pub fn update_metric_balance_sol(name: &str, pubkey: &str, lamports: f64) {
     METRIC_BALANCE_SOL
         .with_label_values(&[name, pubkey])
         .set(lamports);
 }
 
+pub fn update_metric_balance_discrepancy(name: &str, pubkey: &str, lamports: u64) {
+    METRIC_BALANCE_DISCREPANCY_LAMPORTS
+        .with_label_values(&[name, pubkey])
+        .set(lamports as i64);
+}
+
+pub fn remove_metric_balance_discrepancy(name: &str, pubkey: &str) {
+    let _ = METRIC_BALANCE_DISCREPANCY_LAMPORTS.remove_label_values(&[name, pubkey]);
+}
+
 pub fn update_metric_total_balance_sol(name: &str, lamports: f64) {
     METRIC_TOTAL_BALANCE_SOL
         .with_label_values(&[name])
         .set(lamports);
 }
 
+pub fn update_metric_anomaly_score(watcher: &str, name: &str, score: f64) {
+    METRIC_ANOMALY_SCORE
+        .with_label_values(&[watcher, name])
+        .set(score);
+}
+
+pub fn update_metric_balance_smoothed_sol(watcher: &str, name: &str, balance: f64) {
+    METRIC_BALANCE_SMOOTHED_SOL
+        .with_label_values(&[watcher, name])
+        .set(balance);
+}
+
+pub fn update_metric_notifications_sent(notifier: &str) {
+    METRIC_NOTIFICATIONS_SENT
+        .with_label_values(&[notifier])
+        .inc();
+}
+
+pub fn update_metric_notifications_failed(notifier: &str) {
+    METRIC_NOTIFICATIONS_FAILED
+        .with_label_values(&[notifier])
+        .inc();
+}
+
+pub fn remove_metric_notifications(notifier: &str) {
+    let _ = METRIC_NOTIFICATIONS_SENT.remove_label_values(&[notifier]);
+    let _ = METRIC_NOTIFICATIONS_FAILED.remove_label_values(&[notifier]);
+}
+
+pub fn update_metric_account_assertion_failed(
+    name: &str,
+    pubkey: &str,
+    assertion: &str,
+    failed: bool,
+) {
+    METRIC_ACCOUNT_ASSERTION_FAILED
+        .with_label_values(&[name, pubkey, assertion])
+        .set(i64::from(failed));
+}
+
+pub fn remove_metric_account_assertion_failed(name: &str, pubkey: &str) {
+    for assertion in ["owner", "size"] {
+        let _ = METRIC_ACCOUNT_ASSERTION_FAILED.remove_label_values(&[name, pubkey, assertion]);
+    }
+}
+
+pub fn update_metric_vesting_amount(
+    name: &str,
+    pubkey: &str,
+    mint: &str,
+    state: &str,
+    amount: u64,
+) {
+    METRIC_VESTING_AMOUNT
+        .with_label_values(&[name, pubkey, mint, state])
+        .set(amount as f64);
+}
+
+pub fn remove_metric_vesting_amount(name: &str, pubkey: &str, mint: &str) {
+    for state in ["vested", "unvested"] {
+        let _ = METRIC_VESTING_AMOUNT.remove_label_values(&[name, pubkey, mint, state]);
+    }
+}
+
+pub fn update_metric_alert_firing(rule: &str, firing: bool) {
+    METRIC_ALERT_FIRING
+        .with_label_values(&[rule])
+        .set(i64::from(firing));
+}
+
+pub fn update_metric_wallet_pair_hot_share(pair: &str, share: f64, exceeded: bool) {
+    METRIC_WALLET_PAIR_HOT_SHARE
+        .with_label_values(&[pair])
+        .set(share);
+    METRIC_WALLET_PAIR_HOT_SHARE_EXCEEDED
+        .with_label_values(&[pair])
+        .set(i64::from(exceeded));
+}
+
+pub fn update_metric_wallet_pair_limits(pair: &str, max_hot_share: f64, target: Option<f64>) {
+    METRIC_WALLET_PAIR_MAX_HOT_SHARE
+        .with_label_values(&[pair])
+        .set(max_hot_share);
+    if let Some(target) = target {
+        METRIC_WALLET_PAIR_TARGET_HOT_SHARE
+            .with_label_values(&[pair])
+            .set(target);
+    }
+}
+
+pub fn update_metric_wallet_pair_rebalance_sol(pair: &str, sol: f64) {
+    METRIC_WALLET_PAIR_REBALANCE_SOL
+        .with_label_values(&[pair])
+        .set(sol);
+}
+
+pub fn update_metric_epoch_start_balance_sol(name: &str, pubkey: &str, balance: f64) {
+    METRIC_EPOCH_START_BALANCE_SOL
+        .with_label_values(&[name, pubkey])
+        .set(balance);
+}
+
+pub fn update_metric_epoch_start_total_balance_sol(name: &str, balance: f64) {
+    METRIC_EPOCH_START_TOTAL_BALANCE_SOL
+        .with_label_values(&[name])
+        .set(balance);
+}
+
+pub fn update_metric_epoch_info(epoch_info: &EpochInfo) {
+    METRIC_EPOCH.set(epoch_info.epoch as i64);
+    METRIC_SLOT_INDEX.set(epoch_info.slot_index as i64);
+    METRIC_SLOTS_IN_EPOCH.set(epoch_info.slots_in_epoch as i64);
+    METRIC_EPOCH_PROGRESS_RATIO
+        .set(epoch_info.slot_index as f64 / epoch_info.slots_in_epoch as f64);
+}
+
+pub fn update_metric_cluster_progress(slot: u64, block_height: u64) {
+    METRIC_CURRENT_SLOT.set(slot as i64);
+    METRIC_BLOCK_HEIGHT.set(block_height as i64);
+}
+
+pub fn update_metric_snapshot_epoch(epoch: u64) {
+    METRIC_SNAPSHOT_EPOCH.set(epoch as i64);
+}
+
+pub fn update_metric_token_balance(name: &str, pubkey: &str, mint: &str, kind: &str, amount: f64) {
+    METRIC_TOKEN_BALANCE
+        .with_label_values(&[name, pubkey, mint, kind])
+        .set(amount);
+}
+
+pub fn update_metric_token_amount_raw(
+    name: &str,
+    pubkey: &str,
+    mint: &str,
+    program: &str,
+    kind: &str,
+    amount: u64,
+) {
+    METRIC_TOKEN_AMOUNT_RAW
+        .with_label_values(&[name, pubkey, mint, program, kind])
+        .set(amount as f64);
+}
+
+pub fn update_metric_token_withheld_fees(name: &str, pubkey: &str, mint: &str, amount: f64) {
+    METRIC_TOKEN_WITHHELD_FEES
+        .with_label_values(&[name, pubkey, mint])
+        .set(amount);
+}
+
+pub fn update_metric_decoded_account_field(name: &str, pubkey: &str, field: &str, value: f64) {
+    METRIC_DECODED_ACCOUNT_FIELD
+        .with_label_values(&[name, pubkey, field])
+        .set(value);
+}
+
+pub fn update_metric_rpc_response_bytes(watcher: &str, method: &str, bytes: u64) {
+    METRIC_RPC_RESPONSE_BYTES
+        .with_label_values(&[watcher, method])
+        .inc_by(bytes);
+}
+
+pub fn update_metric_rpc_request(watcher: &str, method: &str, credits: f64) {
+    METRIC_RPC_REQUESTS
+        .with_label_values(&[watcher, method])
+        .inc();
+    METRIC_RPC_CREDITS
+        .with_label_values(&[watcher, method])
+        .inc_by(credits);
+}
+
+/// Describes the running `watcher`, replacing any previous description.
+pub fn update_metric_watcher_info(watcher: &str, kind: &str, program: &str, interval: Duration) {
+    remove_metric_watcher_info(watcher);
+    METRIC_WATCHER_INFO
+        .with_label_values(&[watcher, kind, program, &interval.as_secs().to_string()])
+        .set(1.0);
+}
+
+/// Check interval of every running watcher, from `watcher_info`.
+pub fn watcher_intervals() -> HashMap<String, Duration> {
+    let label_names = ["watcher", "type", "program", "interval"];
+    gauge_values(&METRIC_WATCHER_INFO, &label_names)
+        .into_iter()
+        .filter_map(|(labels, _)| {
+            let interval = labels[3].parse().ok()?;
+            Some((labels[0].clone(), Duration::from_secs(interval)))
+        })
+        .collect()
+}
+
+pub fn remove_metric_watcher_info(watcher: &str) {
+    let label_names = ["watcher", "type", "program", "interval"];
+    for (labels, _) in gauge_values(&METRIC_WATCHER_INFO, &label_names) {
+        if labels[0] == watcher {
+            let labels: Vec<_> = labels.iter().map(String::as_str).collect();
+            let _ = METRIC_WATCHER_INFO.remove_label_values(&labels);
+        }
+    }
+}
+
+pub fn update_metric_last_successful_check(watcher: &str, time: SystemTime) {
+    let timestamp = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    METRIC_LAST_SUCCESSFUL_CHECK_TIMESTAMP
+        .with_label_values(&[watcher])
+        .set(timestamp);
+}
+
+pub fn remove_metric_last_successful_check(watcher: &str) {
+    let _ = METRIC_LAST_SUCCESSFUL_CHECK_TIMESTAMP.remove_label_values(&[watcher]);
+}
+
+pub fn update_metric_watcher_rpc_endpoint(watcher: &str, endpoint: &str) {
+    remove_metric_watcher_rpc_endpoint(watcher);
+    METRIC_WATCHER_RPC_ENDPOINT
+        .with_label_values(&[watcher, endpoint])
+        .set(1.0);
+}
+
+pub fn remove_metric_watcher_rpc_endpoint(watcher: &str) {
+    for (labels, _) in gauge_values(&METRIC_WATCHER_RPC_ENDPOINT, &["watcher", "endpoint"]) {
+        if labels[0] == watcher {
+            let _ = METRIC_WATCHER_RPC_ENDPOINT.remove_label_values(&[&labels[0], &labels[1]]);
+        }
+    }
+}
+
+pub fn update_metric_watcher_stale(watcher: &str, stale: bool) {
+    METRIC_WATCHER_STALE
+        .with_label_values(&[watcher])
+        .set(stale.into());
+}
+
+pub fn remove_metric_watcher_stale(watcher: &str) {
+    let _ = METRIC_WATCHER_STALE.remove_label_values(&[watcher]);
+}
+
+pub fn update_metric_rpc_error(watcher: &str, method: &str, kind: &str) {
+    METRIC_RPC_ERRORS
+        .with_label_values(&[watcher, method, kind])
+        .inc();
+}
+
+pub fn observe_metric_rpc_request_duration(watcher: &str, method: &str, duration: Duration) {
+    METRIC_RPC_REQUEST_DURATION
+        .with_label_values(&[watcher, method])
+        .observe(duration.as_secs_f64());
+}
+
+pub fn update_metric_rpc_credits_monthly_projection(watcher: &str, credits: f64) {
+    METRIC_RPC_CREDITS_MONTHLY_PROJECTION
+        .with_label_values(&[watcher])
+        .set(credits);
+}
+
+pub fn remove_metric_rpc_credits_monthly_projection(watcher: &str) {
+    let _ = METRIC_RPC_CREDITS_MONTHLY_PROJECTION.remove_label_values(&[watcher]);
+}
+
+pub fn observe_metric_rpc_endpoint_request_duration(
+    endpoint: &str,
+    class: &str,
+    duration: Duration,
+) {
+    METRIC_RPC_ENDPOINT_REQUEST_DURATION
+        .with_label_values(&[endpoint, class])
+        .observe(duration.as_secs_f64());
+}
+
+/// Mean request duration in seconds, `None` until a request was observed.
+pub fn mean_rpc_endpoint_request_duration(endpoint: &str, class: &str) -> Option<f64> {
+    let histogram = METRIC_RPC_ENDPOINT_REQUEST_DURATION.with_label_values(&[endpoint, class]);
+    match histogram.get_sample_count() {
+        0 => None,
+        count => Some(histogram.get_sample_sum() / count as f64),
+    }
+}
+
+pub fn remove_metric_rpc_endpoint_request_duration(endpoint: &str) {
+    for class in [RequestClass::Read, RequestClass::Scan] {
+        let _ =
+            METRIC_RPC_ENDPOINT_REQUEST_DURATION.remove_label_values(&[endpoint, class.as_str()]);
+    }
+}
+
+pub fn update_metric_rpc_healthy(endpoint: &str, healthy: bool) {
+    METRIC_RPC_HEALTHY
+        .with_label_values(&[endpoint])
+        .set(i64::from(healthy));
+    if !healthy {
+        METRIC_RPC_HEALTH_FAILURES
+            .with_label_values(&[endpoint])
+            .inc();
+    }
+}
+
+pub fn update_metric_rpc_max_accounts_per_request(endpoint: &str, accounts: usize) {
+    METRIC_RPC_MAX_ACCOUNTS_PER_REQUEST
+        .with_label_values(&[endpoint])
+        .set(accounts as i64);
+}
+
+pub fn remove_metric_rpc_max_accounts_per_request(endpoint: &str) {
+    let _ = METRIC_RPC_MAX_ACCOUNTS_PER_REQUEST.remove_label_values(&[endpoint]);
+}
+
+pub fn update_metric_rpc_active_endpoint(endpoint: &str, active: bool) {
+    METRIC_RPC_ACTIVE_ENDPOINT
+        .with_label_values(&[endpoint])
+        .set(active.into());
+}
+
+pub fn remove_metric_rpc_active_endpoint(endpoint: &str) {
+    let _ = METRIC_RPC_ACTIVE_ENDPOINT.remove_label_values(&[endpoint]);
+}
+
+pub fn update_metric_rpc_failover(endpoint: &str) {
+    METRIC_RPC_FAILOVERS.with_label_values(&[endpoint]).inc();
+}
+
+pub fn remove_metric_rpc_failovers(endpoint: &str) {
+    let _ = METRIC_RPC_FAILOVERS.remove_label_values(&[endpoint]);
+}
+
+pub fn remove_metric_rpc_healthy(endpoint: &str) {
+    let _ = METRIC_RPC_HEALTHY.remove_label_values(&[endpoint]);
+    let _ = METRIC_RPC_HEALTH_FAILURES.remove_label_values(&[endpoint]);
+}
+
+pub fn remove_metric_balance_sol(name: &str, pubkey: &str) {
+    let _ = METRIC_BALANCE_SOL.remove_label_values(&[name, pubkey]);
+}
+
+pub fn update_metric_shutting_down() {
+    METRIC_SHUTTING_DOWN.set(1);
+}
+
+pub fn update_metric_rpc_rate_limit_wait(watcher: &str, wait: Duration) {
+    METRIC_RPC_RATE_LIMIT_WAIT
+        .with_label_values(&[watcher])
+        .set(wait.as_secs_f64());
+}
+
+pub fn remove_metric_rpc_rate_limit_wait(watcher: &str) {
+    let _ = METRIC_RPC_RATE_LIMIT_WAIT.remove_label_values(&[watcher]);
+}
+
+pub fn update_metric_balance_subscription_active(watcher: &str, active: bool) {
+    METRIC_BALANCE_SUBSCRIPTION_ACTIVE
+        .with_label_values(&[watcher])
+        .set(active.into());
+}
+
+pub fn remove_metric_balance_subscription_active(watcher: &str) {
+    let _ = METRIC_BALANCE_SUBSCRIPTION_ACTIVE.remove_label_values(&[watcher]);
+}
+
 pub fn reset_metric_balance_sol() {
     METRIC_BALANCE_SOL.reset();
 }
 
+pub fn update_metric_stake(name: &str, pubkey: &str, activation: &StakeActivation) {
+    let labels = [name, pubkey];
+    METRIC_STAKE_ACTIVE_SOL
+        .with_label_values(&labels)
+        .set(lamports_to_sol(activation.active));
+    METRIC_STAKE_ACTIVATING_SOL
+        .with_label_values(&labels)
+        .set(lamports_to_sol(activation.activating));
+    METRIC_STAKE_DEACTIVATING_SOL
+        .with_label_values(&labels)
+        .set(lamports_to_sol(activation.deactivating));
+    METRIC_STAKE_INACTIVE_SOL
+        .with_label_values(&labels)
+        .set(lamports_to_sol(activation.inactive));
+    if let Some(voter) = activation.voter {
+        METRIC_STAKE_DELEGATION
+            .with_label_values(&[name, pubkey, &voter.to_string()])
+            .set(1);
+    }
+    if let Some(epoch) = activation.activation_epoch {
+        METRIC_STAKE_ACTIVATION_EPOCH
+            .with_label_values(&labels)
+            .set(epoch as i64);
+    }
+    if let Some(epoch) = activation.deactivation_epoch {
+        METRIC_STAKE_DEACTIVATION_EPOCH
+            .with_label_values(&labels)
+            .set(epoch as i64);
+    }
+}
+
+pub fn update_metric_vote_account(name: &str, pubkey: &str, state: &VoteAccountState) {
+    let labels = [name, pubkey];
+    METRIC_VOTE_CREDITS
+        .with_label_values(&labels)
+        .set(state.credits as i64);
+    METRIC_VOTE_EPOCH_CREDITS
+        .with_label_values(&labels)
+        .set(state.epoch_credits as i64);
+    METRIC_VOTE_COMMISSION_PERCENT
+        .with_label_values(&labels)
+        .set(i64::from(state.commission));
+    if let Some(slot) = state.last_vote_slot {
+        METRIC_VOTE_LAST_VOTE_SLOT
+            .with_label_values(&labels)
+            .set(slot as i64);
+    }
+    if let Some(slot) = state.root_slot {
+        METRIC_VOTE_ROOT_SLOT
+            .with_label_values(&labels)
+            .set(slot as i64);
+    }
+    if let Some(behind) = state.slots_behind {
+        METRIC_SLOTS_BEHIND
+            .with_label_values(&labels)
+            .set(behind as i64);
+    }
+    METRIC_VALIDATOR_DELINQUENT
+        .with_label_values(&labels)
+        .set(i64::from(state.delinquent));
+}
+
+pub fn reset_metric_vote_account() {
+    METRIC_VOTE_CREDITS.reset();
+    METRIC_VOTE_EPOCH_CREDITS.reset();
+    METRIC_VOTE_COMMISSION_PERCENT.reset();
+    METRIC_VOTE_LAST_VOTE_SLOT.reset();
+    METRIC_VOTE_ROOT_SLOT.reset();
+    METRIC_SLOTS_BEHIND.reset();
+    METRIC_VALIDATOR_DELINQUENT.reset();
+}
+
+pub fn update_metric_withdraw_authority_stake(
+    name: &str,
+    authority: &str,
+    accounts: usize,
+    total: &StakeActivation,
+) {
+    for (state, lamports) in [
+        ("active", total.active),
+        ("activating", total.activating),
+        ("deactivating", total.deactivating),
+        ("inactive", total.inactive),
+    ] {
+        METRIC_WITHDRAW_AUTHORITY_STAKE_SOL
+            .with_label_values(&[name, authority, state])
+            .set(lamports_to_sol(lamports));
+    }
+    METRIC_WITHDRAW_AUTHORITY_STAKE_ACCOUNTS
+        .with_label_values(&[name, authority])
+        .set(accounts as i64);
+}
+
+pub fn remove_metric_withdraw_authority_stake(name: &str, authority: &str) {
+    for state in ["active", "activating", "deactivating", "inactive"] {
+        let _ = METRIC_WITHDRAW_AUTHORITY_STAKE_SOL.remove_label_values(&[name, authority, state]);
+    }
+    let _ = METRIC_WITHDRAW_AUTHORITY_STAKE_ACCOUNTS.remove_label_values(&[name, authority]);
+}
+
+pub fn update_metric_withdraw_authority_stake_account_changes(
+    name: &str,
+    authority: &str,
+    change: &str,
+    count: usize,
+) {
+    METRIC_WITHDRAW_AUTHORITY_STAKE_ACCOUNT_CHANGES
+        .with_label_values(&[name, authority, change])
+        .inc_by(count as u64);
+}
+
+pub fn update_metric_mint_authority(name: &str, mint: &str, authority: &str, pubkey: &str) {
+    METRIC_MINT_AUTHORITY
+        .with_label_values(&[name, mint, authority, pubkey])
+        .set(1);
+}
+
+pub fn update_metric_mint_authority_balance_sol(
+    name: &str,
+    authority: &str,
+    pubkey: &str,
+    balance: f64,
+) {
+    METRIC_MINT_AUTHORITY_BALANCE_SOL
+        .with_label_values(&[name, authority, pubkey])
+        .set(balance);
+}
+
+pub fn update_metric_mint_authority_changes(name: &str, authority: &str) {
+    METRIC_MINT_AUTHORITY_CHANGES
+        .with_label_values(&[name, authority])
+        .inc();
+}
+
+pub fn update_metric_mint_authority_unexpected(name: &str, authority: &str, unexpected: bool) {
+    METRIC_MINT_AUTHORITY_UNEXPECTED
+        .with_label_values(&[name, authority])
+        .set(i64::from(unexpected));
+}
+
+pub fn reset_metric_mint_authority() {
+    METRIC_MINT_AUTHORITY.reset();
+    METRIC_MINT_AUTHORITY_BALANCE_SOL.reset();
+    METRIC_MINT_AUTHORITY_UNEXPECTED.reset();
+}
+
+pub fn reset_metric_stake() {
+    METRIC_STAKE_ACTIVE_SOL.reset();
+    METRIC_STAKE_ACTIVATING_SOL.reset();
+    METRIC_STAKE_DEACTIVATING_SOL.reset();
+    METRIC_STAKE_INACTIVE_SOL.reset();
+    METRIC_STAKE_DELEGATION.reset();
+    METRIC_STAKE_ACTIVATION_EPOCH.reset();
+    METRIC_STAKE_DEACTIVATION_EPOCH.reset();
+}
+
+pub fn reset_metric_token_balance() {
+    METRIC_TOKEN_BALANCE.reset();
+    METRIC_TOKEN_AMOUNT_RAW.reset();
+    METRIC_TOKEN_WITHHELD_FEES.reset();
+}
+
+/// Removes the token balances of `pubkey` exported under `name`, of any mint.
+pub fn remove_metric_token_balance(name: &str, pubkey: &str) {
+    for (gauges, label_names) in [
+        (
+            &*METRIC_TOKEN_BALANCE,
+            &["name", "pubkey", "mint", "kind"][..],
+        ),
+        (
+            &*METRIC_TOKEN_AMOUNT_RAW,
+            &["name", "pubkey", "mint", "program", "kind"][..],
+        ),
+        (
+            &*METRIC_TOKEN_WITHHELD_FEES,
+            &["name", "pubkey", "mint"][..],
+        ),
+    ] {
+        for (labels, _) in gauge_values(gauges, label_names) {
+            if labels[0] == name && labels[1] == pubkey {
+                let labels: Vec<_> = labels.iter().map(String::as_str).collect();
+                let _ = gauges.remove_label_values(&labels);
+            }
+        }
+    }
+}
+
+pub fn reset_metric_decoded_account_field() {
+    METRIC_DECODED_ACCOUNT_FIELD.reset();
+}
+
 pub fn remove_metric_total_balance_sol(name: &str) {
     let _ = METRIC_TOTAL_BALANCE_SOL.remove_label_values(&[name]);
 }
 
+pub fn update_metric_program_accounts_matched(name: &str, accounts: usize) {
+    METRIC_PROGRAM_ACCOUNTS_MATCHED
+        .with_label_values(&[name])
+        .set(accounts as i64);
+}
+
+pub fn update_metric_program_accounts_changes(name: &str, added: usize, removed: usize) {
+    METRIC_PROGRAM_ACCOUNTS_CHANGES
+        .with_label_values(&[name, "added"])
+        .inc_by(added as u64);
+    METRIC_PROGRAM_ACCOUNTS_CHANGES
+        .with_label_values(&[name, "removed"])
+        .inc_by(removed as u64);
+}
+
+/// Exports the aggregates of `hook` over the scan `name`, dropping the ones it
+/// no longer returns.
+pub fn update_metric_program_accounts_aggregates(
+    name: &str,
+    hook: &str,
+    aggregates: &[(String, f64)],
+) {
+    let label_names = ["name", "hook", "aggregate"];
+    for (labels, _) in gauge_values(&METRIC_PROGRAM_ACCOUNTS_AGGREGATE, &label_names) {
+        if labels[0] == name
+            && labels[1] == hook
+            && !aggregates
+                .iter()
+                .any(|(aggregate, _)| *aggregate == labels[2])
+        {
+            let _ =
+                METRIC_PROGRAM_ACCOUNTS_AGGREGATE.remove_label_values(&[name, hook, &labels[2]]);
+        }
+    }
+    for (aggregate, value) in aggregates {
+        METRIC_PROGRAM_ACCOUNTS_AGGREGATE
+            .with_label_values(&[name, hook, aggregate])
+            .set(*value);
+    }
+}
+
+pub fn remove_metric_program_accounts(name: &str) {
+    let _ = METRIC_PROGRAM_ACCOUNTS_MATCHED.remove_label_values(&[name]);
+    for change in ["added", "removed"] {
+        let _ = METRIC_PROGRAM_ACCOUNTS_CHANGES.remove_label_values(&[name, change]);
+    }
+    for (labels, _) in gauge_values(
+        &METRIC_PROGRAM_ACCOUNTS_AGGREGATE,
+        &["name", "hook", "aggregate"],
+    ) {
+        if labels[0] == name {
+            let _ = METRIC_PROGRAM_ACCOUNTS_AGGREGATE
+                .remove_label_values(&[name, &labels[1], &labels[2]]);
+        }
+    }
+}
+
+/// Current values of `gauges` along with their labels, in the order of
+/// `label_names`.
+fn gauge_values(gauges: &GaugeVec, label_names: &[&str]) -> Vec<(Vec<String>, f64)> {
+    gauges
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .map(|metric| {
+            let labels = label_names
+                .iter()
+                .map(|name| {
+                    metric
+                        .get_label()
+                        .iter()
+                        .find(|label| label.get_name() == *name)
+                        .map(|label| label.get_value().to_string())
+                        .unwrap_or_default()
+                })
+                .collect();
+            (labels, metric.get_gauge().get_value())
+        })
+        .collect()
+}
+
+/// Mints of the token accounts with an exported balance.
+pub fn watched_token_mints() -> BTreeSet<String> {
+    gauge_values(&METRIC_TOKEN_BALANCE, &["mint"])
+        .into_iter()
+        .filter_map(|(mut labels, _)| labels.pop())
+        .collect()
+}
+
+pub fn update_metric_price_usd(asset: &str, price: f64) {
+    METRIC_PRICE_USD.with_label_values(&[asset]).set(price);
+}
+
+/// Derives the USD gauges from the exported balances, so that they never
+/// outlive the balance they value.
+pub fn update_metric_usd_valuations(sol_price: Option<f64>, token_prices: &HashMap<String, f64>) {
+    METRIC_BALANCE_USD.reset();
+    METRIC_TOTAL_BALANCE_USD.reset();
+    METRIC_TOKEN_BALANCE_USD.reset();
+    if let Some(price) = sol_price {
+        for (labels, sol) in gauge_values(&METRIC_BALANCE_SOL, &["name", "pubkey"]) {
+            let labels: Vec<_> = labels.iter().map(String::as_str).collect();
+            METRIC_BALANCE_USD
+                .with_label_values(&labels)
+                .set(sol * price);
+        }
+        for (labels, sol) in gauge_values(&METRIC_TOTAL_BALANCE_SOL, &["name"]) {
+            let labels: Vec<_> = labels.iter().map(String::as_str).collect();
+            METRIC_TOTAL_BALANCE_USD
+                .with_label_values(&labels)
+                .set(sol * price);
+        }
+    }
+    let token_labels = ["name", "pubkey", "mint", "kind"];
+    for (labels, amount) in gauge_values(&METRIC_TOKEN_BALANCE, &token_labels) {
+        let Some(price) = token_prices.get(&labels[2]) else {
+            continue;
+        };
+        let labels: Vec<_> = labels.iter().map(String::as_str).collect();
+        METRIC_TOKEN_BALANCE_USD
+            .with_label_values(&labels)
+            .set(amount * price);
+    }
+}
+
 async fn handler() -> Html<String> {
+    update_rpc_credits_projections();
+    update_usd_valuations();
     let mut buffer = Vec::new();
-    TextEncoder::new()
-        .encode(&prometheus::gather(), &mut buffer)
-        .unwrap();
+    let mut families = prometheus::gather();
+    label_metric_families(&mut families);
+    TextEncoder::new().encode(&families, &mut buffer).unwrap();
 
     Html(String::from_utf8(buffer.clone()).unwrap())
 }
 
-pub fn spawn_metrics_server(port: u16) -> JoinHandle<()> {
+pub fn spawn_metrics_server(port: u16, routes: Router) -> JoinHandle<()> {
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     info!("Serving metrics on {}", addr);
 
@@ -62,6 +1398,7 @@ pub fn spawn_metrics_server(port: u16) -> JoinHandle<()> {
             .serve(
                 Router::new()
                     .route("/metrics", get(handler))
+                    .merge(routes)
                     .into_make_service(),
             )
             .await
@@ -0,0 +1,269 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+use log::{error, info};
+use solana_account_decoder::parse_token::is_known_spl_token_id;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{account::Account, native_token::lamports_to_sol, pubkey::Pubkey};
+use spl_token_2022::{extension::StateWithExtensions, state::Mint};
+use tokio::task::JoinHandle;
+
+use crate::{
+    health::{record_failed_check, record_successful_check},
+    intervals::{backoff_duration, check_interval, DEFAULT_CHECK_INTERVAL},
+    metrics::{
+        reset_metric_mint_authority, update_metric_mint_authority,
+        update_metric_mint_authority_balance_sol, update_metric_mint_authority_changes,
+        update_metric_mint_authority_unexpected, update_metric_watcher_info,
+    },
+    observations::{record_observation, Observation},
+    rate_limit::RateLimiter,
+    shutdown::sleep_unless_shutdown,
+};
+
+pub const WATCHER_NAME: &str = "mint_authority";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuthorityType {
+    Mint,
+    Freeze,
+}
+
+impl AuthorityType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthorityType::Mint => "mint",
+            AuthorityType::Freeze => "freeze",
+        }
+    }
+}
+
+/// `pubkey`, or `none` for a revoked authority.
+struct DisplayAuthority(Option<Pubkey>);
+
+impl fmt::Display for DisplayAuthority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(pubkey) => write!(f, "{pubkey}"),
+            None => write!(f, "none"),
+        }
+    }
+}
+
+/// A token mint whose authorities are watched, parsed from
+/// `name=MINT [mint:PUBKEY|none] [freeze:PUBKEY|none]`, optionally pinning
+/// the authorities the mint is expected to have.
+#[derive(Debug, Clone)]
+pub struct WatchedMint {
+    pub name: String,
+    pub mint: Pubkey,
+    /// Expected authority per type, where `None` means revoked.
+    expected: HashMap<AuthorityType, Option<Pubkey>>,
+}
+
+fn parse_authority(value: &str) -> anyhow::Result<Option<Pubkey>> {
+    match value {
+        "none" => Ok(None),
+        _ => match Pubkey::from_str(value) {
+            Ok(pubkey) => Ok(Some(pubkey)),
+            Err(_) => anyhow::bail!("Failed to parse authority from '{value}'"),
+        },
+    }
+}
+
+impl FromStr for WatchedMint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, params)) = s.split_once('=') else {
+            anyhow::bail!("Cannot parse watched mint, expected syntax: name=MINT");
+        };
+        let mut params = params.split(' ').filter(|param| !param.is_empty());
+        let mint = match params.next() {
+            Some(mint) => match Pubkey::from_str(mint) {
+                Ok(mint) => mint,
+                Err(_) => anyhow::bail!("Failed to parse mint from '{mint}'"),
+            },
+            None => anyhow::bail!("Mint of '{name}' not found!"),
+        };
+        let mut expected = HashMap::new();
+        for param in params {
+            match param.split_once(':') {
+                Some(("mint", value)) => {
+                    expected.insert(AuthorityType::Mint, parse_authority(value)?);
+                }
+                Some(("freeze", value)) => {
+                    expected.insert(AuthorityType::Freeze, parse_authority(value)?);
+                }
+                _ => anyhow::bail!("Unsupported parameter '{param}' of mint '{name}'"),
+            }
+        }
+        Ok(WatchedMint {
+            name: name.to_string(),
+            mint,
+            expected,
+        })
+    }
+}
+
+impl WatchedMint {
+    /// Name of the observations of the balance of an authority.
+    fn authority_name(&self, authority_type: AuthorityType) -> String {
+        format!("{}_{}_authority", self.name, authority_type.as_str())
+    }
+}
+
+fn unpack_authorities(account: &Account) -> anyhow::Result<[(AuthorityType, Option<Pubkey>); 2]> {
+    anyhow::ensure!(
+        is_known_spl_token_id(&account.owner),
+        "Account is owned by {}, not a token program",
+        account.owner
+    );
+    let state = StateWithExtensions::<Mint>::unpack(&account.data)?;
+    Ok([
+        (AuthorityType::Mint, state.base.mint_authority.into()),
+        (AuthorityType::Freeze, state.base.freeze_authority.into()),
+    ])
+}
+
+/// Resolves the authorities of `mints`, logging and counting changes since
+/// `previous` and authorities other than expected, then fetches and records
+/// the balances of the authorities.
+async fn check_mint_authorities(
+    rpc_client: &RpcClient,
+    mints: &[WatchedMint],
+    previous: &mut HashMap<(String, AuthorityType), Option<Pubkey>>,
+) -> anyhow::Result<()> {
+    let pubkeys: Vec<_> = mints.iter().map(|mint| mint.mint).collect();
+    let accounts = rpc_client.get_multiple_accounts(&pubkeys).await?;
+
+    reset_metric_mint_authority();
+    let mut authorities = vec![];
+    for (mint, account) in mints.iter().zip(accounts) {
+        let resolved = match account {
+            Some(account) => unpack_authorities(&account),
+            None => Err(anyhow::anyhow!("Account does not exist")),
+        };
+        let resolved = match resolved {
+            Ok(resolved) => resolved,
+            Err(err) => {
+                error!("Cannot decode mint {} ({}): {err}", mint.name, mint.mint);
+                continue;
+            }
+        };
+        for (authority_type, authority) in resolved {
+            let type_name = authority_type.as_str();
+            let key = (mint.name.clone(), authority_type);
+            match previous.insert(key, authority) {
+                Some(before) if before != authority => {
+                    error!(
+                        "The {type_name} authority of mint {} ({}) changed from {} to {}",
+                        mint.name,
+                        mint.mint,
+                        DisplayAuthority(before),
+                        DisplayAuthority(authority)
+                    );
+                    update_metric_mint_authority_changes(&mint.name, type_name);
+                }
+                Some(_) => {}
+                None => info!(
+                    "The {type_name} authority of mint {} ({}) is {}",
+                    mint.name,
+                    mint.mint,
+                    DisplayAuthority(authority)
+                ),
+            }
+            if let Some(expected) = mint.expected.get(&authority_type) {
+                let unexpected = *expected != authority;
+                if unexpected {
+                    error!(
+                        "The {type_name} authority of mint {} ({}) is {}, expected {}",
+                        mint.name,
+                        mint.mint,
+                        DisplayAuthority(authority),
+                        DisplayAuthority(*expected)
+                    );
+                }
+                update_metric_mint_authority_unexpected(&mint.name, type_name, unexpected);
+            }
+            let pubkey = DisplayAuthority(authority).to_string();
+            update_metric_mint_authority(&mint.name, &mint.mint.to_string(), type_name, &pubkey);
+            if let Some(authority) = authority {
+                authorities.push((mint, authority_type, authority));
+            }
+        }
+    }
+    if authorities.is_empty() {
+        return Ok(());
+    }
+
+    let start = Instant::now();
+    let pubkeys: Vec<_> = authorities.iter().map(|(_, _, pubkey)| *pubkey).collect();
+    let response = rpc_client
+        .get_multiple_accounts_with_commitment(&pubkeys, rpc_client.commitment())
+        .await?;
+    let duration = start.elapsed();
+    for ((mint, authority_type, pubkey), account) in authorities.into_iter().zip(response.value) {
+        let lamports = account.map_or(0, |account| account.lamports);
+        update_metric_mint_authority_balance_sol(
+            &mint.name,
+            authority_type.as_str(),
+            &pubkey.to_string(),
+            lamports_to_sol(lamports),
+        );
+        record_observation(Observation {
+            watcher: WATCHER_NAME.to_string(),
+            name: mint.authority_name(authority_type),
+            pubkey: Some(pubkey),
+            lamports,
+            slot: Some(response.context.slot),
+            duration,
+            observed_at: SystemTime::now(),
+        });
+    }
+    Ok(())
+}
+
+/// Resolves the mint and freeze authorities of each watched mint, exporting
+/// them and their SOL balances, and logs when an authority changes or differs
+/// from the one expected. Authority balances are recorded as observations of
+/// the `mint_authority` watcher named `<name>_mint_authority` and
+/// `<name>_freeze_authority`.
+pub fn spawn_mint_authority_watcher(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    mints: Vec<WatchedMint>,
+) -> JoinHandle<()> {
+    update_metric_watcher_info(
+        WATCHER_NAME,
+        WATCHER_NAME,
+        "",
+        check_interval(DEFAULT_CHECK_INTERVAL),
+    );
+    tokio::spawn(async move {
+        info!("Watching authorities of mints: {mints:?}");
+        let mut previous = HashMap::new();
+        loop {
+            rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+            if let Err(err) = check_mint_authorities(&rpc_client, &mints, &mut previous).await {
+                error!("Failed to check mint authorities: {err}");
+                record_failed_check(WATCHER_NAME, &err.to_string());
+                if !sleep_unless_shutdown(backoff_duration(WATCHER_NAME)).await {
+                    break;
+                }
+                continue;
+            }
+            record_successful_check(WATCHER_NAME);
+
+            if !sleep_unless_shutdown(check_interval(DEFAULT_CHECK_INTERVAL)).await {
+                break;
+            }
+        }
+        info!("Mint authority watcher stopped");
+    })
+}
@@ -0,0 +1,59 @@
+use std::io::Write;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::warn;
+use serde_json::json;
+use tokio::{
+    sync::broadcast::error::{RecvError, TryRecvError},
+    task::JoinHandle,
+};
+
+use crate::{
+    observations::{subscribe_observations, Observation},
+    shutdown::shutdown_requested,
+};
+
+fn write_observation(observation: &Observation) {
+    let line = json!({
+        "timestamp": DateTime::<Utc>::from(observation.observed_at)
+            .to_rfc3339_opts(SecondsFormat::Millis, true),
+        "watcher": observation.watcher,
+        "name": observation.name,
+        "pubkey": observation.pubkey.map(|pubkey| pubkey.to_string()),
+        "lamports": observation.lamports,
+        "slot": observation.slot,
+        "duration_ms": observation.duration.as_millis() as u64,
+    });
+    let mut stdout = std::io::stdout().lock();
+    if let Err(err) = writeln!(stdout, "{line}").and_then(|_| stdout.flush()) {
+        warn!("Failed to write observation: {err}");
+    }
+}
+
+/// Writes every observation as a single line of JSON to stdout, keeping
+/// stderr for the human-readable log. Observations still queued on shutdown
+/// are written out before the task exits.
+pub fn spawn_observation_logger() -> JoinHandle<()> {
+    let mut observations = subscribe_observations();
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                observation = observations.recv() => match observation {
+                    Ok(observation) => write_observation(&observation),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Observation log fell behind, skipped {skipped} observations")
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+                _ = shutdown_requested() => break,
+            }
+        }
+        loop {
+            match observations.try_recv() {
+                Ok(observation) => write_observation(&observation),
+                Err(TryRecvError::Lagged(_)) => continue,
+                Err(_) => break,
+            }
+        }
+    })
+}
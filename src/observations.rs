@@ -0,0 +1,89 @@
+use std::{
+    collections::BTreeMap,
+    sync::RwLock,
+    time::{Duration, SystemTime},
+};
+
+use once_cell::sync::Lazy;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::broadcast;
+
+use crate::history::record_history;
+
+/// Buffered observations per subscriber before slow subscribers start lagging.
+const CHANNEL_CAPACITY: usize = 4096;
+
+/// A single balance read by a watcher.
+#[derive(Debug, Clone)]
+pub struct Observation {
+    pub watcher: String,
+    pub name: String,
+    /// Watched account, `None` for totals aggregated over many accounts.
+    pub pubkey: Option<Pubkey>,
+    pub lamports: u64,
+    /// Slot of the RPC response, if the request returned its context.
+    pub slot: Option<u64>,
+    /// Time spent fetching the observation from the RPC.
+    pub duration: Duration,
+    pub observed_at: SystemTime,
+}
+
+/// Latest observation of a balance along with the one before it.
+#[derive(Debug, Clone)]
+pub struct ObservedBalance {
+    pub latest: Observation,
+    pub previous_lamports: Option<u64>,
+}
+
+impl ObservedBalance {
+    pub fn delta_lamports(&self) -> Option<i128> {
+        self.previous_lamports
+            .map(|previous| i128::from(self.latest.lamports) - i128::from(previous))
+    }
+}
+
+static LATEST: Lazy<RwLock<BTreeMap<(String, String), ObservedBalance>>> =
+    Lazy::new(Default::default);
+
+static CHANNEL: Lazy<broadcast::Sender<Observation>> =
+    Lazy::new(|| broadcast::channel(CHANNEL_CAPACITY).0);
+
+/// Keeps `observation` as the latest balance of its watcher and name, adds it
+/// to the history, and publishes it to subscribers.
+pub fn record_observation(observation: Observation) {
+    record_history(&observation);
+    {
+        let mut latest = LATEST.write().unwrap();
+        let key = (observation.watcher.clone(), observation.name.clone());
+        let previous_lamports = latest.get(&key).map(|balance| balance.latest.lamports);
+        latest.insert(
+            key,
+            ObservedBalance {
+                latest: observation.clone(),
+                previous_lamports,
+            },
+        );
+    }
+    publish_observation(observation);
+}
+
+/// Publishes `observation` to subscribers without retaining it, for watchers
+/// whose number of accounts is too large to keep in memory.
+pub fn publish_observation(observation: Observation) {
+    // Sending only fails when nobody is subscribed.
+    let _ = CHANNEL.send(observation);
+}
+
+/// Observations published but not yet received by the slowest subscriber.
+pub fn pending_observations() -> usize {
+    CHANNEL.len()
+}
+
+pub fn subscribe_observations() -> broadcast::Receiver<Observation> {
+    CHANNEL.subscribe()
+}
+
+/// Latest balances ordered by watcher and name.
+pub fn latest_observations() -> Vec<ObservedBalance> {
+    LATEST.read().unwrap().values().cloned().collect()
+}
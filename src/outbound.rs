@@ -0,0 +1,287 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+
+use log::info;
+use once_cell::sync::OnceCell;
+use solana_client::client_error::reqwest::{self, redirect, Url};
+
+/// Destination notifiers may send to: a host name, every subdomain of
+/// `*.DOMAIN`, an IP address, or an IP network in CIDR notation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AllowedDestination {
+    Host(String),
+    Subdomains(String),
+    Network { address: IpAddr, prefix_len: u8 },
+}
+
+impl FromStr for AllowedDestination {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        anyhow::ensure!(!s.is_empty(), "Allowed destination cannot be empty");
+        if let Some(domain) = s.strip_prefix("*.") {
+            anyhow::ensure!(!domain.is_empty(), "Missing domain in '{s}'");
+            return Ok(AllowedDestination::Subdomains(domain.to_ascii_lowercase()));
+        }
+        let (address, prefix_len) = match s.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (s, None),
+        };
+        let Ok(address) = address.parse::<IpAddr>() else {
+            anyhow::ensure!(
+                prefix_len.is_none(),
+                "Cannot parse IP network '{s}', expected syntax: ADDRESS/PREFIX_LEN"
+            );
+            return Ok(AllowedDestination::Host(s.to_ascii_lowercase()));
+        };
+        let max_prefix_len = if address.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Failed to parse prefix length of '{s}'"))?,
+            None => max_prefix_len,
+        };
+        anyhow::ensure!(
+            prefix_len <= max_prefix_len,
+            "Prefix length of '{s}' exceeds {max_prefix_len}"
+        );
+        Ok(AllowedDestination::Network {
+            address,
+            prefix_len,
+        })
+    }
+}
+
+impl AllowedDestination {
+    fn allows_host(&self, host: &str) -> bool {
+        match self {
+            AllowedDestination::Host(allowed) => host.eq_ignore_ascii_case(allowed),
+            AllowedDestination::Subdomains(domain) => host
+                .to_ascii_lowercase()
+                .strip_suffix(domain.as_str())
+                .is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.')),
+            AllowedDestination::Network { .. } => false,
+        }
+    }
+
+    fn allows_ip(&self, ip: IpAddr) -> bool {
+        let AllowedDestination::Network {
+            address,
+            prefix_len,
+        } = self
+        else {
+            return false;
+        };
+        let ip = match (ip, address) {
+            (IpAddr::V6(ip), IpAddr::V4(_)) => match ip.to_ipv4_mapped() {
+                Some(ip) => IpAddr::V4(ip),
+                None => return false,
+            },
+            _ => ip,
+        };
+        match (ip, address) {
+            (IpAddr::V4(ip), IpAddr::V4(address)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(*prefix_len))
+                    .unwrap_or(0);
+                u32::from(ip) & mask == u32::from(*address) & mask
+            }
+            (IpAddr::V6(ip), IpAddr::V6(address)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(*prefix_len))
+                    .unwrap_or(0);
+                u128::from(ip) & mask == u128::from(*address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Restrictions on where notifiers such as the heartbeat and the change
+/// webhook send to, so that a tampered URL cannot exfiltrate balance data.
+#[derive(Debug, Clone, Default)]
+pub struct OutboundPolicy {
+    /// Destinations notifiers may send to, any when empty.
+    pub allowed: Vec<AllowedDestination>,
+    /// Whether notifiers keep connecting to the addresses their host resolved
+    /// to at startup, rather than resolving it again.
+    pub pin_dns: bool,
+}
+
+static OUTBOUND_POLICY: OnceCell<OutboundPolicy> = OnceCell::new();
+
+/// Sets the outbound policy. Can only be called once; until then, notifiers
+/// may send anywhere.
+pub fn set_outbound_policy(policy: OutboundPolicy) -> anyhow::Result<()> {
+    OUTBOUND_POLICY
+        .set(policy)
+        .map_err(|_| anyhow::anyhow!("Outbound policy is already set"))
+}
+
+/// Builds the HTTP client of a notifier sending to `url`, failing if the
+/// outbound policy does not allow it. A host not allowed by name is allowed
+/// when every address it resolves to is, and then pinned to them so that DNS
+/// cannot redirect it later. With an allowlist, redirects are not followed,
+/// as they could lead anywhere.
+pub async fn notifier_http_client(url: &str, timeout: Duration) -> anyhow::Result<reqwest::Client> {
+    let url = Url::parse(url)?;
+    let builder = reqwest::Client::builder().timeout(timeout);
+    let Some(policy) = OUTBOUND_POLICY.get() else {
+        return Ok(builder.build()?);
+    };
+    let builder = builder.redirect(redirect_policy(policy));
+    let Some(host) = url.host_str() else {
+        anyhow::bail!("URL '{url}' has no host");
+    };
+    let host_allowed = policy.allowed.is_empty()
+        || policy
+            .allowed
+            .iter()
+            .any(|allowed| allowed.allows_host(host));
+    if host_allowed && !policy.pin_dns {
+        return Ok(builder.build()?);
+    }
+
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.trim_matches(['[', ']']), port))
+        .await
+        .map_err(|err| anyhow::anyhow!("Cannot resolve {host}: {err}"))?
+        .collect();
+    anyhow::ensure!(!addrs.is_empty(), "{host} resolves to no address");
+    if !host_allowed {
+        for addr in &addrs {
+            anyhow::ensure!(
+                policy
+                    .allowed
+                    .iter()
+                    .any(|allowed| allowed.allows_ip(addr.ip())),
+                "Destination {host} ({}) is not in the outbound allowlist",
+                addr.ip()
+            );
+        }
+    }
+    if host_allowed && !policy.pin_dns {
+        return Ok(builder.build()?);
+    }
+    let ips: Vec<_> = addrs.iter().map(SocketAddr::ip).collect();
+    info!("Pinned {host} to {ips:?}");
+    Ok(builder.resolve_to_addrs(host, &addrs).build()?)
+}
+
+fn redirect_policy(policy: &OutboundPolicy) -> redirect::Policy {
+    if policy.allowed.is_empty() {
+        redirect::Policy::default()
+    } else {
+        redirect::Policy::none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn destination(s: &str) -> AllowedDestination {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_hosts_and_subdomains() {
+        assert_eq!(
+            destination("Hooks.Example.com"),
+            AllowedDestination::Host("hooks.example.com".to_string())
+        );
+        assert_eq!(
+            destination("*.Example.com"),
+            AllowedDestination::Subdomains("example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn single_address_is_full_length_network() {
+        assert_eq!(
+            destination("10.1.2.3"),
+            AllowedDestination::Network {
+                address: ip("10.1.2.3"),
+                prefix_len: 32
+            }
+        );
+        assert_eq!(
+            destination("::1"),
+            AllowedDestination::Network {
+                address: ip("::1"),
+                prefix_len: 128
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_destinations() {
+        assert!("".parse::<AllowedDestination>().is_err());
+        assert!("*.".parse::<AllowedDestination>().is_err());
+        assert!("10.0.0.0/".parse::<AllowedDestination>().is_err());
+        assert!("10.0.0.0/-1".parse::<AllowedDestination>().is_err());
+        assert!("10.0.0.0/33".parse::<AllowedDestination>().is_err());
+        assert!("10.0.0.0/256".parse::<AllowedDestination>().is_err());
+        assert!("::/129".parse::<AllowedDestination>().is_err());
+        assert!("example.com/8".parse::<AllowedDestination>().is_err());
+    }
+
+    #[test]
+    fn network_allows_addresses_within_prefix() {
+        let network = destination("10.1.0.0/16");
+        assert!(network.allows_ip(ip("10.1.255.255")));
+        assert!(!network.allows_ip(ip("10.2.0.1")));
+        let network = destination("2001:db8::/32");
+        assert!(network.allows_ip(ip("2001:db8:ffff::1")));
+        assert!(!network.allows_ip(ip("2001:db9::1")));
+    }
+
+    #[test]
+    fn zero_prefix_allows_every_address_of_its_family() {
+        let network = destination("0.0.0.0/0");
+        assert!(network.allows_ip(ip("255.255.255.255")));
+        assert!(!network.allows_ip(ip("::2")));
+        assert!(destination("::/0").allows_ip(ip("ffff::1")));
+    }
+
+    #[test]
+    fn full_prefix_allows_only_the_address() {
+        let network = destination("192.168.0.1/32");
+        assert!(network.allows_ip(ip("192.168.0.1")));
+        assert!(!network.allows_ip(ip("192.168.0.2")));
+        let network = destination("fe80::1/128");
+        assert!(network.allows_ip(ip("fe80::1")));
+        assert!(!network.allows_ip(ip("fe80::2")));
+    }
+
+    #[test]
+    fn ipv4_network_allows_mapped_ipv6_addresses() {
+        let network = destination("10.0.0.0/8");
+        assert!(network.allows_ip(ip("::ffff:10.9.8.7")));
+        assert!(!network.allows_ip(ip("::ffff:11.0.0.1")));
+    }
+
+    #[test]
+    fn subdomains_exclude_the_domain_itself() {
+        let subdomains = destination("*.example.com");
+        assert!(subdomains.allows_host("hooks.EXAMPLE.com"));
+        assert!(!subdomains.allows_host("example.com"));
+        assert!(!subdomains.allows_host("badexample.com"));
+        assert!(!subdomains.allows_host(".example.com"));
+        assert!(!subdomains.allows_ip(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn hosts_do_not_allow_addresses() {
+        assert!(destination("example.com").allows_host("EXAMPLE.COM"));
+        assert!(!destination("10.0.0.1").allows_host("10.0.0.1"));
+        assert!(!destination("example.com").allows_ip(ip("10.0.0.1")));
+    }
+}
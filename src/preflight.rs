@@ -0,0 +1,93 @@
+use std::{collections::HashMap, time::Duration};
+
+use log::{info, warn};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{explorer::Cluster, rpc::RpcClientFactory};
+
+pub const WATCHER_NAME: &str = "preflight";
+
+/// Timeout of lookups on the public endpoints of other clusters.
+const OTHER_CLUSTER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Looks up `pubkeys` on the public endpoint of every known cluster other
+/// than `cluster`, returning the clusters each of them exists on.
+async fn find_on_other_clusters(
+    cluster: Option<Cluster>,
+    pubkeys: &[Pubkey],
+) -> HashMap<Pubkey, Vec<Cluster>> {
+    let mut found = HashMap::<_, Vec<_>>::new();
+    for other in Cluster::ALL
+        .into_iter()
+        .filter(|other| Some(*other) != cluster)
+    {
+        let rpc_client =
+            RpcClient::new_with_timeout(other.public_rpc_url().to_string(), OTHER_CLUSTER_TIMEOUT);
+        match rpc_client.get_multiple_accounts(pubkeys).await {
+            Ok(accounts) => {
+                for (pubkey, account) in pubkeys.iter().zip(accounts) {
+                    if account.is_some() {
+                        found.entry(*pubkey).or_default().push(other);
+                    }
+                }
+            }
+            Err(err) => info!(
+                "Cannot look up missing accounts on {}: {err}",
+                other.as_str()
+            ),
+        }
+    }
+    found
+}
+
+/// Checks once that every named address exists, warning about each missing
+/// one with a hint at the likely mistake: an address of another cluster, or
+/// an off-curve address such as a token account or other program derived
+/// address that was never created. Returns the names of missing addresses.
+pub async fn check_named_addresses_exist(
+    rpc_clients: &RpcClientFactory,
+    named_pubkeys: &HashMap<Pubkey, String>,
+) -> anyhow::Result<Vec<String>> {
+    let rpc_client = rpc_clients.for_watcher(WATCHER_NAME);
+    let pubkeys: Vec<_> = named_pubkeys.keys().copied().collect();
+    let accounts = rpc_client.get_multiple_accounts(&pubkeys).await?;
+    let missing: Vec<_> = pubkeys
+        .into_iter()
+        .zip(accounts)
+        .filter(|(_, account)| account.is_none())
+        .map(|(pubkey, _)| pubkey)
+        .collect();
+    if missing.is_empty() {
+        info!("All {} named addresses exist", named_pubkeys.len());
+        return Ok(vec![]);
+    }
+
+    let cluster = match rpc_client.get_genesis_hash().await {
+        Ok(genesis_hash) => Cluster::from_genesis_hash(&genesis_hash),
+        Err(err) => {
+            info!("Cannot get genesis hash to tell the cluster apart: {err}");
+            None
+        }
+    };
+    let found_on = find_on_other_clusters(cluster, &missing).await;
+    let mut names = vec![];
+    for pubkey in &missing {
+        let name = &named_pubkeys[pubkey];
+        let mut hints = vec![];
+        if let Some(clusters) = found_on.get(pubkey) {
+            let clusters: Vec<_> = clusters.iter().map(Cluster::as_str).collect();
+            hints.push(format!("it exists on {}", clusters.join(" and ")));
+        }
+        if !pubkey.is_on_curve() {
+            hints.push(
+                "it is off-curve, so it is not a wallet but a program derived address that may not have been created yet"
+                    .to_string(),
+            );
+        }
+        let hints: String = hints.iter().map(|hint| format!("; {hint}")).collect();
+        warn!("Account {name} ({pubkey}) does not exist and reports a balance of 0{hints}");
+        names.push(name.clone());
+    }
+    Ok(names)
+}
@@ -0,0 +1,137 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use solana_client::client_error::reqwest;
+use tokio::task::JoinHandle;
+
+use crate::{
+    amount_format::{set_sol_price_usd, sol_price_usd},
+    metrics::{update_metric_price_usd, update_metric_usd_valuations, watched_token_mints},
+    shutdown::sleep_unless_shutdown,
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// CoinGecko ID of SOL and of the platform token mints are listed under.
+const SOL_ID: &str = "solana";
+const PLATFORM_ID: &str = "solana";
+
+/// Latest USD price per token mint.
+static TOKEN_PRICES_USD: Lazy<Mutex<HashMap<String, f64>>> = Lazy::new(Default::default);
+
+/// Where prices are fetched from, an API compatible with CoinGecko's
+/// `simple/price` and `simple/token_price` endpoints.
+#[derive(Debug, Clone)]
+pub struct PriceFeedConfig {
+    /// Base URL, e.g. `https://api.coingecko.com/api/v3`.
+    pub url: String,
+    pub api_key: Option<String>,
+    pub api_key_header: String,
+    pub interval: Duration,
+}
+
+struct PriceFeed {
+    config: PriceFeedConfig,
+    base_url: reqwest::Url,
+    http_client: reqwest::Client,
+}
+
+impl PriceFeed {
+    async fn get(&self, path: &str, query: &[(&str, &str)]) -> anyhow::Result<Value> {
+        let mut request = self.http_client.get(self.base_url.join(path)?).query(query);
+        if let Some(api_key) = &self.config.api_key {
+            request = request.header(self.config.api_key_header.as_str(), api_key);
+        }
+        Ok(request.send().await?.error_for_status()?.json().await?)
+    }
+
+    async fn sol_price(&self) -> anyhow::Result<f64> {
+        let query = [("ids", SOL_ID), ("vs_currencies", "usd")];
+        let body = self.get("simple/price", &query).await?;
+        body[SOL_ID]["usd"]
+            .as_f64()
+            .ok_or_else(|| anyhow::anyhow!("No USD price of SOL in {body}"))
+    }
+
+    /// Prices of the mints known to the feed. Others are left out.
+    async fn token_prices(&self, mints: &[String]) -> anyhow::Result<HashMap<String, f64>> {
+        let addresses = mints.join(",");
+        let query = [
+            ("contract_addresses", addresses.as_str()),
+            ("vs_currencies", "usd"),
+        ];
+        let body = self
+            .get(&format!("simple/token_price/{PLATFORM_ID}"), &query)
+            .await?;
+        let Value::Object(prices) = body else {
+            anyhow::bail!("Unexpected token prices {body}");
+        };
+        // Some feeds lowercase the addresses.
+        let prices: HashMap<_, _> = prices
+            .into_iter()
+            .filter_map(|(address, price)| Some((address.to_lowercase(), price["usd"].as_f64()?)))
+            .collect();
+        Ok(mints
+            .iter()
+            .filter_map(|mint| Some((mint.clone(), *prices.get(&mint.to_lowercase())?)))
+            .collect())
+    }
+
+    async fn update(&self) -> anyhow::Result<()> {
+        let price = self.sol_price().await?;
+        set_sol_price_usd(price);
+
+        let mints: Vec<_> = watched_token_mints().into_iter().collect();
+        if !mints.is_empty() {
+            for (mint, price) in self.token_prices(&mints).await? {
+                set_token_price_usd(&mint, price);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sets the USD price of a token mint, which values the balances of token
+/// accounts of that mint.
+pub fn set_token_price_usd(mint: &str, price: f64) {
+    TOKEN_PRICES_USD
+        .lock()
+        .unwrap()
+        .insert(mint.to_string(), price);
+    update_metric_price_usd(mint, price);
+}
+
+/// Values the exported SOL and token balances in USD at the latest prices,
+/// as `balance_usd`, `total_balance_usd` and `token_balance_usd`.
+pub fn update_usd_valuations() {
+    update_metric_usd_valuations(sol_price_usd(), &TOKEN_PRICES_USD.lock().unwrap());
+}
+
+/// Periodically fetches the USD price of SOL and of the mints of watched token
+/// accounts. Failed fetches keep the previous prices.
+pub fn spawn_price_feed(config: PriceFeedConfig) -> anyhow::Result<JoinHandle<()>> {
+    let mut base_url = reqwest::Url::parse(&config.url)?;
+    if !base_url.path().ends_with('/') {
+        base_url.set_path(&format!("{}/", base_url.path()));
+    }
+    let feed = PriceFeed {
+        base_url,
+        http_client: reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()?,
+        config,
+    };
+    info!("Fetching prices from {}", feed.base_url);
+
+    Ok(tokio::spawn(async move {
+        loop {
+            if let Err(err) = feed.update().await {
+                warn!("Failed to fetch prices: {err}");
+            }
+            if !sleep_unless_shutdown(feed.config.interval).await {
+                break;
+            }
+        }
+    }))
+}
@@ -0,0 +1,86 @@
+use std::{collections::HashMap, time::Duration};
+
+use axum::{
+    extract::Query,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use tokio::time::sleep;
+
+use crate::auth::{require_role, ApiKeys, Role};
+
+const DEFAULT_PROFILE_SECONDS: u64 = 30;
+const MAX_PROFILE_SECONDS: u64 = 300;
+const SAMPLING_FREQUENCY: i32 = 99;
+
+/// Samples the CPU for `?seconds=N` (30 by default) and renders the result as a
+/// flamegraph SVG.
+async fn cpu_profile(Query(params): Query<HashMap<String, String>>) -> Response {
+    let seconds = params
+        .get("seconds")
+        .and_then(|seconds| seconds.parse().ok())
+        .unwrap_or(DEFAULT_PROFILE_SECONDS)
+        .min(MAX_PROFILE_SECONDS);
+
+    let guard = match pprof::ProfilerGuardBuilder::default()
+        .frequency(SAMPLING_FREQUENCY)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()
+    {
+        Ok(guard) => guard,
+        Err(err) => {
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("Failed to start profiler: {err}"),
+            )
+                .into_response()
+        }
+    };
+    sleep(Duration::from_secs(seconds)).await;
+
+    let mut svg = vec![];
+    if let Err(err) = guard
+        .report()
+        .build()
+        .and_then(|report| report.flamegraph(&mut svg))
+    {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to build profile: {err}"),
+        )
+            .into_response();
+    }
+
+    ([(CONTENT_TYPE, "image/svg+xml")], svg).into_response()
+}
+
+/// Memory usage of the process as reported by the kernel.
+async fn memory_stats() -> Response {
+    match tokio::fs::read_to_string("/proc/self/status").await {
+        Ok(status) => status
+            .lines()
+            .filter(|line| line.starts_with("Vm") || line.starts_with("Rss"))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .into_response(),
+        Err(err) => (
+            StatusCode::NOT_IMPLEMENTED,
+            format!("Memory statistics unavailable: {err}"),
+        )
+            .into_response(),
+    }
+}
+
+/// CPU profiles and memory statistics. Requires an admin API key when any
+/// API keys are configured.
+pub fn profiling_router(keys: &ApiKeys) -> Router {
+    let router = Router::new()
+        .route("/debug/pprof/profile", get(cpu_profile))
+        .route("/debug/memory", get(memory_stats));
+    match keys.is_empty() {
+        true => router,
+        false => require_role(router, keys, Role::Admin),
+    }
+}
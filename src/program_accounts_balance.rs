@@ -1,25 +1,88 @@
-use std::{str::FromStr, sync::Arc, time::Duration};
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
-use log::{error, info};
-use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+use log::{error, info, warn};
+use solana_account_decoder::UiAccountEncoding;
 use solana_client::{
+    client_error::Result as ClientResult,
     nonblocking::rpc_client::RpcClient,
     rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
     rpc_filter::{Memcmp, RpcFilterType},
 };
-use solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey};
-use tokio::{task::JoinHandle, time::sleep};
-
-use crate::metrics::{remove_metric_total_balance_sol, update_metric_total_balance_sol};
+use solana_sdk::{account::Account, native_token::lamports_to_sol, pubkey::Pubkey};
+use tokio::task::JoinHandle;
 
-const CHECK_INTERVAL: Duration = Duration::from_secs(300);
-const BACKOFF_DURATION: Duration = Duration::from_secs(10);
+use crate::{
+    account_set::AccountSetTracker,
+    account_subscription::{push_program_accounts, websocket_url},
+    config::ProgramAccountsConfig,
+    data_slice::AccountType,
+    health::{record_failed_check, record_successful_check},
+    intervals::{backoff_duration, positive_interval},
+    metrics::{
+        remove_metric_total_balance_sol, update_metric_total_balance_sol,
+        update_metric_watcher_info,
+    },
+    observations::{record_observation, Observation},
+    rate_limit::RateLimiter,
+    scan_hook::{run_scan_hooks, scan_hooks_data_slice},
+    shutdown::sleep_unless_shutdown,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ProgramAccountsBalanceConfig {
     name: String,
     program: Pubkey,
     filters: Vec<RpcFilterType>,
+    weight: u32,
+    cost: u32,
+    check_interval: Option<Duration>,
+    /// Whether changes are pushed with `programSubscribe` rather than scanned.
+    subscribe: bool,
+}
+
+impl ProgramAccountsBalanceConfig {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn program(&self) -> &Pubkey {
+        &self.program
+    }
+
+    /// Share of the RPC rate limit and tokens spent per scan.
+    pub fn rate_limit(&self) -> (u32, u32) {
+        (self.weight, self.cost)
+    }
+
+    /// Interval between scans, when it differs from the one of the other
+    /// watchers.
+    pub fn check_interval(&self) -> Option<Duration> {
+        self.check_interval
+    }
+
+    /// Request config of the accounts matching the program and filters, with
+    /// the data slice, commitment and minimum context slot of
+    /// `account_config`, for both scans and subscriptions.
+    pub(crate) fn rpc_config(
+        &self,
+        account_config: RpcAccountInfoConfig,
+    ) -> RpcProgramAccountsConfig {
+        RpcProgramAccountsConfig {
+            filters: Some(self.filters.clone()),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..account_config
+            },
+            ..Default::default()
+        }
+    }
 }
 
 fn parse_rpc_filter_type(param: &str) -> anyhow::Result<RpcFilterType> {
@@ -61,69 +124,242 @@ impl FromStr for ProgramAccountsBalanceConfig {
         let mut params = params.split(' ').collect::<Vec<_>>().into_iter();
 
         let program = match params.next() {
-            Some(program) => Pubkey::from_str(program)
-                .expect(&format!("Failed to parse program ID from '{program}'")),
+            Some(program) => match Pubkey::from_str(program) {
+                Ok(program) => program,
+                Err(_) => anyhow::bail!("Failed to parse program ID from '{program}'"),
+            },
             None => anyhow::bail!("Program ID not found!"),
         };
 
         let mut filters = vec![];
+        let mut weight = 1;
+        let mut cost = 1;
+        let mut check_interval = None;
+        let mut subscribe = false;
         for param in params {
-            filters.push(parse_rpc_filter_type(param)?);
+            match param.split_once(':') {
+                Some(("weight", value)) => weight = value.parse()?,
+                Some(("cost", value)) => cost = value.parse()?,
+                Some(("interval", value)) => {
+                    check_interval = Some(positive_interval(name, value.parse()?)?)
+                }
+                Some(("subscribe", value)) => subscribe = value.parse()?,
+                _ => filters.push(parse_rpc_filter_type(param)?),
+            }
         }
 
         Ok(ProgramAccountsBalanceConfig {
             name: name.to_string(),
             program,
             filters,
+            weight,
+            cost,
+            check_interval,
+            subscribe,
         })
     }
 }
 
+impl TryFrom<&ProgramAccountsConfig> for ProgramAccountsBalanceConfig {
+    type Error = anyhow::Error;
+
+    fn try_from(config: &ProgramAccountsConfig) -> Result<Self, Self::Error> {
+        let program = Pubkey::from_str(&config.program)
+            .map_err(|_| anyhow::anyhow!("Failed to parse program ID from '{}'", config.program))?;
+        Ok(ProgramAccountsBalanceConfig {
+            name: config.name.clone(),
+            program,
+            filters: config
+                .filters
+                .iter()
+                .map(|filter| parse_rpc_filter_type(filter))
+                .collect::<anyhow::Result<_>>()?,
+            weight: config.weight.unwrap_or(1),
+            cost: config.cost.unwrap_or(1),
+            check_interval: config
+                .interval
+                .map(|secs| positive_interval(&config.name, secs))
+                .transpose()?,
+            subscribe: config.subscribe.unwrap_or_default(),
+        })
+    }
+}
+
+/// Fetches all accounts matching `config`, without their data, from a node
+/// that reached at least `min_context_slot`.
+pub async fn get_program_accounts(
+    rpc_client: &RpcClient,
+    config: &ProgramAccountsBalanceConfig,
+    min_context_slot: Option<u64>,
+) -> ClientResult<Vec<(Pubkey, Account)>> {
+    get_program_accounts_with_account_config(
+        rpc_client,
+        config,
+        RpcAccountInfoConfig {
+            data_slice: Some(AccountType::Lamports.data_slice()),
+            min_context_slot,
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Like [`get_program_accounts`], with the data slice, commitment and minimum
+/// context slot of `account_config`.
+pub(crate) async fn get_program_accounts_with_account_config(
+    rpc_client: &RpcClient,
+    config: &ProgramAccountsBalanceConfig,
+    account_config: RpcAccountInfoConfig,
+) -> ClientResult<Vec<(Pubkey, Account)>> {
+    rpc_client
+        .get_program_accounts_with_config(&config.program, config.rpc_config(account_config))
+        .await
+}
+
+/// Sums the balances of all accounts matching `config`, exporting and
+/// recording the total, after running the registered scan hooks on them.
+/// Returns the observation and the matched accounts.
+pub async fn check_program_accounts(
+    rpc_client: &RpcClient,
+    config: &ProgramAccountsBalanceConfig,
+) -> ClientResult<(Observation, Vec<Pubkey>)> {
+    let start = Instant::now();
+    let data_slice = scan_hooks_data_slice();
+    let accounts = get_program_accounts_with_account_config(
+        rpc_client,
+        config,
+        RpcAccountInfoConfig {
+            data_slice: Some(data_slice),
+            ..Default::default()
+        },
+    )
+    .await?;
+    run_scan_hooks(&config.name, &data_slice, &accounts);
+
+    let lamports = accounts.iter().map(|(_, account)| account.lamports).sum();
+    let observation = export_total_balance(config, lamports, None, start.elapsed());
+    info!(
+        "For '{}' found {} accounts with total balance: {}",
+        config.name,
+        accounts.len(),
+        lamports_to_sol(lamports)
+    );
+    let pubkeys = accounts.into_iter().map(|(pubkey, _)| pubkey).collect();
+    Ok((observation, pubkeys))
+}
+
+/// Exports and records `lamports` as the total balance of `config`.
+pub(crate) fn export_total_balance(
+    config: &ProgramAccountsBalanceConfig,
+    lamports: u64,
+    slot: Option<u64>,
+    duration: Duration,
+) -> Observation {
+    update_metric_total_balance_sol(&config.name, lamports_to_sol(lamports));
+    let observation = Observation {
+        watcher: config.name.clone(),
+        name: config.name.clone(),
+        pubkey: None,
+        lamports,
+        slot,
+        duration,
+        observed_at: SystemTime::now(),
+    };
+    record_observation(observation.clone());
+    observation
+}
+
+/// Scans `config` every `interval:SECS` given in it, or else every
+/// `default_check_interval`. With `subscribe:true` and a WebSocket URL set,
+/// changes are pushed instead, and scans only reconcile the total while
+/// subscribed.
 pub fn spawn_program_accounts_balance_watcher(
     rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
     config: ProgramAccountsBalanceConfig,
+    default_check_interval: Duration,
 ) -> JoinHandle<()> {
+    let check_interval = config.check_interval.unwrap_or(default_check_interval);
+    update_metric_watcher_info(
+        &config.name,
+        "program_accounts",
+        &config.program.to_string(),
+        check_interval,
+    );
+    if config.subscribe && websocket_url().is_none() {
+        warn!(
+            "Scanning '{}' as no WebSocket URL is set to subscribe to it",
+            config.name
+        );
+    }
     tokio::spawn(async move {
         info!("Watching: {config:?}");
-        loop {
-            let response = rpc_client
-                .get_program_accounts_with_config(
-                    &config.program,
-                    RpcProgramAccountsConfig {
-                        filters: Some(config.filters.clone()),
-                        account_config: RpcAccountInfoConfig {
-                            data_slice: Some(UiDataSliceConfig {
-                                offset: 0,
-                                length: 0,
-                            }),
-                            encoding: Some(UiAccountEncoding::Base64),
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    },
-                )
-                .await;
-
-            let response = match response {
-                Ok(response) => response,
-                Err(err) => {
-                    error!("Failed to get RPC response: {err}");
-                    remove_metric_total_balance_sol(&config.name);
-                    sleep(BACKOFF_DURATION).await;
+        let mut account_set = AccountSetTracker::open(&config.name)
+            .await
+            .unwrap_or_else(|err| {
+                error!("Not tracking the accounts of '{}': {err}", config.name);
+                None
+            });
+        let subscribed = AtomicBool::new(false);
+        let polling = async {
+            loop {
+                if subscribed.load(Ordering::Relaxed) {
+                    record_successful_check(&config.name);
+                    if !sleep_unless_shutdown(check_interval).await {
+                        break;
+                    }
                     continue;
                 }
-            };
-
-            let balance =
-                lamports_to_sol(response.iter().map(|(_, account)| account.lamports).sum());
-            update_metric_total_balance_sol(&config.name, balance);
-            let count = response.len();
-            info!(
-                "For '{}' found {count} accounts with total balance: {balance}",
-                config.name
-            );
-
-            sleep(CHECK_INTERVAL).await;
+                rate_limiter
+                    .acquire(&config.name, config.weight, config.cost)
+                    .await;
+                let pubkeys = match check_program_accounts(&rpc_client, &config).await {
+                    Ok((_, pubkeys)) => pubkeys,
+                    Err(err) => {
+                        error!("Failed to get RPC response: {err}");
+                        record_failed_check(&config.name, &err.to_string());
+                        remove_metric_total_balance_sol(&config.name);
+                        if !sleep_unless_shutdown(backoff_duration(&config.name)).await {
+                            break;
+                        }
+                        continue;
+                    }
+                };
+                record_successful_check(&config.name);
+                if let Some(account_set) = &mut account_set {
+                    match account_set.update(pubkeys.into_iter().collect()).await {
+                        Ok(diff) if !diff.is_empty() => info!(
+                            "Accounts of '{}' changed: {} added, {} removed",
+                            config.name,
+                            diff.added.len(),
+                            diff.removed.len()
+                        ),
+                        Ok(_) => {}
+                        Err(err) => {
+                            error!("Failed to record the accounts of '{}': {err}", config.name)
+                        }
+                    }
+                }
+
+                if !sleep_unless_shutdown(check_interval).await {
+                    break;
+                }
+            }
+        };
+        match websocket_url().filter(|_| config.subscribe) {
+            Some(url) => {
+                let pushing = push_program_accounts(
+                    url,
+                    &rpc_client,
+                    &rate_limiter,
+                    &config,
+                    check_interval,
+                    &subscribed,
+                );
+                tokio::join!(polling, pushing);
+            }
+            None => polling.await,
         }
+        info!("Stopped watching '{}'", config.name);
     })
 }
@@ -0,0 +1,219 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{error, info, warn};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use tokio::task::JoinHandle;
+
+use crate::{
+    amount_format::set_sol_price_usd,
+    health::{record_failed_check, record_successful_check},
+    intervals::{backoff_duration, check_interval},
+    metrics::update_metric_watcher_info,
+    prices::set_token_price_usd,
+    rate_limit::RateLimiter,
+    shutdown::sleep_unless_shutdown,
+};
+
+pub const WATCHER_NAME: &str = "pyth";
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Prices published longer ago than this are not used.
+const MAX_PRICE_AGE: Duration = Duration::from_secs(300);
+
+/// Legacy push oracle `PriceAccount`: `magic (u32) | version (u32) | type
+/// (u32) | size (u32) | price type (u32) | exponent (i32) | ...`, with the
+/// aggregate price at 208.
+const LEGACY_MAGIC: u32 = 0xa1b2_c3d4;
+const LEGACY_PRICE_ACCOUNT_TYPE: u32 = 3;
+const LEGACY_TYPE_OFFSET: usize = 8;
+const LEGACY_EXPONENT_OFFSET: usize = 20;
+const LEGACY_TIMESTAMP_OFFSET: usize = 96;
+const LEGACY_PRICE_OFFSET: usize = 208;
+const LEGACY_STATUS_OFFSET: usize = 224;
+const LEGACY_STATUS_TRADING: u32 = 1;
+
+/// Pull oracle `PriceUpdateV2`: `discriminator (8) | write authority (32) |
+/// verification level (1 or 2) | feed id (32) | price (i64) | confidence
+/// (u64) | exponent (i32) | publish time (i64) | ...`.
+const PRICE_UPDATE_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+const PRICE_UPDATE_VERIFICATION_OFFSET: usize = 40;
+const VERIFICATION_LEVEL_FULL: u8 = 1;
+
+/// A Pyth price account to read the USD price of an asset from, parsed from
+/// `asset=pubkey` where the asset is `SOL` or a token mint.
+#[derive(Debug, Clone)]
+pub struct PythPriceAccount {
+    pub asset: PricedAsset,
+    pub pubkey: Pubkey,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PricedAsset {
+    Sol,
+    Mint(Pubkey),
+}
+
+impl fmt::Display for PricedAsset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PricedAsset::Sol => write!(f, "SOL"),
+            PricedAsset::Mint(mint) => write!(f, "{mint}"),
+        }
+    }
+}
+
+impl FromStr for PythPriceAccount {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((asset, pubkey)) = s.split_once('=') else {
+            anyhow::bail!("Cannot parse Pyth price account, expected syntax: asset=pubkey");
+        };
+        let asset = match asset {
+            "SOL" | "sol" => PricedAsset::Sol,
+            _ => PricedAsset::Mint(Pubkey::from_str(asset).map_err(|_| {
+                anyhow::anyhow!("Unsupported asset '{asset}', expected SOL or a token mint")
+            })?),
+        };
+        let Ok(pubkey) = Pubkey::from_str(pubkey) else {
+            anyhow::bail!("Failed to parse Pyth price account pubkey from '{pubkey}'");
+        };
+        Ok(PythPriceAccount { asset, pubkey })
+    }
+}
+
+/// Price read from a Pyth price account.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PythPrice {
+    pub price: f64,
+    /// Unix time the price was published at.
+    pub publish_time: i64,
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> anyhow::Result<[u8; N]> {
+    data.get(offset..offset + N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| anyhow::anyhow!("Pyth price account data is too short"))
+}
+
+fn scaled(price: i64, exponent: i32) -> f64 {
+    price as f64 * 10f64.powi(exponent)
+}
+
+/// Decodes a legacy push oracle price account or a pull oracle price update
+/// account, rejecting prices that are not trading or not fully verified.
+pub fn decode_price_account(data: &[u8]) -> anyhow::Result<PythPrice> {
+    if u32::from_le_bytes(read(data, 0)?) == LEGACY_MAGIC {
+        anyhow::ensure!(
+            u32::from_le_bytes(read(data, LEGACY_TYPE_OFFSET)?) == LEGACY_PRICE_ACCOUNT_TYPE,
+            "Pyth account is not a price account"
+        );
+        anyhow::ensure!(
+            u32::from_le_bytes(read(data, LEGACY_STATUS_OFFSET)?) == LEGACY_STATUS_TRADING,
+            "Pyth price is not trading"
+        );
+        let exponent = i32::from_le_bytes(read(data, LEGACY_EXPONENT_OFFSET)?);
+        return Ok(PythPrice {
+            price: scaled(
+                i64::from_le_bytes(read(data, LEGACY_PRICE_OFFSET)?),
+                exponent,
+            ),
+            publish_time: i64::from_le_bytes(read(data, LEGACY_TIMESTAMP_OFFSET)?),
+        });
+    }
+
+    anyhow::ensure!(
+        read(data, 0)? == PRICE_UPDATE_DISCRIMINATOR,
+        "Account is neither a Pyth price account nor a price update"
+    );
+    let message = match read::<1>(data, PRICE_UPDATE_VERIFICATION_OFFSET)?[0] {
+        VERIFICATION_LEVEL_FULL => PRICE_UPDATE_VERIFICATION_OFFSET + 1,
+        _ => anyhow::bail!("Pyth price update is only partially verified"),
+    };
+    let price = i64::from_le_bytes(read(data, message + 32)?);
+    let exponent = i32::from_le_bytes(read(data, message + 48)?);
+    Ok(PythPrice {
+        price: scaled(price, exponent),
+        publish_time: i64::from_le_bytes(read(data, message + 52)?),
+    })
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Reads the USD prices of SOL and token mints from Pyth price accounts over
+/// RPC, for deployments that cannot reach a price API. Stale prices are
+/// ignored, keeping the previous ones.
+pub fn spawn_pyth_price_watcher(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    accounts: Vec<PythPriceAccount>,
+) -> JoinHandle<()> {
+    update_metric_watcher_info(
+        WATCHER_NAME,
+        WATCHER_NAME,
+        "",
+        check_interval(CHECK_INTERVAL),
+    );
+    tokio::spawn(async move {
+        info!("Watching Pyth price accounts: {accounts:?}");
+        let pubkeys: Vec<_> = accounts.iter().map(|account| account.pubkey).collect();
+        loop {
+            rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+            let price_accounts = match rpc_client.get_multiple_accounts(&pubkeys).await {
+                Ok(price_accounts) => price_accounts,
+                Err(err) => {
+                    error!("Failed to get RPC response: {err}");
+                    record_failed_check(WATCHER_NAME, &err.to_string());
+                    if !sleep_unless_shutdown(backoff_duration(WATCHER_NAME)).await {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            let now = unix_now();
+            for (account, price_account) in accounts.iter().zip(price_accounts) {
+                let price = match price_account {
+                    Some(price_account) => decode_price_account(&price_account.data),
+                    None => Err(anyhow::anyhow!("Account does not exist")),
+                };
+                let price = match price {
+                    Ok(price) => price,
+                    Err(err) => {
+                        error!("Cannot read Pyth price account {}: {err}", account.pubkey);
+                        continue;
+                    }
+                };
+                let age = now.saturating_sub(price.publish_time);
+                if age > MAX_PRICE_AGE.as_secs() as i64 {
+                    warn!(
+                        "Ignoring price {} of {} published {age}s ago",
+                        price.price, account.asset
+                    );
+                    continue;
+                }
+                match account.asset {
+                    PricedAsset::Sol => set_sol_price_usd(price.price),
+                    PricedAsset::Mint(mint) => set_token_price_usd(&mint.to_string(), price.price),
+                }
+            }
+            record_successful_check(WATCHER_NAME);
+
+            if !sleep_unless_shutdown(check_interval(CHECK_INTERVAL)).await {
+                break;
+            }
+        }
+        info!("Pyth price watcher stopped");
+    })
+}
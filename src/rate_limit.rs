@@ -0,0 +1,159 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    sync::Mutex,
+    time::Duration,
+};
+
+use tokio::{
+    sync::oneshot,
+    time::{sleep, Instant},
+};
+
+use crate::metrics::update_metric_rpc_rate_limit_wait;
+
+/// Token bucket shared by every watcher talking to the same RPC provider.
+///
+/// When the bucket runs dry, requests are queued and released in weighted fair
+/// queuing order (self-clocked variant): each request is tagged with a virtual
+/// finish time of `max(virtual_time, previous tag of its watcher) + cost / weight`
+/// and the smallest tag is served first. A watcher issuing expensive scans thus
+/// only consumes its weighted share of the budget instead of starving the rest.
+pub struct RateLimiter {
+    rate: Option<f64>,
+    burst: f64,
+    state: Mutex<State>,
+}
+
+struct State {
+    tokens: f64,
+    refilled_at: Instant,
+    virtual_time: f64,
+    finish_tags: HashMap<String, f64>,
+    queue: BinaryHeap<Waiter>,
+    sequence: u64,
+}
+
+struct Waiter {
+    finish: f64,
+    sequence: u64,
+    cost: f64,
+    grant: oneshot::Sender<()>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    // Reversed so that the `BinaryHeap` pops the smallest finish tag first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .finish
+            .total_cmp(&self.finish)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl RateLimiter {
+    /// Limiter allowing `rate` tokens per second with bursts of up to `burst` tokens.
+    pub fn new(rate: f64, burst: f64) -> Self {
+        Self::with_rate(Some(rate), burst)
+    }
+
+    /// Limiter that never delays any request.
+    pub fn unlimited() -> Self {
+        Self::with_rate(None, 0.0)
+    }
+
+    fn with_rate(rate: Option<f64>, burst: f64) -> Self {
+        let burst = burst.max(1.0);
+        Self {
+            rate,
+            burst,
+            state: Mutex::new(State {
+                tokens: burst,
+                refilled_at: Instant::now(),
+                virtual_time: 0.0,
+                finish_tags: Default::default(),
+                queue: Default::default(),
+                sequence: 0,
+            }),
+        }
+    }
+
+    /// Waits until `watcher` may spend `cost` tokens. Requests costing more than
+    /// the burst size are admitted once the bucket is full.
+    pub async fn acquire(&self, watcher: &str, weight: u32, cost: u32) {
+        let Some(rate) = self.rate else {
+            return;
+        };
+        let queued_at = Instant::now();
+
+        let (grant, mut granted) = oneshot::channel();
+        {
+            let mut state = self.state.lock().unwrap();
+            let start = state
+                .finish_tags
+                .get(watcher)
+                .copied()
+                .unwrap_or_default()
+                .max(state.virtual_time);
+            let finish = start + f64::from(cost) / f64::from(weight.max(1));
+            state.finish_tags.insert(watcher.to_string(), finish);
+            state.sequence += 1;
+            let sequence = state.sequence;
+            state.queue.push(Waiter {
+                finish,
+                sequence,
+                cost: f64::from(cost).min(self.burst),
+                grant,
+            });
+        }
+
+        loop {
+            let wait = self.dispatch(rate);
+            tokio::select! {
+                _ = &mut granted => {
+                    update_metric_rpc_rate_limit_wait(watcher, queued_at.elapsed());
+                    return;
+                }
+                _ = sleep(wait) => {}
+            }
+        }
+    }
+
+    /// Hands out tokens to queued requests in finish tag order and returns how
+    /// long to wait until the head of the queue can be served.
+    fn dispatch(&self, rate: f64) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(state.refilled_at).as_secs_f64() * rate;
+        state.tokens = (state.tokens + refill).min(self.burst);
+        state.refilled_at = now;
+
+        while let Some(head) = state.queue.peek() {
+            if state.tokens < head.cost {
+                return Duration::from_secs_f64((head.cost - state.tokens) / rate);
+            }
+            let waiter = state.queue.pop().unwrap();
+            state.virtual_time = waiter.finish;
+            // Requests whose caller went away don't consume the budget.
+            if waiter.grant.send(()).is_ok() {
+                state.tokens -= waiter.cost;
+            }
+        }
+
+        Duration::from_secs_f64(1.0 / rate)
+    }
+}
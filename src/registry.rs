@@ -0,0 +1,179 @@
+use std::{collections::BTreeSet, str::FromStr, sync::Arc, time::Duration};
+
+use log::{error, info};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::{Pubkey, PUBKEY_BYTES};
+use tokio::{sync::watch, task::JoinHandle};
+
+use crate::{
+    derived::PubkeySet,
+    health::{record_failed_check, record_successful_check},
+    intervals::backoff_duration,
+    metrics::update_metric_watcher_info,
+    rate_limit::RateLimiter,
+    shutdown::sleep_unless_shutdown,
+};
+
+/// Size of the length prefix of a Borsh `Vec`.
+const VEC_LENGTH_BYTES: usize = 4;
+
+/// An account whose data references the accounts to watch, such as the
+/// config account of a protocol listing its vaults, given as
+/// `name=PUBKEY offsets:OFFSET,...` with the offsets of single pubkeys,
+/// and/or `vecs:OFFSET,...` with the offsets of Borsh-serialized
+/// `Vec<Pubkey>`, i.e. a little-endian `u32` length followed by the pubkeys.
+#[derive(Debug, Clone)]
+pub struct RegistryAccount {
+    pub name: String,
+    pub pubkey: Pubkey,
+    pub offsets: Vec<usize>,
+    pub vecs: Vec<usize>,
+}
+
+impl FromStr for RegistryAccount {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, params)) = s.split_once('=') else {
+            anyhow::bail!(
+                "Cannot parse registry account '{s}', expected syntax: name=PUBKEY offsets:OFFSET,..."
+            );
+        };
+        let mut params = params.split(' ').filter(|param| !param.is_empty());
+        let pubkey = params.next().unwrap_or_default();
+        let pubkey = Pubkey::from_str(pubkey).map_err(|err| {
+            anyhow::anyhow!("Cannot parse registry account '{pubkey}' of '{name}': {err}")
+        })?;
+        let parse_offsets = |value: &str| {
+            value
+                .split(',')
+                .map(|offset| {
+                    offset.parse::<usize>().map_err(|err| {
+                        anyhow::anyhow!("Cannot parse offset '{offset}' of '{name}': {err}")
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+        let mut offsets = vec![];
+        let mut vecs = vec![];
+        for param in params {
+            match param.split_once(':') {
+                Some(("offsets", value)) => offsets.extend(parse_offsets(value)?),
+                Some(("vecs", value)) => vecs.extend(parse_offsets(value)?),
+                _ => anyhow::bail!("Unsupported parameter '{param}' of registry account '{name}'"),
+            }
+        }
+        anyhow::ensure!(
+            !offsets.is_empty() || !vecs.is_empty(),
+            "Registry account '{name}' requires an offsets:OFFSET,... or vecs:OFFSET,... parameter"
+        );
+        Ok(RegistryAccount {
+            name: name.to_string(),
+            pubkey,
+            offsets,
+            vecs,
+        })
+    }
+}
+
+impl RegistryAccount {
+    /// Pubkeys referenced in `data`, leaving out unset ones, which are all
+    /// zeros. Fails if `data` is too short for any of the offsets, as the
+    /// layout is then not the expected one.
+    fn referenced_pubkeys(&self, data: &[u8]) -> anyhow::Result<BTreeSet<Pubkey>> {
+        let pubkey_at = |offset: usize| -> anyhow::Result<Pubkey> {
+            let bytes = data.get(offset..offset + PUBKEY_BYTES).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Account data of {} is {} bytes long, too short for a pubkey at offset {offset}",
+                    self.pubkey,
+                    data.len()
+                )
+            })?;
+            Ok(Pubkey::try_from(bytes).unwrap())
+        };
+
+        let mut pubkeys = BTreeSet::new();
+        for &offset in &self.offsets {
+            pubkeys.insert(pubkey_at(offset)?);
+        }
+        for &offset in &self.vecs {
+            let length = data
+                .get(offset..offset + VEC_LENGTH_BYTES)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Account data of {} is {} bytes long, too short for a vector at offset {offset}",
+                        self.pubkey,
+                        data.len()
+                    )
+                })?;
+            let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+            for index in 0..length {
+                pubkeys.insert(pubkey_at(offset + VEC_LENGTH_BYTES + index * PUBKEY_BYTES)?);
+            }
+        }
+        pubkeys.remove(&Pubkey::default());
+        Ok(pubkeys)
+    }
+}
+
+/// Reads `registry` every `interval`, publishing the pubkeys referenced in its
+/// data whenever they change, for the derived balance watcher to watch under
+/// the registry's name.
+pub fn spawn_registry_discovery(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    registry: RegistryAccount,
+    interval: Duration,
+) -> (JoinHandle<()>, PubkeySet) {
+    let watcher = format!("{}/discovery", registry.name);
+    update_metric_watcher_info(&watcher, "registry_discovery", "", interval);
+    let (sender, receiver) = watch::channel(Arc::new(BTreeSet::new()));
+    let handle = tokio::spawn(async move {
+        // The first set is published even if empty, for the derived balance
+        // watcher to start checking.
+        let mut published = false;
+        loop {
+            rate_limiter.acquire(&watcher, 1, 1).await;
+            let pubkeys = rpc_client
+                .get_account_data(&registry.pubkey)
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|data| registry.referenced_pubkeys(&data));
+            match pubkeys {
+                Ok(pubkeys) => {
+                    sender.send_if_modified(|current| {
+                        if published && **current == pubkeys {
+                            return false;
+                        }
+                        info!(
+                            "Registry '{}' references {} accounts",
+                            registry.name,
+                            pubkeys.len()
+                        );
+                        *current = Arc::new(pubkeys);
+                        true
+                    });
+                    published = true;
+                    record_successful_check(&watcher);
+                }
+                Err(err) => {
+                    error!(
+                        "Failed to read accounts referenced by registry '{}': {err}",
+                        registry.name
+                    );
+                    record_failed_check(&watcher, &err.to_string());
+                    if !sleep_unless_shutdown(backoff_duration(&watcher)).await {
+                        break;
+                    }
+                    continue;
+                }
+            }
+
+            if !sleep_unless_shutdown(interval).await {
+                break;
+            }
+        }
+        info!("Stopped reading registry '{}'", registry.name);
+    });
+    (handle, receiver)
+}
@@ -0,0 +1,533 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use log::{info, warn};
+use serde_json::json;
+use solana_client::client_error::Result as ClientResult;
+use solana_sdk::pubkey::Pubkey;
+use tokio::{sync::Mutex, task::JoinHandle};
+
+use crate::{
+    address_file_balance::{self, remove_address_file_metrics, spawn_address_file_balance_watcher},
+    audit::{record_audit_event, AuditAction},
+    balance::{
+        self, check_balances, parse_watched_address, spawn_balance_watcher, AccountExpectations,
+        BalanceCheck, WatchedAddress,
+    },
+    config::{ConfigFile, NamedAddressConfig, ProgramAccountsConfig},
+    health::forget_watcher,
+    history::forget_balance_history,
+    metrics::{
+        remove_metric_account_assertion_failed, remove_metric_balance_discrepancy,
+        remove_metric_balance_sol, remove_metric_program_accounts, remove_metric_total_balance_sol,
+        remove_metric_watcher_info,
+    },
+    observations::Observation,
+    program_accounts_balance::{
+        check_program_accounts, spawn_program_accounts_balance_watcher,
+        ProgramAccountsBalanceConfig,
+    },
+    rate_limit::RateLimiter,
+    rpc::RpcClientFactory,
+};
+
+/// Watch lists given in the command line syntax, along with the ones of the
+/// config file.
+#[derive(Debug, Clone, Default)]
+pub struct WatchListArgs {
+    pub named_addresses: Vec<String>,
+    pub named_addresses_files: Vec<PathBuf>,
+    pub program_accounts_configs: Vec<String>,
+    /// Named addresses of the config file, in front of the ones above.
+    pub config_named_addresses: Vec<NamedAddressConfig>,
+    /// Program-accounts scans of the config file, in front of the ones above.
+    pub config_program_accounts: Vec<ProgramAccountsConfig>,
+}
+
+impl WatchListArgs {
+    /// These lists with the ones of `config` in front.
+    pub fn merged(&self, config: &ConfigFile) -> Self {
+        let mut merged = self.clone();
+        merged.config_named_addresses = config.named_addresses.clone();
+        let files = config.named_addresses_files.iter().map(PathBuf::from);
+        merged.named_addresses_files.splice(0..0, files);
+        merged.config_program_accounts = config.program_accounts.clone();
+        merged
+    }
+
+    /// Every named address, the ones of the config file first, each along
+    /// with how to refer to it when it is invalid.
+    pub fn watched_addresses(&self) -> Vec<(String, anyhow::Result<WatchedAddress>)> {
+        let config = self
+            .config_named_addresses
+            .iter()
+            .map(|address| (address.name.clone(), WatchedAddress::try_from(address)));
+        let command_line = self
+            .named_addresses
+            .iter()
+            .map(|arg| (arg.clone(), parse_watched_address(arg)));
+        config.chain(command_line).collect()
+    }
+
+    /// Every program-accounts scan, the ones of the config file first, each
+    /// along with its command line syntax.
+    pub fn program_accounts(&self) -> Vec<(String, anyhow::Result<ProgramAccountsBalanceConfig>)> {
+        let config = self
+            .config_program_accounts
+            .iter()
+            .map(|scan| (scan.to_arg(), ProgramAccountsBalanceConfig::try_from(scan)));
+        let command_line = self
+            .program_accounts_configs
+            .iter()
+            .map(|arg| (arg.clone(), arg.parse()));
+        config.chain(command_line).collect()
+    }
+
+    pub fn parse(&self) -> anyhow::Result<WatchList> {
+        let mut watch_list = WatchList::default();
+        for (_, address) in self.watched_addresses() {
+            let address = address?;
+            let (name, pubkey) = (address.name, address.pubkey);
+            if let Some(previous_name) = watch_list.named_pubkeys.get(&pubkey) {
+                anyhow::bail!("Trying to store pubkey '{pubkey}' with name '{name}' but it is stored with a different name '{previous_name}' already");
+            }
+            watch_list.named_pubkeys.insert(pubkey, name);
+            if !address.expectations.is_empty() {
+                watch_list.expectations.insert(pubkey, address.expectations);
+            }
+            if let Some(check_interval) = address.check_interval {
+                watch_list.check_intervals.insert(pubkey, check_interval);
+            }
+        }
+        watch_list.named_addresses_files = self.named_addresses_files.clone();
+        watch_list.named_addresses_files.dedup();
+        for (arg, config) in self.program_accounts() {
+            let config = config?;
+            if watch_list
+                .program_accounts_configs
+                .iter()
+                .any(|(_, existing)| existing.name() == config.name())
+            {
+                anyhow::bail!(
+                    "Program-accounts scan '{}' is configured twice",
+                    config.name()
+                );
+            }
+            watch_list.program_accounts_configs.push((arg, config));
+        }
+        Ok(watch_list)
+    }
+}
+
+/// Named addresses, address files and program-accounts scans to watch.
+#[derive(Debug, Clone, Default)]
+pub struct WatchList {
+    pub named_pubkeys: HashMap<Pubkey, String>,
+    pub expectations: HashMap<Pubkey, AccountExpectations>,
+    /// Named addresses checked at their own interval.
+    pub check_intervals: HashMap<Pubkey, Duration>,
+    pub named_addresses_files: Vec<PathBuf>,
+    /// Scans along with their command line syntax, which tells whether a scan
+    /// of the same name changed.
+    pub program_accounts_configs: Vec<(String, ProgramAccountsBalanceConfig)>,
+}
+
+struct ProgramAccountsWatcher {
+    arg: String,
+    config: ProgramAccountsBalanceConfig,
+    handle: JoinHandle<()>,
+}
+
+/// Watchers of a [`WatchList`] that can be replaced while running. Only the
+/// watchers affected by a change are restarted.
+pub struct ReloadableWatchers {
+    rpc_clients: RpcClientFactory,
+    rate_limiter: Arc<RateLimiter>,
+    check_interval: Duration,
+    named_pubkeys: HashMap<Pubkey, String>,
+    expectations: HashMap<Pubkey, AccountExpectations>,
+    check_intervals: HashMap<Pubkey, Duration>,
+    balance_watcher: Option<JoinHandle<()>>,
+    address_file_watchers: BTreeMap<PathBuf, JoinHandle<()>>,
+    program_accounts_watchers: BTreeMap<String, ProgramAccountsWatcher>,
+}
+
+/// Outcome of an on-demand check.
+#[derive(Debug, Clone)]
+pub enum CheckResult {
+    /// Every named address with the checked name.
+    Balances(Vec<BalanceCheck>),
+    ProgramAccounts {
+        observation: Observation,
+        accounts: usize,
+    },
+}
+
+/// On-demand check taken out of the watchers, so that it runs without
+/// holding up reloads and other admin calls.
+pub struct PendingCheck {
+    rpc_clients: RpcClientFactory,
+    rate_limiter: Arc<RateLimiter>,
+    target: CheckTarget,
+}
+
+enum CheckTarget {
+    Balances {
+        named_pubkeys: HashMap<Pubkey, String>,
+        expectations: HashMap<Pubkey, AccountExpectations>,
+    },
+    ProgramAccounts(ProgramAccountsBalanceConfig),
+}
+
+impl PendingCheck {
+    /// Watcher whose balances are checked.
+    pub fn watcher(&self) -> &str {
+        match &self.target {
+            CheckTarget::Balances { .. } => balance::WATCHER_NAME,
+            CheckTarget::ProgramAccounts(config) => config.name(),
+        }
+    }
+
+    pub async fn run(self) -> ClientResult<CheckResult> {
+        match &self.target {
+            CheckTarget::Balances {
+                named_pubkeys,
+                expectations,
+            } => {
+                self.rate_limiter.acquire(balance::WATCHER_NAME, 1, 1).await;
+                let rpc_client = self.rpc_clients.for_watcher(balance::WATCHER_NAME);
+                let pubkeys: Vec<_> = named_pubkeys.keys().copied().collect();
+                check_balances(
+                    &self.rpc_clients,
+                    &rpc_client,
+                    &pubkeys,
+                    named_pubkeys,
+                    expectations,
+                )
+                .await
+                .map(CheckResult::Balances)
+            }
+            CheckTarget::ProgramAccounts(config) => {
+                let (weight, cost) = config.rate_limit();
+                self.rate_limiter.acquire(config.name(), weight, cost).await;
+                let rpc_client = self.rpc_clients.for_watcher(config.name());
+                let (observation, pubkeys) = check_program_accounts(&rpc_client, config).await?;
+                Ok(CheckResult::ProgramAccounts {
+                    observation,
+                    accounts: pubkeys.len(),
+                })
+            }
+        }
+    }
+}
+
+/// Watchers shared between the reload triggers and the admin API.
+pub type SharedWatchers = Arc<Mutex<ReloadableWatchers>>;
+
+impl ReloadableWatchers {
+    /// Nothing is watched until a watch list is applied.
+    pub fn new(
+        rpc_clients: RpcClientFactory,
+        rate_limiter: Arc<RateLimiter>,
+        check_interval: Duration,
+    ) -> Self {
+        ReloadableWatchers {
+            rpc_clients,
+            rate_limiter,
+            check_interval,
+            named_pubkeys: HashMap::new(),
+            expectations: HashMap::new(),
+            check_intervals: HashMap::new(),
+            balance_watcher: None,
+            address_file_watchers: BTreeMap::new(),
+            program_accounts_watchers: BTreeMap::new(),
+        }
+    }
+
+    /// What is currently watched.
+    pub fn watch_list(&self) -> WatchList {
+        WatchList {
+            named_pubkeys: self.named_pubkeys.clone(),
+            expectations: self.expectations.clone(),
+            check_intervals: self.check_intervals.clone(),
+            named_addresses_files: self.address_file_watchers.keys().cloned().collect(),
+            program_accounts_configs: self
+                .program_accounts_watchers
+                .values()
+                .map(|watcher| (watcher.arg.clone(), watcher.config.clone()))
+                .collect(),
+        }
+    }
+
+    /// Prepares a check of the named addresses or the program-accounts scan
+    /// called `name`, to run right away regardless of the check interval.
+    /// `None` if nothing is watched under that name.
+    pub fn check_now(&self, name: &str) -> Option<PendingCheck> {
+        let named_pubkeys: HashMap<_, _> = self
+            .named_pubkeys
+            .iter()
+            .filter(|(_, pubkey_name)| *pubkey_name == name)
+            .map(|(pubkey, name)| (*pubkey, name.clone()))
+            .collect();
+        let target = match named_pubkeys.is_empty() {
+            false => CheckTarget::Balances {
+                expectations: self
+                    .expectations
+                    .iter()
+                    .filter(|(pubkey, _)| named_pubkeys.contains_key(pubkey))
+                    .map(|(pubkey, expectations)| (*pubkey, expectations.clone()))
+                    .collect(),
+                named_pubkeys,
+            },
+            true => CheckTarget::ProgramAccounts(
+                self.program_accounts_watchers.get(name)?.config.clone(),
+            ),
+        };
+        Some(PendingCheck {
+            rpc_clients: self.rpc_clients.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            target,
+        })
+    }
+
+    /// Hands over the running tasks, e.g. to wait for them on shutdown.
+    pub fn take_handles(&mut self) -> Vec<JoinHandle<()>> {
+        let mut handles: Vec<_> = self.balance_watcher.take().into_iter().collect();
+        handles.extend(std::mem::take(&mut self.address_file_watchers).into_values());
+        handles.extend(
+            std::mem::take(&mut self.program_accounts_watchers)
+                .into_values()
+                .map(|watcher| watcher.handle),
+        );
+        handles
+    }
+
+    fn spawn_address_file_watcher(&mut self, path: PathBuf) {
+        let handle = spawn_address_file_balance_watcher(
+            self.rpc_clients
+                .for_watcher(address_file_balance::WATCHER_NAME),
+            self.rate_limiter.clone(),
+            path.clone(),
+            self.check_interval,
+        );
+        self.address_file_watchers.insert(path, handle);
+    }
+
+    fn spawn_program_accounts_watcher(
+        &mut self,
+        arg: String,
+        config: ProgramAccountsBalanceConfig,
+    ) {
+        let name = config.name().to_string();
+        let handle = spawn_program_accounts_balance_watcher(
+            self.rpc_clients.for_watcher(&name),
+            self.rate_limiter.clone(),
+            config.clone(),
+            self.check_interval,
+        );
+        let watcher = ProgramAccountsWatcher {
+            arg,
+            config,
+            handle,
+        };
+        self.program_accounts_watchers.insert(name, watcher);
+    }
+
+    /// Applies `watch_list`, stopping watchers of dropped entries along with
+    /// their metrics and starting watchers of new ones. Changes are audited
+    /// as made by `source`.
+    pub async fn apply(&mut self, source: &str, watch_list: WatchList) {
+        self.apply_named_addresses(
+            source,
+            watch_list.named_pubkeys,
+            watch_list.expectations,
+            watch_list.check_intervals,
+        )
+        .await;
+        self.apply_address_files(source, watch_list.named_addresses_files)
+            .await;
+        self.apply_program_accounts(source, watch_list.program_accounts_configs)
+            .await;
+    }
+
+    async fn apply_named_addresses(
+        &mut self,
+        source: &str,
+        named_pubkeys: HashMap<Pubkey, String>,
+        expectations: HashMap<Pubkey, AccountExpectations>,
+        check_intervals: HashMap<Pubkey, Duration>,
+    ) {
+        let unchanged = named_pubkeys == self.named_pubkeys
+            && expectations == self.expectations
+            && check_intervals == self.check_intervals;
+        if unchanged && self.balance_watcher.is_some() {
+            return;
+        }
+        // Stopped first, so that it cannot update metrics removed below.
+        if let Some(mut handle) = self.balance_watcher.take() {
+            stop(&mut handle).await;
+        }
+
+        for (pubkey, name) in &self.named_pubkeys {
+            let key = pubkey.to_string();
+            let new_name = named_pubkeys.get(pubkey);
+            if new_name != Some(name) {
+                remove_metric_balance_sol(name, &key);
+                remove_metric_balance_discrepancy(name, &key);
+                if !named_pubkeys.values().any(|new_name| new_name == name) {
+                    forget_balance_history(balance::WATCHER_NAME, name);
+                }
+            }
+            if self.expectations.contains_key(pubkey) {
+                remove_metric_account_assertion_failed(name, &key);
+            }
+            match new_name {
+                None => {
+                    info!("Stopped watching {name} ({pubkey})");
+                    record_audit_event(
+                        source,
+                        AuditAction::WatcherRemoved,
+                        balance::WATCHER_NAME,
+                        json!({ "name": name, "pubkey": key }),
+                    );
+                }
+                Some(new_name)
+                    if new_name != name
+                        || expectations.get(pubkey) != self.expectations.get(pubkey)
+                        || check_intervals.get(pubkey) != self.check_intervals.get(pubkey) =>
+                {
+                    info!("Watching {new_name} ({pubkey}), previously {name}");
+                    record_audit_event(
+                        source,
+                        AuditAction::WatcherChanged,
+                        balance::WATCHER_NAME,
+                        json!({ "name": new_name, "previous_name": name, "pubkey": key }),
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+        for (pubkey, name) in &named_pubkeys {
+            if !self.named_pubkeys.contains_key(pubkey) {
+                info!("Watching {name} ({pubkey})");
+                record_audit_event(
+                    source,
+                    AuditAction::WatcherAdded,
+                    balance::WATCHER_NAME,
+                    json!({ "name": name, "pubkey": pubkey.to_string() }),
+                );
+            }
+        }
+
+        self.balance_watcher = Some(spawn_balance_watcher(
+            self.rpc_clients.clone(),
+            self.rate_limiter.clone(),
+            named_pubkeys.clone(),
+            expectations.clone(),
+            check_intervals.clone(),
+            self.check_interval,
+        ));
+        self.named_pubkeys = named_pubkeys;
+        self.expectations = expectations;
+        self.check_intervals = check_intervals;
+    }
+
+    async fn apply_address_files(&mut self, source: &str, paths: Vec<PathBuf>) {
+        let removed: Vec<_> = self
+            .address_file_watchers
+            .keys()
+            .filter(|path| !paths.contains(path))
+            .cloned()
+            .collect();
+        for path in removed {
+            let mut handle = self.address_file_watchers.remove(&path).unwrap();
+            stop(&mut handle).await;
+            // Entries removed from the file since its last check are missed.
+            if let Err(err) = remove_address_file_metrics(&path).await {
+                warn!(
+                    "Cannot remove balances of addresses listed in {}: {err}",
+                    path.display()
+                );
+            }
+            let watcher = path.display().to_string();
+            forget_watcher(&watcher);
+            remove_metric_watcher_info(&watcher);
+            info!("Stopped watching addresses listed in {watcher}");
+            record_audit_event(
+                source,
+                AuditAction::WatcherRemoved,
+                &watcher,
+                json!({ "path": path }),
+            );
+        }
+
+        for path in paths {
+            if self.address_file_watchers.contains_key(&path) {
+                continue;
+            }
+            record_audit_event(
+                source,
+                AuditAction::WatcherAdded,
+                &path.display().to_string(),
+                json!({ "path": path }),
+            );
+            self.spawn_address_file_watcher(path);
+        }
+    }
+
+    async fn apply_program_accounts(
+        &mut self,
+        source: &str,
+        configs: Vec<(String, ProgramAccountsBalanceConfig)>,
+    ) {
+        let removed: Vec<_> = self
+            .program_accounts_watchers
+            .iter()
+            .filter(|(name, watcher)| {
+                !configs
+                    .iter()
+                    .any(|(arg, config)| config.name() == *name && *arg == watcher.arg)
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in &removed {
+            let mut watcher = self.program_accounts_watchers.remove(name).unwrap();
+            stop(&mut watcher.handle).await;
+            remove_metric_total_balance_sol(name);
+            remove_metric_program_accounts(name);
+            forget_watcher(name);
+            remove_metric_watcher_info(name);
+            info!("Stopped watching program accounts of '{name}'");
+            if !configs.iter().any(|(_, config)| config.name() == name) {
+                record_audit_event(
+                    source,
+                    AuditAction::WatcherRemoved,
+                    name,
+                    json!({ "config": watcher.arg }),
+                );
+            }
+        }
+
+        for (arg, config) in configs {
+            if self.program_accounts_watchers.contains_key(config.name()) {
+                continue;
+            }
+            let action = match removed.iter().any(|name| name == config.name()) {
+                true => AuditAction::WatcherChanged,
+                false => AuditAction::WatcherAdded,
+            };
+            record_audit_event(source, action, config.name(), json!({ "config": arg }));
+            self.spawn_program_accounts_watcher(arg, config);
+        }
+    }
+}
+
+/// Aborts a watcher task and waits until it is gone.
+async fn stop(handle: &mut JoinHandle<()>) {
+    handle.abort();
+    let _ = handle.await;
+}
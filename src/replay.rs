@@ -0,0 +1,107 @@
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use chrono::DateTime;
+use log::{error, info, warn};
+use serde_json::Value;
+use solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey};
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, BufReader},
+    task::JoinHandle,
+};
+
+use crate::{
+    metrics::{update_metric_balance_sol, update_metric_total_balance_sol},
+    observations::{pending_observations, record_observation, Observation},
+    shutdown::sleep_unless_shutdown,
+};
+
+/// Parses a line written by the observation logger.
+fn parse_observation(line: &str) -> anyhow::Result<Observation> {
+    let value: Value = serde_json::from_str(line)?;
+    let string = |name: &str| {
+        value[name]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Missing '{name}'"))
+    };
+    Ok(Observation {
+        watcher: string("watcher")?.to_string(),
+        name: string("name")?.to_string(),
+        pubkey: value["pubkey"].as_str().map(Pubkey::from_str).transpose()?,
+        lamports: value["lamports"]
+            .as_u64()
+            .ok_or_else(|| anyhow::anyhow!("Missing 'lamports'"))?,
+        slot: value["slot"].as_u64(),
+        duration: Duration::from_millis(value["duration_ms"].as_u64().unwrap_or(0)),
+        observed_at: DateTime::parse_from_rfc3339(string("timestamp")?)?.into(),
+    })
+}
+
+/// Replays observations recorded with `--log-observations-json` from `path`
+/// through the metrics and every observation subscriber, such as alert rules
+/// and sinks, without any RPC. Observations keep their recorded timestamps,
+/// and the time between them is divided by `speed`. Finishes once every
+/// subscriber received the last observation.
+pub fn spawn_replay(path: PathBuf, speed: f64) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        info!("Replaying observations from {} at {speed}x", path.display());
+        let mut lines = match File::open(&path).await {
+            Ok(file) => BufReader::new(file).lines(),
+            Err(err) => {
+                error!("Failed to open {}: {err}", path.display());
+                return;
+            }
+        };
+        let mut count = 0;
+        let mut previous: Option<SystemTime> = None;
+        loop {
+            let line = match lines.next_line().await {
+                Ok(Some(line)) => line,
+                Ok(None) => break,
+                Err(err) => {
+                    error!("Failed to read {}: {err}", path.display());
+                    break;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let observation = match parse_observation(&line) {
+                Ok(observation) => observation,
+                Err(err) => {
+                    warn!("Skipping malformed observation '{line}': {err}");
+                    continue;
+                }
+            };
+
+            let elapsed = previous
+                .and_then(|previous| observation.observed_at.duration_since(previous).ok())
+                .unwrap_or_default();
+            if !sleep_unless_shutdown(elapsed.div_f64(speed)).await {
+                return;
+            }
+            previous = Some(observation.observed_at);
+
+            let balance = lamports_to_sol(observation.lamports);
+            match observation.pubkey {
+                Some(pubkey) => {
+                    update_metric_balance_sol(&observation.name, &pubkey.to_string(), balance)
+                }
+                None => update_metric_total_balance_sol(&observation.name, balance),
+            }
+            record_observation(observation);
+            count += 1;
+        }
+
+        while pending_observations() > 0 {
+            if !sleep_unless_shutdown(Duration::from_millis(100)).await {
+                return;
+            }
+        }
+        info!("Replayed {count} observations from {}", path.display());
+    })
+}
@@ -0,0 +1,1146 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
+    fmt,
+    future::Future,
+    io,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use futures::{stream, StreamExt};
+use log::warn;
+use solana_client::{
+    client_error::{
+        reqwest::{
+            self,
+            header::{HeaderName, HeaderValue},
+        },
+        ClientError, ClientErrorKind, Result as ClientResult,
+    },
+    nonblocking::rpc_client::RpcClient,
+    rpc_client::RpcClientConfig,
+    rpc_request::{RpcError, RpcRequest},
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+use solana_rpc_client::http_sender::HttpSender;
+use solana_sdk::{
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+    hash::Hash,
+};
+
+use crate::{
+    check::MAX_ACCOUNTS_PER_REQUEST,
+    metrics::{
+        mean_rpc_endpoint_request_duration, observe_metric_rpc_endpoint_request_duration,
+        observe_metric_rpc_request_duration, remove_metric_rpc_active_endpoint,
+        remove_metric_rpc_endpoint_request_duration, remove_metric_rpc_failovers,
+        remove_metric_rpc_healthy, remove_metric_rpc_max_accounts_per_request,
+        update_metric_rpc_active_endpoint, update_metric_rpc_error, update_metric_rpc_failover,
+        update_metric_rpc_max_accounts_per_request, update_metric_rpc_response_bytes,
+        update_metric_watcher_rpc_endpoint,
+    },
+    rpc_cost::record_rpc_request,
+    secrets::resolve_secret,
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long idle connections to an endpoint are kept open unless configured
+/// otherwise.
+pub const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Chunks of a `getMultipleAccounts` sent to an endpoint at once.
+const MAX_CONCURRENT_CHUNKS: usize = 4;
+
+tokio::task_local! {
+    /// Labels of the endpoints that answered requests sent within
+    /// [`with_serving_endpoints`].
+    static SERVING_ENDPOINTS: RefCell<BTreeSet<String>>;
+}
+
+/// Runs `future`, returning its output along with the labels of the
+/// endpoints that answered the requests it sent.
+pub async fn with_serving_endpoints<F: Future>(future: F) -> (F::Output, BTreeSet<String>) {
+    SERVING_ENDPOINTS
+        .scope(RefCell::new(BTreeSet::new()), async move {
+            let output = future.await;
+            (output, SERVING_ENDPOINTS.with(RefCell::take))
+        })
+        .await
+}
+
+/// HTTP protocol negotiated with RPC endpoints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// HTTP/1.1, or HTTP/2 when negotiated through TLS ALPN.
+    #[default]
+    Auto,
+    Http1,
+    /// HTTP/2 with prior knowledge, multiplexing all requests over one connection.
+    Http2,
+}
+
+impl FromStr for HttpVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "auto" => HttpVersion::Auto,
+            "http1" => HttpVersion::Http1,
+            "http2" => HttpVersion::Http2,
+            _ => anyhow::bail!("Unsupported HTTP version '{s}', expected auto, http1 or http2"),
+        })
+    }
+}
+
+/// An HTTP header sent with every RPC request, such as the `Authorization`
+/// header of providers that do not take their token in the URL, given as
+/// `Name: value`. The value is left out of debug output.
+#[derive(Clone)]
+pub struct RpcHeader {
+    name: HeaderName,
+    value: String,
+}
+
+impl fmt::Debug for RpcHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RpcHeader")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FromStr for RpcHeader {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, value)) = s.split_once(':') else {
+            anyhow::bail!("Cannot parse RPC header, expected syntax: Name: value");
+        };
+        let name = HeaderName::from_str(name.trim())
+            .map_err(|err| anyhow::anyhow!("Invalid RPC header name '{name}': {err}"))?;
+        Ok(RpcHeader {
+            name,
+            value: value.trim().to_string(),
+        })
+    }
+}
+
+impl RpcHeader {
+    /// Resolves the value with [`resolve_secret`], so that it can be read
+    /// from a file or be age-encrypted.
+    pub fn with_resolved_secret(mut self) -> anyhow::Result<Self> {
+        self.value = resolve_secret(&self.value)?;
+        Ok(self)
+    }
+
+    /// The value, marked sensitive so that the HTTP client never shows it.
+    fn header_value(&self) -> anyhow::Result<HeaderValue> {
+        let mut value = HeaderValue::from_str(&self.value)
+            .map_err(|_| anyhow::anyhow!("Invalid value of RPC header '{}'", self.name))?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+}
+
+/// Connection reuse settings and headers of the HTTP client shared by all
+/// RPC clients.
+#[derive(Debug, Clone)]
+pub struct HttpClientConfig {
+    pub pool_max_idle_per_host: Option<usize>,
+    pub pool_idle_timeout: Duration,
+    pub tcp_keepalive: Option<Duration>,
+    pub http_version: HttpVersion,
+    pub headers: Vec<RpcHeader>,
+    /// Longest wait for a connection to an endpoint to be established.
+    pub connect_timeout: Option<Duration>,
+    /// Longest wait for a response, unless a watcher has a shorter one.
+    pub request_timeout: Duration,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        Self {
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: DEFAULT_POOL_IDLE_TIMEOUT,
+            tcp_keepalive: None,
+            http_version: HttpVersion::Auto,
+            headers: vec![],
+            connect_timeout: None,
+            request_timeout: REQUEST_TIMEOUT,
+        }
+    }
+}
+
+impl HttpClientConfig {
+    fn build(&self) -> anyhow::Result<reqwest::Client> {
+        let mut headers = HttpSender::default_headers();
+        for header in &self.headers {
+            headers.insert(header.name.clone(), header.header_value()?);
+        }
+        let mut builder = reqwest::Client::builder()
+            .default_headers(headers)
+            .timeout(self.request_timeout)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_keepalive(self.tcp_keepalive);
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(max_idle) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        builder = match self.http_version {
+            HttpVersion::Auto => builder,
+            HttpVersion::Http1 => builder.http1_only(),
+            HttpVersion::Http2 => builder.http2_prior_knowledge(),
+        };
+        Ok(builder.build()?)
+    }
+}
+
+/// How the endpoints a watcher sends its requests to are ordered. Requests
+/// fail over to the next endpoint in that order whatever the strategy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RoutingStrategy {
+    /// The endpoint with the lowest mean latency for the request class.
+    #[default]
+    Latency,
+    /// Each endpoint in turn, spreading the requests of the watcher evenly.
+    RoundRobin,
+    /// The endpoint that went the longest without a request of any watcher.
+    LeastRecentlyUsed,
+}
+
+impl FromStr for RoutingStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "latency" => RoutingStrategy::Latency,
+            "round-robin" => RoutingStrategy::RoundRobin,
+            "lru" => RoutingStrategy::LeastRecentlyUsed,
+            _ => anyhow::bail!(
+                "Unsupported routing strategy '{s}', expected latency, round-robin or lru"
+            ),
+        })
+    }
+}
+
+/// Parses `processed`, `confirmed` or `finalized`.
+pub fn parse_commitment(s: &str) -> anyhow::Result<CommitmentConfig> {
+    let commitment = match s {
+        "processed" => CommitmentLevel::Processed,
+        "confirmed" => CommitmentLevel::Confirmed,
+        "finalized" => CommitmentLevel::Finalized,
+        _ => anyhow::bail!(
+            "Cannot parse commitment '{s}', expected one of processed, confirmed or finalized"
+        ),
+    };
+    Ok(CommitmentConfig { commitment })
+}
+
+/// Routing strategy of a single watcher, `watcher=STRATEGY`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatcherRouting {
+    pub watcher: String,
+    pub strategy: RoutingStrategy,
+}
+
+impl FromStr for WatcherRouting {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((watcher, strategy)) = s.split_once('=') else {
+            anyhow::bail!("Cannot parse watcher routing '{s}', expected syntax: watcher=STRATEGY");
+        };
+        Ok(WatcherRouting {
+            watcher: watcher.to_string(),
+            strategy: strategy.parse()?,
+        })
+    }
+}
+
+/// Request timeout of a single watcher, `watcher=SECS`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatcherTimeout {
+    pub watcher: String,
+    pub timeout: Duration,
+}
+
+impl FromStr for WatcherTimeout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((watcher, secs)) = s.split_once('=') else {
+            anyhow::bail!("Cannot parse watcher timeout '{s}', expected syntax: watcher=SECS");
+        };
+        let secs: f64 = secs
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Failed to parse timeout of '{watcher}' from '{secs}'"))?;
+        anyhow::ensure!(
+            secs.is_finite() && secs > 0.0,
+            "Timeout of '{watcher}' must be positive, got {secs}"
+        );
+        Ok(WatcherTimeout {
+            watcher: watcher.to_string(),
+            timeout: Duration::from_secs_f64(secs),
+        })
+    }
+}
+
+/// Builds one [`RpcClient`] per watcher so that requests can be attributed to
+/// the watcher issuing them, while all clients share the same endpoints and
+/// HTTP connection pool. Clones share the endpoints too, so endpoints added or
+/// removed through any clone apply to every client.
+#[derive(Clone)]
+pub struct RpcClientFactory {
+    router: Arc<EndpointRouter>,
+    http_client: reqwest::Client,
+    /// Genesis hash of the cluster every endpoint must serve, once required.
+    genesis_hash: Option<Hash>,
+    /// Routing strategy of watchers not routed by latency.
+    routing: Arc<HashMap<String, RoutingStrategy>>,
+    /// Commitment of the queries of the clients built.
+    commitment: CommitmentConfig,
+    /// Request timeout of watchers timing out sooner than the HTTP client.
+    timeouts: Arc<HashMap<String, Duration>>,
+    /// Watchers clients were built for.
+    watchers: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl RpcClientFactory {
+    /// Without any `urls`, requests fail until an endpoint is added, which
+    /// only suits replaying recorded observations.
+    pub fn new(urls: Vec<String>, http_config: &HttpClientConfig) -> anyhow::Result<Self> {
+        let factory = Self {
+            router: Arc::new(EndpointRouter {
+                endpoints: Default::default(),
+                active_endpoint: Mutex::new(None),
+                request_timeout: http_config.request_timeout,
+            }),
+            http_client: http_config.build()?,
+            genesis_hash: None,
+            routing: Default::default(),
+            commitment: CommitmentConfig::default(),
+            timeouts: Default::default(),
+            watchers: Default::default(),
+        };
+        for url in urls {
+            reqwest::Url::parse(&url)?;
+            factory.insert_endpoint(url);
+        }
+        Ok(factory)
+    }
+
+    /// Routes the requests of the clients built from now on for each of the
+    /// watchers of `routing` with its strategy.
+    pub fn set_routing(&mut self, routing: Vec<WatcherRouting>) {
+        self.routing = Arc::new(
+            routing
+                .into_iter()
+                .map(|routing| (routing.watcher, routing.strategy))
+                .collect(),
+        );
+    }
+
+    /// Gives up on each request of the clients built from now on for each of
+    /// the watchers of `timeouts` after its timeout, failing over to the next
+    /// endpoint, rather than after the request timeout of the HTTP client.
+    pub fn set_timeouts(&mut self, timeouts: Vec<WatcherTimeout>) {
+        self.timeouts = Arc::new(
+            timeouts
+                .into_iter()
+                .map(|timeout| (timeout.watcher, timeout.timeout))
+                .collect(),
+        );
+    }
+
+    /// Watchers given a timeout that no client was built for so far, most
+    /// likely misspelled.
+    pub fn unused_timeouts(&self) -> Vec<String> {
+        let watchers = self.watchers.lock().unwrap();
+        let mut unused: Vec<_> = self
+            .timeouts
+            .keys()
+            .filter(|watcher| !watchers.contains(*watcher))
+            .cloned()
+            .collect();
+        unused.sort();
+        unused
+    }
+
+    /// Queries chain state at `commitment` with the clients built from now
+    /// on, rather than at `finalized`.
+    pub fn set_commitment(&mut self, commitment: CommitmentConfig) {
+        self.commitment = commitment;
+    }
+
+    /// Verifies that every endpoint serves the cluster with `genesis_hash`,
+    /// and refuses endpoints of any other cluster from then on, so that
+    /// balances of one cluster are never reported as those of another.
+    pub async fn require_genesis_hash(&mut self, genesis_hash: Hash) -> anyhow::Result<()> {
+        for (label, client) in self.endpoint_clients() {
+            verify_genesis_hash(&label, &client, &genesis_hash).await?;
+        }
+        self.genesis_hash = Some(genesis_hash);
+        Ok(())
+    }
+
+    pub fn endpoint_labels(&self) -> Vec<String> {
+        self.router
+            .endpoints()
+            .iter()
+            .map(|endpoint| endpoint.label.clone())
+            .collect()
+    }
+
+    /// Starts routing requests to `url` as well, returning the label of the
+    /// new endpoint.
+    pub async fn add_endpoint(&self, url: String) -> anyhow::Result<String> {
+        reqwest::Url::parse(&url)?;
+        if let Some(genesis_hash) = &self.genesis_hash {
+            let client = RpcClient::new_sender(
+                HttpSender::new_with_client(url.clone(), self.http_client.clone()),
+                RpcClientConfig::default(),
+            );
+            verify_genesis_hash(&endpoint_label(&url), &client, genesis_hash).await?;
+        }
+        Ok(self.insert_endpoint(url))
+    }
+
+    fn insert_endpoint(&self, url: String) -> String {
+        let mut endpoints = self.router.endpoints.write().unwrap();
+        let host = endpoint_label(&url);
+        let mut label = host.clone();
+        let mut suffix = 0;
+        while endpoints.iter().any(|endpoint| endpoint.label == label) {
+            suffix += 1;
+            label = format!("{host}-{suffix}");
+        }
+        endpoints.push(Arc::new(Endpoint {
+            label: label.clone(),
+            sender: HttpSender::new_with_client(url, self.http_client.clone()),
+            max_accounts_per_request: AtomicUsize::new(MAX_ACCOUNTS_PER_REQUEST),
+            last_used: Mutex::new(None),
+            request_timeout: self.router.request_timeout,
+        }));
+        update_metric_rpc_max_accounts_per_request(&label, MAX_ACCOUNTS_PER_REQUEST);
+        update_metric_rpc_active_endpoint(&label, false);
+        label
+    }
+
+    /// Stops routing new requests to the endpoint labeled `label`. Requests
+    /// already sent to it are left to complete. The last endpoint cannot be
+    /// removed.
+    pub fn remove_endpoint(&self, label: &str) -> anyhow::Result<()> {
+        let mut endpoints = self.router.endpoints.write().unwrap();
+        let Some(index) = endpoints
+            .iter()
+            .position(|endpoint| endpoint.label == label)
+        else {
+            anyhow::bail!("Unknown RPC endpoint '{label}'");
+        };
+        anyhow::ensure!(endpoints.len() > 1, "Cannot remove the last RPC endpoint");
+        endpoints.remove(index);
+        remove_metric_rpc_endpoint_request_duration(label);
+        remove_metric_rpc_healthy(label);
+        remove_metric_rpc_max_accounts_per_request(label);
+        remove_metric_rpc_active_endpoint(label);
+        remove_metric_rpc_failovers(label);
+        Ok(())
+    }
+
+    /// Routes requests to `url` instead of the endpoint labeled `label`,
+    /// returning the label of the new endpoint.
+    pub async fn replace_endpoint(&self, label: &str, url: String) -> anyhow::Result<String> {
+        anyhow::ensure!(
+            self.endpoint_labels()
+                .iter()
+                .any(|existing| existing == label),
+            "Unknown RPC endpoint '{label}'"
+        );
+        let new_label = self.add_endpoint(url).await?;
+        self.remove_endpoint(label)?;
+        Ok(new_label)
+    }
+
+    /// Routes requests to exactly `urls`, adding the missing endpoints before
+    /// removing the others so that requests always have somewhere to go.
+    /// Returns the labels of the endpoints added and of those removed.
+    pub async fn sync_endpoints(
+        &self,
+        urls: Vec<String>,
+    ) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+        let current: Vec<_> = self
+            .router
+            .endpoints()
+            .iter()
+            .map(|endpoint| (endpoint.label.clone(), endpoint.sender.url()))
+            .collect();
+        let mut added = vec![];
+        for url in &urls {
+            if !current.iter().any(|(_, current)| current == url) {
+                added.push(self.add_endpoint(url.clone()).await?);
+            }
+        }
+        let mut removed = vec![];
+        for (label, url) in current {
+            if !urls.contains(&url) {
+                self.remove_endpoint(&label)?;
+                removed.push(label);
+            }
+        }
+        Ok((added, removed))
+    }
+
+    /// One client per endpoint, bypassing routing, with the endpoint's label.
+    pub fn endpoint_clients(&self) -> Vec<(String, RpcClient)> {
+        self.router
+            .endpoints()
+            .iter()
+            .map(|endpoint| {
+                let sender =
+                    HttpSender::new_with_client(endpoint.sender.url(), self.http_client.clone());
+                (
+                    endpoint.label.clone(),
+                    RpcClient::new_sender(
+                        sender,
+                        RpcClientConfig::with_commitment(self.commitment),
+                    ),
+                )
+            })
+            .collect()
+    }
+
+    pub fn for_watcher(&self, watcher: &str) -> Arc<RpcClient> {
+        self.watchers.lock().unwrap().insert(watcher.to_string());
+        Arc::new(RpcClient::new_sender(
+            WatcherRpcSender {
+                watcher: watcher.to_string(),
+                router: self.router.clone(),
+                strategy: self.routing.get(watcher).copied().unwrap_or_default(),
+                timeout: self.timeouts.get(watcher).copied(),
+                requests: AtomicUsize::new(0),
+                pinned_endpoint: None,
+                latest_endpoint: Mutex::new(None),
+            },
+            RpcClientConfig::with_commitment(self.commitment),
+        ))
+    }
+
+    /// One client of `watcher` per endpoint, each sending every request to
+    /// its endpoint only, with the endpoint's label.
+    pub fn endpoint_clients_for_watcher(&self, watcher: &str) -> Vec<(String, Arc<RpcClient>)> {
+        self.watchers.lock().unwrap().insert(watcher.to_string());
+        self.router
+            .endpoints()
+            .iter()
+            .map(|endpoint| {
+                let client = RpcClient::new_sender(
+                    WatcherRpcSender {
+                        watcher: watcher.to_string(),
+                        router: self.router.clone(),
+                        strategy: RoutingStrategy::default(),
+                        timeout: self.timeouts.get(watcher).copied(),
+                        requests: AtomicUsize::new(0),
+                        pinned_endpoint: Some(endpoint.label.clone()),
+                        latest_endpoint: Mutex::new(None),
+                    },
+                    RpcClientConfig::with_commitment(self.commitment),
+                );
+                (endpoint.label.clone(), Arc::new(client))
+            })
+            .collect()
+    }
+}
+
+async fn verify_genesis_hash(
+    label: &str,
+    client: &RpcClient,
+    expected: &Hash,
+) -> anyhow::Result<()> {
+    let genesis_hash = client
+        .get_genesis_hash()
+        .await
+        .map_err(|err| anyhow::anyhow!("Cannot get genesis hash of RPC endpoint {label}: {err}"))?;
+    anyhow::ensure!(
+        genesis_hash == *expected,
+        "RPC endpoint {label} serves the cluster with genesis hash {genesis_hash}, expected {expected}"
+    );
+    Ok(())
+}
+
+/// Identifies an endpoint in metrics and logs by its host only, as the path and
+/// query of RPC URLs commonly carry API keys.
+fn endpoint_label(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Classifies a failed request for `rpc_errors_total`.
+fn error_kind(err: &ClientError) -> &'static str {
+    match err.kind() {
+        ClientErrorKind::Reqwest(err) if err.is_timeout() => "timeout",
+        ClientErrorKind::Io(err) if err.kind() == io::ErrorKind::TimedOut => "timeout",
+        ClientErrorKind::Reqwest(err)
+            if err.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS) =>
+        {
+            "rate_limited"
+        }
+        ClientErrorKind::Reqwest(err) if err.is_status() => "http_status",
+        ClientErrorKind::Reqwest(_) | ClientErrorKind::Io(_) => "transport",
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { .. }) => "rpc",
+        ClientErrorKind::RpcError(RpcError::ParseError(_)) | ClientErrorKind::SerdeJson(_) => {
+            "decode"
+        }
+        _ => "other",
+    }
+}
+
+/// Requests with very different cost profiles, routed independently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestClass {
+    /// Cheap point reads such as `getMultipleAccounts`.
+    Read,
+    /// Heavy scans over many accounts such as `getProgramAccounts`.
+    Scan,
+}
+
+impl RequestClass {
+    pub fn of(request: &RpcRequest) -> Self {
+        match request {
+            RpcRequest::GetProgramAccounts
+            | RpcRequest::GetLargestAccounts
+            | RpcRequest::GetTokenAccountsByOwner
+            | RpcRequest::GetTokenAccountsByDelegate => RequestClass::Scan,
+            _ => RequestClass::Read,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestClass::Read => "read",
+            RequestClass::Scan => "scan",
+        }
+    }
+}
+
+struct Endpoint {
+    label: String,
+    sender: HttpSender,
+    /// Most accounts per `getMultipleAccounts` the endpoint accepted, halved
+    /// whenever it rejects a request for its size.
+    max_accounts_per_request: AtomicUsize,
+    /// When the latest request was sent to the endpoint.
+    last_used: Mutex<Option<Instant>>,
+    /// Duration failed requests are recorded as.
+    request_timeout: Duration,
+}
+
+impl Endpoint {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        let class = RequestClass::of(&request);
+        let start = Instant::now();
+        *self.last_used.lock().unwrap() = Some(start);
+        let response = self.sender.send(request, params).await;
+        let duration = match response {
+            Ok(_) => {
+                let _ = SERVING_ENDPOINTS.try_with(|endpoints| {
+                    endpoints.borrow_mut().insert(self.label.clone());
+                });
+                start.elapsed()
+            }
+            Err(_) => self.request_timeout,
+        };
+        observe_metric_rpc_endpoint_request_duration(&self.label, class.as_str(), duration);
+        response
+    }
+
+    /// Sends `getMultipleAccounts` in chunks the endpoint accepts, up to
+    /// [`MAX_CONCURRENT_CHUNKS`] at once, merging their results into one
+    /// response at the lowest slot of any chunk.
+    async fn send_multiple_accounts(
+        &self,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        let pubkeys = match params.get(0).and_then(serde_json::Value::as_array) {
+            Some(pubkeys) if !pubkeys.is_empty() => pubkeys.clone(),
+            _ => return self.send(RpcRequest::GetMultipleAccounts, params).await,
+        };
+        let mut merged: Option<serde_json::Value> = None;
+        let mut accounts = Vec::with_capacity(pubkeys.len());
+        'chunking: while accounts.len() < pubkeys.len() {
+            let size = self.max_accounts_per_request.load(Ordering::Relaxed);
+            let requests: Vec<_> = pubkeys[accounts.len()..]
+                .chunks(size)
+                .map(|chunk| {
+                    let mut chunk_params = params.clone();
+                    chunk_params[0] = serde_json::Value::from(chunk.to_vec());
+                    let response = self.send(RpcRequest::GetMultipleAccounts, chunk_params);
+                    async move { (chunk.len(), response.await) }
+                })
+                .collect();
+            let mut responses = stream::iter(requests).buffered(MAX_CONCURRENT_CHUNKS);
+            // Chunks are answered in order, so that a rejected one is sent
+            // again in smaller chunks along with those after it.
+            while let Some((chunk_size, response)) = responses.next().await {
+                let mut response = match response {
+                    Ok(response) => response,
+                    Err(err) if chunk_size > 1 && is_size_rejection(&err) => {
+                        let halved = halve_chunk_size(&self.max_accounts_per_request, chunk_size);
+                        if let Some(halved) = halved {
+                            warn!(
+                                "RPC endpoint {} rejected {chunk_size} accounts per request, sending {halved} from now on: {err}",
+                                self.label
+                            );
+                            update_metric_rpc_max_accounts_per_request(&self.label, halved);
+                        }
+                        continue 'chunking;
+                    }
+                    Err(err) => return Err(err),
+                };
+                if let Some(serde_json::Value::Array(chunk)) = response.get_mut("value") {
+                    accounts.append(chunk);
+                } else {
+                    return Err(ClientError::from(ClientErrorKind::Custom(
+                        "Malformed getMultipleAccounts response".to_string(),
+                    )));
+                }
+                merge_min_slot(&mut merged, response);
+            }
+        }
+        let mut merged = merged.unwrap_or_default();
+        merged["value"] = serde_json::Value::from(accounts);
+        Ok(merged)
+    }
+}
+
+/// Lowers `max_accounts_per_request` to half the rejected `chunk_size`.
+/// Returns the new size, or `None` if concurrent rejections already lowered
+/// it that far.
+fn halve_chunk_size(max_accounts_per_request: &AtomicUsize, chunk_size: usize) -> Option<usize> {
+    let halved = chunk_size / 2;
+    let previous = max_accounts_per_request.fetch_min(halved, Ordering::Relaxed);
+    (previous > halved).then_some(halved)
+}
+
+/// Merges the `getMultipleAccounts` `response` of a chunk into `merged`,
+/// keeping the context of the response at the lowest slot.
+fn merge_min_slot(merged: &mut Option<serde_json::Value>, mut response: serde_json::Value) {
+    let slot = response["context"]["slot"].as_u64();
+    match merged {
+        Some(merged) if slot < merged["context"]["slot"].as_u64() => {
+            merged["context"] = response["context"].take();
+        }
+        Some(_) => {}
+        None => *merged = Some(response),
+    }
+}
+
+/// Whether the endpoint rejected a request for carrying too many accounts,
+/// as RPC nodes do above 100 with "Too many inputs provided", or for its
+/// payload size.
+fn is_size_rejection(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Reqwest(err) => {
+            err.status() == Some(reqwest::StatusCode::PAYLOAD_TOO_LARGE)
+        }
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, message, .. }) => {
+            let message = message.to_ascii_lowercase();
+            *code == -32602 && (message.contains("too many") || message.contains("max"))
+        }
+        _ => false,
+    }
+}
+
+/// Sends each request to the endpoint with the lowest mean latency recorded
+/// for its request class, unless the watcher sending it is routed with
+/// another [`RoutingStrategy`]. Endpoints without any samples yet are
+/// preferred so that every endpoint gets measured, and failed requests are
+/// recorded as a full timeout so that failing endpoints stop receiving
+/// traffic. A request that fails or times out is retried against the next
+/// endpoint in that order, unless every endpoint would reject it.
+struct EndpointRouter {
+    /// Only empty when replaying. Requests hold on to the endpoint they were
+    /// sent to, so endpoints can be swapped out while requests are in flight.
+    endpoints: RwLock<Vec<Arc<Endpoint>>>,
+    /// Label of the endpoint that answered the latest request, exported as
+    /// `rpc_active_endpoint`.
+    active_endpoint: Mutex<Option<String>>,
+    /// Request timeout of the HTTP client, which failed and timed out
+    /// requests are recorded as.
+    request_timeout: Duration,
+}
+
+impl EndpointRouter {
+    fn endpoints(&self) -> Vec<Arc<Endpoint>> {
+        self.endpoints.read().unwrap().clone()
+    }
+
+    /// Endpoints in the order the `index`-th request of a watcher routed with
+    /// `strategy` is sent to them, when of `class`.
+    fn ranked(
+        &self,
+        class: RequestClass,
+        strategy: RoutingStrategy,
+        index: usize,
+    ) -> Vec<Arc<Endpoint>> {
+        let mut endpoints = self.endpoints();
+        match strategy {
+            RoutingStrategy::Latency => {
+                let mut ranked: Vec<_> = endpoints
+                    .into_iter()
+                    .map(|endpoint| {
+                        let mean =
+                            mean_rpc_endpoint_request_duration(&endpoint.label, class.as_str());
+                        (mean.unwrap_or_default(), endpoint)
+                    })
+                    .collect();
+                ranked.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+                endpoints = ranked.into_iter().map(|(_, endpoint)| endpoint).collect();
+            }
+            RoutingStrategy::RoundRobin if !endpoints.is_empty() => {
+                let len = endpoints.len();
+                endpoints.rotate_left(index % len);
+            }
+            RoutingStrategy::RoundRobin => {}
+            RoutingStrategy::LeastRecentlyUsed => {
+                endpoints.sort_by_cached_key(|endpoint| *endpoint.last_used.lock().unwrap());
+            }
+        }
+        endpoints
+    }
+
+    /// Sends `request` to the first of `endpoints` that answers it, within
+    /// `timeout` if given, returning the response along with that endpoint.
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+        endpoints: Vec<Arc<Endpoint>>,
+        timeout: Option<Duration>,
+    ) -> ClientResult<(serde_json::Value, Arc<Endpoint>)> {
+        let mut endpoints = endpoints.into_iter().peekable();
+        let mut last_err = ClientError::from(ClientErrorKind::Custom(
+            "No RPC endpoint configured".to_string(),
+        ));
+        while let Some(endpoint) = endpoints.next() {
+            let response = async {
+                match request {
+                    RpcRequest::GetMultipleAccounts => {
+                        endpoint.send_multiple_accounts(params.clone()).await
+                    }
+                    _ => endpoint.send(request, params.clone()).await,
+                }
+            };
+            let response = match timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, response).await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        // Penalized like any failure, for latency routing to
+                        // prefer other endpoints.
+                        observe_metric_rpc_endpoint_request_duration(
+                            &endpoint.label,
+                            RequestClass::of(&request).as_str(),
+                            self.request_timeout,
+                        );
+                        Err(ClientError::from(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            format!("No response within {timeout:?}"),
+                        )))
+                    }
+                },
+                None => response.await,
+            };
+            match response {
+                Ok(response) => return Ok((response, endpoint)),
+                Err(err) if is_invalid_request(&err) => return Err(err),
+                Err(err) => {
+                    if let Some(next) = endpoints.peek() {
+                        warn!(
+                            "RPC endpoint {} failed {request}, failing over to {}: {err}",
+                            endpoint.label, next.label
+                        );
+                        update_metric_rpc_failover(&endpoint.label);
+                    }
+                    last_err = err;
+                }
+            }
+        }
+        Err(last_err)
+    }
+
+    fn set_active_endpoint(&self, label: &str) {
+        let mut active_endpoint = self.active_endpoint.lock().unwrap();
+        if active_endpoint.as_deref() == Some(label) {
+            return;
+        }
+        if let Some(previous) = active_endpoint.as_deref() {
+            if self
+                .endpoints()
+                .iter()
+                .any(|endpoint| endpoint.label == previous)
+            {
+                update_metric_rpc_active_endpoint(previous, false);
+            }
+        }
+        update_metric_rpc_active_endpoint(label, true);
+        *active_endpoint = Some(label.to_string());
+    }
+}
+
+/// Whether the request itself was rejected as invalid, for an unknown method
+/// or for invalid params, which other endpoints would reject as well.
+fn is_invalid_request(err: &ClientError) -> bool {
+    matches!(
+        err.kind(),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            code: -32602..=-32600,
+            ..
+        })
+    )
+}
+
+/// Transport of a single watcher, recording per-watcher request metrics.
+struct WatcherRpcSender {
+    watcher: String,
+    router: Arc<EndpointRouter>,
+    strategy: RoutingStrategy,
+    /// Time after which each request to an endpoint is given up on, when
+    /// sooner than the HTTP client does.
+    timeout: Option<Duration>,
+    /// Requests sent so far, for round-robin routing.
+    requests: AtomicUsize,
+    /// Label of the only endpoint requests are sent to, bypassing routing.
+    pinned_endpoint: Option<String>,
+    /// Label of the endpoint that answered the latest request, exported as
+    /// `watcher_rpc_endpoint_info` to tell which provider served the data.
+    latest_endpoint: Mutex<Option<String>>,
+}
+
+#[async_trait]
+impl RpcSender for WatcherRpcSender {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        let method = request.to_string();
+        record_rpc_request(&self.watcher, &method);
+        let start = Instant::now();
+        let endpoints = match &self.pinned_endpoint {
+            Some(label) => self
+                .router
+                .endpoints()
+                .into_iter()
+                .filter(|endpoint| endpoint.label == *label)
+                .collect(),
+            None => {
+                let index = self.requests.fetch_add(1, Ordering::Relaxed);
+                self.router
+                    .ranked(RequestClass::of(&request), self.strategy, index)
+            }
+        };
+        let mut response = self
+            .router
+            .send(request, params.clone(), endpoints.clone(), self.timeout)
+            .await;
+        // A dropped connection is retried right away rather than failing the
+        // check, which would back off and blank its metrics until it retries.
+        if let Err(err) = &response {
+            if error_kind(err) == "transport" {
+                warn!(
+                    "Retrying {method} of watcher '{}' after a transport error: {err}",
+                    self.watcher
+                );
+                update_metric_rpc_error(&self.watcher, &method, error_kind(err));
+                record_rpc_request(&self.watcher, &method);
+                response = self
+                    .router
+                    .send(request, params, endpoints, self.timeout)
+                    .await;
+            }
+        }
+        observe_metric_rpc_request_duration(&self.watcher, &method, start.elapsed());
+        if let Err(err) = &response {
+            update_metric_rpc_error(&self.watcher, &method, error_kind(err));
+        }
+        let (response, endpoint) = response?;
+        // Requests pinned to an endpoint go to every endpoint in turn, which
+        // says nothing about where the watcher's data comes from.
+        if self.pinned_endpoint.is_none() {
+            self.router.set_active_endpoint(&endpoint.label);
+            let mut latest_endpoint = self.latest_endpoint.lock().unwrap();
+            if latest_endpoint.as_deref() != Some(endpoint.label.as_str()) {
+                update_metric_watcher_rpc_endpoint(&self.watcher, &endpoint.label);
+                *latest_endpoint = Some(endpoint.label.clone());
+            }
+        }
+        // The transport only hands out the decoded JSON-RPC result, so its
+        // re-encoded size stands in for the number of bytes received.
+        let bytes = serde_json::to_vec(&response).map_or(0, |bytes| bytes.len());
+        update_metric_rpc_response_bytes(&self.watcher, &method, bytes as u64);
+        Ok(response)
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        let mut stats = RpcTransportStats::default();
+        for endpoint in self.router.endpoints() {
+            let endpoint_stats = endpoint.sender.get_transport_stats();
+            stats.request_count += endpoint_stats.request_count;
+            stats.elapsed_time += endpoint_stats.elapsed_time;
+            stats.rate_limited_time += endpoint_stats.rate_limited_time;
+        }
+        stats
+    }
+
+    fn url(&self) -> String {
+        self.router
+            .endpoints()
+            .first()
+            .map_or_else(String::new, |endpoint| endpoint.sender.url())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{extract::State, routing::post, Json, Router};
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    #[test]
+    fn halving_reports_each_size_once() {
+        let max = AtomicUsize::new(MAX_ACCOUNTS_PER_REQUEST);
+        assert_eq!(halve_chunk_size(&max, 100), Some(50));
+        // Concurrent chunks of the same size rejected afterwards.
+        assert_eq!(halve_chunk_size(&max, 100), None);
+        assert_eq!(halve_chunk_size(&max, 3), Some(1));
+        assert_eq!(max.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn merge_keeps_lowest_slot() {
+        let mut merged = None;
+        merge_min_slot(
+            &mut merged,
+            json!({ "context": { "slot": 7 }, "value": [] }),
+        );
+        merge_min_slot(
+            &mut merged,
+            json!({ "context": { "slot": 5, "apiVersion": "1.17" } }),
+        );
+        merge_min_slot(&mut merged, json!({ "context": { "slot": u64::MAX } }));
+        merge_min_slot(&mut merged, json!({ "context": { "slot": 5 } }));
+        assert_eq!(
+            merged.unwrap()["context"],
+            json!({ "slot": 5, "apiVersion": "1.17" })
+        );
+    }
+
+    #[test]
+    fn merge_starts_at_slot_zero() {
+        let mut merged = None;
+        merge_min_slot(&mut merged, json!({ "context": { "slot": 0 } }));
+        merge_min_slot(&mut merged, json!({ "context": { "slot": 1 } }));
+        assert_eq!(merged.unwrap()["context"]["slot"], 0);
+    }
+
+    /// Serves `getMultipleAccounts`, rejecting requests for more than `limit`
+    /// accounts as RPC nodes do. Each account is answered with its own key,
+    /// at a slot lower for chunks further in.
+    async fn serve_multiple_accounts(limit: usize) -> Endpoint {
+        async fn handle(State(limit): State<usize>, Json(request): Json<Value>) -> Json<Value> {
+            let pubkeys = request["params"][0].as_array().cloned().unwrap_or_default();
+            if pubkeys.len() > limit {
+                return Json(json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "error": { "code": -32602, "message": "Too many inputs provided" },
+                }));
+            }
+            let first: u64 = pubkeys
+                .first()
+                .and_then(Value::as_str)
+                .map_or(0, |pubkey| pubkey.parse().unwrap());
+            Json(json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": { "context": { "slot": 100 - first }, "value": pubkeys },
+            }))
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let router = Router::new().route("/", post(handle)).with_state(limit);
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(router.into_make_service());
+        tokio::spawn(server);
+        Endpoint {
+            label: "test".to_string(),
+            sender: HttpSender::new(url),
+            max_accounts_per_request: AtomicUsize::new(MAX_ACCOUNTS_PER_REQUEST),
+            last_used: Mutex::new(None),
+            request_timeout: REQUEST_TIMEOUT,
+        }
+    }
+
+    fn pubkeys(count: usize) -> Value {
+        json!([(0..count).map(|i| i.to_string()).collect::<Vec<_>>()])
+    }
+
+    #[tokio::test]
+    async fn halves_chunks_until_accepted() {
+        let endpoint = serve_multiple_accounts(3).await;
+        let response = endpoint.send_multiple_accounts(pubkeys(10)).await.unwrap();
+        assert_eq!(response["value"], pubkeys(10)[0]);
+        // Chunks of 2 accounts, the last one starting at the 9th.
+        assert_eq!(response["context"]["slot"], 92);
+        assert_eq!(endpoint.max_accounts_per_request.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn single_request_within_limit() {
+        let endpoint = serve_multiple_accounts(MAX_ACCOUNTS_PER_REQUEST).await;
+        let count = MAX_ACCOUNTS_PER_REQUEST;
+        let response = endpoint
+            .send_multiple_accounts(pubkeys(count))
+            .await
+            .unwrap();
+        assert_eq!(response["value"], pubkeys(count)[0]);
+        assert_eq!(response["context"]["slot"], 100);
+        assert_eq!(
+            endpoint.max_accounts_per_request.load(Ordering::Relaxed),
+            MAX_ACCOUNTS_PER_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_request_is_sent_as_is() {
+        let endpoint = serve_multiple_accounts(0).await;
+        let response = endpoint.send_multiple_accounts(pubkeys(0)).await.unwrap();
+        assert_eq!(response["value"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn gives_up_once_single_accounts_are_rejected() {
+        let endpoint = serve_multiple_accounts(0).await;
+        let err = endpoint
+            .send_multiple_accounts(pubkeys(5))
+            .await
+            .unwrap_err();
+        assert!(is_size_rejection(&err));
+        assert_eq!(endpoint.max_accounts_per_request.load(Ordering::Relaxed), 1);
+    }
+}
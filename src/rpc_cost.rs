@@ -0,0 +1,113 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::{Lazy, OnceCell};
+
+use crate::metrics::{
+    remove_metric_rpc_credits_monthly_projection, update_metric_rpc_credits_monthly_projection,
+    update_metric_rpc_request,
+};
+
+/// Credits charged for methods without a configured cost.
+const DEFAULT_CREDITS: f64 = 1.0;
+/// Usage observed before projecting it, so that startup bursts are not taken
+/// for the steady rate. Covers one cycle of the default check interval.
+const MIN_PROJECTION_WINDOW: Duration = Duration::from_secs(300);
+const MONTH: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Credits an RPC provider charges per call of a JSON-RPC method,
+/// `method=credits`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodCost {
+    pub method: String,
+    pub credits: f64,
+}
+
+impl FromStr for MethodCost {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((method, credits)) = s.split_once('=') else {
+            anyhow::bail!("Cannot parse method cost, expected syntax: method=credits");
+        };
+        let credits: f64 = credits.parse()?;
+        anyhow::ensure!(
+            credits >= 0.0,
+            "Cost of method '{method}' cannot be negative"
+        );
+        Ok(MethodCost {
+            method: method.to_string(),
+            credits,
+        })
+    }
+}
+
+static METHOD_COSTS: OnceCell<HashMap<String, f64>> = OnceCell::new();
+
+/// Credits used by a watcher since its first request.
+#[derive(Debug)]
+struct Usage {
+    first_request: Instant,
+    credits: f64,
+}
+
+static USAGE: Lazy<Mutex<BTreeMap<String, Usage>>> = Lazy::new(Default::default);
+
+/// Sets the credits charged per method. Can only be called once; until then,
+/// every call costs one credit.
+pub fn set_method_costs(costs: Vec<MethodCost>) -> anyhow::Result<()> {
+    let costs = costs
+        .into_iter()
+        .map(|cost| (cost.method, cost.credits))
+        .collect();
+    METHOD_COSTS
+        .set(costs)
+        .map_err(|_| anyhow::anyhow!("RPC method costs are already set"))
+}
+
+fn method_credits(method: &str) -> f64 {
+    METHOD_COSTS
+        .get()
+        .and_then(|costs| costs.get(method))
+        .copied()
+        .unwrap_or(DEFAULT_CREDITS)
+}
+
+/// Accounts for a request of `method` sent by `watcher`.
+pub fn record_rpc_request(watcher: &str, method: &str) {
+    let credits = method_credits(method);
+    update_metric_rpc_request(watcher, method, credits);
+    USAGE
+        .lock()
+        .unwrap()
+        .entry(watcher.to_string())
+        .or_insert_with(|| Usage {
+            first_request: Instant::now(),
+            credits: 0.0,
+        })
+        .credits += credits;
+}
+
+/// Projects the credits each watcher uses in a month from its usage since
+/// its first request.
+pub fn update_rpc_credits_projections() {
+    for (watcher, usage) in USAGE.lock().unwrap().iter() {
+        let elapsed = usage.first_request.elapsed();
+        if elapsed < MIN_PROJECTION_WINDOW {
+            continue;
+        }
+        let scale = MONTH.as_secs_f64() / elapsed.as_secs_f64();
+        update_metric_rpc_credits_monthly_projection(watcher, usage.credits * scale);
+    }
+}
+
+/// Drops the usage of a removed watcher, so that a watcher added back under
+/// the same name starts its projection over.
+pub(crate) fn forget_rpc_usage(watcher: &str) {
+    USAGE.lock().unwrap().remove(watcher);
+    remove_metric_rpc_credits_monthly_projection(watcher);
+}
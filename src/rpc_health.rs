@@ -0,0 +1,55 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use log::{info, warn};
+use tokio::task::JoinHandle;
+
+use crate::{
+    health::record_successful_check,
+    metrics::{update_metric_rpc_healthy, update_metric_watcher_info},
+    rate_limit::RateLimiter,
+    rpc::RpcClientFactory,
+    shutdown::sleep_unless_shutdown,
+};
+
+pub const WATCHER_NAME: &str = "rpc_health";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Polls `getHealth` on every RPC endpoint, including the ones added at
+/// runtime, exporting `rpc_healthy` and counting failures in
+/// `rpc_health_failures_total`. Logs when an endpoint turns unhealthy or
+/// recovers.
+pub fn spawn_rpc_health_watcher(
+    rpc_clients: RpcClientFactory,
+    rate_limiter: Arc<RateLimiter>,
+) -> JoinHandle<()> {
+    update_metric_watcher_info(WATCHER_NAME, WATCHER_NAME, "", POLL_INTERVAL);
+    tokio::spawn(async move {
+        let mut healthy = HashMap::new();
+        loop {
+            for (endpoint, rpc_client) in rpc_clients.endpoint_clients() {
+                rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+                let health = rpc_client.get_health().await;
+                let was_healthy = healthy.insert(endpoint.clone(), health.is_ok());
+                match health {
+                    Ok(()) if was_healthy == Some(false) => {
+                        info!("RPC endpoint {endpoint} is healthy again")
+                    }
+                    Ok(()) => {}
+                    Err(err) if was_healthy != Some(false) => {
+                        warn!("RPC endpoint {endpoint} is unhealthy: {err}")
+                    }
+                    Err(_) => {}
+                }
+                update_metric_rpc_healthy(&endpoint, healthy[&endpoint]);
+            }
+            // Unhealthy endpoints are what it reports, not a failed check.
+            record_successful_check(WATCHER_NAME);
+
+            if !sleep_unless_shutdown(POLL_INTERVAL).await {
+                break;
+            }
+        }
+        info!("RPC health watcher stopped");
+    })
+}
@@ -0,0 +1,91 @@
+use std::sync::{Arc, RwLock};
+
+use once_cell::sync::Lazy;
+use solana_account_decoder::UiDataSliceConfig;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+
+use crate::{
+    data_slice::{covering_slice, Field},
+    metrics::update_metric_program_accounts_aggregates,
+};
+
+static SCAN_HOOKS: Lazy<RwLock<Vec<Arc<dyn ScanHook>>>> = Lazy::new(Default::default);
+
+/// An account matched by a program-accounts scan, with only the part of its
+/// data covering the fields that hooks read.
+#[derive(Debug, Clone, Copy)]
+pub struct ScannedAccount<'a> {
+    pub pubkey: &'a Pubkey,
+    pub lamports: u64,
+    data: &'a [u8],
+    /// Offset in the full account data that `data` starts at.
+    data_offset: usize,
+}
+
+impl<'a> ScannedAccount<'a> {
+    /// Reads `field`, which must be among the fields of a registered hook.
+    pub fn read(&self, field: Field) -> Option<&'a [u8]> {
+        let start = field.offset.checked_sub(self.data_offset)?;
+        self.data.get(start..start + field.length)
+    }
+
+    pub fn read_u64(&self, field: Field) -> Option<u64> {
+        Some(u64::from_le_bytes(self.read(field)?.try_into().ok()?))
+    }
+}
+
+/// Computes custom aggregates over the accounts matched by every
+/// program-accounts scan, before their balances are summed. Aggregates are
+/// exported as `program_accounts_aggregate{name, hook, aggregate}`, for
+/// embedders reusing the scheduling, filters and metrics of scans.
+pub trait ScanHook: Send + Sync + 'static {
+    /// Identifies the hook in the `hook` label.
+    fn name(&self) -> &str;
+
+    /// Fields of the account data the hook reads, fetched along with the
+    /// balances. Accounts come without data unless a hook reads any.
+    fn fields(&self) -> &[Field] {
+        &[]
+    }
+
+    /// Aggregates the accounts matched by the scan called `scan`, returning
+    /// each aggregate by name.
+    fn aggregate(&self, scan: &str, accounts: &[ScannedAccount]) -> Vec<(String, f64)>;
+}
+
+/// Runs `hook` on every program-accounts scan from now on.
+pub fn register_scan_hook(hook: impl ScanHook) {
+    SCAN_HOOKS.write().unwrap().push(Arc::new(hook));
+}
+
+/// Part of the account data scans fetch for the registered hooks.
+pub(crate) fn scan_hooks_data_slice() -> UiDataSliceConfig {
+    let hooks = SCAN_HOOKS.read().unwrap();
+    covering_slice(hooks.iter().flat_map(|hook| hook.fields()))
+}
+
+/// Runs the registered hooks on the accounts matched by the scan `scan`,
+/// fetched with `data_slice`.
+pub(crate) fn run_scan_hooks(
+    scan: &str,
+    data_slice: &UiDataSliceConfig,
+    accounts: &[(Pubkey, Account)],
+) {
+    let hooks = SCAN_HOOKS.read().unwrap().clone();
+    if hooks.is_empty() {
+        return;
+    }
+    let accounts: Vec<_> = accounts
+        .iter()
+        .map(|(pubkey, account)| ScannedAccount {
+            pubkey,
+            lamports: account.lamports,
+            data: &account.data,
+            data_offset: data_slice.offset,
+        })
+        .collect();
+    for hook in hooks {
+        let aggregates = hook.aggregate(scan, &accounts);
+        update_metric_program_accounts_aggregates(scan, hook.name(), &aggregates);
+    }
+}
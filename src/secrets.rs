@@ -0,0 +1,115 @@
+use std::{path::Path, process::Command};
+
+#[cfg(feature = "age")]
+use once_cell::sync::OnceCell;
+
+const FILE_PREFIX: &str = "file:";
+const AGE_ARMOR_HEADER: &[u8] = b"-----BEGIN AGE ENCRYPTED FILE-----";
+const AGE_HEADER: &[u8] = b"age-encryption.org/";
+
+#[cfg(feature = "age")]
+static AGE_IDENTITIES: OnceCell<Vec<age::x25519::Identity>> = OnceCell::new();
+
+/// Reads the age identities that encrypted secrets are decrypted with, in the
+/// format written by `age-keygen`. Can only be called once.
+#[cfg(feature = "age")]
+pub fn set_age_identities(path: &Path) -> anyhow::Result<()> {
+    let identities = age::IdentityFile::from_file(path.display().to_string())
+        .map_err(|err| anyhow::anyhow!("Cannot read age identities '{}': {err}", path.display()))?
+        .into_identities();
+    anyhow::ensure!(
+        !identities.is_empty(),
+        "No age identity in '{}'",
+        path.display()
+    );
+    AGE_IDENTITIES
+        .set(identities)
+        .map_err(|_| anyhow::anyhow!("age identities are already set"))
+}
+
+/// Resolves a secret given on the command line or in the config file.
+/// `file:PATH` stands for the content of the file, such as a Docker or
+/// Kubernetes secret mount, and age-encrypted values, armored or not, are
+/// decrypted. Anything else is taken literally. Trailing newlines are
+/// removed.
+pub fn resolve_secret(value: &str) -> anyhow::Result<String> {
+    let content = match value.strip_prefix(FILE_PREFIX) {
+        Some(path) => std::fs::read(path)
+            .map_err(|err| anyhow::anyhow!("Cannot read secret file '{path}': {err}"))?,
+        None => value.as_bytes().to_vec(),
+    };
+    let content = match is_age_encrypted(&content) {
+        true => decrypt_age(trim_ascii_start(&content))?,
+        false => content,
+    };
+    let secret =
+        String::from_utf8(content).map_err(|_| anyhow::anyhow!("Secret is not valid UTF-8"))?;
+    Ok(secret.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn trim_ascii_start(content: &[u8]) -> &[u8] {
+    let start = content
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(content.len());
+    &content[start..]
+}
+
+fn is_age_encrypted(content: &[u8]) -> bool {
+    let content = trim_ascii_start(content);
+    content.starts_with(AGE_ARMOR_HEADER) || content.starts_with(AGE_HEADER)
+}
+
+#[cfg(feature = "age")]
+fn decrypt_age(ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+    use std::io::Read;
+
+    let identities = AGE_IDENTITIES.get().ok_or_else(|| {
+        anyhow::anyhow!("Secret is age-encrypted, but no --age-identity-file is given")
+    })?;
+    let decryptor = match age::Decryptor::new(age::armor::ArmoredReader::new(ciphertext))? {
+        age::Decryptor::Recipients(decryptor) => decryptor,
+        age::Decryptor::Passphrase(_) => {
+            anyhow::bail!("Passphrase-encrypted secrets are not supported")
+        }
+    };
+    let mut plaintext = vec![];
+    decryptor
+        .decrypt(
+            identities
+                .iter()
+                .map(|identity| identity as &dyn age::Identity),
+        )
+        .map_err(|err| anyhow::anyhow!("Cannot decrypt age-encrypted secret: {err}"))?
+        .read_to_end(&mut plaintext)?;
+    Ok(plaintext)
+}
+
+#[cfg(not(feature = "age"))]
+fn decrypt_age(_ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!("Secret is age-encrypted, but the watcher was built without the age feature")
+}
+
+/// Whether a YAML document carries SOPS metadata, as files encrypted with
+/// `sops --encrypt` do.
+pub fn is_sops_encrypted(yaml: &yaml_rust::Yaml) -> bool {
+    !yaml["sops"]["mac"].is_badvalue()
+}
+
+/// Decrypts a SOPS-encrypted YAML file with the `sops` binary, which finds
+/// the keys itself, for example through `SOPS_AGE_KEY_FILE` or cloud KMS
+/// credentials.
+pub fn decrypt_sops(path: &Path) -> anyhow::Result<String> {
+    let output = Command::new("sops")
+        .args(["--decrypt", "--input-type", "yaml", "--output-type", "yaml"])
+        .arg(path)
+        .output()
+        .map_err(|err| anyhow::anyhow!("Cannot run sops to decrypt '{}': {err}", path.display()))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "sops failed to decrypt '{}': {}",
+        path.display(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8(output.stdout)?)
+}
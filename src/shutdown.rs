@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+
+static SHUTDOWN: Lazy<CancellationToken> = Lazy::new(CancellationToken::new);
+
+/// Asks all watchers to stop scheduling new checks. Checks already in flight
+/// run to completion.
+pub fn request_shutdown() {
+    SHUTDOWN.cancel();
+}
+
+pub fn is_shutdown_requested() -> bool {
+    SHUTDOWN.is_cancelled()
+}
+
+pub async fn shutdown_requested() {
+    SHUTDOWN.cancelled().await
+}
+
+/// Sleeps for `duration`, returning early with `false` if a shutdown is
+/// requested in the meantime.
+pub async fn sleep_unless_shutdown(duration: Duration) -> bool {
+    tokio::select! {
+        _ = sleep(duration) => true,
+        _ = shutdown_requested() => false,
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use log::{error, warn};
+use once_cell::sync::Lazy;
+use tokio::{
+    sync::broadcast::{
+        self,
+        error::{RecvError, TryRecvError},
+    },
+    task::JoinHandle,
+    time::{interval, sleep, MissedTickBehavior},
+};
+
+use crate::{
+    metrics::{update_metric_notifications_failed, update_metric_notifications_sent},
+    observations::{subscribe_observations, Observation},
+    shutdown::shutdown_requested,
+};
+
+/// Attempts to publish a batch before it is dropped.
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Sinks fed by `spawn_sink`, kept for dry runs.
+static SINKS: Lazy<Mutex<Vec<Arc<dyn MetricSink>>>> = Lazy::new(Default::default);
+
+/// Destination that observations are pushed to, such as a webhook or a
+/// monitoring system that cannot scrape the Prometheus endpoint.
+#[async_trait]
+pub trait MetricSink: Send + Sync + 'static {
+    /// Identifies the sink in logs and in the `notifier` label of delivery
+    /// metrics.
+    fn name(&self) -> &str;
+
+    /// Publishes a batch of observations. A failed batch is retried as a
+    /// whole, so implementations must not keep partial state on error.
+    async fn publish(&self, observations: &[Observation]) -> anyhow::Result<()>;
+
+    /// Whether publishing `observation` now would notify anyone, for sinks
+    /// that skip some observations.
+    fn would_publish(&self, _observation: &Observation) -> bool {
+        true
+    }
+}
+
+/// Names of the sinks that would notify anyone of `observation`, without
+/// publishing it.
+pub fn sinks_publishing(observation: &Observation) -> Vec<String> {
+    SINKS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|sink| sink.would_publish(observation))
+        .map(|sink| sink.name().to_string())
+        .collect()
+}
+
+/// How observations are grouped before being handed to a sink.
+#[derive(Debug, Clone)]
+pub struct BatchConfig {
+    pub max_batch_size: usize,
+    pub flush_interval: Duration,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 100,
+            flush_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+async fn flush(sink: &dyn MetricSink, batch: &mut Vec<Observation>) {
+    if batch.is_empty() {
+        return;
+    }
+    let mut delay = INITIAL_RETRY_DELAY;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let result = sink.publish(batch).await;
+        match &result {
+            Ok(()) => update_metric_notifications_sent(sink.name()),
+            Err(_) => update_metric_notifications_failed(sink.name()),
+        }
+        match result {
+            Ok(()) => break,
+            Err(err) if attempt == MAX_ATTEMPTS => {
+                error!(
+                    "Dropping {} observations after {attempt} failed attempts to publish to {}: {err}",
+                    batch.len(),
+                    sink.name()
+                );
+                #[cfg(feature = "sentry")]
+                crate::error_reporting::report_sink_error(sink.name(), &err.to_string());
+            }
+            Err(err) => {
+                warn!(
+                    "Failed to publish to {}, retrying in {delay:?}: {err}",
+                    sink.name()
+                );
+                sleep(delay).await;
+                delay *= 2;
+            }
+        }
+    }
+    batch.clear();
+}
+
+/// Feeds `observations` to `sink` in batches of up to `max_batch_size`,
+/// flushed at least every `flush_interval`, until the channel closes or a
+/// shutdown is requested. Failed batches are retried with exponential
+/// backoff. Observations still queued then are flushed before returning.
+pub(crate) async fn run_sink(
+    sink: &dyn MetricSink,
+    config: &BatchConfig,
+    mut observations: broadcast::Receiver<Observation>,
+) {
+    let mut batch = Vec::with_capacity(config.max_batch_size);
+    let mut ticker = interval(config.flush_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            observation = observations.recv() => match observation {
+                Ok(observation) => {
+                    batch.push(observation);
+                    if batch.len() >= config.max_batch_size {
+                        flush(sink, &mut batch).await;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Sink {} fell behind, skipped {skipped} observations", sink.name())
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = ticker.tick() => flush(sink, &mut batch).await,
+            _ = shutdown_requested() => break,
+        }
+    }
+    loop {
+        match observations.try_recv() {
+            Ok(observation) => batch.push(observation),
+            Err(TryRecvError::Lagged(_)) => continue,
+            Err(_) => break,
+        }
+    }
+    for mut chunk in batch
+        .chunks(config.max_batch_size)
+        .map(<[Observation]>::to_vec)
+    {
+        flush(sink, &mut chunk).await;
+    }
+}
+
+/// Feeds every observation to `sink` with [`run_sink`] in the background.
+pub fn spawn_sink(sink: impl MetricSink, config: BatchConfig) -> JoinHandle<()> {
+    let sink = Arc::new(sink);
+    SINKS.lock().unwrap().push(sink.clone());
+    let observations = subscribe_observations();
+    tokio::spawn(async move { run_sink(&*sink, &config, observations).await })
+}
@@ -0,0 +1,117 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use log::{info, warn};
+use solana_sdk::native_token::lamports_to_sol;
+use tokio::{sync::broadcast::error::RecvError, task::JoinHandle};
+
+use crate::{
+    alert_rules::BalanceRef,
+    metrics::update_metric_balance_smoothed_sol,
+    observations::{subscribe_observations, Observation},
+    shutdown::shutdown_requested,
+};
+
+/// A balance to smooth, parsed from `[watcher/]name=HALF_LIFE_SECS`.
+#[derive(Debug, Clone)]
+pub struct SmoothedBalance {
+    balance: BalanceRef,
+    /// Time after which an observation weighs half as much as a new one.
+    half_life: Duration,
+}
+
+impl FromStr for SmoothedBalance {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((balance, half_life)) = s.split_once('=') else {
+            anyhow::bail!(
+                "Cannot parse smoothed balance, expected syntax: [watcher/]name=HALF_LIFE_SECS"
+            );
+        };
+        let half_life: f64 = half_life
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Failed to parse half-life from '{half_life}'"))?;
+        anyhow::ensure!(
+            half_life.is_finite() && half_life > 0.0,
+            "Half-life of '{balance}' must be positive, got {half_life}"
+        );
+        Ok(SmoothedBalance {
+            balance: balance.parse()?,
+            half_life: Duration::from_secs_f64(half_life),
+        })
+    }
+}
+
+/// Exponentially weighted moving average over time, so that irregular
+/// observations weigh by how long ago they were made rather than by count.
+#[derive(Debug, Clone, Copy)]
+struct Ewma {
+    value: f64,
+    observed_at: SystemTime,
+}
+
+impl Ewma {
+    fn update(&mut self, value: f64, observed_at: SystemTime, half_life: Duration) {
+        let elapsed = observed_at
+            .duration_since(self.observed_at)
+            .unwrap_or_default();
+        let weight = 1.0 - 0.5f64.powf(elapsed.as_secs_f64() / half_life.as_secs_f64());
+        self.value += weight * (value - self.value);
+        self.observed_at = self.observed_at.max(observed_at);
+    }
+}
+
+fn smooth(
+    smoothed_balances: &[SmoothedBalance],
+    averages: &mut HashMap<(String, String), Ewma>,
+    observation: &Observation,
+) {
+    let Some(smoothed) = smoothed_balances.iter().find(|smoothed| {
+        smoothed
+            .balance
+            .matches(&observation.watcher, &observation.name)
+    }) else {
+        return;
+    };
+    let balance = lamports_to_sol(observation.lamports);
+    let average = averages
+        .entry((observation.watcher.clone(), observation.name.clone()))
+        .and_modify(|average| average.update(balance, observation.observed_at, smoothed.half_life))
+        .or_insert(Ewma {
+            value: balance,
+            observed_at: observation.observed_at,
+        });
+    update_metric_balance_smoothed_sol(&observation.watcher, &observation.name, average.value);
+}
+
+/// Exports an exponentially weighted moving average of each balance in
+/// `smoothed_balances` as `balance_smoothed_sol`, for thresholds that should
+/// not flap on the constant small churn of accounts such as fee payers.
+pub fn spawn_balance_smoother(smoothed_balances: Vec<SmoothedBalance>) -> JoinHandle<()> {
+    for smoothed in &smoothed_balances {
+        info!(
+            "Smoothing balance {} with a half-life of {:?}",
+            smoothed.balance, smoothed.half_life
+        );
+    }
+    let mut observations = subscribe_observations();
+    tokio::spawn(async move {
+        let mut averages = HashMap::new();
+        loop {
+            tokio::select! {
+                observation = observations.recv() => match observation {
+                    Ok(observation) => smooth(&smoothed_balances, &mut averages, &observation),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Balance smoother fell behind, skipped {skipped} observations")
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = shutdown_requested() => break,
+            }
+        }
+    })
+}
@@ -0,0 +1,263 @@
+use std::{collections::HashMap, sync::Arc, time::SystemTime};
+
+use axum::{extract::State, http::StatusCode, routing::get, Extension, Json, Router};
+use chrono::{DateTime, SecondsFormat, Utc};
+use once_cell::sync::OnceCell;
+use serde_json::{json, Value};
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcAccountInfoConfig};
+use solana_sdk::{
+    native_token::lamports_to_sol,
+    pubkey::Pubkey,
+    signature::{read_keypair, Keypair, Signer},
+};
+
+use crate::{
+    auth::{require_role, ApiKeys, Caller, Role},
+    balance,
+    check::MAX_ACCOUNTS_PER_REQUEST,
+    data_slice::AccountType,
+    program_accounts_balance::{get_program_accounts, ProgramAccountsBalanceConfig},
+    rate_limit::RateLimiter,
+    rpc::{with_serving_endpoints, RpcClientFactory},
+    tenant::is_visible_to,
+};
+
+const WATCHER_NAME: &str = "snapshot";
+
+static SIGNING_KEY: OnceCell<Keypair> = OnceCell::new();
+
+/// Sets the ed25519 key snapshots are signed with, given as the JSON array of
+/// bytes written by `solana-keygen`, and returns its public key. Can only be
+/// called once.
+pub fn set_snapshot_signing_key(keypair_json: &str) -> anyhow::Result<Pubkey> {
+    let keypair = read_keypair(&mut keypair_json.as_bytes())
+        .map_err(|err| anyhow::anyhow!("Cannot parse snapshot signing key: {err}"))?;
+    let pubkey = keypair.pubkey();
+    SIGNING_KEY
+        .set(keypair)
+        .map_err(|_| anyhow::anyhow!("Snapshot signing key is already set"))?;
+    Ok(pubkey)
+}
+
+/// Balance of a named address in a [`Snapshot`].
+#[derive(Debug, Clone)]
+pub struct SnapshotBalance {
+    pub name: String,
+    pub pubkey: Pubkey,
+    pub exists: bool,
+    pub lamports: u64,
+    pub slot: u64,
+}
+
+/// Program-accounts total in a [`Snapshot`].
+#[derive(Debug, Clone)]
+pub struct SnapshotTotal {
+    pub name: String,
+    pub accounts: usize,
+    pub lamports: u64,
+    pub min_context_slot: Option<u64>,
+}
+
+/// Configured balances read from nodes at or past a single slot.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub slot: Option<u64>,
+    pub consistent: bool,
+    pub taken_at: SystemTime,
+    /// Labels of the RPC endpoints that served the reads.
+    pub endpoints: Vec<String>,
+    pub balances: Vec<SnapshotBalance>,
+    pub totals: Vec<SnapshotTotal>,
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> Value {
+        let balances: Vec<_> = self
+            .balances
+            .iter()
+            .map(|balance| {
+                json!({
+                    "name": balance.name,
+                    "pubkey": balance.pubkey.to_string(),
+                    "exists": balance.exists,
+                    "lamports": balance.lamports,
+                    "sol": lamports_to_sol(balance.lamports),
+                    "slot": balance.slot,
+                })
+            })
+            .collect();
+        let totals: Vec<_> = self
+            .totals
+            .iter()
+            .map(|total| {
+                json!({
+                    "name": total.name,
+                    "accounts": total.accounts,
+                    "lamports": total.lamports,
+                    "sol": lamports_to_sol(total.lamports),
+                    "min_context_slot": total.min_context_slot,
+                })
+            })
+            .collect();
+        json!({
+            "slot": self.slot,
+            "consistent": self.consistent,
+            "taken_at": DateTime::<Utc>::from(self.taken_at)
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+            "endpoints": self.endpoints,
+            "balances": balances,
+            "totals": totals,
+        })
+    }
+
+    /// The snapshot as JSON or, when a signing key is set, as an attestation
+    /// auditors can verify without trusting the watcher's host: `message` is
+    /// the snapshot serialized as JSON, and `signature` is the base58 ed25519
+    /// signature of its UTF-8 bytes by `signer`.
+    pub fn to_attestation(&self) -> Value {
+        let snapshot = self.to_json();
+        let Some(keypair) = SIGNING_KEY.get() else {
+            return snapshot;
+        };
+        let message = snapshot.to_string();
+        json!({
+            "message": message,
+            "signer": keypair.pubkey().to_string(),
+            "signature": keypair.sign_message(message.as_bytes()).to_string(),
+        })
+    }
+}
+
+/// Everything needed to check the configured balances on demand.
+pub struct Snapshotter {
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    named_pubkeys: Vec<(String, Pubkey)>,
+    program_accounts_configs: Vec<ProgramAccountsBalanceConfig>,
+}
+
+impl Snapshotter {
+    pub fn new(
+        rpc_clients: &RpcClientFactory,
+        rate_limiter: Arc<RateLimiter>,
+        named_pubkeys: &HashMap<Pubkey, String>,
+        program_accounts_configs: Vec<ProgramAccountsBalanceConfig>,
+    ) -> Self {
+        let mut named_pubkeys: Vec<_> = named_pubkeys
+            .iter()
+            .map(|(pubkey, name)| (name.clone(), *pubkey))
+            .collect();
+        named_pubkeys.sort();
+        Self {
+            rpc_client: rpc_clients.for_watcher(WATCHER_NAME),
+            rate_limiter,
+            named_pubkeys,
+            program_accounts_configs,
+        }
+    }
+
+    /// Reads every named address and program-accounts total once. All reads
+    /// after the first are served by nodes at or past the slot of the first
+    /// one, and `consistent` reports whether every response carried the same
+    /// slot. Scans do not report their slot, so totals only carry the slot
+    /// they were guaranteed to be at or past.
+    pub async fn snapshot(&self) -> anyhow::Result<Snapshot> {
+        let taken_at = SystemTime::now();
+        let (snapshot, endpoints) = with_serving_endpoints(self.read_balances(taken_at)).await;
+        let mut snapshot = snapshot?;
+        snapshot.endpoints = endpoints.into_iter().collect();
+        Ok(snapshot)
+    }
+
+    async fn read_balances(&self, taken_at: SystemTime) -> anyhow::Result<Snapshot> {
+        let mut min_context_slot = None;
+        let mut slots = vec![];
+        let mut balances = vec![];
+        for chunk in self.named_pubkeys.chunks(MAX_ACCOUNTS_PER_REQUEST) {
+            let pubkeys: Vec<_> = chunk.iter().map(|(_, pubkey)| *pubkey).collect();
+            self.rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+            let response = self
+                .rpc_client
+                .get_multiple_accounts_with_config(
+                    &pubkeys,
+                    RpcAccountInfoConfig {
+                        data_slice: Some(AccountType::Lamports.data_slice()),
+                        min_context_slot,
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            let slot = response.context.slot;
+            min_context_slot.get_or_insert(slot);
+            slots.push(slot);
+            for ((name, pubkey), account) in chunk.iter().zip(response.value) {
+                let lamports = account.map(|account| account.lamports);
+                balances.push(SnapshotBalance {
+                    name: name.clone(),
+                    pubkey: *pubkey,
+                    exists: lamports.is_some(),
+                    lamports: lamports.unwrap_or(0),
+                    slot,
+                });
+            }
+        }
+
+        let mut totals = vec![];
+        for config in &self.program_accounts_configs {
+            let (weight, cost) = config.rate_limit();
+            self.rate_limiter.acquire(config.name(), weight, cost).await;
+            let accounts = get_program_accounts(&self.rpc_client, config, min_context_slot).await?;
+            let lamports = accounts.iter().map(|(_, account)| account.lamports).sum();
+            totals.push(SnapshotTotal {
+                name: config.name().to_string(),
+                accounts: accounts.len(),
+                lamports,
+                min_context_slot,
+            });
+        }
+
+        Ok(Snapshot {
+            slot: min_context_slot,
+            consistent: slots.windows(2).all(|slots| slots[0] == slots[1]),
+            taken_at,
+            endpoints: vec![],
+            balances,
+            totals,
+        })
+    }
+}
+
+async fn snapshot(
+    State(snapshotter): State<Arc<Snapshotter>>,
+    caller: Option<Extension<Caller>>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let tenant = caller.and_then(|Extension(caller)| caller.tenant);
+    let tenant = tenant.as_deref();
+    match snapshotter.snapshot().await {
+        Ok(mut snapshot) => {
+            snapshot
+                .balances
+                .retain(|address| is_visible_to(tenant, balance::WATCHER_NAME, &address.name));
+            snapshot
+                .totals
+                .retain(|total| is_visible_to(tenant, &total.name, &total.name));
+            Ok(Json(snapshot.to_attestation()))
+        }
+        Err(err) => Err((StatusCode::BAD_GATEWAY, err.to_string())),
+    }
+}
+
+/// On-demand, point-in-time check of the named addresses and program-accounts
+/// totals on `/snapshot`, subject to the RPC rate limit. Address files are
+/// not included, and snapshots are signed when a signing key is set. Requires
+/// a read-only or admin API key when any API keys are configured, keys
+/// restricted to a tenant only get that tenant's balances and totals.
+pub fn snapshot_router(keys: &ApiKeys, snapshotter: Arc<Snapshotter>) -> Router {
+    let router = Router::new()
+        .route("/snapshot", get(snapshot))
+        .with_state(snapshotter);
+    match keys.is_empty() {
+        true => router,
+        false => require_role(router, keys, Role::ReadOnly),
+    }
+}
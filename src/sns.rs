@@ -0,0 +1,97 @@
+use std::{collections::HashMap, str::FromStr};
+
+use once_cell::sync::Lazy;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{hash::hashv, pubkey::Pubkey};
+
+use crate::check::MAX_ACCOUNTS_PER_REQUEST;
+
+pub const WATCHER_NAME: &str = "sns";
+const HASH_PREFIX: &str = "SPL Name Service";
+/// Size of the name record header preceding the record data.
+const NAME_RECORD_HEADER_LEN: usize = 96;
+
+static NAME_PROGRAM_ID: Lazy<Pubkey> =
+    Lazy::new(|| Pubkey::from_str("namesLPneVptA9Z5rqUDD9tMTWEJwofgaYwp8cawRkX").unwrap());
+static NAME_OFFERS_PROGRAM_ID: Lazy<Pubkey> =
+    Lazy::new(|| Pubkey::from_str("85iDfUvr3HJyLM2zcq5BXSiDvUWfw6cSE1FfNBo8Ap29").unwrap());
+static REVERSE_LOOKUP_CLASS: Lazy<Pubkey> =
+    Lazy::new(|| Pubkey::from_str("33m47vH6Eav6jr5Ry86XjhRft2jRBLDnDgPSHoquXi2Z").unwrap());
+
+/// Account holding the domain an owner marked as their primary one.
+fn favourite_domain_key(owner: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"favourite_domain", owner.as_ref()],
+        &NAME_OFFERS_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Reverse lookup record storing the name of the domain `name_account`.
+fn reverse_lookup_key(name_account: &Pubkey) -> Pubkey {
+    let hashed_name = hashv(&[HASH_PREFIX.as_bytes(), name_account.to_string().as_bytes()]);
+    Pubkey::find_program_address(
+        &[
+            hashed_name.as_ref(),
+            REVERSE_LOOKUP_CLASS.as_ref(),
+            Pubkey::default().as_ref(),
+        ],
+        &NAME_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Reads the name account referenced by a favourite domain record, laid out
+/// as a one byte tag followed by the name account.
+fn parse_favourite_domain(data: &[u8]) -> Option<Pubkey> {
+    data.get(1..33)?.try_into().ok().map(Pubkey::new_from_array)
+}
+
+/// Reads the domain from a reverse lookup record, stored after the header as
+/// a little-endian `u32` length followed by the UTF-8 name.
+fn parse_reverse_lookup(data: &[u8]) -> Option<String> {
+    let data = data.get(NAME_RECORD_HEADER_LEN..)?;
+    let len = u32::from_le_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let name = std::str::from_utf8(data.get(4..4 + len)?).ok()?;
+    Some(format!("{name}.sol"))
+}
+
+async fn get_account_data(
+    rpc_client: &RpcClient,
+    pubkeys: &[Pubkey],
+) -> anyhow::Result<Vec<Option<Vec<u8>>>> {
+    let mut data = Vec::with_capacity(pubkeys.len());
+    for chunk in pubkeys.chunks(MAX_ACCOUNTS_PER_REQUEST) {
+        let accounts = rpc_client.get_multiple_accounts(chunk).await?;
+        data.extend(
+            accounts
+                .into_iter()
+                .map(|account| account.map(|account| account.data)),
+        );
+    }
+    Ok(data)
+}
+
+/// Resolves the primary `.sol` domain of each of `owners` through the Solana
+/// Name Service. Owners without a primary domain are left out.
+pub async fn resolve_sns_names(
+    rpc_client: &RpcClient,
+    owners: &[Pubkey],
+) -> anyhow::Result<HashMap<Pubkey, String>> {
+    let favourites: Vec<_> = owners.iter().map(favourite_domain_key).collect();
+    let name_accounts: Vec<_> = owners
+        .iter()
+        .zip(get_account_data(rpc_client, &favourites).await?)
+        .filter_map(|(owner, data)| Some((*owner, parse_favourite_domain(&data?)?)))
+        .collect();
+
+    let reverse_lookups: Vec<_> = name_accounts
+        .iter()
+        .map(|(_, name_account)| reverse_lookup_key(name_account))
+        .collect();
+    Ok(name_accounts
+        .iter()
+        .zip(get_account_data(rpc_client, &reverse_lookups).await?)
+        .filter_map(|((owner, _), data)| Some((*owner, parse_reverse_lookup(&data?)?)))
+        .collect())
+}
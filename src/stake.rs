@@ -0,0 +1,538 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use log::{error, info, warn};
+use serde_json::{json, Value};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::{
+    account::{from_account, Account},
+    clock::{Clock, Epoch},
+    epoch_schedule::EpochSchedule,
+    feature,
+    feature_set::reduce_stake_warmup_cooldown,
+    pubkey::Pubkey,
+    stake::{self, state::StakeStateV2},
+    stake_history::StakeHistory,
+    sysvar,
+};
+use tokio::task::JoinHandle;
+
+use crate::{
+    health::{record_failed_check, record_successful_check},
+    intervals::{backoff_duration, check_interval, DEFAULT_CHECK_INTERVAL},
+    metrics::{
+        remove_metric_withdraw_authority_stake, reset_metric_stake, update_metric_stake,
+        update_metric_watcher_info, update_metric_withdraw_authority_stake,
+        update_metric_withdraw_authority_stake_account_changes,
+    },
+    rate_limit::RateLimiter,
+    shutdown::sleep_unless_shutdown,
+};
+
+pub const WATCHER_NAME: &str = "stake";
+pub const AUTHORITY_WATCHER_NAME: &str = "stake_authority";
+
+/// Offset of `Meta::authorized.withdrawer` in stake account data, after the
+/// state tag, the rent-exempt reserve and the staker.
+const WITHDRAWER_OFFSET: usize = 4 + 8 + 32;
+
+/// Lamports of a stake account by activation state, adding up to its balance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StakeActivation {
+    pub voter: Option<Pubkey>,
+    pub activation_epoch: Option<Epoch>,
+    pub deactivation_epoch: Option<Epoch>,
+    /// Effective stake that is not deactivating.
+    pub active: u64,
+    pub activating: u64,
+    pub deactivating: u64,
+    /// Undelegated lamports, including the rent-exempt reserve.
+    pub inactive: u64,
+}
+
+/// Cluster state that stake activates and deactivates by.
+struct StakeEnvironment {
+    epoch: Epoch,
+    history: StakeHistory,
+    /// Epoch since which stake warms up and cools down at the reduced rate.
+    new_rate_activation_epoch: Option<Epoch>,
+}
+
+/// Decodes the delegation of a stake account and how much of it is active in
+/// the current epoch, following warmup and cooldown through the stake history.
+fn stake_activation(
+    account: &Account,
+    environment: &StakeEnvironment,
+) -> anyhow::Result<StakeActivation> {
+    anyhow::ensure!(
+        account.owner == stake::program::id(),
+        "Account is owned by {}, not the stake program",
+        account.owner
+    );
+    let state: StakeStateV2 = account.deserialize_data()?;
+    let delegation = match state {
+        StakeStateV2::Stake(_, stake, _) => stake.delegation,
+        StakeStateV2::Initialized(_) => {
+            return Ok(StakeActivation {
+                inactive: account.lamports,
+                ..Default::default()
+            })
+        }
+        StakeStateV2::Uninitialized | StakeStateV2::RewardsPool => {
+            anyhow::bail!("Stake account is not initialized")
+        }
+    };
+    let status = delegation.stake_activating_and_deactivating(
+        environment.epoch,
+        Some(&environment.history),
+        environment.new_rate_activation_epoch,
+    );
+    let active = status.effective - status.deactivating;
+    Ok(StakeActivation {
+        voter: Some(delegation.voter_pubkey),
+        activation_epoch: Some(delegation.activation_epoch),
+        deactivation_epoch: Some(delegation.deactivation_epoch)
+            .filter(|epoch| *epoch != Epoch::MAX),
+        active,
+        activating: status.activating,
+        deactivating: status.deactivating,
+        inactive: account
+            .lamports
+            .saturating_sub(status.effective + status.activating),
+    })
+}
+
+/// Accounts the stake environment is decoded from, by `stake_environment`.
+fn environment_pubkeys() -> [Pubkey; 4] {
+    [
+        sysvar::clock::id(),
+        sysvar::stake_history::id(),
+        sysvar::epoch_schedule::id(),
+        reduce_stake_warmup_cooldown::id(),
+    ]
+}
+
+fn stake_environment(accounts: Vec<Option<Account>>) -> anyhow::Result<StakeEnvironment> {
+    let mut sysvars = accounts.into_iter();
+    let mut next_sysvar = |name| {
+        sysvars
+            .next()
+            .flatten()
+            .ok_or_else(|| anyhow::anyhow!("Cannot read the {name} sysvar"))
+    };
+    let clock: Clock = from_account(&next_sysvar("clock")?)
+        .ok_or_else(|| anyhow::anyhow!("Cannot decode the clock sysvar"))?;
+    let history: StakeHistory = from_account(&next_sysvar("stake history")?)
+        .ok_or_else(|| anyhow::anyhow!("Cannot decode the stake history sysvar"))?;
+    let epoch_schedule: EpochSchedule = from_account(&next_sysvar("epoch schedule")?)
+        .ok_or_else(|| anyhow::anyhow!("Cannot decode the epoch schedule sysvar"))?;
+    let new_rate_activation_epoch = sysvars
+        .next()
+        .flatten()
+        .and_then(|account| feature::from_account(&account)?.activated_at)
+        .map(|slot| epoch_schedule.get_epoch(slot));
+    Ok(StakeEnvironment {
+        epoch: clock.epoch,
+        history,
+        new_rate_activation_epoch,
+    })
+}
+
+async fn check_stake_accounts(
+    rpc_client: &RpcClient,
+    stake_accounts: &[(String, Pubkey)],
+) -> anyhow::Result<()> {
+    let mut pubkeys: Vec<_> = stake_accounts.iter().map(|(_, pubkey)| *pubkey).collect();
+    pubkeys.extend(environment_pubkeys());
+    let mut accounts = rpc_client.get_multiple_accounts(&pubkeys).await?;
+    let environment = stake_environment(accounts.split_off(stake_accounts.len()))?;
+
+    reset_metric_stake();
+    for ((name, pubkey), account) in stake_accounts.iter().zip(accounts) {
+        let activation = match account {
+            Some(account) => stake_activation(&account, &environment),
+            None => Err(anyhow::anyhow!("Account does not exist")),
+        };
+        match activation {
+            Ok(activation) => {
+                info!("Stake account {name} ({pubkey}): {activation:?}");
+                update_metric_stake(name, &pubkey.to_string(), &activation);
+            }
+            Err(err) => error!("Cannot decode stake account {name} ({pubkey}): {err}"),
+        }
+    }
+    Ok(())
+}
+
+/// Reports how much of each stake account is active, activating,
+/// deactivating or inactive, along with its delegation.
+pub fn spawn_stake_watcher(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    stake_accounts: Vec<(String, Pubkey)>,
+) -> JoinHandle<()> {
+    update_metric_watcher_info(
+        WATCHER_NAME,
+        WATCHER_NAME,
+        "",
+        check_interval(DEFAULT_CHECK_INTERVAL),
+    );
+    tokio::spawn(async move {
+        info!("Watching stake accounts: {stake_accounts:?}");
+        loop {
+            rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+            if let Err(err) = check_stake_accounts(&rpc_client, &stake_accounts).await {
+                error!("Failed to check stake accounts: {err}");
+                record_failed_check(WATCHER_NAME, &err.to_string());
+                reset_metric_stake();
+                if !sleep_unless_shutdown(backoff_duration(WATCHER_NAME)).await {
+                    break;
+                }
+                continue;
+            }
+            record_successful_check(WATCHER_NAME);
+
+            if !sleep_unless_shutdown(check_interval(DEFAULT_CHECK_INTERVAL)).await {
+                break;
+            }
+        }
+        info!("Stake watcher stopped");
+    })
+}
+
+/// What a stake account of a withdraw authority held at a check, to tell
+/// merges and splits apart from accounts that merely come and go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct StakeAccountSnapshot {
+    staker: Pubkey,
+    lamports: u64,
+    rent_exempt_reserve: u64,
+}
+
+fn stake_account_snapshot(account: &Account) -> Option<StakeAccountSnapshot> {
+    let meta = account.deserialize_data::<StakeStateV2>().ok()?.meta()?;
+    Some(StakeAccountSnapshot {
+        staker: meta.authorized.staker,
+        lamports: account.lamports,
+        rent_exempt_reserve: meta.rent_exempt_reserve,
+    })
+}
+
+/// Change to the stake accounts of a withdraw authority between two checks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum StakeAccountChange {
+    /// `sources` were closed and their `lamports` went to `destinations`.
+    Merge {
+        sources: Vec<Pubkey>,
+        destinations: Vec<Pubkey>,
+        lamports: u64,
+    },
+    /// `destinations` appeared with `lamports` taken from `sources`.
+    Split {
+        sources: Vec<Pubkey>,
+        destinations: Vec<Pubkey>,
+        lamports: u64,
+    },
+    /// Accounts that appeared otherwise.
+    Added(Vec<Pubkey>),
+    /// Accounts that disappeared otherwise.
+    Removed(Vec<Pubkey>),
+}
+
+impl StakeAccountChange {
+    fn kind(&self) -> &'static str {
+        match self {
+            StakeAccountChange::Merge { .. } => "merge",
+            StakeAccountChange::Split { .. } => "split",
+            StakeAccountChange::Added(_) => "added",
+            StakeAccountChange::Removed(_) => "removed",
+        }
+    }
+
+    /// Merges and splits count once, added and removed accounts each.
+    fn count(&self) -> usize {
+        match self {
+            StakeAccountChange::Merge { .. } | StakeAccountChange::Split { .. } => 1,
+            StakeAccountChange::Added(pubkeys) | StakeAccountChange::Removed(pubkeys) => {
+                pubkeys.len()
+            }
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let strings = |pubkeys: &[Pubkey]| -> Vec<String> {
+            pubkeys.iter().map(|pubkey| pubkey.to_string()).collect()
+        };
+        match self {
+            StakeAccountChange::Merge {
+                sources,
+                destinations,
+                lamports,
+            }
+            | StakeAccountChange::Split {
+                sources,
+                destinations,
+                lamports,
+            } => json!({
+                "change": self.kind(),
+                "sources": strings(sources),
+                "destinations": strings(destinations),
+                "lamports": lamports,
+            }),
+            StakeAccountChange::Added(pubkeys) | StakeAccountChange::Removed(pubkeys) => json!({
+                "change": self.kind(),
+                "accounts": strings(pubkeys),
+            }),
+        }
+    }
+}
+
+/// Accounts of one staker that changed between two checks.
+#[derive(Debug, Default)]
+struct StakerChanges {
+    removed: Vec<(Pubkey, u64)>,
+    added: Vec<(Pubkey, StakeAccountSnapshot)>,
+    gained: Vec<(Pubkey, u64)>,
+    lost: Vec<(Pubkey, u64)>,
+}
+
+/// Classifies accounts that disappeared while others of the same staker
+/// gained exactly their lamports as merged, and accounts that appeared while
+/// others of the same staker lost their lamports, up to the rent-exempt
+/// reserve the new accounts were funded with, as split off. Anything else,
+/// including merges and splits overlapping with rewards or deposits between
+/// two checks, is reported as accounts added and removed.
+fn classify_stake_account_changes(
+    previous: &BTreeMap<Pubkey, StakeAccountSnapshot>,
+    current: &BTreeMap<Pubkey, StakeAccountSnapshot>,
+) -> Vec<StakeAccountChange> {
+    let mut stakers: BTreeMap<Pubkey, StakerChanges> = BTreeMap::new();
+    for (pubkey, before) in previous {
+        match current.get(pubkey) {
+            None => stakers
+                .entry(before.staker)
+                .or_default()
+                .removed
+                .push((*pubkey, before.lamports)),
+            Some(after) if after.lamports > before.lamports => stakers
+                .entry(after.staker)
+                .or_default()
+                .gained
+                .push((*pubkey, after.lamports - before.lamports)),
+            Some(after) if after.lamports < before.lamports => stakers
+                .entry(after.staker)
+                .or_default()
+                .lost
+                .push((*pubkey, before.lamports - after.lamports)),
+            Some(_) => {}
+        }
+    }
+    for (pubkey, after) in current {
+        if !previous.contains_key(pubkey) {
+            stakers
+                .entry(after.staker)
+                .or_default()
+                .added
+                .push((*pubkey, *after));
+        }
+    }
+
+    let total = |accounts: &[(Pubkey, u64)]| accounts.iter().map(|(_, lamports)| lamports).sum();
+    let pubkeys = |accounts: &[(Pubkey, u64)]| accounts.iter().map(|(pubkey, _)| *pubkey).collect();
+    let mut changes = vec![];
+    for changes_of_staker in stakers.into_values() {
+        let StakerChanges {
+            removed,
+            added,
+            gained,
+            lost,
+        } = changes_of_staker;
+        let removed_lamports: u64 = total(&removed);
+        let lost_lamports: u64 = total(&lost);
+        let added_lamports: u64 = added.iter().map(|(_, account)| account.lamports).sum();
+        let added_reserves: u64 = added
+            .iter()
+            .map(|(_, account)| account.rent_exempt_reserve)
+            .sum();
+        let added_pubkeys: Vec<_> = added.iter().map(|(pubkey, _)| *pubkey).collect();
+        if !removed.is_empty()
+            && added.is_empty()
+            && lost.is_empty()
+            && removed_lamports == total(&gained)
+        {
+            changes.push(StakeAccountChange::Merge {
+                sources: pubkeys(&removed),
+                destinations: pubkeys(&gained),
+                lamports: removed_lamports,
+            });
+        } else if !added.is_empty()
+            && removed.is_empty()
+            && gained.is_empty()
+            && !lost.is_empty()
+            && lost_lamports <= added_lamports
+            && lost_lamports + added_reserves >= added_lamports
+        {
+            changes.push(StakeAccountChange::Split {
+                sources: pubkeys(&lost),
+                destinations: added_pubkeys,
+                lamports: lost_lamports,
+            });
+        } else {
+            if !added_pubkeys.is_empty() {
+                changes.push(StakeAccountChange::Added(added_pubkeys));
+            }
+            if !removed.is_empty() {
+                changes.push(StakeAccountChange::Removed(pubkeys(&removed)));
+            }
+        }
+    }
+    changes
+}
+
+/// Sums the stake of all stake accounts whose withdraw authority is
+/// `authority`, returning the number of accounts, their total stake and what
+/// each of them held.
+async fn check_withdraw_authority(
+    rpc_client: &RpcClient,
+    authority: &Pubkey,
+) -> anyhow::Result<(
+    usize,
+    StakeActivation,
+    BTreeMap<Pubkey, StakeAccountSnapshot>,
+)> {
+    let environment_accounts = rpc_client
+        .get_multiple_accounts(&environment_pubkeys())
+        .await?;
+    let environment = stake_environment(environment_accounts)?;
+    let accounts = rpc_client
+        .get_program_accounts_with_config(
+            &stake::program::id(),
+            RpcProgramAccountsConfig {
+                filters: Some(vec![
+                    RpcFilterType::DataSize(StakeStateV2::size_of() as u64),
+                    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                        WITHDRAWER_OFFSET,
+                        authority.to_bytes().to_vec(),
+                    )),
+                ]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let mut total = StakeActivation::default();
+    let mut snapshots = BTreeMap::new();
+    for (pubkey, account) in &accounts {
+        if let Some(snapshot) = stake_account_snapshot(account) {
+            snapshots.insert(*pubkey, snapshot);
+        }
+        match stake_activation(account, &environment) {
+            Ok(activation) => {
+                total.active += activation.active;
+                total.activating += activation.activating;
+                total.deactivating += activation.deactivating;
+                total.inactive += activation.inactive;
+            }
+            Err(err) => error!("Cannot decode stake account {pubkey}: {err}"),
+        }
+    }
+    Ok((accounts.len(), total, snapshots))
+}
+
+fn report_stake_account_change(name: &str, authority: &Pubkey, change: &StakeAccountChange) {
+    let authority_label = authority.to_string();
+    let event = change.to_json();
+    match change {
+        StakeAccountChange::Merge { .. } | StakeAccountChange::Split { .. } => {
+            info!("Stake accounts of withdraw authority {name} ({authority}) changed: {event}")
+        }
+        StakeAccountChange::Added(_) | StakeAccountChange::Removed(_) => {
+            warn!("Stake accounts of withdraw authority {name} ({authority}) changed: {event}")
+        }
+    }
+    update_metric_withdraw_authority_stake_account_changes(
+        name,
+        &authority_label,
+        change.kind(),
+        change.count(),
+    );
+}
+
+/// Reports the total active, activating, deactivating and inactive stake of
+/// all stake accounts controlled by each named withdraw authority, found with
+/// `getProgramAccounts` on the stake program. Accounts merged or split
+/// between two checks are reported as such, rather than as accounts that
+/// disappeared and appeared.
+pub fn spawn_withdraw_authority_watcher(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    authorities: Vec<(String, Pubkey)>,
+) -> JoinHandle<()> {
+    update_metric_watcher_info(
+        AUTHORITY_WATCHER_NAME,
+        AUTHORITY_WATCHER_NAME,
+        "",
+        check_interval(DEFAULT_CHECK_INTERVAL),
+    );
+    tokio::spawn(async move {
+        info!("Watching stake of withdraw authorities: {authorities:?}");
+        let mut known_accounts: HashMap<Pubkey, BTreeMap<Pubkey, StakeAccountSnapshot>> =
+            HashMap::new();
+        loop {
+            let mut failed = false;
+            for (name, authority) in &authorities {
+                rate_limiter.acquire(AUTHORITY_WATCHER_NAME, 1, 1).await;
+                let authority_label = authority.to_string();
+                match check_withdraw_authority(&rpc_client, authority).await {
+                    Ok((accounts, total, snapshots)) => {
+                        info!(
+                            "Withdraw authority {name} ({authority}) controls {accounts} stake accounts: {total:?}"
+                        );
+                        update_metric_withdraw_authority_stake(
+                            name,
+                            &authority_label,
+                            accounts,
+                            &total,
+                        );
+                        if let Some(previous) = known_accounts.get(authority) {
+                            for change in classify_stake_account_changes(previous, &snapshots) {
+                                report_stake_account_change(name, authority, &change);
+                            }
+                        }
+                        known_accounts.insert(*authority, snapshots);
+                    }
+                    Err(err) => {
+                        error!("Failed to check stake of withdraw authority {name}: {err}");
+                        record_failed_check(AUTHORITY_WATCHER_NAME, &err.to_string());
+                        remove_metric_withdraw_authority_stake(name, &authority_label);
+                        failed = true;
+                    }
+                }
+            }
+            if !failed {
+                record_successful_check(AUTHORITY_WATCHER_NAME);
+            }
+
+            let interval = if failed {
+                backoff_duration(AUTHORITY_WATCHER_NAME)
+            } else {
+                check_interval(DEFAULT_CHECK_INTERVAL)
+            };
+            if !sleep_unless_shutdown(interval).await {
+                break;
+            }
+        }
+        info!("Withdraw authority watcher stopped");
+    })
+}
@@ -0,0 +1,123 @@
+use std::{fmt::Write, net::SocketAddr, sync::Arc, time::SystemTime};
+
+use axum::{extract::State, response::Html, routing::get, Router};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::info;
+use tokio::task::JoinHandle;
+
+use crate::{
+    alert_rules::thresholds_of, amount_format::format_lamports, health::watcher_health,
+    observations::latest_observations,
+};
+
+/// Seconds after which browsers reload the page.
+const REFRESH_SECS: u64 = 60;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{border:1px solid #ccc;padding:.3em .8em;text-align:left}\
+.ok{color:#080}.failing{color:#c00}";
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn timestamp(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Renders the page. Leaves out pubkeys, errors, which may quote RPC URLs
+/// carrying API keys, and anything else not meant for stakeholders.
+fn render(title: &str) -> String {
+    let title = escape_html(title);
+    let mut html = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+         <meta http-equiv=\"refresh\" content=\"{REFRESH_SECS}\">\
+         <title>{title}</title><style>{STYLE}</style></head><body><h1>{title}</h1>"
+    );
+
+    html.push_str("<h2>Balances</h2><table><tr><th>Name</th><th>Balance</th><th>Thresholds</th><th>Updated</th></tr>");
+    for balance in latest_observations() {
+        let observation = &balance.latest;
+        let thresholds = thresholds_of(
+            &observation.watcher,
+            &observation.name,
+            observation.lamports,
+        );
+        let thresholds = if thresholds.is_empty() {
+            "-".to_string()
+        } else {
+            thresholds
+                .iter()
+                .map(|(threshold, breached)| {
+                    let (class, state) = if *breached {
+                        ("failing", "breached")
+                    } else {
+                        ("ok", "ok")
+                    };
+                    format!(
+                        "<span class=\"{class}\">{} {state}</span>",
+                        escape_html(threshold)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("<br>")
+        };
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{thresholds}</td><td>{}</td></tr>",
+            escape_html(&observation.name),
+            escape_html(&format_lamports(observation.lamports)),
+            timestamp(observation.observed_at),
+        );
+    }
+    html.push_str("</table>");
+
+    html.push_str(
+        "<h2>Watchers</h2><table><tr><th>Watcher</th><th>State</th><th>Last success</th></tr>",
+    );
+    for (watcher, health) in watcher_health() {
+        let (class, state) = if health.is_healthy() {
+            ("ok", "healthy")
+        } else {
+            ("failing", "failing")
+        };
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td class=\"{class}\">{state}</td><td>{}</td></tr>",
+            escape_html(&watcher),
+            health
+                .last_success
+                .map_or_else(|| "never".to_string(), timestamp),
+        );
+    }
+    let _ = write!(
+        html,
+        "</table><p>Generated at {}</p></body></html>",
+        timestamp(SystemTime::now())
+    );
+    html
+}
+
+async fn status_page(State(title): State<Arc<str>>) -> Html<String> {
+    Html(render(&title))
+}
+
+/// Serves a read-only HTML page of the latest balances against the
+/// thresholds of the alert rules, and of watcher health, on its own `addr`,
+/// for sharing with people who have no access to the metrics or the API.
+pub fn spawn_status_page(addr: SocketAddr, title: String) -> JoinHandle<()> {
+    info!("Serving status page on {addr}");
+    tokio::spawn(async move {
+        let router = Router::new()
+            .route("/", get(status_page))
+            .with_state(Arc::<str>::from(title));
+        axum::Server::bind(&addr)
+            .serve(router.into_make_service())
+            .await
+            .unwrap();
+    })
+}
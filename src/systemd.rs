@@ -0,0 +1,92 @@
+use std::time::{Duration, SystemTime};
+
+use log::{info, warn};
+use sd_notify::NotifyState;
+use tokio::{task::JoinHandle, time::interval};
+
+use crate::{
+    health::{running_watcher_health, watcher_health},
+    intervals::backoff_max,
+    metrics::watcher_intervals,
+    shutdown::shutdown_requested,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Time a check may take, e.g. a slow scan or a wait for the rate limit, on
+/// top of the wait before it.
+const CHECK_GRACE: Duration = Duration::from_secs(120);
+
+fn notify(state: &[NotifyState]) {
+    if let Err(err) = sd_notify::notify(false, state) {
+        warn!("Failed to notify systemd: {err}");
+    }
+}
+
+/// A watcher whose latest check is older than its interval or longest
+/// backoff, plus [`CHECK_GRACE`], such as one stuck on a hung request.
+fn stalled_watcher() -> Option<String> {
+    let intervals = watcher_intervals();
+    let now = SystemTime::now();
+    watcher_health().into_iter().find_map(|(watcher, health)| {
+        let allowed = intervals
+            .get(&watcher)?
+            .max(&backoff_max())
+            .saturating_add(CHECK_GRACE);
+        let since_last_check = now.duration_since(health.last_check()?).unwrap_or_default();
+        (since_last_check > allowed).then_some(watcher)
+    })
+}
+
+/// Whether every running watcher, including the ones added after startup,
+/// completed a check successfully.
+fn all_watchers_succeeded() -> bool {
+    running_watcher_health()
+        .iter()
+        .all(|(_, health)| health.last_success.is_some())
+}
+
+/// Reports readiness to systemd once every running watcher completed its
+/// first successful check, and services the watchdog while every watcher keeps checking, so
+/// that systemd restarts a wedged process. Returns `None` when not running as
+/// a `Type=notify` unit.
+pub fn spawn_systemd_notifier() -> Option<JoinHandle<()>> {
+    std::env::var_os("NOTIFY_SOCKET")?;
+
+    let mut watchdog_usec = 0;
+    let watchdog = sd_notify::watchdog_enabled(false, &mut watchdog_usec)
+        .then(|| Duration::from_micros(watchdog_usec) / 2);
+    let period = watchdog.map_or(POLL_INTERVAL, |watchdog| watchdog.min(POLL_INTERVAL));
+
+    Some(tokio::spawn(async move {
+        let mut ready = false;
+        let mut stalled = None;
+        let mut ticks = interval(period);
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => {}
+                _ = shutdown_requested() => break,
+            }
+
+            if !ready && all_watchers_succeeded() {
+                info!("All watchers completed their first check, notifying systemd");
+                notify(&[NotifyState::Ready, NotifyState::Status("Watching balances")]);
+                ready = true;
+            }
+            if watchdog.is_some() {
+                match stalled_watcher() {
+                    None => {
+                        stalled = None;
+                        notify(&[NotifyState::Watchdog]);
+                    }
+                    Some(watcher) => {
+                        if stalled.as_ref() != Some(&watcher) {
+                            warn!("Watcher '{watcher}' stopped checking, no longer servicing the systemd watchdog");
+                        }
+                        stalled = Some(watcher);
+                    }
+                }
+            }
+        }
+        notify(&[NotifyState::Stopping]);
+    }))
+}
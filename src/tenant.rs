@@ -0,0 +1,102 @@
+use std::{collections::HashMap, str::FromStr};
+
+use once_cell::sync::OnceCell;
+use prometheus::proto::{LabelPair, MetricFamily};
+
+pub const TENANT_LABEL: &str = "tenant";
+
+/// A team owning a set of watchers or named balances.
+#[derive(Debug, Clone)]
+pub struct Tenant {
+    pub name: String,
+    pub members: Vec<String>,
+}
+
+impl FromStr for Tenant {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, members)) = s.split_once('=') else {
+            anyhow::bail!("Cannot parse tenant '{s}', expected syntax: tenant=name,name,...");
+        };
+        anyhow::ensure!(!name.is_empty(), "Tenant name cannot be empty");
+        Ok(Tenant {
+            name: name.to_string(),
+            members: members
+                .split(',')
+                .filter(|member| !member.is_empty())
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+}
+
+/// Tenant of every watcher and named balance that was assigned one.
+static TENANTS: OnceCell<HashMap<String, String>> = OnceCell::new();
+
+/// Assigns watchers and named balances to tenants. Can only be called once;
+/// nothing belongs to any tenant until then.
+pub fn set_tenants(tenants: Vec<Tenant>) -> anyhow::Result<()> {
+    let mut members = HashMap::new();
+    for tenant in tenants {
+        for member in tenant.members {
+            if let Some(previous) = members.insert(member.clone(), tenant.name.clone()) {
+                anyhow::ensure!(
+                    previous == tenant.name,
+                    "'{member}' is assigned to both tenant '{previous}' and '{}'",
+                    tenant.name
+                );
+            }
+        }
+    }
+    TENANTS
+        .set(members)
+        .map_err(|_| anyhow::anyhow!("Tenants are already configured"))
+}
+
+/// Tenant owning the balance `name` of `watcher`, looked up by the balance
+/// name first and by the watcher otherwise.
+pub fn tenant_of<'a>(watcher: &str, name: &str) -> Option<&'a str> {
+    let tenants = TENANTS.get()?;
+    tenants
+        .get(name)
+        .or_else(|| tenants.get(watcher))
+        .map(String::as_str)
+}
+
+/// Whether a caller scoped to `tenant` may see the balance `name` of
+/// `watcher`. Callers without a tenant see everything.
+pub fn is_visible_to(tenant: Option<&str>, watcher: &str, name: &str) -> bool {
+    tenant.is_none() || tenant_of(watcher, name) == tenant
+}
+
+/// Adds a `tenant` label to every metric whose `watcher` or `name` label
+/// belongs to a tenant.
+pub fn label_metric_families(families: &mut [MetricFamily]) {
+    if TENANTS
+        .get()
+        .filter(|tenants| !tenants.is_empty())
+        .is_none()
+    {
+        return;
+    }
+    for family in families {
+        for metric in family.mut_metric().iter_mut() {
+            let label = |label_name: &str| {
+                metric
+                    .get_label()
+                    .iter()
+                    .find(|label| label.get_name() == label_name)
+                    .map(|label| label.get_value().to_string())
+                    .unwrap_or_default()
+            };
+            let Some(tenant) = tenant_of(&label("watcher"), &label("name")) else {
+                continue;
+            };
+            let mut pair = LabelPair::new();
+            pair.set_name(TENANT_LABEL.to_string());
+            pair.set_value(tenant.to_string());
+            metric.mut_label().push(pair);
+        }
+    }
+}
@@ -0,0 +1,569 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use log::{error, info};
+use solana_account_decoder::parse_token::is_known_spl_token_id;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    account::{from_account, Account},
+    clock::Clock,
+    pubkey::Pubkey,
+    sysvar,
+};
+use spl_token_2022::{
+    extension::{
+        interest_bearing_mint::InterestBearingConfig,
+        transfer_fee::{TransferFeeAmount, TransferFeeConfig},
+        BaseStateWithExtensions, StateWithExtensions,
+    },
+    state::{Account as TokenAccount, Mint},
+};
+use tokio::task::JoinHandle;
+
+use crate::{
+    derive::{associated_token_address, parse_token_program},
+    derived::PubkeySet,
+    health::{record_failed_check, record_successful_check},
+    intervals::{backoff_duration, check_interval, DEFAULT_CHECK_INTERVAL},
+    metrics::{
+        remove_metric_token_balance, reset_metric_token_balance, update_metric_token_amount_raw,
+        update_metric_token_balance, update_metric_token_withheld_fees, update_metric_watcher_info,
+    },
+    rate_limit::RateLimiter,
+    shutdown::sleep_unless_shutdown,
+};
+
+pub const WATCHER_NAME: &str = "token";
+
+/// A watched token account, given by its address or, as `OWNER:MINT`
+/// optionally followed by `:PROGRAM`, as the associated token account of an
+/// owner for a mint.
+#[derive(Debug, Clone)]
+pub enum TokenAccountAddress {
+    Address(Pubkey),
+    /// Derived with the token program that owns the mint unless one is given.
+    Associated {
+        owner: Pubkey,
+        mint: Pubkey,
+        token_program: Option<Pubkey>,
+    },
+}
+
+impl FromStr for TokenAccountAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<_> = s.split(':').collect();
+        Ok(match parts[..] {
+            [address] => TokenAccountAddress::Address(address.parse()?),
+            [owner, mint] | [owner, mint, _] => TokenAccountAddress::Associated {
+                owner: owner.parse()?,
+                mint: mint.parse()?,
+                token_program: parts.get(2).copied().map(parse_token_program).transpose()?,
+            },
+            _ => anyhow::bail!(
+                "Cannot parse token account '{s}', expected syntax: pubkey or owner:mint[:program]"
+            ),
+        })
+    }
+}
+
+impl fmt::Display for TokenAccountAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenAccountAddress::Address(pubkey) => write!(f, "{pubkey}"),
+            TokenAccountAddress::Associated {
+                owner,
+                mint,
+                token_program: None,
+            } => write!(f, "{owner}:{mint}"),
+            TokenAccountAddress::Associated {
+                owner,
+                mint,
+                token_program: Some(token_program),
+            } => write!(f, "{owner}:{mint}:{token_program}"),
+        }
+    }
+}
+
+/// Parses a `name=address` pair as passed to `--token-account`. A bare
+/// address is named after itself.
+pub fn parse_token_account(s: &str) -> anyhow::Result<(String, TokenAccountAddress)> {
+    let (name, address) = s.split_once('=').unwrap_or((s, s));
+    let address = address
+        .parse()
+        .map_err(|err| anyhow::anyhow!("Cannot parse token account '{name}': {err}"))?;
+    Ok((name.to_string(), address))
+}
+
+/// Balance of a watched token account, in the smallest unit of its mint.
+#[derive(Debug, Clone)]
+struct TokenBalance {
+    program: &'static str,
+    mint: Pubkey,
+    amount: u64,
+    /// Transfer fees withheld in the account, claimable by the mint's
+    /// withdraw authority only.
+    withheld_fees: u64,
+}
+
+/// What converting and transferring amounts of a mint depends on.
+#[derive(Debug, Clone)]
+struct MintInfo {
+    decimals: u8,
+    transfer_fee_config: Option<TransferFeeConfig>,
+    interest_bearing_config: Option<InterestBearingConfig>,
+}
+
+impl MintInfo {
+    /// Converts a raw amount to the UI amount, including the interest
+    /// accrued until `unix_timestamp` for interest-bearing mints.
+    fn ui_amount(&self, amount: u64, unix_timestamp: i64) -> f64 {
+        let interest = self.interest_bearing_config.and_then(|config| {
+            config
+                .amount_to_ui_amount(amount, self.decimals, unix_timestamp)?
+                .parse()
+                .ok()
+        });
+        interest.unwrap_or_else(|| amount as f64 / 10f64.powi(i32::from(self.decimals)))
+    }
+
+    /// Fee charged for transferring `amount` out during `epoch`, rounded up
+    /// as the token program does.
+    fn transfer_fee(&self, epoch: u64, amount: u64) -> u64 {
+        self.transfer_fee_config
+            .as_ref()
+            .and_then(|config| config.calculate_epoch_fee(epoch, amount))
+            .unwrap_or(0)
+    }
+}
+
+fn program_name(program: &Pubkey) -> anyhow::Result<&'static str> {
+    anyhow::ensure!(
+        is_known_spl_token_id(program),
+        "Account is owned by {program}, not a token program"
+    );
+    Ok(match *program == spl_token_2022::id() {
+        true => "spl-token-2022",
+        false => "spl-token",
+    })
+}
+
+/// Parses an SPL Token or Token-2022 account, the latter with its extensions.
+fn unpack_token_account(account: &Account) -> anyhow::Result<TokenBalance> {
+    let program = program_name(&account.owner)?;
+    let state = StateWithExtensions::<TokenAccount>::unpack(&account.data)?;
+    // Malformed extensions would otherwise pass for missing ones below.
+    state.get_extension_types()?;
+    Ok(TokenBalance {
+        program,
+        mint: state.base.mint,
+        amount: state.base.amount,
+        withheld_fees: state
+            .get_extension::<TransferFeeAmount>()
+            .map_or(0, |fees| fees.withheld_amount.into()),
+    })
+}
+
+fn unpack_mint(account: &Account) -> anyhow::Result<MintInfo> {
+    program_name(&account.owner)?;
+    let state = StateWithExtensions::<Mint>::unpack(&account.data)?;
+    state.get_extension_types()?;
+    Ok(MintInfo {
+        decimals: state.base.decimals,
+        transfer_fee_config: state.get_extension::<TransferFeeConfig>().ok().copied(),
+        interest_bearing_config: state.get_extension::<InterestBearingConfig>().ok().copied(),
+    })
+}
+
+/// Resolves the addresses of `token_accounts`, deriving associated token
+/// accounts with the program that owns their mint unless one is given.
+async fn resolve_token_accounts(
+    rpc_client: &RpcClient,
+    rate_limiter: &RateLimiter,
+    token_accounts: &[(String, TokenAccountAddress)],
+) -> anyhow::Result<Vec<(String, Pubkey)>> {
+    let mut mints: Vec<_> = token_accounts
+        .iter()
+        .filter_map(|(_, address)| match address {
+            TokenAccountAddress::Associated {
+                mint,
+                token_program: None,
+                ..
+            } => Some(*mint),
+            _ => None,
+        })
+        .collect();
+    mints.sort();
+    mints.dedup();
+    let mut mint_programs = HashMap::new();
+    if !mints.is_empty() {
+        rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+        let accounts = rpc_client.get_multiple_accounts(&mints).await?;
+        for (mint, account) in mints.iter().zip(accounts) {
+            let Some(account) = account else {
+                anyhow::bail!("Mint {mint} does not exist");
+            };
+            program_name(&account.owner)?;
+            mint_programs.insert(*mint, account.owner);
+        }
+    }
+
+    let mut named_pubkeys = vec![];
+    for (name, address) in token_accounts {
+        let pubkey = match address {
+            TokenAccountAddress::Address(pubkey) => *pubkey,
+            TokenAccountAddress::Associated {
+                owner,
+                mint,
+                token_program,
+            } => {
+                let token_program = token_program.unwrap_or_else(|| mint_programs[mint]);
+                let pubkey = associated_token_address(owner, mint, &token_program);
+                info!("Token account {name} of {owner} for {mint} is {pubkey}");
+                pubkey
+            }
+        };
+        named_pubkeys.push((name.clone(), pubkey));
+    }
+    Ok(named_pubkeys)
+}
+
+async fn check_token_accounts(
+    rpc_client: &RpcClient,
+    rate_limiter: &RateLimiter,
+    watcher: &str,
+    named_pubkeys: &[(String, Pubkey)],
+) -> anyhow::Result<()> {
+    let pubkeys: Vec<_> = named_pubkeys.iter().map(|(_, pubkey)| *pubkey).collect();
+    rate_limiter.acquire(watcher, 1, 1).await;
+    let accounts = rpc_client.get_multiple_accounts(&pubkeys).await?;
+
+    let mut balances = vec![];
+    for ((name, pubkey), account) in named_pubkeys.iter().zip(accounts) {
+        let Some(account) = account else {
+            error!("Token account {name} ({pubkey}) does not exist");
+            continue;
+        };
+        match unpack_token_account(&account) {
+            Ok(balance) => balances.push((name, pubkey, balance)),
+            Err(err) => error!("Cannot parse token account {name} ({pubkey}): {err}"),
+        }
+    }
+    if balances.is_empty() {
+        return Ok(());
+    }
+
+    // The clock is fetched along with the mints, as transfer fees depend on
+    // the epoch and interest on the time.
+    let mut mints: Vec<_> = balances
+        .iter()
+        .map(|(_, _, balance)| balance.mint)
+        .collect();
+    mints.sort();
+    mints.dedup();
+    mints.push(sysvar::clock::id());
+    rate_limiter.acquire(watcher, 1, 1).await;
+    let mut accounts = rpc_client.get_multiple_accounts(&mints).await?;
+    let clock: Clock = accounts
+        .pop()
+        .flatten()
+        .and_then(|account| from_account(&account))
+        .ok_or_else(|| anyhow::anyhow!("Cannot read the clock sysvar"))?;
+    let mut mint_infos: HashMap<Pubkey, MintInfo> = HashMap::new();
+    for (mint, account) in mints.iter().zip(accounts) {
+        match account.as_ref().map(unpack_mint) {
+            Some(Ok(info)) => {
+                mint_infos.insert(*mint, info);
+            }
+            Some(Err(err)) => error!("Cannot parse mint {mint}: {err}"),
+            None => error!("Mint {mint} does not exist"),
+        }
+    }
+
+    for (name, pubkey, balance) in balances {
+        let Some(mint) = mint_infos.get(&balance.mint) else {
+            continue;
+        };
+        let fee = mint.transfer_fee(clock.epoch, balance.amount);
+        let net_amount = balance.amount - fee;
+        info!("Token balance {pubkey}: {balance:?}, net of transfer fees {net_amount}");
+        let (pubkey, mint_pubkey) = (pubkey.to_string(), balance.mint.to_string());
+        for (kind, amount) in [("gross", balance.amount), ("net", net_amount)] {
+            let ui_amount = mint.ui_amount(amount, clock.unix_timestamp);
+            update_metric_token_balance(name, &pubkey, &mint_pubkey, kind, ui_amount);
+        }
+        for (kind, amount) in [
+            ("gross", balance.amount),
+            ("net", net_amount),
+            ("withheld", balance.withheld_fees),
+        ] {
+            update_metric_token_amount_raw(
+                name,
+                &pubkey,
+                &mint_pubkey,
+                balance.program,
+                kind,
+                amount,
+            );
+        }
+        update_metric_token_withheld_fees(
+            name,
+            &pubkey,
+            &mint_pubkey,
+            mint.ui_amount(balance.withheld_fees, clock.unix_timestamp),
+        );
+    }
+    Ok(())
+}
+
+/// Reports the balance of token accounts, SPL Token or Token-2022, both gross
+/// and net of the transfer fee its mint would charge for withdrawing all of
+/// it, along with the transfer fees withheld in the account. Amounts are
+/// exported raw and as UI amounts, which include accrued interest for
+/// interest-bearing mints.
+pub fn spawn_token_balance_watcher(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    token_accounts: Vec<(String, TokenAccountAddress)>,
+) -> JoinHandle<()> {
+    update_metric_watcher_info(
+        WATCHER_NAME,
+        WATCHER_NAME,
+        "",
+        check_interval(DEFAULT_CHECK_INTERVAL),
+    );
+    tokio::spawn(async move {
+        info!("Watching token accounts: {token_accounts:?}");
+        let mut named_pubkeys = None;
+        loop {
+            let result = async {
+                let named_pubkeys = match &mut named_pubkeys {
+                    Some(named_pubkeys) => named_pubkeys,
+                    None => named_pubkeys.insert(
+                        resolve_token_accounts(&rpc_client, &rate_limiter, &token_accounts).await?,
+                    ),
+                };
+                check_token_accounts(&rpc_client, &rate_limiter, WATCHER_NAME, named_pubkeys).await
+            };
+            match result.await {
+                Ok(()) => record_successful_check(WATCHER_NAME),
+                Err(err) => {
+                    error!("Failed to check token accounts: {err}");
+                    record_failed_check(WATCHER_NAME, &err.to_string());
+                    reset_metric_token_balance();
+                    if !sleep_unless_shutdown(backoff_duration(WATCHER_NAME)).await {
+                        break;
+                    }
+                    continue;
+                }
+            }
+
+            if !sleep_unless_shutdown(check_interval(DEFAULT_CHECK_INTERVAL)).await {
+                break;
+            }
+        }
+        info!("Token balance watcher stopped");
+    })
+}
+
+/// Watches the token balance of every account in the set produced upstream,
+/// exported under `name`, like [`spawn_token_balance_watcher`]. When the set
+/// changes, balances of accounts that left it are removed and the new set is
+/// checked right away.
+pub fn spawn_derived_token_balance_watcher(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    name: String,
+    mut pubkey_set: PubkeySet,
+    check_interval: Duration,
+) -> JoinHandle<()> {
+    let watcher = format!("{name}/tokens");
+    update_metric_watcher_info(&watcher, "derived_token_balance", "", check_interval);
+    tokio::spawn(async move {
+        info!("Watching token balances of accounts derived for '{name}'");
+        // Nothing to check until the upstream set was produced once.
+        if pubkey_set.changed().await.is_err() {
+            return;
+        }
+        let mut watched = Arc::new(BTreeSet::new());
+        loop {
+            let current = pubkey_set.borrow_and_update().clone();
+            for pubkey in watched.difference(&current) {
+                remove_metric_token_balance(&name, &pubkey.to_string());
+            }
+            watched = current;
+
+            let named_pubkeys: Vec<_> = watched
+                .iter()
+                .map(|pubkey| (name.clone(), *pubkey))
+                .collect();
+            let interval =
+                match check_token_accounts(&rpc_client, &rate_limiter, &watcher, &named_pubkeys)
+                    .await
+                {
+                    Ok(()) => {
+                        record_successful_check(&watcher);
+                        check_interval
+                    }
+                    Err(err) => {
+                        error!("Failed to check token accounts derived for '{name}': {err}");
+                        record_failed_check(&watcher, &err.to_string());
+                        backoff_duration(&watcher)
+                    }
+                };
+            tokio::select! {
+                keep_running = sleep_unless_shutdown(interval) => if !keep_running {
+                    break;
+                },
+                changed = pubkey_set.changed() => if changed.is_err() {
+                    break;
+                },
+            }
+        }
+        info!("Stopped watching token balances of accounts derived for '{name}'");
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use spl_token_2022::{
+        extension::{ExtensionType, StateWithExtensionsMut},
+        state::AccountState,
+    };
+
+    use super::*;
+
+    fn token_2022_account(amount: u64, withheld_fees: Option<u64>) -> Account {
+        let extensions: Vec<_> = withheld_fees
+            .iter()
+            .map(|_| ExtensionType::TransferFeeAmount)
+            .collect();
+        let len = ExtensionType::try_calculate_account_len::<TokenAccount>(&extensions).unwrap();
+        let mut data = vec![0; len];
+        let mut state =
+            StateWithExtensionsMut::<TokenAccount>::unpack_uninitialized(&mut data).unwrap();
+        state.base = TokenAccount {
+            mint: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            amount,
+            state: AccountState::Initialized,
+            ..Default::default()
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        if let Some(withheld_fees) = withheld_fees {
+            state
+                .init_extension::<TransferFeeAmount>(true)
+                .unwrap()
+                .withheld_amount = withheld_fees.into();
+        }
+        Account {
+            data,
+            owner: spl_token_2022::id(),
+            ..Default::default()
+        }
+    }
+
+    fn token_2022_mint(decimals: u8, fee: Option<(u16, u64)>) -> Account {
+        let extensions: Vec<_> = fee
+            .iter()
+            .map(|_| ExtensionType::TransferFeeConfig)
+            .collect();
+        let len = ExtensionType::try_calculate_account_len::<Mint>(&extensions).unwrap();
+        let mut data = vec![0; len];
+        let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        if let Some((basis_points, maximum_fee)) = fee {
+            let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+            config.newer_transfer_fee.transfer_fee_basis_points = basis_points.into();
+            config.newer_transfer_fee.maximum_fee = maximum_fee.into();
+            config.older_transfer_fee = config.newer_transfer_fee;
+        }
+        state.base = Mint {
+            decimals,
+            is_initialized: true,
+            ..Default::default()
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        Account {
+            data,
+            owner: spl_token_2022::id(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unpacks_account_without_extensions() {
+        let balance = unpack_token_account(&token_2022_account(42, None)).unwrap();
+        assert_eq!(balance.program, "spl-token-2022");
+        assert_eq!(balance.amount, 42);
+        assert_eq!(balance.withheld_fees, 0);
+    }
+
+    #[test]
+    fn unpacks_withheld_transfer_fees() {
+        let account = token_2022_account(u64::MAX, Some(u64::MAX));
+        let balance = unpack_token_account(&account).unwrap();
+        assert_eq!(balance.amount, u64::MAX);
+        assert_eq!(balance.withheld_fees, u64::MAX);
+    }
+
+    #[test]
+    fn rejects_empty_and_truncated_data() {
+        let mut account = token_2022_account(1, Some(1));
+        account.data.truncate(account.data.len() - 1);
+        assert!(unpack_token_account(&account).is_err());
+        account.data.clear();
+        assert!(unpack_token_account(&account).is_err());
+        assert!(unpack_mint(&account).is_err());
+    }
+
+    #[test]
+    fn rejects_accounts_of_other_programs() {
+        let mut account = token_2022_account(1, None);
+        account.owner = Pubkey::default();
+        assert!(unpack_token_account(&account).is_err());
+    }
+
+    #[test]
+    fn rejects_mint_as_token_account() {
+        assert!(unpack_token_account(&token_2022_mint(6, None)).is_err());
+    }
+
+    #[test]
+    fn mint_without_fee_charges_nothing() {
+        let mint = unpack_mint(&token_2022_mint(6, None)).unwrap();
+        assert_eq!(mint.decimals, 6);
+        assert_eq!(mint.transfer_fee(0, u64::MAX), 0);
+    }
+
+    #[test]
+    fn transfer_fee_is_capped() {
+        let mint = unpack_mint(&token_2022_mint(0, Some((100, 5)))).unwrap();
+        assert_eq!(mint.transfer_fee(0, 0), 0);
+        assert_eq!(mint.transfer_fee(0, 1), 1);
+        assert_eq!(mint.transfer_fee(0, 200), 2);
+        assert_eq!(mint.transfer_fee(0, u64::MAX), 5);
+    }
+
+    #[test]
+    fn full_fee_does_not_overflow() {
+        let mint = unpack_mint(&token_2022_mint(0, Some((10_000, u64::MAX)))).unwrap();
+        assert_eq!(mint.transfer_fee(u64::MAX, u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn ui_amount_applies_decimals() {
+        let mint = unpack_mint(&token_2022_mint(0, None)).unwrap();
+        assert_eq!(mint.ui_amount(0, 0), 0.0);
+        assert_eq!(mint.ui_amount(u64::MAX, 0), u64::MAX as f64);
+        let mint = unpack_mint(&token_2022_mint(9, None)).unwrap();
+        assert_eq!(mint.ui_amount(1_500_000_000, 0), 1.5);
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    io::{stdout, Stdout},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    amount_format::{amount_unit, format_number},
+    health::watcher_health,
+    metrics::mean_rpc_endpoint_request_duration,
+    observations::latest_observations,
+    rpc::RequestClass,
+    shutdown::is_shutdown_requested,
+};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    ExecutableCommand,
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Layout},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Borders, Cell, Row, Table},
+    Frame, Terminal,
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+fn ago(time: SystemTime) -> String {
+    match time.elapsed() {
+        Ok(elapsed) => format!("{}s ago", elapsed.as_secs()),
+        Err(_) => "just now".to_string(),
+    }
+}
+
+fn latency(endpoint: &str, class: RequestClass) -> String {
+    match mean_rpc_endpoint_request_duration(endpoint, class.as_str()) {
+        Some(seconds) => format!("{:.0} ms", seconds * 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+fn draw(frame: &mut Frame, endpoints: &[String]) {
+    let observations = latest_observations();
+    let watchers = watcher_health();
+    let [accounts_area, watchers_area, endpoints_area, help_area] = Layout::vertical([
+        Constraint::Min(3),
+        Constraint::Length(watchers.len() as u16 + 3),
+        Constraint::Length(endpoints.len() as u16 + 3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.size());
+
+    let rows = observations.iter().map(|balance| {
+        let observation = &balance.latest;
+        let delta = match balance.delta_lamports() {
+            Some(delta) if delta < 0 => {
+                Cell::from(format!("-{}", format_number(delta.unsigned_abs() as u64)))
+                    .style(Style::default().fg(Color::Red))
+            }
+            Some(delta) if delta > 0 => Cell::from(format!("+{}", format_number(delta as u64)))
+                .style(Style::default().fg(Color::Green)),
+            _ => Cell::from(""),
+        };
+        Row::new(vec![
+            Cell::from(observation.watcher.clone()),
+            Cell::from(observation.name.clone()),
+            Cell::from(
+                observation
+                    .pubkey
+                    .map(|p| p.to_string())
+                    .unwrap_or_default(),
+            ),
+            Cell::from(format_number(observation.lamports)),
+            delta,
+            Cell::from(ago(observation.observed_at)),
+        ])
+    });
+    frame.render_widget(
+        Table::new(
+            rows,
+            [
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+                Constraint::Length(44),
+                Constraint::Percentage(15),
+                Constraint::Percentage(15),
+                Constraint::Percentage(10),
+            ],
+        )
+        .header(
+            Row::new([
+                "Watcher",
+                "Name",
+                "Pubkey",
+                &format!("Balance ({})", amount_unit().as_str()),
+                "Change",
+                "Checked",
+            ])
+            .bold(),
+        )
+        .block(Block::default().borders(Borders::ALL).title("Balances")),
+        accounts_area,
+    );
+
+    let rows = watchers.iter().map(|(watcher, health)| {
+        let failing = match (health.last_success, &health.last_failure) {
+            (Some(success), Some((failure, _))) => failure > &success,
+            (None, Some(_)) => true,
+            _ => false,
+        };
+        Row::new(vec![
+            Cell::from(watcher.clone()),
+            Cell::from(health.last_success.map(ago).unwrap_or_default()),
+            Cell::from(
+                health
+                    .last_failure
+                    .as_ref()
+                    .map(|(time, error)| format!("{}: {error}", ago(*time)))
+                    .unwrap_or_default(),
+            ),
+        ])
+        .style(if failing {
+            Style::default().fg(Color::Red)
+        } else {
+            Style::default()
+        })
+    });
+    frame.render_widget(
+        Table::new(
+            rows,
+            [
+                Constraint::Percentage(20),
+                Constraint::Percentage(15),
+                Constraint::Percentage(65),
+            ],
+        )
+        .header(Row::new(["Watcher", "Last success", "Last failure"]).bold())
+        .block(Block::default().borders(Borders::ALL).title("Watchers")),
+        watchers_area,
+    );
+
+    let rows = endpoints.iter().map(|endpoint| {
+        Row::new(vec![
+            endpoint.clone(),
+            latency(endpoint, RequestClass::Read),
+            latency(endpoint, RequestClass::Scan),
+        ])
+    });
+    frame.render_widget(
+        Table::new(
+            rows,
+            [
+                Constraint::Percentage(50),
+                Constraint::Percentage(25),
+                Constraint::Percentage(25),
+            ],
+        )
+        .header(Row::new(["Endpoint", "Mean read latency", "Mean scan latency"]).bold())
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("RPC endpoints"),
+        ),
+        endpoints_area,
+    );
+
+    frame.render_widget(Line::from("Press q to quit").dim(), help_area);
+}
+
+fn draw_until_quit(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    endpoints: &[String],
+) -> anyhow::Result<()> {
+    while !is_shutdown_requested() {
+        terminal.draw(|frame| draw(frame, endpoints))?;
+        if !event::poll(REFRESH_INTERVAL)? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            let ctrl_c =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            if key.kind == KeyEventKind::Press
+                && (ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc))
+            {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Renders a live dashboard of watched balances, watcher health and RPC
+/// endpoint latencies until the user quits or a shutdown is requested. Blocks
+/// the calling thread.
+pub fn run_dashboard(endpoints: &[String]) -> anyhow::Result<()> {
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+    let result = Terminal::new(CrosstermBackend::new(stdout()))
+        .map_err(anyhow::Error::from)
+        .and_then(|mut terminal| draw_until_quit(&mut terminal, endpoints));
+    disable_raw_mode()?;
+    stdout().execute(LeaveAlternateScreen)?;
+    result
+}
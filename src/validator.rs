@@ -0,0 +1,76 @@
+use log::{info, warn};
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcGetVoteAccountsConfig};
+use solana_sdk::pubkey::Pubkey;
+
+pub const WATCHER_NAME: &str = "validator";
+
+/// A validator given by its identity, with the vote account it votes with.
+#[derive(Debug, Clone)]
+pub struct Validator {
+    pub name: String,
+    pub identity: Pubkey,
+    pub vote_account: Pubkey,
+}
+
+impl Validator {
+    /// `--named-address` arguments watching the SOL balance of the identity,
+    /// which pays the vote fees, and of the vote account.
+    pub fn named_addresses(&self) -> [String; 2] {
+        [
+            format!("{}_identity={}", self.name, self.identity),
+            format!("{}_vote={}", self.name, self.vote_account),
+        ]
+    }
+
+    /// `--vote-account` argument watching the vote state.
+    pub fn vote_account_arg(&self) -> String {
+        format!("{}={}", self.name, self.vote_account)
+    }
+}
+
+/// Resolves the vote account of each `(name, identity)` from
+/// `getVoteAccounts`, including delinquent and unstaked ones. Of several vote
+/// accounts of one identity, the one with the most stake is used.
+pub async fn resolve_validators(
+    rpc_client: &RpcClient,
+    identities: &[(String, Pubkey)],
+) -> anyhow::Result<Vec<Validator>> {
+    let vote_accounts = rpc_client
+        .get_vote_accounts_with_config(RpcGetVoteAccountsConfig {
+            keep_unstaked_delinquents: Some(true),
+            ..Default::default()
+        })
+        .await?;
+    let all: Vec<_> = vote_accounts
+        .current
+        .iter()
+        .chain(&vote_accounts.delinquent)
+        .collect();
+
+    let mut validators = vec![];
+    for (name, identity) in identities {
+        let identity_str = identity.to_string();
+        let mut matching: Vec<_> = all
+            .iter()
+            .filter(|vote_account| vote_account.node_pubkey == identity_str)
+            .collect();
+        matching.sort_by_key(|vote_account| std::cmp::Reverse(vote_account.activated_stake));
+        let Some(vote_account) = matching.first() else {
+            anyhow::bail!("No vote account found for validator {name} with identity {identity}");
+        };
+        if matching.len() > 1 {
+            warn!(
+                "Validator {name} ({identity}) has {} vote accounts, watching the one with the most stake",
+                matching.len()
+            );
+        }
+        let vote_account: Pubkey = vote_account.vote_pubkey.parse()?;
+        info!("Validator {name} ({identity}) votes with {vote_account}");
+        validators.push(Validator {
+            name: name.clone(),
+            identity: *identity,
+            vote_account,
+        });
+    }
+    Ok(validators)
+}
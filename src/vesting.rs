@@ -0,0 +1,280 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{error, info};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use tokio::task::JoinHandle;
+
+use crate::{
+    data_slice::Field,
+    health::{record_failed_check, record_successful_check},
+    intervals::{backoff_duration, check_interval, DEFAULT_CHECK_INTERVAL},
+    metrics::{
+        remove_metric_vesting_amount, update_metric_vesting_amount, update_metric_watcher_info,
+    },
+    rate_limit::RateLimiter,
+    shutdown::sleep_unless_shutdown,
+};
+
+pub const WATCHER_NAME: &str = "vesting";
+
+/// Tokens still held by a vesting contract, in the smallest unit of `mint`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VestedAmounts {
+    pub mint: Pubkey,
+    /// Released by the schedule but not withdrawn yet.
+    pub vested: u64,
+    /// Not released by the schedule yet.
+    pub unvested: u64,
+}
+
+/// Decodes the schedule of a vesting or escrow contract account.
+pub trait VestingDecoder: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Amounts held by the contract with account `data` at unix time `now`.
+    fn decode(&self, data: &[u8], now: u64) -> anyhow::Result<VestedAmounts>;
+}
+
+/// Decoders selectable by name, with `decoder:NAME` in `--vesting-contract`.
+pub fn vesting_decoder(name: &str) -> anyhow::Result<Arc<dyn VestingDecoder>> {
+    Ok(match name {
+        "streamflow" => Arc::new(Streamflow),
+        "bonfida" => Arc::new(BonfidaVesting),
+        _ => anyhow::bail!("Unsupported vesting decoder '{name}', expected streamflow or bonfida"),
+    })
+}
+
+/// Streamflow stream (`Contract`) account, vesting `cliff_amount` at `cliff`
+/// and then `amount_per_period` every `period` seconds until the whole net
+/// deposit is released. Cancelled streams stop vesting when cancelled.
+pub struct Streamflow;
+
+const STREAMFLOW_AMOUNT_WITHDRAWN: Field = Field::new(17, 8);
+const STREAMFLOW_CANCELED_AT: Field = Field::new(25, 8);
+const STREAMFLOW_MINT: Field = Field::new(177, 32);
+const STREAMFLOW_NET_AMOUNT_DEPOSITED: Field = Field::new(417, 8);
+const STREAMFLOW_PERIOD: Field = Field::new(425, 8);
+const STREAMFLOW_AMOUNT_PER_PERIOD: Field = Field::new(433, 8);
+const STREAMFLOW_CLIFF: Field = Field::new(441, 8);
+const STREAMFLOW_CLIFF_AMOUNT: Field = Field::new(449, 8);
+
+impl VestingDecoder for Streamflow {
+    fn name(&self) -> &'static str {
+        "streamflow"
+    }
+
+    fn decode(&self, data: &[u8], now: u64) -> anyhow::Result<VestedAmounts> {
+        let read = |field: Field| {
+            field
+                .read_u64(data)
+                .ok_or_else(|| anyhow::anyhow!("Streamflow contract data is too short"))
+        };
+        let deposited = read(STREAMFLOW_NET_AMOUNT_DEPOSITED)?;
+        let withdrawn = read(STREAMFLOW_AMOUNT_WITHDRAWN)?;
+        let period = read(STREAMFLOW_PERIOD)?;
+        let cliff = read(STREAMFLOW_CLIFF)?;
+        let now = match read(STREAMFLOW_CANCELED_AT)? {
+            0 => now,
+            canceled_at => now.min(canceled_at),
+        };
+
+        let released = match now.checked_sub(cliff) {
+            None => 0,
+            Some(since_cliff) => {
+                let periods = since_cliff.checked_div(period).unwrap_or(0);
+                read(STREAMFLOW_CLIFF_AMOUNT)?
+                    .saturating_add(periods.saturating_mul(read(STREAMFLOW_AMOUNT_PER_PERIOD)?))
+                    .min(deposited)
+            }
+        };
+        Ok(VestedAmounts {
+            mint: STREAMFLOW_MINT
+                .read_pubkey(data)
+                .ok_or_else(|| anyhow::anyhow!("Streamflow contract data is too short"))?,
+            vested: released.saturating_sub(withdrawn),
+            unvested: deposited - released,
+        })
+    }
+}
+
+/// Bonfida token vesting account: a header followed by `release_time (u64) |
+/// amount (u64)` schedules, whose amounts are zeroed once unlocked.
+pub struct BonfidaVesting;
+
+const BONFIDA_MINT: Field = Field::new(32, 32);
+const BONFIDA_HEADER_SIZE: usize = 65;
+const BONFIDA_SCHEDULE_SIZE: usize = 16;
+
+impl VestingDecoder for BonfidaVesting {
+    fn name(&self) -> &'static str {
+        "bonfida"
+    }
+
+    fn decode(&self, data: &[u8], now: u64) -> anyhow::Result<VestedAmounts> {
+        let Some(mint) = BONFIDA_MINT.read_pubkey(data) else {
+            anyhow::bail!("Bonfida vesting data is too short");
+        };
+        let mut amounts = VestedAmounts {
+            mint,
+            vested: 0,
+            unvested: 0,
+        };
+        let schedules = data.get(BONFIDA_HEADER_SIZE..).unwrap_or_default();
+        for schedule in schedules.chunks_exact(BONFIDA_SCHEDULE_SIZE) {
+            let release_time = Field::new(0, 8).read_u64(schedule).unwrap();
+            let amount = Field::new(8, 8).read_u64(schedule).unwrap();
+            match release_time <= now {
+                true => amounts.vested += amount,
+                false => amounts.unvested += amount,
+            }
+        }
+        Ok(amounts)
+    }
+}
+
+/// A vesting contract account decoded by a [`VestingDecoder`].
+#[derive(Clone)]
+pub struct VestingContract {
+    pub name: String,
+    pub pubkey: Pubkey,
+    pub decoder: Arc<dyn VestingDecoder>,
+}
+
+impl fmt::Debug for VestingContract {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VestingContract")
+            .field("name", &self.name)
+            .field("pubkey", &self.pubkey)
+            .field("decoder", &self.decoder.name())
+            .finish()
+    }
+}
+
+impl FromStr for VestingContract {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, params)) = s.split_once('=') else {
+            anyhow::bail!(
+                "Cannot parse vesting contract, expected syntax: name=pubkey decoder:NAME"
+            );
+        };
+        let mut params = params.split(' ').filter(|param| !param.is_empty());
+        let pubkey = params.next().unwrap_or_default();
+        let Ok(pubkey) = Pubkey::from_str(pubkey) else {
+            anyhow::bail!("Failed to parse vesting contract pubkey from '{pubkey}'");
+        };
+        let mut decoder = None;
+        for param in params {
+            match param.split_once(':') {
+                Some(("decoder", value)) => decoder = Some(vesting_decoder(value)?),
+                _ => anyhow::bail!("Unsupported parameter '{param}' of vesting contract '{name}'"),
+            }
+        }
+        let Some(decoder) = decoder else {
+            anyhow::bail!("Vesting contract '{name}' requires a decoder:NAME parameter");
+        };
+        Ok(VestingContract {
+            name: name.to_string(),
+            pubkey,
+            decoder,
+        })
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Reports the vested and unvested amounts held by each of `contracts`.
+pub fn spawn_vesting_watcher(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    contracts: Vec<VestingContract>,
+) -> JoinHandle<()> {
+    update_metric_watcher_info(
+        WATCHER_NAME,
+        WATCHER_NAME,
+        "",
+        check_interval(DEFAULT_CHECK_INTERVAL),
+    );
+    tokio::spawn(async move {
+        info!("Watching vesting contracts: {contracts:?}");
+        let pubkeys: Vec<_> = contracts.iter().map(|contract| contract.pubkey).collect();
+        // Mints last reported per contract, to remove their metrics again.
+        let mut mints = HashMap::new();
+        loop {
+            rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+            let accounts = match rpc_client.get_multiple_accounts(&pubkeys).await {
+                Ok(accounts) => accounts,
+                Err(err) => {
+                    error!("Failed to get RPC response: {err}");
+                    record_failed_check(WATCHER_NAME, &err.to_string());
+                    if !sleep_unless_shutdown(backoff_duration(WATCHER_NAME)).await {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            let now = unix_now();
+            // Latest contract that could not be decoded, failing the check.
+            let mut failure = None;
+            for (contract, account) in contracts.iter().zip(accounts) {
+                let pubkey = contract.pubkey.to_string();
+                let amounts = match account {
+                    Some(account) => contract.decoder.decode(&account.data, now),
+                    None => Err(anyhow::anyhow!("Account does not exist")),
+                };
+                match amounts {
+                    Ok(amounts) => {
+                        info!("Vesting contract {pubkey}: {amounts:?}");
+                        let mint = amounts.mint.to_string();
+                        mints.insert(contract.pubkey, mint.clone());
+                        update_metric_vesting_amount(
+                            &contract.name,
+                            &pubkey,
+                            &mint,
+                            "vested",
+                            amounts.vested,
+                        );
+                        update_metric_vesting_amount(
+                            &contract.name,
+                            &pubkey,
+                            &mint,
+                            "unvested",
+                            amounts.unvested,
+                        );
+                    }
+                    Err(err) => {
+                        let err = format!("Cannot decode vesting contract {pubkey}: {err}");
+                        error!("{err}");
+                        if let Some(mint) = mints.remove(&contract.pubkey) {
+                            remove_metric_vesting_amount(&contract.name, &pubkey, &mint);
+                        }
+                        failure = Some(err);
+                    }
+                }
+            }
+            match failure {
+                Some(err) => record_failed_check(WATCHER_NAME, &err),
+                None => record_successful_check(WATCHER_NAME),
+            }
+
+            if !sleep_unless_shutdown(check_interval(DEFAULT_CHECK_INTERVAL)).await {
+                break;
+            }
+        }
+        info!("Vesting watcher stopped");
+    })
+}
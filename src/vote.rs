@@ -0,0 +1,151 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use log::{error, info};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_request::DELINQUENT_VALIDATOR_SLOT_DISTANCE,
+};
+use solana_sdk::{
+    account::Account,
+    clock::Slot,
+    pubkey::Pubkey,
+    vote::{self, state::VoteState},
+};
+use tokio::task::JoinHandle;
+
+use crate::{
+    health::{record_failed_check, record_successful_check},
+    intervals::{backoff_duration, check_interval},
+    metrics::{reset_metric_vote_account, update_metric_vote_account, update_metric_watcher_info},
+    rate_limit::RateLimiter,
+    shutdown::sleep_unless_shutdown,
+};
+
+pub const WATCHER_NAME: &str = "vote";
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What a validator monitors of its vote account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoteAccountState {
+    pub commission: u8,
+    /// Credits earned over the lifetime of the account.
+    pub credits: u64,
+    /// Credits earned in the latest epoch the account voted in.
+    pub epoch_credits: u64,
+    pub last_vote_slot: Option<Slot>,
+    pub root_slot: Option<Slot>,
+    /// Slots since the last vote, up to the slot the account was read at.
+    pub slots_behind: Option<u64>,
+    /// Whether the cluster considers the validator delinquent, having not
+    /// voted in the last `DELINQUENT_VALIDATOR_SLOT_DISTANCE` slots.
+    pub delinquent: bool,
+}
+
+fn vote_account_state(account: &Account, slot: Slot) -> anyhow::Result<VoteAccountState> {
+    anyhow::ensure!(
+        account.owner == vote::program::id(),
+        "Account is owned by {}, not the vote program",
+        account.owner
+    );
+    let state = VoteState::deserialize(&account.data)
+        .map_err(|_| anyhow::anyhow!("Cannot decode vote state"))?;
+    let epoch_credits = state
+        .epoch_credits()
+        .last()
+        .map_or(0, |(_, credits, previous_credits)| {
+            credits - previous_credits
+        });
+    let last_vote_slot = state.last_voted_slot();
+    let slots_behind = last_vote_slot.map(|last_vote_slot| slot.saturating_sub(last_vote_slot));
+    Ok(VoteAccountState {
+        commission: state.commission,
+        credits: state.credits(),
+        epoch_credits,
+        last_vote_slot,
+        root_slot: state.root_slot,
+        slots_behind,
+        delinquent: match slots_behind {
+            Some(behind) => behind > DELINQUENT_VALIDATOR_SLOT_DISTANCE,
+            None => true,
+        },
+    })
+}
+
+/// Checks `vote_accounts`, logging when a validator becomes delinquent or
+/// recovers, as tracked in `delinquent`.
+async fn check_vote_accounts(
+    rpc_client: &RpcClient,
+    vote_accounts: &[(String, Pubkey)],
+    delinquent: &mut HashMap<Pubkey, bool>,
+) -> anyhow::Result<()> {
+    let pubkeys: Vec<_> = vote_accounts.iter().map(|(_, pubkey)| *pubkey).collect();
+    let response = rpc_client
+        .get_multiple_accounts_with_commitment(&pubkeys, rpc_client.commitment())
+        .await?;
+    let slot = response.context.slot;
+
+    reset_metric_vote_account();
+    for ((name, pubkey), account) in vote_accounts.iter().zip(response.value) {
+        let state = match account {
+            Some(account) => vote_account_state(&account, slot),
+            None => Err(anyhow::anyhow!("Account does not exist")),
+        };
+        match state {
+            Ok(state) => {
+                info!("Vote account {name} ({pubkey}): {state:?}");
+                let was_delinquent = delinquent.insert(*pubkey, state.delinquent);
+                if state.delinquent && was_delinquent != Some(true) {
+                    error!(
+                        "Validator of vote account {name} ({pubkey}) is delinquent, last voted {} slots ago",
+                        state
+                            .slots_behind
+                            .map_or("never".to_string(), |behind| behind.to_string())
+                    );
+                } else if !state.delinquent && was_delinquent == Some(true) {
+                    info!("Validator of vote account {name} ({pubkey}) is voting again");
+                }
+                update_metric_vote_account(name, &pubkey.to_string(), &state);
+            }
+            Err(err) => error!("Cannot decode vote account {name} ({pubkey}): {err}"),
+        }
+    }
+    Ok(())
+}
+
+/// Reports the credits, commission, last vote and root slot of each vote
+/// account, and how far its validator is behind in voting.
+pub fn spawn_vote_account_watcher(
+    rpc_client: Arc<RpcClient>,
+    rate_limiter: Arc<RateLimiter>,
+    vote_accounts: Vec<(String, Pubkey)>,
+) -> JoinHandle<()> {
+    update_metric_watcher_info(
+        WATCHER_NAME,
+        WATCHER_NAME,
+        "",
+        check_interval(CHECK_INTERVAL),
+    );
+    tokio::spawn(async move {
+        info!("Watching vote accounts: {vote_accounts:?}");
+        let mut delinquent = HashMap::new();
+        loop {
+            rate_limiter.acquire(WATCHER_NAME, 1, 1).await;
+            let checked = check_vote_accounts(&rpc_client, &vote_accounts, &mut delinquent).await;
+            if let Err(err) = checked {
+                error!("Failed to check vote accounts: {err}");
+                record_failed_check(WATCHER_NAME, &err.to_string());
+                reset_metric_vote_account();
+                if !sleep_unless_shutdown(backoff_duration(WATCHER_NAME)).await {
+                    break;
+                }
+                continue;
+            }
+            record_successful_check(WATCHER_NAME);
+
+            if !sleep_unless_shutdown(check_interval(CHECK_INTERVAL)).await {
+                break;
+            }
+        }
+        info!("Vote account watcher stopped");
+    })
+}
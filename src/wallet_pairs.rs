@@ -0,0 +1,170 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use log::{error, info, warn};
+use solana_sdk::native_token::lamports_to_sol;
+use tokio::{sync::broadcast::error::RecvError, task::JoinHandle};
+
+use crate::{
+    alert_rules::BalanceRef,
+    amount_format::format_lamports,
+    metrics::{
+        update_metric_wallet_pair_hot_share, update_metric_wallet_pair_limits,
+        update_metric_wallet_pair_rebalance_sol,
+    },
+    observations::{subscribe_observations, Observation},
+    shutdown::shutdown_requested,
+};
+
+/// A hot wallet and the cold wallet backing it, parsed from
+/// `name=hot:[watcher/]name cold:[watcher/]name max:SHARE [target:SHARE]`.
+/// The hot wallet must not hold more than `max` of the combined funds, and
+/// is ideally kept at `target`.
+#[derive(Debug, Clone)]
+pub struct WalletPair {
+    pub name: String,
+    hot: BalanceRef,
+    cold: BalanceRef,
+    max_hot_share: f64,
+    target_hot_share: Option<f64>,
+}
+
+fn parse_share(value: &str) -> anyhow::Result<f64> {
+    let share: f64 = value.parse()?;
+    anyhow::ensure!(
+        (0.0..=1.0).contains(&share),
+        "Share {share} is not between 0 and 1"
+    );
+    Ok(share)
+}
+
+impl FromStr for WalletPair {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, params)) = s.split_once('=') else {
+            anyhow::bail!(
+                "Cannot parse wallet pair, expected syntax: name=hot:NAME cold:NAME max:SHARE"
+            );
+        };
+        let (mut hot, mut cold, mut max_hot_share, mut target_hot_share) = (None, None, None, None);
+        for param in params.split(' ').filter(|param| !param.is_empty()) {
+            match param.split_once(':') {
+                Some(("hot", value)) => hot = Some(value.parse()?),
+                Some(("cold", value)) => cold = Some(value.parse()?),
+                Some(("max", value)) => max_hot_share = Some(parse_share(value)?),
+                Some(("target", value)) => target_hot_share = Some(parse_share(value)?),
+                _ => anyhow::bail!("Unsupported parameter '{param}' of wallet pair '{name}'"),
+            }
+        }
+        let (Some(hot), Some(cold), Some(max_hot_share)) = (hot, cold, max_hot_share) else {
+            anyhow::bail!("Wallet pair '{name}' requires hot:NAME, cold:NAME and max:SHARE");
+        };
+        if let Some(target) = target_hot_share {
+            anyhow::ensure!(
+                target <= max_hot_share,
+                "Target share {target} of wallet pair '{name}' exceeds its maximum {max_hot_share}"
+            );
+        }
+        Ok(WalletPair {
+            name: name.to_string(),
+            hot,
+            cold,
+            max_hot_share,
+            target_hot_share,
+        })
+    }
+}
+
+impl fmt::Display for WalletPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "hot {} and cold {}, hot share at most {}",
+            self.hot, self.cold, self.max_hot_share
+        )?;
+        if let Some(target) = self.target_hot_share {
+            write!(f, ", targeting {target}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Latest balances of the hot and cold wallet of a pair.
+#[derive(Debug, Clone, Copy, Default)]
+struct PairBalances {
+    hot: Option<u64>,
+    cold: Option<u64>,
+    exceeded: bool,
+}
+
+fn evaluate(pair: &WalletPair, balances: &mut PairBalances, observation: &Observation) {
+    if pair.hot.matches(&observation.watcher, &observation.name) {
+        balances.hot = Some(observation.lamports);
+    } else if pair.cold.matches(&observation.watcher, &observation.name) {
+        balances.cold = Some(observation.lamports);
+    } else {
+        return;
+    }
+    let (Some(hot), Some(cold)) = (balances.hot, balances.cold) else {
+        return;
+    };
+    let total = hot as f64 + cold as f64;
+    if total == 0.0 {
+        return;
+    }
+    let share = hot as f64 / total;
+    let exceeded = share > pair.max_hot_share;
+    update_metric_wallet_pair_hot_share(&pair.name, share, exceeded);
+    if let Some(target) = pair.target_hot_share {
+        let excess = lamports_to_sol(hot) - target * lamports_to_sol(hot + cold);
+        update_metric_wallet_pair_rebalance_sol(&pair.name, excess);
+    }
+    if exceeded && !balances.exceeded {
+        error!(
+            "Hot wallet of '{}' holds {:.1}% of the combined funds, more than the allowed {:.1}%: {} hot, {} cold",
+            pair.name,
+            share * 100.0,
+            pair.max_hot_share * 100.0,
+            format_lamports(hot),
+            format_lamports(cold)
+        );
+    } else if !exceeded && balances.exceeded {
+        info!(
+            "Hot wallet of '{}' is back within its share at {:.1}%",
+            pair.name,
+            share * 100.0
+        );
+    }
+    balances.exceeded = exceeded;
+}
+
+/// Tracks the share of the combined funds each pair's hot wallet holds,
+/// exporting it along with the limits, and logs when a hot wallet exceeds its
+/// allowed share.
+pub fn spawn_wallet_pair_evaluator(pairs: Vec<WalletPair>) -> JoinHandle<()> {
+    for pair in &pairs {
+        info!("Wallet pair '{}': {pair}", pair.name);
+        update_metric_wallet_pair_limits(&pair.name, pair.max_hot_share, pair.target_hot_share);
+    }
+    let mut observations = subscribe_observations();
+    tokio::spawn(async move {
+        let mut balances: HashMap<String, PairBalances> = HashMap::new();
+        loop {
+            tokio::select! {
+                observation = observations.recv() => match observation {
+                    Ok(observation) => {
+                        for pair in &pairs {
+                            let pair_balances = balances.entry(pair.name.clone()).or_default();
+                            evaluate(pair, pair_balances, &observation);
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Wallet pair evaluator fell behind, skipped {skipped} observations")
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = shutdown_requested() => break,
+            }
+        }
+    })
+}
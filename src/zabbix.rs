@@ -0,0 +1,121 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use log::warn;
+use serde_json::{json, Value};
+use solana_sdk::native_token::lamports_to_sol;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+use crate::{observations::Observation, sink::MetricSink};
+
+const HEADER: &[u8; 5] = b"ZBXD\x01";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Upper bound on the size of a response accepted from the server.
+const MAX_RESPONSE_LENGTH: u64 = 1 << 20;
+
+pub const DEFAULT_ITEM_KEY: &str = "solana.balance[{watcher},{name}]";
+
+/// Pushes balances in SOL to a Zabbix server or proxy using the sender
+/// protocol, as trapper items of `host`. Item keys are rendered from a
+/// template where `{watcher}`, `{name}` and `{pubkey}` are substituted.
+pub struct ZabbixSender {
+    server: String,
+    host: String,
+    item_key: String,
+}
+
+impl ZabbixSender {
+    pub fn new(server: String, host: String, item_key: String) -> Self {
+        Self {
+            server,
+            host,
+            item_key,
+        }
+    }
+
+    fn item_key(&self, observation: &Observation) -> String {
+        self.item_key
+            .replace("{watcher}", &observation.watcher)
+            .replace("{name}", &observation.name)
+            .replace(
+                "{pubkey}",
+                &observation
+                    .pubkey
+                    .map(|pubkey| pubkey.to_string())
+                    .unwrap_or_default(),
+            )
+    }
+
+    async fn send(&self, request: &Value) -> anyhow::Result<Value> {
+        let body = serde_json::to_vec(request)?;
+        let mut stream = TcpStream::connect(&self.server).await?;
+        let mut packet = Vec::with_capacity(HEADER.len() + 8 + body.len());
+        packet.extend_from_slice(HEADER);
+        packet.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        packet.extend_from_slice(&body);
+        stream.write_all(&packet).await?;
+
+        let mut header = [0; 13];
+        stream.read_exact(&mut header).await?;
+        anyhow::ensure!(
+            header.starts_with(HEADER),
+            "Unexpected response header from {}",
+            self.server
+        );
+        let length = u64::from_le_bytes(header[5..].try_into()?);
+        anyhow::ensure!(
+            length <= MAX_RESPONSE_LENGTH,
+            "Response from {} is too large ({length} bytes)",
+            self.server
+        );
+        let mut response = vec![0; length as usize];
+        stream.read_exact(&mut response).await?;
+        Ok(serde_json::from_slice(&response)?)
+    }
+}
+
+#[async_trait]
+impl MetricSink for ZabbixSender {
+    fn name(&self) -> &str {
+        "zabbix"
+    }
+
+    async fn publish(&self, observations: &[Observation]) -> anyhow::Result<()> {
+        let data: Vec<_> = observations
+            .iter()
+            .map(|observation| {
+                let clock = observation
+                    .observed_at
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                json!({
+                    "host": self.host,
+                    "key": self.item_key(observation),
+                    "value": lamports_to_sol(observation.lamports).to_string(),
+                    "clock": clock.as_secs(),
+                    "ns": clock.subsec_nanos(),
+                })
+            })
+            .collect();
+        let request = json!({ "request": "sender data", "data": data });
+
+        let response = timeout(REQUEST_TIMEOUT, self.send(&request)).await??;
+        match response["response"].as_str() {
+            Some("success") => {
+                // Items that do not exist or are not trapper items are only
+                // reported in the info string, retrying would not help.
+                if let Some(info) = response["info"].as_str() {
+                    if !info.contains("failed: 0") {
+                        warn!("Zabbix rejected some items: {info}");
+                    }
+                }
+                Ok(())
+            }
+            _ => anyhow::bail!("Zabbix server responded with {response}"),
+        }
+    }
+}